@@ -0,0 +1,469 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::process;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::config::{self, Anchor, Area, Config, MappingTarget};
+use crate::device::Bounds;
+use crate::mapping;
+use crate::monitor::{self, Monitor};
+use crate::stdout;
+
+/// The columns of the sketch of the screen in the area selection.
+const SKETCH_COLUMNS: usize = 48;
+/// The rows of the sketch of the screen in the area selection.
+const SKETCH_ROWS: usize = 12;
+
+/// How much of each side of the monitor is left out of the area, in percent.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// area::Insets { left: 10, top: 0, right: 10, bottom: 0 }
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Insets {
+    pub left: u8,
+    pub top: u8,
+    pub right: u8,
+    pub bottom: u8
+}
+
+impl Insets {
+    /// Returns the insets in the order of the editable rows.
+    fn sides(&self) -> [u8; 4] {
+        [self.left, self.top, self.right, self.bottom]
+    }
+
+    /// Returns the insets to change in the order of the editable rows.
+    fn sides_mut(&mut self) -> [&mut u8; 4] {
+        [&mut self.left, &mut self.top, &mut self.right, &mut self.bottom]
+    }
+
+    /// Returns whether some of the monitor is left in the area.
+    pub fn valid(&self) -> bool {
+        (self.left as u32 + self.right as u32) < 100 && (self.top as u32 + self.bottom as u32) < 100
+    }
+}
+
+/// Returns the size of the screen covering all the monitors.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (width, height) = area::screen_size(&monitor::list_monitors().unwrap_or_default());
+/// ```
+pub fn screen_size(monitors: &[Monitor]) -> (u32, u32) {
+    let (left, top) = screen_origin(monitors);
+    let right = monitors.iter().map(|m| m.x as i64 + m.width as i64).max().unwrap_or(0);
+    let bottom = monitors.iter().map(|m| m.y as i64 + m.height as i64).max().unwrap_or(0);
+    ((right - left as i64).max(0) as u32, (bottom - top as i64).max(0) as u32)
+}
+
+/// Returns the top left corner of the screen, where the output starts.
+fn screen_origin(monitors: &[Monitor]) -> (i32, i32) {
+    (
+        monitors.iter().map(|m| m.x).min().unwrap_or(0),
+        monitors.iter().map(|m| m.y).min().unwrap_or(0)
+    )
+}
+
+/// Returns the area of the output covering the monitor without the insets,
+/// or `None` if the insets leave nothing of it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let monitors = monitor::list_monitors().unwrap_or_default();
+/// let area = area::inset_area(&monitors, &monitors[0], area::Insets::default()).unwrap();
+/// ```
+pub fn inset_area(monitors: &[Monitor], monitor: &Monitor, insets: Insets) -> Option<Area> {
+    if !insets.valid() {
+        return None;
+    }
+
+    let (left, top) = screen_origin(monitors);
+    let inset = |size: u32, percent: u8| (size as u64 * percent as u64 / 100) as u32;
+    let (x, y) = (inset(monitor.width, insets.left), inset(monitor.height, insets.top));
+    let width = monitor.width - x - inset(monitor.width, insets.right);
+    let height = monitor.height - y - inset(monitor.height, insets.bottom);
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    Some(Area {
+        x: (monitor.x - left) as u32 + x,
+        y: (monitor.y - top) as u32 + y,
+        width,
+        height,
+        width_mm: None,
+        height_mm: None
+    })
+}
+
+/// Returns the area of the output covering the bounding box of the chosen monitors,
+/// or `None` if none are chosen or they cover nothing.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let monitors = monitor::list_monitors().unwrap_or_default();
+/// let area = area::union_area(&monitors, &monitors.iter().collect::<Vec<_>>()).unwrap();
+/// ```
+pub fn union_area(monitors: &[Monitor], chosen: &[&Monitor]) -> Option<Area> {
+    let (left, top) = screen_origin(monitors);
+    let x = chosen.iter().map(|m| m.x as i64).min()?;
+    let y = chosen.iter().map(|m| m.y as i64).min()?;
+    let right = chosen.iter().map(|m| m.x as i64 + m.width as i64).max()?;
+    let bottom = chosen.iter().map(|m| m.y as i64 + m.height as i64).max()?;
+    if right <= x || bottom <= y {
+        return None;
+    }
+
+    Some(Area {
+        x: (x - left as i64) as u32,
+        y: (y - top as i64) as u32,
+        width: (right - x) as u32,
+        height: (bottom - y) as u32,
+        width_mm: None,
+        height_mm: None
+    })
+}
+
+/// Returns the config mapping onto its target among the monitors,
+/// with the whole screen as the output and the bounding box of the target
+/// monitors as the area. The config is returned as it is for the area target.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = area::apply_target(config::get_config()?, &monitor::list_monitors().unwrap_or_default())?;
+/// ```
+pub fn apply_target(mut conf: Config, monitors: &[Monitor]) -> Result<Config> {
+    let chosen: Vec<&Monitor> = match &conf.mapping.target {
+        MappingTarget::Area => return Ok(conf),
+        MappingTarget::All => monitors.iter().collect(),
+        MappingTarget::Monitors(names) => {
+            let mut chosen = vec![];
+            for name in names {
+                match monitors.iter().find(|m| m.name == *name) {
+                    Some(monitor) => chosen.push(monitor),
+                    None => return Err(anyhow!("Unknown monitor of the mapping target: {}", name))
+                }
+            }
+            chosen
+        }
+    };
+
+    let area = union_area(monitors, &chosen)
+        .ok_or_else(|| anyhow!("The monitors of the mapping target cover nothing."))?;
+    let (width, height) = screen_size(monitors);
+    conf.mapping.width = Some(width);
+    conf.mapping.height = Some(height);
+    conf.mapping.area = Some(area);
+    Ok(conf)
+}
+
+/// Returns the config mapping onto its target among the monitors
+/// the display server reports, like `apply_target`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = area::resolve_target(config::get_config()?)?;
+/// ```
+pub fn resolve_target(conf: Config) -> Result<Config> {
+    if conf.mapping.target == MappingTarget::Area {
+        return Ok(conf);
+    }
+
+    let monitors = monitor::list_monitors()
+        .ok_or_else(|| anyhow!("Couldn't reach a display server to find the monitors of the mapping target."))?;
+    let conf = apply_target(conf, &monitors)?;
+    if let Some(area) = conf.mapping.area {
+        debugln!("Mapping onto {} covering: {}x{}+{}+{}", conf.mapping.target, area.width, area.height, area.x, area.y);
+    }
+    Ok(conf)
+}
+
+/// The rectangle of the output the strokes land in, in pixels,
+/// with the monitors it lies on.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// area::ActiveArea {
+///     x: 0,
+///     y: 0,
+///     width: 1920,
+///     height: 1080,
+///     monitors: vec![String::from("eDP-1")]
+/// }
+/// ```
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActiveArea {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub monitors: Vec<String>
+}
+
+/// Returns the rectangle the touchpad is mapped onto with the config,
+/// whose target is already resolved, and the monitors overlapping it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let monitors = monitor::list_monitors().unwrap_or_default();
+/// let active = area::active_area(&area::apply_target(conf, &monitors)?, bounds, &monitors);
+/// ```
+pub fn active_area(conf: &Config, bounds: Bounds, monitors: &[Monitor]) -> ActiveArea {
+    let area = mapping::reach_area(conf, bounds);
+
+    let (left, top) = screen_origin(monitors);
+    let overlaps = |m: &&Monitor| {
+        let (x, y) = (m.x as i64 - left as i64, m.y as i64 - top as i64);
+        let (ax, ay) = (area.x as i64, area.y as i64);
+        x < ax + area.width as i64 && ax < x + m.width as i64
+            && y < ay + area.height as i64 && ay < y + m.height as i64
+    };
+
+    ActiveArea {
+        x: area.x,
+        y: area.y,
+        width: area.width,
+        height: area.height,
+        monitors: monitors.iter().filter(overlaps).map(|m| m.name.clone()).collect()
+    }
+}
+
+/// Draws the screen scaled down to the given number of columns and rows,
+/// with `.` for the monitors and `#` for the area.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for line in area::sketch(&monitors, Some(area), 48, 12) {
+///     println!("{}", line);
+/// }
+/// ```
+pub fn sketch(monitors: &[Monitor], area: Option<Area>, columns: usize, rows: usize) -> Vec<String> {
+    let (width, height) = screen_size(monitors);
+    let (left, top) = screen_origin(monitors);
+    if width == 0 || height == 0 {
+        return vec![];
+    }
+
+    // The middle of each cell decides what it shows.
+    let inside = |x: f64, y: f64, rx: i64, ry: i64, rw: u32, rh: u32| {
+        x >= rx as f64 && x < (rx + rw as i64) as f64 && y >= ry as f64 && y < (ry + rh as i64) as f64
+    };
+    (0..rows).map(|row| (0..columns).map(|column| {
+        let x = (column as f64 + 0.5) * width as f64 / columns as f64;
+        let y = (row as f64 + 0.5) * height as f64 / rows as f64;
+        if area.is_some_and(|a| inside(x, y, a.x as i64, a.y as i64, a.width, a.height)) {
+            '#'
+        } else if monitors.iter().any(|m| inside(x, y, m.x as i64 - left as i64, m.y as i64 - top as i64, m.width, m.height)) {
+            '.'
+        } else {
+            ' '
+        }
+    }).collect()).collect()
+}
+
+/// Maps the touchpad onto the area of the screen, making the output the whole screen.
+fn save_area(area: Area, screen: (u32, u32)) -> Result<()> {
+    let mut conf = config::get_file_config()?;
+    if (conf.mapping.width, conf.mapping.height) != (Some(screen.0), Some(screen.1)) {
+        logln!("Setting the output size to the screen size: \x1b[0;39m{}x{}", screen.0, screen.1);
+    }
+    conf.mapping.width = Some(screen.0);
+    conf.mapping.height = Some(screen.1);
+    conf.mapping.area = Some(area);
+    // The selected area is where it's shown, from the top left.
+    conf.mapping.anchor = Anchor::TopLeft;
+
+    let problems = conf.validate();
+    if !problems.is_empty() {
+        for problem in problems {
+            errln!("{}", problem);
+        }
+        errln!("Didn't save the area.");
+        process::exit(1);
+    }
+
+    config::save_config(&conf)?;
+    successln!("Mapped the touchpad onto the area: \x1b[0;39m{}x{}+{}+{}", area.width, area.height, area.x, area.y);
+    Ok(())
+}
+
+/// Lays out the lines of the area selection, with the cursor on the row `cur`,
+/// the monitor first and the insets after it.
+fn layout_lines(monitors: &[Monitor], chosen: usize, insets: Insets, cur: usize) -> Vec<String> {
+    let monitor = &monitors[chosen];
+    let area = inset_area(monitors, monitor, insets);
+    let marker = |row: usize| if row == cur { " >> " } else { " => " };
+
+    let mut lines = vec![
+        String::from("---===egawari=Area===---"),
+        String::new(),
+        format!("{}Monitor = \x1b[0;39m{:?} \x1b[1;39m({}x{}+{}+{})", marker(0), monitor.name, monitor.width, monitor.height, monitor.x, monitor.y)
+    ];
+    for (i, (name, side)) in ["Left", "Top", "Right", "Bottom"].iter().zip(insets.sides().iter()).enumerate() {
+        lines.push(format!("{}{} Inset = \x1b[0;39m{}%", marker(i + 1), name, side));
+    }
+    lines.push(String::new());
+    lines.push(match area {
+        Some(area) => format!(" => Area = \x1b[0;39m{}x{}+{}+{}", area.width, area.height, area.x, area.y),
+        None => String::from(" => Area = \x1b[0;39mnothing, the insets cover the monitor")
+    });
+    lines.push(String::new());
+    lines.extend(sketch(monitors, area, SKETCH_COLUMNS, SKETCH_ROWS).into_iter().map(|line| format!("    \x1b[0;39m{}", line)));
+    lines.push(String::new());
+    lines.push(String::from("---====================---"));
+    lines.push(String::new());
+    lines.push(String::from(r#" => Use "Up" and "Down" to move, "Left" and "Right" to change, "Enter" to save and "Esc" to quit without saving."#));
+    lines
+}
+
+/// Lets the user choose the monitor and the insets with a live preview,
+/// returning the chosen area or `None` if the selection is quit.
+fn select_interactive(monitors: &[Monitor]) -> Option<Area> {
+    let window = stdout::init_curses_wcolors();
+    window.keypad(true);
+    pancurses::noecho();
+    pancurses::curs_set(0);
+
+    let mut chosen = monitors.iter().position(|m| m.primary).unwrap_or(0);
+    let mut insets = Insets::default();
+    let mut cur = 0;
+    let selected = loop {
+        window.erase();
+        for (y, line) in layout_lines(monitors, chosen, insets, cur).iter().enumerate() {
+            colwmvaddstr!(&window, y as i32, 0, line.as_str());
+        }
+        window.refresh();
+
+        let step = match window.getch() {
+            Some(pancurses::Input::KeyUp) => {
+                cur = cur.checked_sub(1).unwrap_or(4);
+                continue;
+            },
+            Some(pancurses::Input::KeyDown) => {
+                cur = (cur + 1) % 5;
+                continue;
+            },
+            Some(pancurses::Input::KeyLeft) => -1,
+            Some(pancurses::Input::KeyRight) => 1,
+            Some(pancurses::Input::KeyEnter) | Some(pancurses::Input::Character('\n')) => {
+                if let Some(area) = inset_area(monitors, &monitors[chosen], insets) {
+                    break Some(area);
+                }
+                continue;
+            },
+            Some(pancurses::Input::Character('\u{1b}')) | Some(pancurses::Input::Character('q')) => break None,
+            Some(pancurses::Input::KeyResize) => {
+                pancurses::resize_term(0, 0);
+                continue;
+            },
+            _ => continue
+        };
+
+        if cur == 0 {
+            chosen = (chosen as i64 + step).rem_euclid(monitors.len() as i64) as usize;
+        } else {
+            let sides = insets.sides_mut();
+            let side = &mut *sides[cur - 1];
+            *side = (*side as i64 + step).clamp(0, 99) as u8;
+        }
+    };
+
+    pancurses::curs_set(1);
+    pancurses::endwin();
+    selected
+}
+
+/// Parses the insets given as the left, top, right and bottom percents.
+fn parse_insets(args: &[String]) -> Result<Insets> {
+    let mut insets = Insets::default();
+    if args.is_empty() {
+        return Ok(insets);
+    }
+    if args.len() != 4 {
+        return Err(anyhow!("The insets must be the left, top, right and bottom percents."));
+    }
+
+    for (side, arg) in insets.sides_mut().iter_mut().zip(args) {
+        **side = arg.parse().ok().filter(|percent| *percent < 100)
+            .ok_or_else(|| anyhow!("Invalid inset percent: {}", arg))?;
+    }
+    Ok(insets)
+}
+
+/// Runs the `select-area` command, mapping the touchpad onto a monitor
+/// without the given insets. Without arguments the monitor and the insets
+/// are chosen interactively, with a preview of the area.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// area::select_area(&[String::from("eDP-1"), String::from("10"), String::from("0"), String::from("10"), String::from("0")]).unwrap();
+/// ```
+pub fn select_area(args: &[String]) -> Result<()> {
+    let monitors = match monitor::list_monitors() {
+        Some(monitors) if !monitors.is_empty() => monitors,
+        Some(_) => {
+            errln!("The display server reports no enabled monitors.");
+            process::exit(1);
+        },
+        None => {
+            errln!("Couldn't reach a display server.");
+            logln!("Selecting the area needs: \x1b[0;39mxrandr or wlr-randr");
+            process::exit(1);
+        }
+    };
+    let screen = screen_size(&monitors);
+
+    let area = match args.split_first() {
+        Some((name, insets)) => {
+            let monitor = match monitors.iter().find(|m| m.name == *name) {
+                Some(monitor) => monitor,
+                None => {
+                    errln!("Unknown monitor: \x1b[0;39m{}", name);
+                    logln!("See: \x1b[0;39megawari monitors");
+                    process::exit(1);
+                }
+            };
+            match inset_area(&monitors, monitor, parse_insets(insets)?) {
+                Some(area) => area,
+                None => return Err(anyhow!("The insets leave nothing of the monitor."))
+            }
+        },
+        None => match select_interactive(&monitors) {
+            Some(area) => area,
+            None => {
+                warnln!("Didn't change the area.");
+                return Ok(());
+            }
+        }
+    };
+
+    save_area(area, screen)
+}
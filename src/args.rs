@@ -0,0 +1,228 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::path::PathBuf;
+use anyhow::{anyhow, Result};
+use crate::config::InputBounds;
+use crate::stdout::ColorMode;
+
+/// The options as their long name, short name and description.
+/// Long names ending with `=` take a value.
+pub const OPTIONS: &[(&str, Option<char>, &str)] = &[
+    ("config=", None, "Uses the given config file"),
+    ("no-wrap", None, "Stops the cursor of config at the ends"),
+    ("no-save", None, "Opens config read-only without saving"),
+    ("autosave=", None, "Autosaves the changes of config every given seconds"),
+    ("no-watch", None, "Doesn't reload the config file of run when it changes"),
+    ("dry-run", None, "Prints the tablet events instead of emitting them"),
+    ("safe", None, "Runs without grabbing the touchpad, printing the tablet events"),
+    ("dump-events=", None, "Records the input events of run to the given file"),
+    ("stats", None, "Logs the event statistics of run periodically"),
+    ("timeout=", None, "Stops run after the given seconds"),
+    ("overlay", None, "Outlines the mapped area of run on the screen"),
+    ("json", None, "Prints as JSON"),
+    ("install", None, "Installs the udev rule of udev-rule"),
+    ("color=", None, "Colors the output: auto, always or never"),
+    ("bounds=", None, "Maps map-point and active-area with the given min_x,max_x,min_y,max_y"),
+    ("verbose", Some('v'), "Also prints the debugging messages"),
+    ("quiet", Some('q'), "Only prints the warnings and errors"),
+    ("force", None, "Purges or runs even while egawari is running"),
+    ("yes", Some('y'), "Skips the confirmations")
+];
+
+/// The options given on the command line.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// args::Options {
+///     config: Some(PathBuf::from("/tmp/egawari.toml")),
+///     yes: true,
+///     ..Default::default()
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub config: Option<PathBuf>,
+    pub no_wrap: bool,
+    pub no_save: bool,
+    pub autosave: Option<u64>,
+    pub no_watch: bool,
+    pub dry_run: bool,
+    pub safe: bool,
+    pub dump_events: Option<PathBuf>,
+    pub stats: bool,
+    pub timeout: Option<u32>,
+    pub overlay: bool,
+    pub json: bool,
+    pub install: bool,
+    pub color: Option<ColorMode>,
+    pub bounds: Option<InputBounds>,
+    pub verbose: bool,
+    pub quiet: bool,
+    pub force: bool,
+    pub yes: bool
+}
+
+impl Options {
+    /// Sets the option with the long name, with its value if it takes one.
+    fn set(&mut self, name: &str, value: Option<String>) -> Result<()> {
+        match name {
+            "config" => self.config = value.map(PathBuf::from),
+            "no-wrap" => self.no_wrap = true,
+            "no-save" => self.no_save = true,
+            "autosave" => self.autosave = Some(value.unwrap_or_default().parse::<u64>()
+                .map_err(|_| anyhow!("The option --autosave needs a number of seconds."))?),
+            "no-watch" => self.no_watch = true,
+            "dry-run" => self.dry_run = true,
+            "safe" => self.safe = true,
+            "dump-events" => self.dump_events = value.map(PathBuf::from),
+            "stats" => self.stats = true,
+            "timeout" => self.timeout = Some(value.unwrap_or_default().parse::<u32>().ok().filter(|seconds| *seconds > 0)
+                .ok_or_else(|| anyhow!("The option --timeout needs a positive number of seconds."))?),
+            "overlay" => self.overlay = true,
+            "json" => self.json = true,
+            "install" => self.install = true,
+            "color" => self.color = Some(value.as_deref().and_then(ColorMode::parse)
+                .ok_or_else(|| anyhow!("The option --color needs auto, always or never."))?),
+            "bounds" => self.bounds = Some(value.as_deref().and_then(parse_bounds)
+                .ok_or_else(|| anyhow!("The option --bounds needs min_x,max_x,min_y,max_y."))?),
+            "verbose" => self.verbose = true,
+            "quiet" => self.quiet = true,
+            "force" => self.force = true,
+            "yes" => self.yes = true,
+            _ => ()
+        }
+        Ok(())
+    }
+}
+
+/// Parses the bounds given as `min_x,max_x,min_y,max_y`.
+fn parse_bounds(value: &str) -> Option<InputBounds> {
+    let values = value.split(',').map(|value| value.trim().parse::<i32>().ok()).collect::<Option<Vec<i32>>>()?;
+    match values[..] {
+        [min_x, max_x, min_y, max_y] => Some(InputBounds { min_x, max_x, min_y, max_y }),
+        _ => None
+    }
+}
+
+/// The parsed command line.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// args::Args {
+///     command: Some(String::from("profile")),
+///     args: vec![String::from("load"), String::from("drawing")],
+///     options: args::Options::default()
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Args {
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    pub options: Options
+}
+
+/// Returns the long name of the option and whether it takes a value,
+/// or `None` if there's no such option.
+fn find_long(name: &str) -> Option<(&'static str, bool)> {
+    OPTIONS.iter()
+        .map(|(long, _, _)| (long.trim_end_matches('='), long.ends_with('=')))
+        .find(|(long, _)| *long == name)
+}
+
+/// Returns the long name of the short option and whether it takes a value,
+/// or `None` if there's no such option.
+fn find_short(name: char) -> Option<(&'static str, bool)> {
+    OPTIONS.iter()
+        .find(|(_, short, _)| *short == Some(name))
+        .map(|(long, _, _)| (long.trim_end_matches('='), long.ends_with('=')))
+}
+
+/// Parses the command line arguments, without the program name.
+/// Options can come before or after the command and are given as
+/// `--key`, `--key=value`, `--key value` or bundled short flags like `-qy`.
+/// Everything after `--` is an argument, and so are negative numbers like `-5`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let parsed = args::parse(&env::args().skip(1).collect::<Vec<String>>())?;
+/// ```
+pub fn parse(raw: &[String]) -> Result<Args> {
+    let mut parsed = Args::default();
+    let mut positional = vec![];
+    let mut iter = raw.iter();
+
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            positional.extend(iter.by_ref().cloned());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None)
+            };
+            let (name, takes_value) = find_long(name)
+                .ok_or_else(|| anyhow!("Unknown option: --{}", name))?;
+
+            let value = match (takes_value, value) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(iter.next().cloned()
+                    .ok_or_else(|| anyhow!("The option --{} needs a value.", name))?),
+                (false, Some(_)) => return Err(anyhow!("The option --{} doesn't take a value.", name)),
+                (false, None) => None
+            };
+            parsed.options.set(name, value)?;
+            continue;
+        }
+
+        // No short option is a digit, so these are negative numbers like raw coordinates.
+        if let Some(short) = arg.strip_prefix('-').filter(|short| !short.is_empty() && !short.starts_with(|c: char| c.is_ascii_digit())) {
+            for (i, c) in short.char_indices() {
+                let (name, takes_value) = find_short(c)
+                    .ok_or_else(|| anyhow!("Unknown option: -{}", c))?;
+                if !takes_value {
+                    parsed.options.set(name, None)?;
+                    continue;
+                }
+
+                // The rest of the bundle, or else the next argument, is the value.
+                let rest = &short[i + c.len_utf8()..];
+                let value = if !rest.is_empty() {
+                    rest.to_string()
+                } else {
+                    iter.next().cloned().ok_or_else(|| anyhow!("The option -{} needs a value.", c))?
+                };
+                parsed.options.set(name, Some(value))?;
+                break;
+            }
+            continue;
+        }
+
+        positional.push(arg.to_string());
+    }
+
+    if !positional.is_empty() {
+        parsed.command = Some(positional.remove(0));
+    }
+    parsed.args = positional;
+    Ok(parsed)
+}
@@ -0,0 +1,293 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::process;
+use anyhow::Result;
+
+use crate::args::OPTIONS;
+use crate::config::KEYS;
+use crate::stdout::ColorMode;
+
+/// The commands and their descriptions.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("help", "Shows the help text"),
+    ("config", "Edits the configuration interactively"),
+    ("init", "Writes the default configuration to edit it"),
+    ("reset", "Restores the default configuration"),
+    ("purge", "Removes the configuration and the runtime files"),
+    ("uninstall", "Removes the configuration and the runtime files"),
+    ("export", "Writes the configuration to a file"),
+    ("import", "Validates and uses the configuration in a file"),
+    ("get", "Shows a configuration value"),
+    ("list-keys", "Lists the configuration keys"),
+    ("print-config", "Prints the configuration in effect"),
+    ("devices", "Lists the input devices"),
+    ("monitors", "Lists the monitors"),
+    ("screens", "Lists the monitors"),
+    ("select-area", "Maps the touchpad onto a monitor"),
+    ("doctor", "Checks that the virtual tablet can be created"),
+    ("udev-rule", "Prints or installs the udev rule for /dev/uinput"),
+    ("status", "Shows the state of the running egawari"),
+    ("run", "Maps the touchpad to a virtual graphics tablet"),
+    ("replay", "Prints the tablet events of a recording"),
+    ("map-point", "Prints where a raw position is mapped"),
+    ("active-area", "Prints the rectangle the strokes land in"),
+    ("test-output", "Draws a square with a virtual tablet"),
+    ("watch", "Shows the touchpad values live"),
+    ("toggle", "Pauses or resumes the mapping"),
+    ("profile", "Manages the configuration profiles"),
+    ("completions", "Prints the shell completion script")
+];
+
+/// The subcommands of `profile`.
+pub const PROFILE_COMMANDS: &[&str] = &["list", "save", "load", "delete"];
+
+/// The shells the completion scripts are made for.
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// The options whose value is a file.
+const FILE_OPTIONS: &[&str] = &["config=", "dump-events="];
+
+/// What the value of an option completes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OptionValue {
+    /// The option takes no value.
+    Flag,
+    /// A file name.
+    File,
+    /// One of the words.
+    Words(Vec<&'static str>),
+    /// Anything typed, like a number, with nothing to complete.
+    Free
+}
+
+/// Returns what the value of the option, like `color=`, completes to.
+fn option_value(long: &str) -> OptionValue {
+    match long {
+        _ if !long.ends_with('=') => OptionValue::Flag,
+        _ if FILE_OPTIONS.contains(&long) => OptionValue::File,
+        "color=" => OptionValue::Words(ColorMode::ALL.iter().map(|mode| mode.name()).collect()),
+        _ => OptionValue::Free
+    }
+}
+
+/// Returns the names of the options with values of the kind, like `--config|--dump-events`,
+/// as a pattern of a shell `case`.
+fn option_pattern(value: &OptionValue) -> String {
+    let names: Vec<String> = OPTIONS.iter()
+        .filter(|(long, _, _)| option_value(long) == *value)
+        .map(|(long, _, _)| format!("--{}", long.trim_end_matches('=')))
+        .collect();
+    names.join("|")
+}
+
+/// Returns the words of the options, like `--json` and `-y`.
+fn option_words() -> Vec<String> {
+    let mut words = vec![];
+    for (long, short, _) in OPTIONS {
+        words.push(format!("--{}", long));
+        if let Some(short) = short {
+            words.push(format!("-{}", short));
+        }
+    }
+
+    words
+}
+
+/// Returns the paths of the config keys, like `mapping.flip_x`.
+fn key_words() -> Vec<&'static str> {
+    KEYS.iter().map(|(path, _, _)| *path).collect()
+}
+
+/// Returns the bash completion script.
+fn bash() -> String {
+    let commands: Vec<&str> = COMMANDS.iter().map(|(name, _)| *name).collect();
+    format!(r#"_egawari() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local prefix=""
+
+    # Bash splits "--color=never" into "--color", "=" and "never".
+    if [[ "$cur" == "=" ]]; then
+        prefix="="
+        cur=""
+    elif [[ "$prev" == "=" ]]; then
+        prev="${{COMP_WORDS[COMP_CWORD-2]}}"
+    fi
+
+    case "$prev" in
+        profile) COMPREPLY=($(compgen -W "{profile}" -- "$cur")); return ;;
+        completions) COMPREPLY=($(compgen -W "{shells}" -- "$cur")); return ;;
+        get) COMPREPLY=($(compgen -W "{keys}" -- "$cur")); return ;;
+        export|import|replay) COMPREPLY=($(compgen -f -- "$cur")); return ;;
+        # The path stays open after a directory.
+        {file_options}) compopt -o nospace; COMPREPLY=($(compgen -f -P "$prefix" -- "$cur")); return ;;
+        --color) COMPREPLY=($(compgen -W "{colors}" -P "$prefix" -- "$cur")); return ;;
+        {free_options}) return ;;
+    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{options}" -- "$cur"))
+        # The value of an option follows its "=" directly.
+        [[ ${{#COMPREPLY[@]}} -eq 1 && "${{COMPREPLY[0]}}" == *= ]] && compopt -o nospace
+    else
+        COMPREPLY=($(compgen -W "{commands}" -- "$cur"))
+    fi
+}}
+
+complete -F _egawari egawari
+"#,
+        profile = PROFILE_COMMANDS.join(" "),
+        shells = SHELLS.join(" "),
+        keys = key_words().join(" "),
+        file_options = option_pattern(&OptionValue::File),
+        free_options = option_pattern(&OptionValue::Free),
+        colors = ColorMode::ALL.iter().map(|mode| mode.name()).collect::<Vec<&str>>().join(" "),
+        options = option_words().join(" "),
+        commands = commands.join(" ")
+    )
+}
+
+/// Returns the zsh completion script.
+fn zsh() -> String {
+    let commands: Vec<String> = COMMANDS.iter()
+        .map(|(name, description)| format!("        '{}:{}'", name, description))
+        .collect();
+    let options: Vec<String> = OPTIONS.iter()
+        .map(|(long, short, description)| {
+            let value = match option_value(long) {
+                OptionValue::Flag => String::new(),
+                OptionValue::File => String::from(":file:_files"),
+                OptionValue::Words(words) => format!(":value:({})", words.join(" ")),
+                OptionValue::Free => String::from(":value: ")
+            };
+            match short {
+                Some(short) => format!("        '(-{short} --{long})'{{-{short},--{long}}}'[{description}]{value}'",
+                    short = short, long = long, description = description, value = value),
+                None => format!("        '--{}[{}]{}'", long, description, value)
+            }
+        })
+        .collect();
+
+    format!(r#"#compdef egawari
+
+_egawari() {{
+    local -a commands
+    commands=(
+{commands}
+    )
+
+    _arguments \
+{options} \
+        '1:command:->command' \
+        '*::argument:->argument'
+
+    case $state in
+        command) _describe 'command' commands ;;
+        argument)
+            case $words[1] in
+                profile) _values 'profile command' {profile} ;;
+                completions) _values 'shell' {shells} ;;
+                get) _values 'key' {keys} ;;
+                export|import|replay) _files ;;
+            esac
+            ;;
+    esac
+}}
+
+_egawari "$@"
+"#,
+        commands = commands.join("\n"),
+        options = options.join(" \\\n"),
+        profile = PROFILE_COMMANDS.join(" "),
+        shells = SHELLS.join(" "),
+        keys = key_words().join(" ")
+    )
+}
+
+/// Returns the fish completion script.
+fn fish() -> String {
+    let mut lines = vec![String::from("complete -c egawari -f")];
+    for (name, description) in COMMANDS {
+        lines.push(format!("complete -c egawari -n __fish_use_subcommand -a {} -d '{}'", name, description));
+    }
+
+    lines.push(format!("complete -c egawari -n '__fish_seen_subcommand_from profile' -a '{}'", PROFILE_COMMANDS.join(" ")));
+    lines.push(format!("complete -c egawari -n '__fish_seen_subcommand_from completions' -a '{}'", SHELLS.join(" ")));
+    lines.push(format!("complete -c egawari -n '__fish_seen_subcommand_from get' -a '{}'", key_words().join(" ")));
+    lines.push(String::from("complete -c egawari -n '__fish_seen_subcommand_from export import replay' -F"));
+
+    for (long, short, description) in OPTIONS {
+        let mut line = format!("complete -c egawari -l {}", long.trim_end_matches('='));
+        if let Some(short) = short {
+            line.push_str(format!(" -s {}", short).as_str());
+        }
+        match option_value(long) {
+            OptionValue::Flag => (),
+            OptionValue::File => line.push_str(" -r -F"),
+            OptionValue::Words(words) => line.push_str(format!(" -r -f -a '{}'", words.join(" ")).as_str()),
+            OptionValue::Free => line.push_str(" -r -f")
+        }
+        line.push_str(format!(" -d '{}'", description).as_str());
+        lines.push(line);
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Returns the completion script for the shell,
+/// or `None` if the shell isn't supported.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// print!("{}", completions::script("bash").unwrap());
+/// ```
+pub fn script(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(bash()),
+        "zsh" => Some(zsh()),
+        "fish" => Some(fish()),
+        _ => None
+    }
+}
+
+/// Runs the `completions` command, printing the completion
+/// script for the shell so it can be redirected to a file.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// completions::completions(&[String::from("zsh")]).unwrap();
+/// ```
+pub fn completions(args: &[String]) -> Result<()> {
+    let shell = args.first().map(|shell| shell.as_str()).unwrap_or_default();
+    match script(shell) {
+        Some(script) => print!("{}", script),
+        None => {
+            if shell.is_empty() {
+                errln!("No shell provided.");
+            } else {
+                errln!("Unknown shell: \x1b[0;39m{}", shell);
+            }
+            logln!("Supported shells: \x1b[0;39m{}", SHELLS.join(", "));
+            process::exit(1);
+        }
+    }
+
+    Ok(())
+}
@@ -15,14 +15,28 @@
 ** You should have received a copy of the GNU General Public License
 ** along with this program.  If not, see <https://www.gnu.org/licenses/>.
 ****************************************************************************/
-use std::{fs, env};
+use std::{fs, env, io};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Instant;
 use dirs::config_dir;
-use anyhow::{Context, Result};
-use serde::{Serialize, Deserialize};
+use evdev::{AbsoluteAxisType, Key, RelativeAxisType};
+use fancy_regex::Regex;
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::Lazy;
+use serde::{de::{self, DeserializeOwned}, Serialize, Deserialize, Deserializer};
+use serde_json::Value;
+use unicode_width::UnicodeWidthStr;
 use pancurses;
 use toml;
 
-use crate::stdout::init_curses_wcolors;
+use crate::args::Options;
+use crate::daemon;
+use crate::device::{self, PAD_BUTTONS_MAX, VIRTUAL_NAME, VIRTUAL_NAME_MAX};
+use crate::stdout::{self, init_curses_wcolors, Color, Palette};
+use crate::profile::{active_profile, is_valid_name};
 
 /// The configuration struct.
 /// 
@@ -30,396 +44,3823 @@ use crate::stdout::init_curses_wcolors;
 /// 
 /// ```rust
 /// config::Config {
+///     log_file: Some(PathBuf::from("/tmp/egawari.log")),
+///     require_session: config::SessionType::Any,
 ///     input: config::Input {
-///         name: String::from("SynPS/2 Synaptics TouchPad")
+///         names: vec![String::from("SynPS/2 Synaptics TouchPad")],
+///         id: None,
+///         path: None,
+///         grab: true,
+///         fallback_bounds: None
 ///     },
 ///     display: Some(config::Display {
 ///         display: Some(String::from(":0")),
 ///         screen: 0
-///     })
+///     }),
+///     tablet: config::Tablet {
+///         hover_on_lift: true,
+///         pressure_curve: vec![[0, 0], [50, 30], [100, 100]],
+///         ..Default::default()
+///     },
+///     tilt: None,
+///     smoothing: None,
+///     mapping: config::Mapping::default(),
+///     overlay: None,
+///     activation: None,
+///     eraser: None,
+///     pad: None,
+///     stylus_buttons: None,
+///     routes: vec![],
+///     focus_rules: vec![],
+///     profile_switch: None,
+///     theme: None
 /// }
 /// ```
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
+    /// The file the messages are appended to, in addition to printing them.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+    /// The session type `run` refuses to start without.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "SessionType::is_any")]
+    pub require_session: SessionType,
+    /// Older or trimmed files without the table read as no device,
+    /// which `run` refuses to start with.
+    #[serde(default)]
     pub input: Input,
-    pub display: Option<Display>
+    /// The display the tablet is mapped to, or `None` to detect it at runtime.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub display: Option<Display>,
+    #[serde(default)]
+    pub tablet: Tablet,
+    /// The pen tilt emulation, or `None` to report an upright pen.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<Tilt>,
+    /// The smoothing of the positions, or `None` to report them as they are.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<Smoothing>,
+    #[serde(default)]
+    pub mapping: Mapping,
+    /// The outline of the area shown on the screen, or `None` to show none.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<Overlay>,
+    /// The key held to map, or `None` to map all the time.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub activation: Option<Activation>,
+    /// What switches the pen to the eraser, or `None` to only use the pen.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub eraser: Option<Eraser>,
+    /// The virtual tablet pad with express keys, or `None` for no pad.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub pad: Option<Pad>,
+    /// The keys held as the buttons on the side of the pen, or `None` for none.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub stylus_buttons: Option<StylusButtons>,
+    /// The extra events the touchpad axes are routed to, along with the tablet events.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<Route>,
+    /// The profiles used while a window they match is focused, in priority order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub focus_rules: Vec<FocusRule>,
+    /// The keys switching between the profiles while egawari runs, or `None` for none.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub profile_switch: Option<ProfileSwitch>,
+    /// The highlight colors of the output, or `None` for the default ones.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>
+}
+
+impl Config {
+    /// Returns the events the routes emit, skipping the ones with unknown targets.
+    pub fn route_targets(&self) -> Vec<RouteTarget> {
+        self.routes.iter().filter_map(Route::target).collect()
+    }
+
+    /// Returns the problems that make the config unusable, if any.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// for problem in conf.validate() {
+    ///     errln!("{}", problem);
+    /// }
+    /// ```
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mapping = &self.mapping;
+
+        if !matches!(mapping.rotation, 0 | 90 | 180 | 270) {
+            problems.push(format!("The rotation must be 0, 90, 180 or 270 degrees, not {}.", mapping.rotation));
+        }
+        if mapping.width == Some(0) || mapping.height == Some(0) {
+            problems.push(String::from("The output width and height can't be 0."));
+        }
+        if !(mapping.scale_x > 0.0 && mapping.scale_y > 0.0 && mapping.scale_x.is_finite() && mapping.scale_y.is_finite()) {
+            problems.push(String::from("The scales must be positive numbers."));
+        }
+        if !(mapping.acceleration >= 0.0 && mapping.acceleration.is_finite()) {
+            problems.push(String::from("The acceleration can't be negative."));
+        }
+
+        if let Some(area) = mapping.area {
+            if area.width == 0 || area.height == 0 {
+                problems.push(String::from("The area width and height can't be 0."));
+            }
+            if [area.width_mm, area.height_mm].iter().flatten().any(|mm| !mm.is_finite() || *mm <= 0.0) {
+                problems.push(String::from("The physical width and height of the area must be positive."));
+            }
+            // The area is placed by the anchor only once the output size is known.
+            let placed = match (mapping.width, mapping.height) {
+                (Some(width), Some(height)) => mapping.anchor.place(area, width, height),
+                _ => area
+            };
+            // Ends past the largest number don't fit in any output.
+            let fits = |start: u32, size: u32, total: u32| start.checked_add(size).is_some_and(|end| end <= total);
+            if mapping.width.is_some_and(|width| !fits(placed.x, area.width, width) || !fits(area.x, area.width, width))
+                || mapping.height.is_some_and(|height| !fits(placed.y, area.height, height) || !fits(area.y, area.height, height)) {
+                problems.push(String::from("The area doesn't fit in the output."));
+            }
+        }
+
+        if let Some(quad) = mapping.quad {
+            if !quad.convex() {
+                problems.push(String::from("The corners of the quad must make a convex quadrilateral."));
+            }
+            if quad.corners().iter().any(|[x, y]| mapping.width.is_some_and(|width| *x >= width)
+                || mapping.height.is_some_and(|height| *y >= height)) {
+                problems.push(String::from("The quad doesn't fit in the output."));
+            }
+        }
+
+        if let Some(snap) = mapping.edge_snap {
+            if !(0.0..=50.0).contains(&snap.percent) {
+                problems.push(String::from("The edge snap percent must be from 0 to 50."));
+            }
+        }
+        if self.overlay.is_some_and(|overlay| overlay.thickness == 0) {
+            problems.push(String::from("The thickness of the overlay can't be 0."));
+        }
+
+        let curve = &self.tablet.pressure_curve;
+        if curve.len() == 1 {
+            problems.push(String::from("The pressure curve needs at least two points."));
+        }
+        if curve.iter().any(|[input, _]| *input > 100) {
+            problems.push(String::from("The inputs of the pressure curve must be from 0 to 100."));
+        }
+        if curve.windows(2).any(|pair| pair[0][0] >= pair[1][0]) {
+            problems.push(String::from("The inputs of the pressure curve must increase from point to point."));
+        }
+        if self.tablet.pressure_device.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            problems.push(String::from("The pressure device name can't be empty."));
+        }
+        if self.tablet.output_device.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            problems.push(String::from("The output device name can't be empty."));
+        }
+        if self.tablet.virtual_name.trim().is_empty() {
+            problems.push(String::from("The virtual tablet name can't be empty."));
+        } else if self.tablet.virtual_name.len() > VIRTUAL_NAME_MAX {
+            problems.push(format!("The virtual tablet name can't be longer than {} bytes.", VIRTUAL_NAME_MAX));
+        }
+
+        if let Some(smoothing) = self.smoothing {
+            if !(0.0..1.0).contains(&smoothing.factor) {
+                problems.push(String::from("The smoothing factor must be from 0 to below 1."));
+            }
+        }
+        if let Some(tilt) = self.tilt {
+            if tilt.angle > 90 {
+                problems.push(String::from("The tilt angle must be from 0 to 90 degrees."));
+            }
+            if tilt.source == TiltSource::TwoFinger
+                && (self.tablet.hover_mode == HoverMode::TwoFinger || self.tablet.passthrough_gestures) {
+                problems.push(String::from("The two-finger tilt can't be used with two-finger hovering or passed through gestures."));
+            }
+        }
+
+        if let Some(bounds) = self.input.fallback_bounds {
+            if bounds.max_x <= bounds.min_x || bounds.max_y <= bounds.min_y {
+                problems.push(String::from("The fallback bounds must have each maximum above its minimum."));
+            }
+        }
+        if let Some(activation) = &self.activation {
+            if activation.key().is_none() {
+                problems.push(format!("Unknown activation key: {}", activation.key));
+            }
+        }
+        if let Some(eraser) = &self.eraser {
+            match &eraser.key {
+                Some(name) if eraser.key().is_none() => problems.push(format!("Unknown eraser key: {}", name)),
+                None if eraser.device.is_some() => problems.push(String::from("The eraser device needs an eraser key.")),
+                _ => ()
+            }
+        }
+        if let Some(pad) = &self.pad {
+            if pad.buttons == 0 || pad.buttons > PAD_BUTTONS_MAX {
+                problems.push(format!("The pad must have from 1 to {} buttons.", PAD_BUTTONS_MAX));
+            }
+            for binding in &pad.bindings {
+                if binding.button >= pad.buttons {
+                    problems.push(format!("The pad has no button {}, they're numbered from 0.", binding.button));
+                }
+                match (&binding.key, binding.gesture) {
+                    (Some(name), None) if binding.key().is_none() => problems.push(format!("Unknown pad key: {}", name)),
+                    (Some(_), Some(_)) | (None, None) => problems.push(String::from("Each pad binding needs either a key or a gesture.")),
+                    _ => ()
+                }
+            }
+            if pad.device.is_some() && pad.bindings.iter().all(|binding| binding.key.is_none()) {
+                problems.push(String::from("The pad device needs a binding with a key."));
+            }
+            if self.eraser.as_ref().is_some_and(|eraser| eraser.key.is_none())
+                && pad.bindings.iter().any(|binding| binding.gesture == Some(PadGesture::ThreeFingerTap)) {
+                problems.push(String::from("The three-finger tap can't both switch to the eraser and press a pad button."));
+            }
+        }
+        for route in &self.routes {
+            if route.target().is_none() {
+                problems.push(format!("Unknown route target: {}", route.to));
+            }
+            if !route.scale.is_finite() {
+                problems.push(String::from("The route scale must be a number."));
+            }
+            if route.threshold > 100 {
+                problems.push(String::from("The route threshold must be from 0 to 100."));
+            }
+        }
+        if let Some(stylus) = &self.stylus_buttons {
+            if stylus.bindings.is_empty() {
+                problems.push(String::from("The stylus buttons need a binding."));
+            }
+            for binding in stylus.bindings.iter().filter(|binding| binding.key().is_none()) {
+                problems.push(format!("Unknown stylus button key: {}", binding.key));
+            }
+        }
+        for rule in &self.focus_rules {
+            for pattern in rule.class.iter().chain(rule.title.iter()) {
+                if Regex::new(pattern).is_err() {
+                    problems.push(format!("Invalid focus rule pattern: {}", pattern));
+                }
+            }
+            if !is_valid_name(rule.profile.as_str()) {
+                problems.push(format!("Invalid focus rule profile name: {}", rule.profile));
+            }
+        }
+        if let Some(switch) = &self.profile_switch {
+            if switch.bindings.is_empty() {
+                problems.push(String::from("The profile switch needs a binding."));
+            }
+            for binding in &switch.bindings {
+                if binding.key().is_none() {
+                    problems.push(format!("Unknown profile switch key: {}", binding.key));
+                }
+                if let Some(profile) = binding.profile.as_ref().filter(|profile| !is_valid_name(profile)) {
+                    problems.push(format!("Invalid profile switch profile name: {}", profile));
+                }
+            }
+        }
+        if let Some(theme) = &self.theme {
+            if theme.indices().iter().any(|index| *index > 7) {
+                problems.push(String::from("The theme colors must be palette indices from 0 to 7."));
+            }
+        }
+
+        problems
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        match env::consts::OS {
+            "linux" => Config {
+                log_file: None,
+                require_session: SessionType::Any,
+                input: Input::default(),
+                display: Some(Display {
+                    display: Some(":0".to_string()),
+                    screen: 0
+                }),
+                tablet: Tablet::default(),
+                tilt: None,
+                smoothing: None,
+                mapping: Mapping::default(),
+                overlay: None,
+                activation: None,
+                eraser: None,
+                pad: None,
+                stylus_buttons: None,
+                routes: vec![],
+                focus_rules: vec![],
+                profile_switch: None,
+                theme: None
+            },
+            _ => Config {
+                log_file: None,
+                require_session: SessionType::Any,
+                input: Input::default(),
+                display: None,
+                tablet: Tablet::default(),
+                tilt: None,
+                smoothing: None,
+                mapping: Mapping::default(),
+                overlay: None,
+                activation: None,
+                eraser: None,
+                pad: None,
+                stylus_buttons: None,
+                routes: vec![],
+                focus_rules: vec![],
+                profile_switch: None,
+                theme: None
+            }
+        }
+    }
+}
+
+/// The activation configuration struct. The touchpad is only mapped
+/// while the key is held and works as usual otherwise, like a momentary `toggle`.
+/// The key is read along with the touchpad, so a touch starting
+/// in the same instant as the press may start drawing on the next event.
+/// Keys of another device, like a keyboard, add no delay to the touchpad events.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Activation {
+///     key: String::from("KEY_LEFTALT"),
+///     device: Some(String::from("AT Translated Set 2 keyboard"))
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Activation {
+    /// The evdev name of the key or button, like `KEY_LEFTALT` or `BTN_LEFT`.
+    pub key: String,
+    /// The input device reporting the key, or `None` for the touchpad itself.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>
+}
+
+impl Activation {
+    /// Returns the key, or `None` if there's no key with the name.
+    pub fn key(&self) -> Option<Key> {
+        Key::from_str(self.key.as_str()).ok()
+    }
+}
+
+/// The eraser configuration struct. Drawing apps switch to the eraser
+/// while the virtual tablet reports the eraser instead of the pen.
+/// With a key, the eraser is used while the key is held. Without one,
+/// a quick tap of three fingers switches between the pen and the eraser.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Eraser {
+///     key: Some(String::from("KEY_LEFTCTRL")),
+///     device: Some(String::from("AT Translated Set 2 keyboard"))
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Eraser {
+    /// The evdev name of the key or button held to erase, or `None` for the three-finger tap.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The input device reporting the key, or `None` for the touchpad itself.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>
+}
+
+impl Eraser {
+    /// Returns the key, or `None` if there's none or no key with the name.
+    pub fn key(&self) -> Option<Key> {
+        Key::from_str(self.key.as_ref()?.as_str()).ok()
+    }
+}
+
+/// The tablet pad configuration struct. A virtual pad is created along with
+/// the virtual tablet, with the buttons from `BTN_0` on, which drawing apps
+/// bind to their actions like the express keys of a tablet.
+/// Each binding presses a button while a key is held, or with a quick tap
+/// of three or four fingers on the touchpad.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Pad {
+///     buttons: 2,
+///     device: Some(String::from("AT Translated Set 2 keyboard")),
+///     bindings: vec![
+///         config::PadBinding { button: 0, key: Some(String::from("KEY_F13")), gesture: None },
+///         config::PadBinding { button: 1, key: None, gesture: Some(config::PadGesture::FourFingerTap) }
+///     ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Pad {
+    /// The number of buttons of the pad.
+    pub buttons: u8,
+    /// The input device reporting the keys, or `None` for the touchpad itself.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bindings: Vec<PadBinding>
+}
+
+impl Pad {
+    /// Returns the button the key presses, if any.
+    pub fn key_button(&self, key: Key) -> Option<u8> {
+        self.bindings.iter()
+            .find(|binding| binding.key() == Some(key))
+            .map(|binding| binding.button)
+    }
+
+    /// Returns the button the tap of the number of fingers presses, if any.
+    pub fn tap_button(&self, fingers: usize) -> Option<u8> {
+        self.bindings.iter()
+            .find(|binding| binding.gesture.is_some_and(|gesture| gesture.fingers() == fingers))
+            .map(|binding| binding.button)
+    }
+}
+
+/// What presses a button of the pad, either a key or a gesture.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::PadBinding {
+///     button: 0,
+///     key: Some(String::from("KEY_F13")),
+///     gesture: None
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PadBinding {
+    /// The button pressed, from 0.
+    pub button: u8,
+    /// The evdev name of the key pressing the button while it's held.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// The touchpad gesture pressing and releasing the button.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gesture: Option<PadGesture>
+}
+
+impl PadBinding {
+    /// Returns the key, or `None` if there's none or no key with the name.
+    pub fn key(&self) -> Option<Key> {
+        Key::from_str(self.key.as_ref()?.as_str()).ok()
+    }
+}
+
+/// The touchpad gestures pressing a pad button.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PadGesture {
+    /// Three fingers landing and lifting within the double tap time.
+    ThreeFingerTap,
+    /// Four fingers landing and lifting within the double tap time.
+    FourFingerTap
+}
+
+impl PadGesture {
+    /// Returns the number of fingers of the tap.
+    pub fn fingers(&self) -> usize {
+        match self {
+            PadGesture::ThreeFingerTap => 3,
+            PadGesture::FourFingerTap => 4
+        }
+    }
+}
+
+/// The stylus buttons configuration struct. The virtual pen reports
+/// its lower and upper barrel buttons as held while a key bound
+/// to them is, which drawing apps use like the buttons of a real pen.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::StylusButtons {
+///     device: Some(String::from("AT Translated Set 2 keyboard")),
+///     bindings: vec![
+///         config::StylusBinding { key: String::from("KEY_LEFTSHIFT"), button: config::StylusButton::Lower },
+///         config::StylusBinding { key: String::from("KEY_LEFTMETA"), button: config::StylusButton::Upper }
+///     ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StylusButtons {
+    /// The input device reporting the keys, or `None` for the touchpad itself.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub bindings: Vec<StylusBinding>
+}
+
+impl StylusButtons {
+    /// Returns the buttons held while the keys are, in the order of `StylusButton::ALL`.
+    pub fn held(&self, keys: &[Key]) -> [bool; 2] {
+        StylusButton::ALL.map(|button| self.bindings.iter()
+            .any(|binding| binding.button == button && binding.key().is_some_and(|key| keys.contains(&key))))
+    }
+
+    /// Returns whether a binding has the key.
+    pub fn binds(&self, key: Key) -> bool {
+        self.bindings.iter().any(|binding| binding.key() == Some(key))
+    }
+}
+
+/// A key holding a stylus button while it's held.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::StylusBinding {
+///     key: String::from("KEY_LEFTSHIFT"),
+///     button: config::StylusButton::Lower
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StylusBinding {
+    /// The evdev name of the key, like `KEY_LEFTSHIFT`.
+    pub key: String,
+    pub button: StylusButton
+}
+
+impl StylusBinding {
+    /// Returns the key, or `None` if there's no key with the name.
+    pub fn key(&self) -> Option<Key> {
+        Key::from_str(self.key.as_str()).ok()
+    }
+}
+
+/// The barrel buttons of the virtual pen.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StylusButton {
+    /// The button nearer the tip, `BTN_STYLUS`.
+    Lower,
+    /// The button farther from the tip, `BTN_STYLUS2`.
+    Upper
+}
+
+impl StylusButton {
+    /// Every button, from the lower one.
+    pub const ALL: [StylusButton; 2] = [StylusButton::Lower, StylusButton::Upper];
+
+    /// Returns the evdev button reported for it.
+    pub fn key(&self) -> Key {
+        match self {
+            StylusButton::Lower => Key::BTN_STYLUS,
+            StylusButton::Upper => Key::BTN_STYLUS2
+        }
+    }
+}
+
+/// A route of a touchpad axis to an extra event, for uses other than drawing
+/// like zooming with the pressure. While a finger touches, a relative axis moves
+/// by the change of the source scaled by `scale`, and a key is held while
+/// the source is at least `threshold` percent of its range.
+/// The tablet keeps reporting the axis as usual.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Route {
+///     from: config::RouteSource::Pressure,
+///     to: String::from("REL_WHEEL"),
+///     scale: 0.1,
+///     threshold: 50
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Route {
+    pub from: RouteSource,
+    /// The evdev name of the relative axis or the key, like `REL_WHEEL` or `KEY_ZOOMIN`.
+    pub to: String,
+    /// How far a relative axis moves per percent the source changes, negative to turn it around.
+    #[serde(default = "default_route_scale")]
+    pub scale: f32,
+    /// The percent of the range of the source a key is held from.
+    #[serde(default = "default_route_threshold")]
+    pub threshold: u8
+}
+
+/// A relative axis moves by a tenth per percent, ten steps over the range.
+fn default_route_scale() -> f32 {
+    0.1
+}
+
+/// A key is held from half the range.
+fn default_route_threshold() -> u8 {
+    50
+}
+
+impl Route {
+    /// Returns the event the source is routed to, or `None` if there's none with the name.
+    pub fn target(&self) -> Option<RouteTarget> {
+        RelativeAxisType::from_str(self.to.as_str()).map(RouteTarget::Relative)
+            .or_else(|_| Key::from_str(self.to.as_str()).map(RouteTarget::Key))
+            .ok()
+    }
+}
+
+/// The touchpad axes a route reads.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteSource {
+    Pressure,
+    X,
+    Y
+}
+
+/// The event a route emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteTarget {
+    Relative(RelativeAxisType),
+    Key(Key)
+}
+
+/// A rule using a profile while the focused window matches it.
+/// The class and the title are regular expressions searched in the
+/// class or instance and in the title of the window. A rule without
+/// one of them matches any value of it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::FocusRule {
+///     class: Some(String::from("^krita$")),
+///     title: None,
+///     profile: String::from("drawing")
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FocusRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub profile: String
+}
+
+/// The profile switch configuration struct. Each binding switches the running
+/// egawari to its profile when its key is pressed, or to the next of the saved
+/// profiles in alphabetical order without one. The profiles are used like the ones
+/// of the focus rules, without loading them as the configuration, and the keys
+/// are the ones of the configuration egawari started with.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::ProfileSwitch {
+///     device: Some(String::from("AT Translated Set 2 keyboard")),
+///     notify: true,
+///     bindings: vec![
+///         config::ProfileBinding { key: String::from("KEY_F9"), profile: None },
+///         config::ProfileBinding { key: String::from("KEY_F10"), profile: Some(String::from("drawing")) }
+///     ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProfileSwitch {
+    /// The input device reporting the keys, or `None` for the touchpad itself.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Whether the switches are also shown as desktop notifications.
+    #[serde(default)]
+    pub notify: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bindings: Vec<ProfileBinding>
+}
+
+impl ProfileSwitch {
+    /// Returns the binding of the key, if any.
+    pub fn binding(&self, key: Key) -> Option<&ProfileBinding> {
+        self.bindings.iter().find(|binding| binding.key() == Some(key))
+    }
+}
+
+/// A key switching to a profile, or to the next one without a profile.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::ProfileBinding {
+///     key: String::from("KEY_F10"),
+///     profile: Some(String::from("drawing"))
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProfileBinding {
+    /// The evdev name of the key or button, like `KEY_F9` or `BTN_MIDDLE`.
+    pub key: String,
+    /// The profile switched to, or `None` for the next saved one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>
+}
+
+impl ProfileBinding {
+    /// Returns the key, or `None` if there's no key with the name.
+    pub fn key(&self) -> Option<Key> {
+        Key::from_str(self.key.as_str()).ok()
+    }
+}
+
+/// The theme configuration struct, mapping each highlighted part
+/// of the output to an index of the classic ANSI palette:
+/// 0 black, 1 red, 2 green, 3 yellow, 4 blue, 5 magenta, 6 cyan and 7 white.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Theme {
+///     operator: 4,
+///     string: 4,
+///     bracket: 4,
+///     error: 1,
+///     success: 4
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Theme {
+    /// The operators and separators, like `=`, `:` and `,`.
+    pub operator: u8,
+    /// The quoted strings.
+    pub string: u8,
+    /// The brackets, parentheses and angle brackets.
+    pub bracket: u8,
+    /// The arrow of the error messages.
+    pub error: u8,
+    /// The arrow of the success messages.
+    pub success: u8
+}
+
+impl Theme {
+    /// Returns the palette indices of the theme.
+    fn indices(&self) -> [u8; 5] {
+        [self.operator, self.string, self.bracket, self.error, self.success]
+    }
+
+    /// Returns the default palette with the colors of the theme,
+    /// keeping their boldness.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// stdout::set_palette(theme.palette());
+    /// ```
+    pub fn palette(&self) -> Palette {
+        let color = |default: Color, ansi: u8| Color::from_ansi(default.bold, ansi);
+        let defaults = stdout::PALETTE;
+        Palette {
+            operator: color(defaults.operator, self.operator),
+            separator: color(defaults.separator, self.operator),
+            string: color(defaults.string, self.string),
+            quote: color(defaults.quote, self.string),
+            bracket: color(defaults.bracket, self.bracket),
+            paren: color(defaults.paren, self.bracket),
+            angle: color(defaults.angle, self.bracket),
+            error: color(defaults.error, self.error),
+            success: color(defaults.success, self.success),
+            ..defaults
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let defaults = stdout::PALETTE;
+        Theme {
+            operator: defaults.operator.ansi,
+            string: defaults.string.ansi,
+            bracket: defaults.bracket.ansi,
+            error: defaults.error.ansi,
+            success: defaults.success.ansi
+        }
+    }
+}
+
+/// The input configuration struct.
+/// The devices are listed in priority order and the first
+/// present one is used, unless the event node path is set.
+/// Configs with a single `name` are read as a list of that name.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Input {
+///     names: vec![
+///         String::from("Apple Inc. Magic Trackpad"),
+///         String::from("SynPS/2 Synaptics TouchPad")
+///     ],
+///     id: None,
+///     path: None,
+///     grab: true,
+///     fallback_bounds: None
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(from = "InputFile")]
+pub struct Input {
+    pub names: Vec<String>,
+    /// The vendor and product IDs of the device, matched instead of the names if set.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub id: Option<DeviceId>,
+    /// The event node of the device, like `/dev/input/event5`,
+    /// opened directly instead of matching the IDs or the names if set.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Whether the device is grabbed while it's mapped, so it stops moving the pointer.
+    /// Without the grab, the touchpad should be disabled in the desktop settings
+    /// instead, or the pointer follows both the touchpad and the tablet.
+    pub grab: bool,
+    /// The axis ranges used if the device reports an empty or inverted one.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub fallback_bounds: Option<InputBounds>
+}
+
+/// The USB or Bluetooth vendor and product IDs of an input device,
+/// written as hexadecimal `vendor:product` like `1267:abcd`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let id: config::DeviceId = "1267:abcd".parse().unwrap();
+/// assert_eq!(id, config::DeviceId { vendor: 0x1267, product: 0xabcd });
+/// assert_eq!(id.to_string(), "1267:abcd");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct DeviceId {
+    pub vendor: u16,
+    pub product: u16
+}
+
+impl FromStr for DeviceId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |part: &str| u16::from_str_radix(part.trim(), 16).ok();
+        s.split_once(':')
+            .and_then(|(vendor, product)| Some(DeviceId { vendor: parse(vendor)?, product: parse(product)? }))
+            .ok_or_else(|| anyhow!("Invalid device ID, expected vendor:product in hexadecimal: {}", s))
+    }
+}
+
+impl TryFrom<String> for DeviceId {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<DeviceId> for String {
+    fn from(id: DeviceId) -> Self {
+        id.to_string()
+    }
+}
+
+impl std::fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor, self.product)
+    }
+}
+
+/// The axis ranges of the touchpad, for devices reporting unusable ones.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::InputBounds {
+///     min_x: 0,
+///     max_x: 1216,
+///     min_y: 0,
+///     max_y: 680
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputBounds {
+    pub min_x: i32,
+    pub max_x: i32,
+    pub min_y: i32,
+    pub max_y: i32
+}
+
+/// The input configuration as it's read from the file,
+/// either with the old single `name` or the `names` list.
+#[derive(Deserialize)]
+struct InputFile {
+    name: Option<String>,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    id: Option<DeviceId>,
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default = "default_grab")]
+    grab: bool,
+    #[serde(default)]
+    fallback_bounds: Option<InputBounds>
+}
+
+/// The input device is grabbed unless it's turned off.
+fn default_grab() -> bool {
+    true
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            names: vec![],
+            id: None,
+            path: None,
+            grab: default_grab(),
+            fallback_bounds: None
+        }
+    }
+}
+
+impl From<InputFile> for Input {
+    fn from(file: InputFile) -> Self {
+        let mut names = file.names;
+        if let Some(name) = file.name.filter(|name| !name.is_empty()) {
+            if !names.contains(&name) {
+                names.insert(0, name);
+            }
+        }
+
+        Input { names, id: file.id, path: file.path, grab: file.grab, fallback_bounds: file.fallback_bounds }
+    }
+}
+
+/// The display configuration struct.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Display {
+///     display: Some(String::from(":0")),
+///     screen: 0
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Display {
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    pub screen: u8
+}
+
+/// The virtual tablet configuration struct.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Tablet {
+///     start_paused: false,
+///     hover_on_lift: false,
+///     hover_mode: config::HoverMode::DoubleTap,
+///     double_tap_ms: 250,
+///     lift_timeout_ms: 500,
+///     report_rate: 0,
+///     passthrough_gestures: true,
+///     hide_cursor: false,
+///     touch_settle_ms: 10,
+///     contact_debounce_ms: 30,
+///     touch_threshold: 30,
+///     pressure_source: config::PressureSource::Force,
+///     invert_pressure: false,
+///     pressure_curve: vec![[0, 0], [50, 30], [100, 100]],
+///     pressure_device: None,
+///     output_device: None,
+///     virtual_name: String::from("egawari virtual tablet")
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Tablet {
+    pub start_paused: bool,
+    pub hover_on_lift: bool,
+    pub hover_mode: HoverMode,
+    pub double_tap_ms: u32,
+    pub lift_timeout_ms: u32,
+    /// The maximum rate of the position reports in Hz, or 0 to report every event.
+    /// Lower rates coalesce the events, saving CPU at the cost of latency.
+    pub report_rate: u16,
+    pub passthrough_gestures: bool,
+    /// Whether the X11 cursor is hidden while egawari runs. On Wayland
+    /// the compositor decides whether the cursor is shown.
+    pub hide_cursor: bool,
+    /// The longest time in milliseconds a new touch is held back
+    /// until its position is stable, or 0 to report it right away.
+    pub touch_settle_ms: u32,
+    /// How long in milliseconds a lift is held back, continuing the contact
+    /// if the finger touches down again in time, or 0 to lift right away.
+    /// Bridges the short dropouts of flaky touchpads in the middle of a stroke.
+    pub contact_debounce_ms: u32,
+    /// The lowest pressure a contact draws with, or 0 to draw with any contact.
+    /// Lighter contacts, like a resting palm, move the pen without touching down.
+    pub touch_threshold: u32,
+    /// The axis of the touchpad the pressure is read from.
+    pub pressure_source: PressureSource,
+    /// Whether the pressure is turned around within its range,
+    /// for touchpads reporting less for a harder contact.
+    pub invert_pressure: bool,
+    /// The control points of the pressure curve as input and output percents
+    /// of the pressure range, or empty to report the pressure as it is.
+    /// The pressure is interpolated between the points and flat outside them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pressure_curve: Vec<[u8; 2]>,
+    /// The input device the pressure is read from instead of the touchpad, if any,
+    /// for rigs with a separate pressure-sensitive surface. Its samples
+    /// are merged into the reports of the touchpad.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub pressure_device: Option<String>,
+    /// The existing input device the tablet events are written into instead of
+    /// a virtual tablet of egawari's own, by its name or its event node path,
+    /// for sharing one device between tools. Its creator removes it, never egawari.
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub output_device: Option<String>,
+    /// The name of the virtual tablet device, which drawing apps
+    /// and udev rules may identify the tablet by.
+    pub virtual_name: String
+}
+
+impl Default for Tablet {
+    fn default() -> Self {
+        Tablet {
+            start_paused: false,
+            hover_on_lift: false,
+            hover_mode: HoverMode::Off,
+            double_tap_ms: 250,
+            lift_timeout_ms: 0,
+            report_rate: 0,
+            passthrough_gestures: false,
+            hide_cursor: false,
+            touch_settle_ms: 0,
+            contact_debounce_ms: 0,
+            touch_threshold: 0,
+            pressure_source: PressureSource::Force,
+            invert_pressure: false,
+            pressure_curve: vec![],
+            pressure_device: None,
+            output_device: None,
+            virtual_name: String::from(VIRTUAL_NAME)
+        }
+    }
+}
+
+/// The axis of the touchpad the pressure of the pen is read from.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureSource {
+    /// The force of the contact.
+    #[default]
+    Force,
+    /// The size of the contact, larger as more of the finger is down.
+    TouchMajor
+}
+
+impl PressureSource {
+    /// Returns the evdev axis of the pressure source.
+    pub fn axis(&self) -> AbsoluteAxisType {
+        match self {
+            PressureSource::Force => AbsoluteAxisType::ABS_PRESSURE,
+            PressureSource::TouchMajor => AbsoluteAxisType::ABS_MT_TOUCH_MAJOR
+        }
+    }
+}
+
+/// The type of the graphical session, which `run` can be restricted to.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionType {
+    /// Runs in any session, or without one.
+    #[default]
+    Any,
+    X11,
+    Wayland
+}
+
+impl SessionType {
+    /// Returns whether any session will do.
+    pub fn is_any(&self) -> bool {
+        *self == SessionType::Any
+    }
+
+    /// Returns the name of the session type in the messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionType::Any => "any",
+            SessionType::X11 => "X11",
+            SessionType::Wayland => "Wayland"
+        }
+    }
+}
+
+/// How hovering the pen is emulated, as touchpads only report contact.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HoverMode {
+    /// Every touch draws.
+    #[default]
+    Off,
+    /// A touch right after a quick tap moves the pen without drawing.
+    DoubleTap,
+    /// Touching with two or more fingers moves the pen without drawing.
+    TwoFinger
+}
+
+/// The pen tilt emulation configuration struct. The pen leans by the angle
+/// in a direction read from the touchpad, reported on the tilt axes
+/// of the virtual tablet for the brushes responding to it.
+/// 
+/// The orientation source needs a touchpad reporting the orientation
+/// of the contact (`ABS_MT_ORIENTATION`), which few do, and can't tell
+/// which end of the finger is the tip, so the pen leans towards the user.
+/// The two-finger source leans the pen towards a second finger resting
+/// on the touchpad, so it needs a touchpad tracking at least two contacts
+/// and can't be used along with two-finger hovering or passed through gestures.
+/// Without the data, the pen stands upright.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Tilt {
+///     source: config::TiltSource::TwoFinger,
+///     angle: 45
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Tilt {
+    pub source: TiltSource,
+    /// How far the pen leans from upright in degrees, from 0 to 90.
+    pub angle: u8
+}
+
+impl Default for Tilt {
+    fn default() -> Self {
+        Tilt {
+            source: TiltSource::Orientation,
+            angle: 45
+        }
+    }
+}
+
+/// The smoothing configuration struct. Each reported position is pulled
+/// towards the previous one by the factor, an exponential moving average
+/// steadying shaky strokes at the cost of some lag. Each contact starts
+/// from its own position, and the relative mode isn't smoothed.
+/// With `pressure_aware` the smoothing fades as the pressure rises,
+/// so hard strokes stay crisp while light ones are steadied.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Smoothing {
+///     factor: 0.5,
+///     pressure_aware: true
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Smoothing {
+    /// How much of the previous position is kept, from 0 for none to below 1.
+    pub factor: f32,
+    /// Whether the smoothing lessens as the pressure rises, down to none at the full pressure.
+    pub pressure_aware: bool
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing {
+            factor: 0.5,
+            pressure_aware: false
+        }
+    }
+}
+
+/// The outline of the mapped area drawn on the screen under X11.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Overlay {
+///     seconds: 3,
+///     thickness: 4
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Overlay {
+    /// How many seconds the outline stays after the area changes, or 0 to always show it.
+    pub seconds: u32,
+    /// The width of the outline in pixels.
+    pub thickness: u32
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay {
+            seconds: 3,
+            thickness: 4
+        }
+    }
+}
+
+/// Where the direction the pen leans in is read from.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TiltSource {
+    /// The orientation of the contact, along the finger.
+    #[default]
+    Orientation,
+    /// The direction from the first contact to the second one.
+    TwoFinger
+}
+
+/// The mapping configuration struct.
+/// The output size defaults to the touchpad resolution
+/// and the area defaults to the whole output.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Mapping {
+///     mode: config::MappingMode::Absolute,
+///     acceleration: 0.0,
+///     target: config::MappingTarget::Area,
+///     width: Some(1920),
+///     height: Some(1080),
+///     rotation: 0,
+///     flip_x: false,
+///     flip_y: false,
+///     keep_aspect: true,
+///     scale_x: 1.5,
+///     scale_y: 1.0,
+///     area: Some(config::Area {
+///         x: 0,
+///         y: 0,
+///         width: 960,
+///         height: 540,
+///         width_mm: None,
+///         height_mm: None
+///     }),
+///     anchor: config::Anchor::Center,
+///     quad: None,
+///     edge_snap: None
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Mapping {
+    pub mode: MappingMode,
+    /// How much faster quick motions move the pen in the relative mode.
+    /// The motion of a report is multiplied by one plus the acceleration
+    /// times the distance moved, in percent of the touchpad size.
+    pub acceleration: f32,
+    /// What the touchpad is mapped onto, the area or some monitors of the screen.
+    pub target: MappingTarget,
+    #[serde(deserialize_with = "unset")]
+    pub width: Option<u32>,
+    #[serde(deserialize_with = "unset")]
+    pub height: Option<u32>,
+    pub rotation: u16,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub keep_aspect: bool,
+    /// The gain of the horizontal touchpad motion around the center of the touchpad.
+    /// Values above 1 cover the area with less motion, clamping at its edges.
+    pub scale_x: f32,
+    /// The gain of the vertical touchpad motion, like `scale_x`.
+    pub scale_y: f32,
+    #[serde(deserialize_with = "unset")]
+    pub area: Option<Area>,
+    /// Where the area is placed on the output. Its position is
+    /// the margin from the anchored edges, or the shift from the center.
+    pub anchor: Anchor,
+    /// The quadrilateral the touchpad is mapped onto instead of the area, if any.
+    #[serde(deserialize_with = "unset")]
+    pub quad: Option<Quad>,
+    /// How close to the edges the mapped positions snap onto them, if at all.
+    #[serde(deserialize_with = "unset")]
+    pub edge_snap: Option<EdgeSnap>
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Mapping {
+            mode: MappingMode::Absolute,
+            acceleration: 0.0,
+            target: MappingTarget::Area,
+            width: None,
+            height: None,
+            rotation: 0,
+            flip_x: false,
+            flip_y: false,
+            keep_aspect: false,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            area: None,
+            anchor: Anchor::TopLeft,
+            quad: None,
+            edge_snap: None
+        }
+    }
+}
+
+/// How the touchpad moves the pen.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingMode {
+    /// Each position on the touchpad is a position on the output.
+    #[default]
+    Absolute,
+    /// The pen moves by the motion on the touchpad, like a cursor.
+    /// The area, the quad, the rotation and the flips don't apply,
+    /// but the gains and the output size still scale the motion.
+    Relative
+}
+
+impl MappingMode {
+    /// Every mode, in the order the editor cycles through them.
+    pub const ALL: [MappingMode; 2] = [MappingMode::Absolute, MappingMode::Relative];
+
+    /// Returns the name of the mode in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MappingMode::Absolute => "absolute",
+            MappingMode::Relative => "relative"
+        }
+    }
+}
+
+/// Where the area is placed on the output, as the corner, edge or center
+/// it's aligned to. The position of the area is the margin from the
+/// anchored edges, so `x` counts from the right edge for the right anchors
+/// and `y` from the bottom edge for the bottom ones. Along a centered axis
+/// the position shifts the area right or down from the center.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let area = config::Area { x: 0, y: 0, width: 960, height: 540, width_mm: None, height_mm: None };
+/// assert_eq!(config::Anchor::Center.place(area, 1920, 1080).x, 480);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight
+}
+
+impl Anchor {
+    /// Returns the area placed on the output of the size by the anchor.
+    pub fn place(&self, area: Area, width: u32, height: u32) -> Area {
+        let (horizontal, vertical) = match self {
+            Anchor::TopLeft => (Align::Start, Align::Start),
+            Anchor::Top => (Align::Center, Align::Start),
+            Anchor::TopRight => (Align::End, Align::Start),
+            Anchor::Left => (Align::Start, Align::Center),
+            Anchor::Center => (Align::Center, Align::Center),
+            Anchor::Right => (Align::End, Align::Center),
+            Anchor::BottomLeft => (Align::Start, Align::End),
+            Anchor::Bottom => (Align::Center, Align::End),
+            Anchor::BottomRight => (Align::End, Align::End)
+        };
+        Area {
+            x: horizontal.place(area.x, area.width, width),
+            y: vertical.place(area.y, area.height, height),
+            ..area
+        }
+    }
+}
+
+/// How the area is aligned along an axis of the output.
+enum Align {
+    Start,
+    Center,
+    End
+}
+
+impl Align {
+    /// Returns the start of the area of the size along the axis
+    /// of the total size, given its margin or shift.
+    fn place(&self, offset: u32, size: u32, total: u32) -> u32 {
+        let free = total.saturating_sub(size);
+        match self {
+            Align::Start => offset,
+            Align::Center => (free / 2).saturating_add(offset),
+            Align::End => free.saturating_sub(offset)
+        }
+    }
+}
+
+/// What the touchpad is mapped onto, written as `area`, `all`
+/// or `monitor:` followed by the monitor names separated by commas.
+/// The monitors are looked up when egawari runs, and the output becomes
+/// the whole screen with the area covering the bounding box of the monitors,
+/// in place of the configured output size and area. Monitors of different
+/// sizes or not side by side leave dead zones in the bounding box, parts
+/// of it off every monitor, where the pen goes but can't be seen.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let target: config::MappingTarget = "monitor:DP-1,HDMI-1".parse().unwrap();
+/// assert_eq!(target, config::MappingTarget::Monitors(vec![String::from("DP-1"), String::from("HDMI-1")]));
+/// assert_eq!(target.to_string(), "monitor:DP-1,HDMI-1");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum MappingTarget {
+    /// The configured area, or the whole output without one.
+    #[default]
+    Area,
+    /// Every monitor, the whole virtual desktop.
+    All,
+    /// The bounding box of the named monitors.
+    Monitors(Vec<String>)
+}
+
+impl FromStr for MappingTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "area" => Ok(MappingTarget::Area),
+            "all" => Ok(MappingTarget::All),
+            s => {
+                let names: Option<Vec<String>> = s.strip_prefix("monitor:").map(|names| {
+                    names.split(',').map(|name| name.trim().to_string()).collect()
+                });
+                match names {
+                    Some(names) if names.iter().all(|name| !name.is_empty()) => Ok(MappingTarget::Monitors(names)),
+                    _ => Err(anyhow!("Invalid mapping target, expected area, all or monitor:NAME[,NAME...]: {}", s))
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for MappingTarget {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<MappingTarget> for String {
+    fn from(target: MappingTarget) -> Self {
+        target.to_string()
+    }
+}
+
+impl std::fmt::Display for MappingTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MappingTarget::Area => write!(f, "area"),
+            MappingTarget::All => write!(f, "all"),
+            MappingTarget::Monitors(names) => write!(f, "monitor:{}", names.join(","))
+        }
+    }
+}
+
+/// The distance from the edges of the area within which the mapped positions
+/// snap onto the edges, so noisy touchpads still reach the first and the last pixel.
+/// The distance is the larger of the pixels and the percent of the area size.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::EdgeSnap {
+///     pixels: 4,
+///     percent: 0.0
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EdgeSnap {
+    #[serde(default)]
+    pub pixels: u32,
+    #[serde(default)]
+    pub percent: f32
+}
+
+/// A quadrilateral of the output the touchpad is mapped onto with a perspective
+/// transform, for keystone correction. The corners are positions in pixels
+/// the corners of the (rotated) touchpad land on exactly.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Quad {
+///     top_left: [100, 0],
+///     top_right: [1820, 0],
+///     bottom_right: [1919, 1079],
+///     bottom_left: [0, 1079]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quad {
+    pub top_left: [u32; 2],
+    pub top_right: [u32; 2],
+    pub bottom_right: [u32; 2],
+    pub bottom_left: [u32; 2]
+}
+
+impl Quad {
+    /// Returns the corners clockwise from the top left.
+    pub fn corners(&self) -> [[u32; 2]; 4] {
+        [self.top_left, self.top_right, self.bottom_right, self.bottom_left]
+    }
+
+    /// Returns whether the corners make a convex quadrilateral,
+    /// turning the same way at each corner without any being straight.
+    pub fn convex(&self) -> bool {
+        let corners = self.corners().map(|[x, y]| (x as i64, y as i64));
+        let turns: Vec<i64> = (0..4)
+            .map(|i| {
+                let (a, b, c) = (corners[i], corners[(i + 1) % 4], corners[(i + 2) % 4]);
+                (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0)
+            })
+            .collect();
+        turns.iter().all(|turn| *turn > 0) || turns.iter().all(|turn| *turn < 0)
+    }
+}
+
+/// The area of the output the touchpad is mapped onto, in pixels.
+/// The physical size of the area sets the resolution the virtual tablet
+/// reports, so drawing applications know its real size. It defaults to
+/// the size of the touchpad, if the touchpad reports its resolution.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::Area {
+///     x: 0,
+///     y: 0,
+///     width: 960,
+///     height: 540,
+///     width_mm: Some(254.0),
+///     height_mm: Some(143.0)
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    #[serde(default)]
+    pub x: u32,
+    #[serde(default)]
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub width_mm: Option<f32>,
+    #[serde(default, deserialize_with = "unset", skip_serializing_if = "Option::is_none")]
+    pub height_mm: Option<f32>
+}
+
+/// The config file path given with `--config`, if any.
+static CONFIG_OVERRIDE: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+
+/// Overrides the path of the config file, or restores the default one.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::set_config_path(Some(PathBuf::from("/etc/egawari.toml")));
+/// ```
+pub fn set_config_path(path: Option<PathBuf>) {
+    *CONFIG_OVERRIDE.write().unwrap() = path;
+}
+
+/// Returns the egawari config directory.
+/// Located at `$CONFIG_DIR/egawari`
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let profiles = config::egawari_dir().unwrap().join("profiles");
+/// ```
+pub fn egawari_dir() -> Result<PathBuf> {
+    let dir = config_dir()
+        .ok_or_else(|| anyhow!("Couldn't determine the config directory, use --config=<path> to choose the config file."))?;
+    Ok(dir.join("egawari"))
+}
+
+/// The config file shared by the users of the machine, read under their own.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/egawari/egawari.toml";
+
+/// Returns the path of the config file.
+/// Located at `$CONFIG_DIR/egawari/egawari.toml`, unless overridden with `--config`.
+/// `$CONFIG_DIR` is `$XDG_CONFIG_HOME`, or `~/.config` if it's unset.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// println!("{}", config::config_path().unwrap().display());
+/// ```
+pub fn config_path() -> Result<PathBuf> {
+    if let Some(path) = CONFIG_OVERRIDE.read().unwrap().as_ref() {
+        return Ok(path.clone());
+    }
+
+    Ok(egawari_dir()?.join("egawari.toml"))
+}
+
+/// Returns the present config files in the order they're read, each one
+/// over the ones before it: the system-wide file and the user's file,
+/// or only the file given with `--config`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for file in config::config_files().unwrap() {
+///     println!("{}", file.display());
+/// }
+/// ```
+pub fn config_files() -> Result<Vec<PathBuf>> {
+    let user = config_path()?;
+    let system = match CONFIG_OVERRIDE.read().unwrap().is_some() {
+        true => None,
+        false => Some(Path::new(SYSTEM_CONFIG_PATH))
+    };
+
+    Ok(search_config_files(system, user.as_path()))
+}
+
+/// Returns the present ones of the system-wide and the user's config files,
+/// the system-wide one first.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let files = config::search_config_files(Some(Path::new("/etc/egawari/egawari.toml")), &config::config_path()?);
+/// ```
+pub fn search_config_files(system: Option<&Path>, user: &Path) -> Vec<PathBuf> {
+    system.into_iter()
+        .chain(std::iter::once(user))
+        .filter(|file| file.exists())
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// Returns the configuration in effect as struct.
+/// Environment variables override the config files,
+/// which override the defaults:
+/// 
+/// - `EGAWARI_INPUT_NAME` sets the only input name.
+/// - `EGAWARI_DISPLAY` sets the X display.
+/// - `EGAWARI_SCREEN` sets the X screen.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf: config::Config = config::get_config().unwrap();
+/// ```
+pub fn get_config() -> Result<Config> {
+    Ok(load_config()?.0)
+}
+
+/// Returns the configuration in effect like `get_config`,
+/// with the config files it was read from.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (conf, files) = config::load_config().unwrap();
+/// ```
+pub fn load_config() -> Result<(Config, Vec<PathBuf>)> {
+    let files = config_files()?;
+    let mut config = read_configs(&files)?;
+    apply_env_overrides(&mut config)?;
+
+    Ok((config, files))
+}
+
+/// The environment variables overriding the config
+/// as their name and the key path they set.
+const ENV_KEYS: &[(&str, &str)] = &[
+    ("EGAWARI_INPUT_NAME", "input.names"),
+    ("EGAWARI_DISPLAY", "display.display"),
+    ("EGAWARI_SCREEN", "display.screen")
+];
+
+/// Applies the environment variable overrides to the config.
+/// Unset variables leave the fields intact.
+fn apply_env_overrides(config: &mut Config) -> Result<()> {
+    if let Ok(name) = env::var("EGAWARI_INPUT_NAME") {
+        // The path and the IDs would win over the name otherwise.
+        config.input.names = vec![name];
+        config.input.id = None;
+        config.input.path = None;
+    }
+
+    if let Ok(dp) = env::var("EGAWARI_DISPLAY") {
+        config.display.get_or_insert(Display { display: None, screen: 0 }).display = Some(dp);
+    }
+
+    if let Ok(screen) = env::var("EGAWARI_SCREEN") {
+        let screen = screen.trim().parse()
+            .with_context(|| format!("Couldn't parse EGAWARI_SCREEN as a screen number: {}", screen))?;
+        config.display.get_or_insert(Display { display: None, screen: 0 }).screen = screen;
+    }
+
+    Ok(())
+}
+
+/// Returns the configuration in the config files as struct,
+/// without the environment variable overrides.
+/// The user's file at `$CONFIG_DIR/egawari/egawari.toml`
+/// is read over the system-wide one.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf: config::Config = config::get_file_config().unwrap();
+/// config::save_config(&conf).unwrap();
+/// ```
+pub fn get_file_config() -> Result<Config> {
+    read_configs(&config_files()?)
+}
+
+/// Saves the given config struct to the config file, keeping its include.
+/// The file only keeps the values that differ from the files read under it,
+/// the system-wide one and the included one, and unsets with `false`
+/// the values they set that the config doesn't.
+/// Config file is located at `$CONFIG_DIR/egawari/egawari.toml`
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut conf = config::get_file_config().unwrap();
+/// conf.mapping.flip_x = true;
+/// config::save_config(&conf).unwrap();
+/// ```
+pub fn save_config(config: &Config) -> Result<()> {
+    let file = config_path()?;
+    let include = fs::read_to_string(file.as_path()).ok()
+        .and_then(|raw| raw.parse::<toml::Value>().ok())
+        .and_then(|value| value.get("include").and_then(|include| include.as_str()).map(String::from));
+    // The user's file only keeps what differs from the files read under it.
+    let mut bases = base_files(file.as_path())?;
+    bases.extend(include.as_deref().map(|include| resolve_include(file.as_path(), include)));
+
+    match bases.is_empty() {
+        true => write_config(file.as_path(), config),
+        false => write_overlay(file.as_path(), include.as_deref(), &read_configs(&bases)?, config)
+    }
+}
+
+/// Writes the config as the whole file at the path, dropping its include.
+/// What the base files read under it, like the system-wide one,
+/// set and the config doesn't is unset, so the config is the one in effect.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::replace_config(&config::config_path()?, &[PathBuf::from("/etc/egawari/egawari.toml")], &config::Config::default()).unwrap();
+/// ```
+pub fn replace_config(file: &Path, bases: &[PathBuf], config: &Config) -> Result<()> {
+    match bases.is_empty() {
+        true => write_config(file, config),
+        false => write_overlay(file, None, &read_configs(bases)?, config)
+    }
+}
+
+/// Returns the config files read under the user's file, like the system-wide one.
+fn base_files(file: &Path) -> Result<Vec<PathBuf>> {
+    Ok(config_files()?.into_iter().filter(|base| base != file).collect())
+}
+
+/// Writes the config to the file as an overlay of the base, read from the files
+/// under it, keeping the include and only the values that differ from the base.
+fn write_overlay(file: &Path, include: Option<&str>, base: &Config, config: &Config) -> Result<()> {
+    let base = toml::Value::try_from(base).context("Couldn't convert the config to TOML.")?;
+    let mut value = toml::Value::try_from(config).context("Couldn't convert the config to TOML.")?;
+    unset_missing(&mut value, &base);
+    prune(&mut value, &base);
+    if let (toml::Value::Table(table), Some(include)) = (&mut value, include) {
+        table.insert(String::from("include"), toml::Value::String(include.to_string()));
+    }
+
+    let raw = toml::to_string_pretty(&value).context("Couldn't convert the config to TOML.")?;
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Couldn't create the config directory: {}", dir.display()))?;
+    }
+    fs::write(file, raw).with_context(|| format!("Couldn't write to the config file: {}", file.display()))?;
+
+    Ok(())
+}
+
+/// Unsets the values of the base that are left out of the value, for being
+/// `None` or the default, with `false`, or with an empty array for the arrays.
+fn unset_missing(value: &mut toml::Value, base: &toml::Value) {
+    if let (toml::Value::Table(table), toml::Value::Table(base)) = (value, base) {
+        for (key, base) in base {
+            match table.get_mut(key) {
+                Some(value) => unset_missing(value, base),
+                None => {
+                    let unset = match base {
+                        toml::Value::Array(_) => toml::Value::Array(vec![]),
+                        _ => toml::Value::Boolean(false)
+                    };
+                    table.insert(key.clone(), unset);
+                }
+            }
+        }
+    }
+}
+
+/// Deserializes the field like its type, reading `false` as its default,
+/// so a file can unset a value set by the files under it.
+fn unset<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default
+{
+    match toml::Value::deserialize(deserializer)? {
+        toml::Value::Boolean(false) => Ok(T::default()),
+        value => value.try_into().map_err(de::Error::custom)
+    }
+}
+
+/// Removes the values that are the same in the base, and the tables left empty.
+fn prune(value: &mut toml::Value, base: &toml::Value) {
+    if let (toml::Value::Table(table), toml::Value::Table(base)) = (value, base) {
+        let mut same = vec![];
+        for (key, value) in table.iter_mut() {
+            if let Some(base) = base.get(key) {
+                prune(value, base);
+                if value == base || matches!(value, toml::Value::Table(table) if table.is_empty()) {
+                    same.push(key.clone());
+                }
+            }
+        }
+        for key in same {
+            table.remove(&key);
+        }
+    }
+}
+
+/// Runs the `init` command, writing the default configuration to the config file
+/// so there's a file to edit. An existing file is only overwritten
+/// if the `yes` option is given.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::init(&args::Options::default()).unwrap();
+/// ```
+pub fn init(opts: &Options) -> Result<()> {
+    let path = config_path()?;
+    if path.exists() && !opts.yes {
+        errln!("The config file already exists: \x1b[0;39m{}", path.display());
+        logln!("Overwrite it with the defaults with: \x1b[0;39megawari init -y");
+        std::process::exit(1);
+    }
+
+    write_config(path.as_path(), &Config::default())?;
+    successln!("Created the configuration at: \x1b[0;39m{}", path.display());
+
+    Ok(())
+}
+
+/// Runs the `reset` command, replacing the config file, include and all,
+/// with the default configuration after a confirmation,
+/// unless the `yes` option is given. What the system-wide file sets is unset.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::reset(&args::Options { yes: true, ..Default::default() }).unwrap();
+/// ```
+pub fn reset(opts: &Options) -> Result<()> {
+    let path = config_path()?;
+    let yes = opts.yes;
+    if !yes && !stdout::confirm(format!("Reset the configuration at \x1b[0;39m{}\x1b[1;39m to the defaults?", path.display()).as_str()) {
+        warnln!("Didn't reset the configuration.");
+        return Ok(());
+    }
+
+    replace_config(path.as_path(), &base_files(path.as_path())?, &Config::default())?;
+    successln!("Reset the configuration at: \x1b[0;39m{}", path.display());
+
+    Ok(())
+}
+
+/// Removes the directory and the files that exist among the paths,
+/// returning the removed ones.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for path in config::remove_paths(&egawari_dir()?, &daemon::runtime_files())? {
+///     println!("{}", path.display());
+/// }
+/// ```
+pub fn remove_paths(dir: &Path, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+    if dir.is_dir() {
+        fs::remove_dir_all(dir).with_context(|| format!("Couldn't remove the directory: {}", dir.display()))?;
+        removed.push(dir.to_path_buf());
+    }
+    for file in files {
+        if fs::symlink_metadata(file).is_ok() {
+            fs::remove_file(file).with_context(|| format!("Couldn't remove the file: {}", file.display()))?;
+            removed.push(file.clone());
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Runs the `purge` command, removing the config directory with the config file,
+/// the profiles and the autosave file, and the runtime files, after a confirmation
+/// unless the `yes` option is given. Refuses while egawari is running,
+/// unless the `force` option is given. A config file given with `--config`
+/// is left alone, as it's outside the config directory.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::purge(&args::Options { yes: true, ..Default::default() }).unwrap();
+/// ```
+pub fn purge(opts: &Options) -> Result<()> {
+    if let Some(pid) = daemon::running_pid().filter(|_| !opts.force) {
+        errln!("egawari is running with the PID: \x1b[0;39m{}", pid);
+        logln!("Stop it first, or purge anyway with: \x1b[0;39megawari purge --force");
+        std::process::exit(1);
+    }
+
+    let dir = egawari_dir()?;
+    if !opts.yes && !stdout::confirm(format!("Remove \x1b[0;39m{}\x1b[1;39m and the runtime files of egawari?", dir.display()).as_str()) {
+        warnln!("Didn't remove anything.");
+        return Ok(());
+    }
+
+    let removed = remove_paths(dir.as_path(), &daemon::runtime_files())?;
+    if removed.is_empty() {
+        warnln!("Found nothing to remove.");
+    }
+    for path in removed {
+        successln!("Removed: \x1b[0;39m{}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Runs the `export` command, writing the configuration to the given file.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::export(&[String::from("tablet.toml")]).unwrap();
+/// ```
+pub fn export(args: &[String]) -> Result<()> {
+    let file = match args.first() {
+        Some(file) => PathBuf::from(file),
+        None => {
+            errln!("No file provided.");
+            logln!("See: \x1b[0;39megawari help");
+            std::process::exit(1);
+        }
+    };
+
+    write_config(file.as_path(), &get_file_config()?)?;
+    successln!("Exported the configuration to: \x1b[0;39m{}", file.display());
+
+    Ok(())
+}
+
+/// Runs the `import` command, validating the config in the given file
+/// and copying it into place, replacing the include of the config file
+/// and unsetting what the system-wide file sets that it doesn't.
+/// Overwriting the configuration needs a confirmation, unless the `yes` option is given.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::import(&[String::from("tablet.toml")], &args::Options::default()).unwrap();
+/// ```
+pub fn import(args: &[String], opts: &Options) -> Result<()> {
+    let file = match args.first() {
+        Some(file) => PathBuf::from(file),
+        None => {
+            errln!("No file provided.");
+            logln!("See: \x1b[0;39megawari help");
+            std::process::exit(1);
+        }
+    };
+
+    let config = read_config(file.as_path())?;
+    let problems = config.validate();
+    if !problems.is_empty() {
+        for problem in problems {
+            errln!("{}", problem);
+        }
+        errln!("Refusing to import: \x1b[0;39m{}", file.display());
+        std::process::exit(1);
+    }
+
+    let path = config_path()?;
+    let yes = opts.yes;
+    if !yes && path.exists()
+        && !stdout::confirm(format!("Overwrite the configuration at \x1b[0;39m{}\x1b[1;39m?", path.display()).as_str()) {
+        warnln!("Didn't import the configuration.");
+        return Ok(());
+    }
+
+    replace_config(path.as_path(), &base_files(path.as_path())?, &config)?;
+    successln!("Imported the configuration from: \x1b[0;39m{}", file.display());
+
+    Ok(())
+}
+
+/// Reads the config struct from the TOML file at the given path.
+/// A file with an `include` key is read on top of the included file,
+/// whose path is relative to the directory of the including file.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = config::read_config(Path::new("sketch.toml")).unwrap();
+/// ```
+pub fn read_config(file: &Path) -> Result<Config> {
+    let value = load_value(file, &mut vec![])?;
+    let config: Config = value.try_into().context("Couldn't parse the config file.")?;
+
+    Ok(config)
+}
+
+/// Reads the config struct from the TOML files, each one merged
+/// over the ones before it like an include. No files give the defaults.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = config::read_configs(&[PathBuf::from("/etc/egawari/egawari.toml"), config::config_path()?]).unwrap();
+/// ```
+pub fn read_configs(files: &[PathBuf]) -> Result<Config> {
+    if files.is_empty() {
+        return Ok(Config::default());
+    }
+
+    let value = merged_value(files)?;
+    let config: Config = value.try_into().context("Couldn't parse the config file.")?;
+
+    Ok(config)
+}
+
+/// Reads the TOML files with their includes, each one merged over the ones before it.
+fn merged_value(files: &[PathBuf]) -> Result<toml::Value> {
+    let mut value = toml::Value::Table(toml::value::Table::new());
+    for file in files {
+        merge(&mut value, load_value(file.as_path(), &mut vec![])?);
+    }
+
+    Ok(value)
+}
+
+/// Returns the path of the file included by the given one.
+fn resolve_include(file: &Path, include: &str) -> PathBuf {
+    match file.parent() {
+        Some(dir) => dir.join(include),
+        None => PathBuf::from(include)
+    }
+}
+
+/// Reads the TOML file with its includes merged under it.
+/// The files being read are kept in `chain` to catch include cycles.
+fn load_value(file: &Path, chain: &mut Vec<PathBuf>) -> Result<toml::Value> {
+    let canonical = fs::canonicalize(file)
+        .with_context(|| format!("Couldn't read the config file: {}", file.display()))?;
+    if chain.contains(&canonical) {
+        let cycle: Vec<String> = chain.iter()
+            .skip_while(|path| **path != canonical)
+            .chain(std::iter::once(&canonical))
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(anyhow!("The config files include each other: {}", cycle.join(" -> ")));
+    }
+
+    let raw = fs::read_to_string(file)
+        .with_context(|| format!("Couldn't read the config file: {}", file.display()))?;
+    let mut value: toml::Value = raw.parse()
+        .with_context(|| format!("Couldn't parse the config file: {}", file.display()))?;
+    let include = match value.as_table_mut().and_then(|table| table.remove("include")) {
+        Some(toml::Value::String(include)) => include,
+        Some(_) => return Err(anyhow!("The include of the config file must be a path: {}", file.display())),
+        None => return Ok(value)
+    };
+
+    chain.push(canonical);
+    let mut base = load_value(resolve_include(file, include.as_str()).as_path(), chain)?;
+    chain.pop();
+    merge(&mut base, value);
+    Ok(base)
+}
+
+/// Overlays the value on the base, merging the tables key by key
+/// and replacing everything else.
+fn merge(base: &mut toml::Value, value: toml::Value) {
+    match (base, value) {
+        (toml::Value::Table(base), toml::Value::Table(table)) => {
+            for (key, value) in table {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        },
+        (base, value) => *base = value
+    }
+}
+
+/// Writes the given config struct as TOML to the file at the given path,
+/// creating its parent directories if needed.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = config::get_config().unwrap();
+/// config::write_config(Path::new("sketch.toml"), &conf).unwrap();
+/// ```
+pub fn write_config(file: &Path, config: &Config) -> Result<()> {
+    let raw = toml::to_string_pretty(config).context("Couldn't convert the config to TOML.")?;
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Couldn't create the config directory: {}", dir.display()))?;
+    }
+    fs::write(file, raw).with_context(|| format!("Couldn't write to the config file: {}", file.display()))?;
+
+    Ok(())
+}
+
+/// Looks up the value at the dotted key path, like `mapping.flip_x`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let value = serde_json::to_value(&conf).unwrap();
+/// let names = config::lookup(&value, "input.names");
+/// ```
+pub fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(value, |v, k| v.get(k))
+}
+
+/// Collects the leaf values under the key path with their full key paths.
+fn flatten(key: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let path = if key.is_empty() { k.to_string() } else { format!("{}.{}", key, k) };
+                flatten(path.as_str(), v, out);
+            }
+        },
+        _ => out.push((key.to_string(), value.clone()))
+    }
+}
+
+/// Runs the `get` command, printing the config value at the key path,
+/// or the whole config if no key is given.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::get(&[String::from("input.names")]).unwrap();
+/// ```
+pub fn get(args: &[String]) -> Result<()> {
+    let conf = get_config()?;
+    let root = serde_json::to_value(&conf).context("Couldn't convert the config to JSON.")?;
+
+    let key = args.first().map(|k| k.as_str()).unwrap_or("");
+    let value = if key.is_empty() {
+        &root
+    } else {
+        match lookup(&root, key) {
+            Some(value) => value,
+            None => {
+                errln!("Unknown config key: \x1b[0;39m{}", key);
+                logln!("See: \x1b[0;39megawari get");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    if stdout::json_enabled() {
+        println!("{}", serde_json::to_string_pretty(value).context("Couldn't convert the config to JSON.")?);
+        return Ok(());
+    }
+
+    let mut leaves = vec![];
+    flatten(key, value, &mut leaves);
+    for (path, value) in leaves {
+        logln!("{} = \x1b[0;39m{}", path, value);
+    }
+
+    Ok(())
+}
+
+/// Where a value of the configuration in effect comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    System,
+    File,
+    Env
+}
+
+impl Source {
+    /// Returns the name of the source, as shown by `print-config`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::System => "system",
+            Source::File => "file",
+            Source::Env => "env"
+        }
+    }
+}
+
+/// Returns the key paths of the configuration in effect with where their values come from,
+/// the environment variables, the user's config file with its includes,
+/// the system-wide config file or the defaults.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = config::get_config().unwrap();
+/// for (path, source) in config::config_sources(&conf).unwrap() {
+///     println!("{} = {}", path, source.name());
+/// }
+/// ```
+pub fn config_sources(config: &Config) -> Result<Vec<(String, Source)>> {
+    let (system, user): (Vec<PathBuf>, Vec<PathBuf>) = config_files()?.into_iter()
+        .partition(|file| file == Path::new(SYSTEM_CONFIG_PATH));
+    let json = |files: &[PathBuf]| -> Result<Value> {
+        match files.is_empty() {
+            true => Ok(Value::Null),
+            false => serde_json::to_value(merged_value(files)?).context("Couldn't convert the config to JSON.")
+        }
+    };
+    let (system, file) = (json(&system)?, json(&user)?);
+    let root = serde_json::to_value(config).context("Couldn't convert the config to JSON.")?;
+
+    let mut leaves = vec![];
+    flatten("", &root, &mut leaves);
+    let sources = leaves.into_iter().map(|(path, _)| {
+        let source = if ENV_KEYS.iter().any(|(name, key)| *key == path && env::var_os(name).is_some()) {
+            Source::Env
+        } else if lookup(&file, path.as_str()).is_some() {
+            Source::File
+        } else if lookup(&system, path.as_str()).is_some() {
+            Source::System
+        } else {
+            Source::Default
+        };
+        (path, source)
+    }).collect();
+
+    Ok(sources)
+}
+
+/// Appends the sources of the values to their lines in the TOML as comments.
+/// Arrays of tables are annotated on their headers.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let sources = vec![(String::from("mapping.flip_x"), config::Source::File)];
+/// assert_eq!(config::annotate_sources("[mapping]\nflip_x = true\n", &sources), "[mapping]\nflip_x = true # file\n");
+/// ```
+pub fn annotate_sources(raw: &str, sources: &[(String, Source)]) -> String {
+    let source = |path: &str| sources.iter().find(|(key, _)| key == path).map(|(_, source)| *source);
+    let mut table = String::new();
+    let mut result = String::new();
+
+    for line in raw.lines() {
+        // Only the headers and the keys start at the beginning of the line,
+        // the items of the arrays are indented.
+        let path = if let Some(header) = line.strip_prefix("[[").and_then(|line| line.strip_suffix("]]")) {
+            table = header.to_string();
+            Some(header.to_string())
+        } else if let Some(header) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            table = header.to_string();
+            None
+        } else if let Some((key, _)) = line.split_once(" = ").filter(|_| !line.starts_with(' ')) {
+            Some(if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) })
+        } else {
+            None
+        };
+
+        result.push_str(line);
+        if let Some(source) = path.and_then(|path| source(path.as_str())) {
+            result.push_str(format!(" # {}", source.name()).as_str());
+        }
+        result.push('\n');
+    }
+
+    result
+}
+
+/// Runs the `print-config` command, printing the configuration in effect
+/// as TOML with where each value comes from and the config files read,
+/// without changing the config file.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::print_config().unwrap();
+/// ```
+pub fn print_config() -> Result<()> {
+    let (conf, files) = load_config()?;
+    let sources = config_sources(&conf)?;
+
+    if stdout::json_enabled() {
+        let sources: serde_json::Map<String, Value> = sources.into_iter()
+            .map(|(path, source)| (path, Value::from(source.name())))
+            .collect();
+        let value = serde_json::json!({
+            "config": serde_json::to_value(&conf).context("Couldn't convert the config to JSON.")?,
+            "sources": sources,
+            "files": files
+        });
+        println!("{}", serde_json::to_string_pretty(&value).context("Couldn't convert the config to JSON.")?);
+        return Ok(());
+    }
+
+    // The comments leave the TOML readable as a config file.
+    match files.is_empty() {
+        true => println!("# No config file, the defaults are in effect."),
+        false => println!("# Read from: {}", files.iter().map(|file| file.display().to_string()).collect::<Vec<_>>().join(", "))
+    }
+    let raw = toml::to_string_pretty(&conf).context("Couldn't convert the config to TOML.")?;
+    print!("{}", annotate_sources(raw.as_str(), &sources));
+
+    Ok(())
+}
+
+/// The keys of the config file as their path, type and description.
+/// The keys of the optional sections are listed as well.
+pub const KEYS: &[(&str, &str, &str)] = &[
+    ("log_file", "path", "The file the messages are appended to, in addition to printing them"),
+    ("require_session", "any, x11 or wayland", "The session type run refuses to start without"),
+    ("input.names", "list of strings", "The input devices in priority order, the first present one is used"),
+    ("input.id", "string", "The vendor:product IDs of the input device in hexadecimal, matched instead of the names"),
+    ("input.path", "string", "The event node of the input device, like /dev/input/event5, opened instead of matching the IDs or the names"),
+    ("input.grab", "bool", "Grabs the input device while it's mapped, so it stops moving the pointer"),
+    ("input.fallback_bounds.min_x", "number", "The lowest X position used if the device reports an empty or inverted range"),
+    ("input.fallback_bounds.max_x", "number", "The highest X position used if the device reports an empty or inverted range"),
+    ("input.fallback_bounds.min_y", "number", "The lowest Y position used if the device reports an empty or inverted range"),
+    ("input.fallback_bounds.max_y", "number", "The highest Y position used if the device reports an empty or inverted range"),
+    ("display.display", "string", "The display the tablet is mapped to, detected at runtime if unset"),
+    ("display.screen", "number", "The screen of the display the tablet is mapped to"),
+    ("tablet.start_paused", "bool", "Starts run with the mapping paused"),
+    ("tablet.hover_on_lift", "bool", "Keeps the pen in proximity after lifting the finger"),
+    ("tablet.hover_mode", "off, double_tap or two_finger", "How hovering the pen is emulated"),
+    ("tablet.double_tap_ms", "number", "The longest tap in milliseconds that starts a hovering double tap"),
+    ("tablet.lift_timeout_ms", "number", "How long in milliseconds the pen hovers after lifting, or 0 to leave right away"),
+    ("tablet.report_rate", "number", "The maximum rate of the position reports in Hz, or 0 to report every event"),
+    ("tablet.passthrough_gestures", "bool", "Leaves the multi-finger gestures to the touchpad instead of drawing"),
+    ("tablet.hide_cursor", "bool", "Hides the X11 cursor while egawari runs, which the compositor decides on Wayland"),
+    ("tablet.touch_settle_ms", "number", "The longest time in milliseconds a new touch is held back until its position is stable"),
+    ("tablet.contact_debounce_ms", "number", "How long in milliseconds a lift is held back in case the finger touches down again, or 0 to lift right away"),
+    ("tablet.touch_threshold", "number", "The lowest pressure a contact draws with, or 0 to draw with any contact"),
+    ("tablet.pressure_source", "force or touch_major", "The axis of the touchpad the pressure is read from, the force or the size of the contact"),
+    ("tablet.invert_pressure", "bool", "Turns the pressure around within its range, for touchpads reporting less for a harder contact"),
+    ("tablet.pressure_curve", "list of [input, output] percents", "The control points of the pressure curve, interpolated between, or empty to report the pressure as it is"),
+    ("tablet.pressure_device", "string", "The input device the pressure is read from instead of the touchpad, for a separate pressure-sensitive surface"),
+    ("tablet.output_device", "string", "The existing input device the tablet events are written into instead of creating a virtual tablet, by its name or event node path"),
+    ("tablet.virtual_name", "string", "The name of the virtual tablet device, for drawing apps and udev rules"),
+    ("smoothing.factor", "float", "How much of the previous position each reported one keeps, from 0 for no smoothing to below 1"),
+    ("smoothing.pressure_aware", "bool", "Lessens the smoothing as the pressure rises, down to none at the full pressure"),
+    ("tilt.source", "orientation or two_finger", "Where the direction the pen leans in is read from, the contact orientation or a second finger"),
+    ("tilt.angle", "number", "How far the pen leans from upright in degrees, from 0 to 90"),
+    ("mapping.mode", "absolute or relative", "Whether the touchpad positions or its motion move the pen"),
+    ("mapping.acceleration", "float", "How much faster quick motions move the pen in the relative mode"),
+    ("mapping.target", "area, all or monitor:NAME[,NAME...]", "What the touchpad is mapped onto, the area, the whole screen or the bounding box of the monitors"),
+    ("mapping.width", "number", "The width of the output, defaulting to the touchpad resolution"),
+    ("mapping.height", "number", "The height of the output, defaulting to the touchpad resolution"),
+    ("mapping.rotation", "number", "The rotation of the touchpad in degrees, 0, 90, 180 or 270"),
+    ("mapping.flip_x", "bool", "Flips the horizontal touchpad motion"),
+    ("mapping.flip_y", "bool", "Flips the vertical touchpad motion"),
+    ("mapping.keep_aspect", "bool", "Shrinks the area to the aspect ratio of the touchpad"),
+    ("mapping.scale_x", "float", "The gain of the horizontal touchpad motion around its center"),
+    ("mapping.scale_y", "float", "The gain of the vertical touchpad motion around its center"),
+    ("mapping.area.x", "number", "The left of the area the touchpad is mapped to"),
+    ("mapping.area.y", "number", "The top of the area the touchpad is mapped to"),
+    ("mapping.area.width", "number", "The width of the area the touchpad is mapped to"),
+    ("mapping.area.height", "number", "The height of the area the touchpad is mapped to"),
+    ("mapping.area.width_mm", "float", "The physical width of the area in millimeters, for the tablet resolution"),
+    ("mapping.area.height_mm", "float", "The physical height of the area in millimeters, for the tablet resolution"),
+    ("mapping.anchor", "top_left, top, top_right, left, center, right, bottom_left, bottom or bottom_right", "Where the area is placed on the output, its position being the margin from the anchored edges"),
+    ("mapping.quad.top_left", "x and y numbers", "The corner of the quad the top left of the touchpad is mapped to"),
+    ("mapping.quad.top_right", "x and y numbers", "The corner of the quad the top right of the touchpad is mapped to"),
+    ("mapping.quad.bottom_right", "x and y numbers", "The corner of the quad the bottom right of the touchpad is mapped to"),
+    ("mapping.quad.bottom_left", "x and y numbers", "The corner of the quad the bottom left of the touchpad is mapped to"),
+    ("mapping.edge_snap.pixels", "number", "How many pixels from the edges the positions snap onto them"),
+    ("mapping.edge_snap.percent", "float", "How many percent of the area size from the edges the positions snap onto them"),
+    ("overlay.seconds", "number", "How many seconds the outline of the area stays shown after it changes, or 0 to always show it"),
+    ("overlay.thickness", "number", "The width of the outline of the area in pixels"),
+    ("activation.key", "string", "The evdev name of the key held to map, like KEY_LEFTALT"),
+    ("activation.device", "string", "The input device reporting the key, defaulting to the touchpad itself"),
+    ("eraser.key", "string", "The evdev name of the key held to erase, or unset to switch with a three-finger tap"),
+    ("eraser.device", "string", "The input device reporting the eraser key, defaulting to the touchpad itself"),
+    ("pad.buttons", "number", "The number of buttons of the virtual tablet pad, up to 10"),
+    ("pad.device", "string", "The input device reporting the pad keys, defaulting to the touchpad itself"),
+    ("pad.bindings", "list of button, key or gesture", "What presses the pad buttons, a held key or a three_finger_tap or four_finger_tap gesture"),
+    ("routes", "list of from, to, scale and threshold", "The pressure, x or y axis routed to a relative axis like REL_WHEEL or held as a key, along with the tablet events"),
+    ("focus_rules", "list of class, title and profile", "The profiles used while a focused window matches their class and title patterns"),
+    ("stylus_buttons.device", "string", "The input device reporting the stylus button keys, defaulting to the touchpad itself"),
+    ("stylus_buttons.bindings", "list of key and button", "The keys holding the lower or upper button of the pen while they're held"),
+    ("profile_switch.device", "string", "The input device reporting the profile switch keys, defaulting to the touchpad itself"),
+    ("profile_switch.notify", "bool", "Also shows the profile switches as desktop notifications"),
+    ("profile_switch.bindings", "list of key and profile", "The keys switching to their profile, or to the next saved one without a profile"),
+    ("theme.operator", "number", "The palette index of the operators and separators"),
+    ("theme.string", "number", "The palette index of the quoted strings"),
+    ("theme.bracket", "number", "The palette index of the brackets"),
+    ("theme.error", "number", "The palette index of the arrow of the error messages"),
+    ("theme.success", "number", "The palette index of the arrow of the success messages")
+];
+
+/// Returns the default values of the keys, with the default theme
+/// instead of the theme section being absent.
+fn key_defaults() -> Result<Value> {
+    let defaults = Config {
+        theme: Some(Theme::default()),
+        ..Config::default()
+    };
+    serde_json::to_value(&defaults).context("Couldn't convert the config to JSON.")
+}
+
+/// Runs the `list-keys` command, printing every key of the config file
+/// with its type, default value and description.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::list_keys().unwrap();
+/// ```
+pub fn list_keys() -> Result<()> {
+    let defaults = key_defaults()?;
+    let default = |path: &str| lookup(&defaults, path).cloned().unwrap_or(Value::Null);
+
+    if stdout::json_enabled() {
+        let keys: Vec<Value> = KEYS.iter()
+            .map(|(path, key_type, description)| serde_json::json!({
+                "key": path,
+                "type": key_type,
+                "default": default(path),
+                "description": description
+            }))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&keys).context("Couldn't convert the keys to JSON.")?);
+        return Ok(());
+    }
+
+    for (path, key_type, description) in KEYS {
+        let default = match default(path) {
+            Value::Null => String::from("unset"),
+            value => value.to_string()
+        };
+        logln!("{} <{}> = {} => \x1b[0;39m{}.", path, key_type, default, description);
+    }
+
+    Ok(())
+}
+
+/// The behaviour of the config key.
+#[derive(PartialEq, Eq)]
+pub enum ConfigKeyType {
+    Button(ConfigButton),
+    String,
+    Number,
+    Float,
+    Bool,
+    /// One of a few choices, moving to the next one on "Space".
+    Cycle
+}
+
+/// What pressing a button in the editor does.
+#[derive(PartialEq, Eq)]
+pub enum ConfigButton {
+    AutomaticSetup,
+    AddInput,
+    AddDisplay,
+    SetDisplay
+}
+
+/// The config fields that can be edited interactively.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf: &mut config::Config = &mut config::get_config()?;
+/// config::ConfigField::InputName(0).set(conf, config::ConfigValue::String(String::from("SynPS/2 Synaptics TouchPad")));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigField {
+    /// The input name at the index of the list.
+    InputName(usize),
+    InputId,
+    InputPath,
+    InputGrab,
+    DisplayDisplay,
+    DisplayScreen,
+    TabletStartPaused,
+    TabletHoverOnLift,
+    TabletReportRate,
+    TabletPassthroughGestures,
+    TabletHideCursor,
+    TabletTouchSettleMs,
+    TabletTouchThreshold,
+    TabletVirtualName,
+    MappingMode,
+    MappingFlipX,
+    MappingFlipY,
+    MappingScaleX,
+    MappingScaleY
+}
+
+/// The value of a config field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Number(u32),
+    Float(f32),
+    Bool(bool),
+    /// The name of a choice, like the mode of the mapping.
+    Choice(&'static str)
+}
+
+impl std::fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigValue::String(s) => write!(f, "{}", s),
+            ConfigValue::Number(n) => write!(f, "{}", n),
+            ConfigValue::Float(n) => write!(f, "{}", n),
+            ConfigValue::Bool(b) => write!(f, "{}", b),
+            ConfigValue::Choice(c) => write!(f, "{}", c)
+        }
+    }
+}
+
+impl ConfigField {
+    /// Returns the value of the field in the given config,
+    /// or `None` if the section containing it is absent.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let conf: config::Config = config::get_config()?;
+    /// let name = config::ConfigField::InputName(0).get(&conf);
+    /// ```
+    pub fn get(&self, config: &Config) -> Option<ConfigValue> {
+        match self {
+            ConfigField::InputName(i) => config.input.names.get(*i)
+                .cloned()
+                .map(ConfigValue::String),
+            ConfigField::InputId => Some(ConfigValue::String(config.input.id.map(|id| id.to_string()).unwrap_or_default())),
+            ConfigField::InputPath => Some(ConfigValue::String(config.input.path.as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default())),
+            ConfigField::InputGrab => Some(ConfigValue::Bool(config.input.grab)),
+            ConfigField::DisplayDisplay => config.display.as_ref()
+                .and_then(|d| d.display.clone())
+                .map(ConfigValue::String),
+            ConfigField::DisplayScreen => config.display.as_ref()
+                .map(|d| ConfigValue::Number(d.screen as u32)),
+            ConfigField::TabletStartPaused => Some(ConfigValue::Bool(config.tablet.start_paused)),
+            ConfigField::TabletHoverOnLift => Some(ConfigValue::Bool(config.tablet.hover_on_lift)),
+            ConfigField::TabletReportRate => Some(ConfigValue::Number(config.tablet.report_rate as u32)),
+            ConfigField::TabletPassthroughGestures => Some(ConfigValue::Bool(config.tablet.passthrough_gestures)),
+            ConfigField::TabletHideCursor => Some(ConfigValue::Bool(config.tablet.hide_cursor)),
+            ConfigField::TabletTouchSettleMs => Some(ConfigValue::Number(config.tablet.touch_settle_ms)),
+            ConfigField::TabletTouchThreshold => Some(ConfigValue::Number(config.tablet.touch_threshold)),
+            ConfigField::TabletVirtualName => Some(ConfigValue::String(config.tablet.virtual_name.clone())),
+            ConfigField::MappingMode => Some(ConfigValue::Choice(config.mapping.mode.name())),
+            ConfigField::MappingFlipX => Some(ConfigValue::Bool(config.mapping.flip_x)),
+            ConfigField::MappingFlipY => Some(ConfigValue::Bool(config.mapping.flip_y)),
+            ConfigField::MappingScaleX => Some(ConfigValue::Float(config.mapping.scale_x)),
+            ConfigField::MappingScaleY => Some(ConfigValue::Float(config.mapping.scale_y))
+        }
+    }
+
+    /// Sets the field in the given config to the value.
+    /// Does nothing if the section containing the field is absent
+    /// or the value is of the wrong type.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let conf: &mut config::Config = &mut config::get_config()?;
+    /// config::ConfigField::DisplayScreen.set(conf, config::ConfigValue::Number(1));
+    /// ```
+    pub fn set(&self, config: &mut Config, value: ConfigValue) {
+        match (self, value) {
+            (ConfigField::InputName(i), ConfigValue::String(s)) => {
+                if let Some(name) = config.input.names.get_mut(*i) {
+                    *name = s;
+                }
+            },
+            (ConfigField::InputId, ConfigValue::String(s)) => {
+                if s.trim().is_empty() {
+                    config.input.id = None;
+                } else if let Ok(id) = s.parse() {
+                    config.input.id = Some(id);
+                }
+            },
+            (ConfigField::InputPath, ConfigValue::String(s)) => {
+                config.input.path = Some(PathBuf::from(s.trim())).filter(|path| !path.as_os_str().is_empty());
+            },
+            (ConfigField::InputGrab, ConfigValue::Bool(b)) => {
+                config.input.grab = b;
+            },
+            (ConfigField::DisplayDisplay, ConfigValue::String(s)) => {
+                if let Some(Display { display: Some(dp), .. }) = &mut config.display {
+                    *dp = s;
+                }
+            },
+            (ConfigField::DisplayScreen, ConfigValue::Number(n)) => {
+                if let (Some(display), Ok(n)) = (&mut config.display, u8::try_from(n)) {
+                    display.screen = n;
+                }
+            },
+            (ConfigField::TabletStartPaused, ConfigValue::Bool(b)) => {
+                config.tablet.start_paused = b;
+            },
+            (ConfigField::TabletHoverOnLift, ConfigValue::Bool(b)) => {
+                config.tablet.hover_on_lift = b;
+            },
+            (ConfigField::TabletReportRate, ConfigValue::Number(n)) => {
+                if let Ok(n) = u16::try_from(n) {
+                    config.tablet.report_rate = n;
+                }
+            },
+            (ConfigField::TabletPassthroughGestures, ConfigValue::Bool(b)) => {
+                config.tablet.passthrough_gestures = b;
+            },
+            (ConfigField::TabletHideCursor, ConfigValue::Bool(b)) => {
+                config.tablet.hide_cursor = b;
+            },
+            (ConfigField::TabletTouchSettleMs, ConfigValue::Number(n)) => {
+                config.tablet.touch_settle_ms = n;
+            },
+            (ConfigField::TabletTouchThreshold, ConfigValue::Number(n)) => {
+                config.tablet.touch_threshold = n;
+            },
+            (ConfigField::TabletVirtualName, ConfigValue::String(s)) => {
+                config.tablet.virtual_name = s;
+            },
+            (ConfigField::MappingMode, ConfigValue::Choice(c)) => {
+                if let Some(mode) = MappingMode::ALL.iter().find(|mode| mode.name() == c) {
+                    config.mapping.mode = *mode;
+                }
+            },
+            (ConfigField::MappingFlipX, ConfigValue::Bool(b)) => {
+                config.mapping.flip_x = b;
+            },
+            (ConfigField::MappingFlipY, ConfigValue::Bool(b)) => {
+                config.mapping.flip_y = b;
+            },
+            (ConfigField::MappingScaleX, ConfigValue::Float(n)) => {
+                config.mapping.scale_x = n;
+            },
+            (ConfigField::MappingScaleY, ConfigValue::Float(n)) => {
+                config.mapping.scale_y = n;
+            },
+            _ => ()
+        }
+    }
+
+    /// Returns the largest number the field holds, for the number fields
+    /// narrower than the numbers the editor takes.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// assert_eq!(config::ConfigField::DisplayScreen.max(), 255);
+    /// ```
+    pub fn max(&self) -> u32 {
+        match self {
+            ConfigField::DisplayScreen => u8::MAX as u32,
+            ConfigField::TabletReportRate => u16::MAX as u32,
+            _ => u32::MAX
+        }
+    }
+
+    /// Returns the problem of the value of the field in the given config,
+    /// or `None` if it's valid or the section containing it is absent.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// if let Some(problem) = config::ConfigField::InputName(0).problem(&conf) {
+    ///     errln!("{}", problem);
+    /// }
+    /// ```
+    pub fn problem(&self, config: &Config) -> Option<String> {
+        let problem = match (self, self.get(config)?) {
+            (ConfigField::InputName(_), ConfigValue::String(name)) if name.trim().is_empty() =>
+                "The input name can't be empty.",
+            (ConfigField::DisplayDisplay, ConfigValue::String(dp)) if dp.trim().is_empty() =>
+                "The display can't be empty, unset it to detect the display at runtime.",
+            (ConfigField::TabletVirtualName, ConfigValue::String(name)) if name.trim().is_empty() =>
+                "The virtual tablet name can't be empty.",
+            (ConfigField::MappingScaleX, ConfigValue::Float(scale)) | (ConfigField::MappingScaleY, ConfigValue::Float(scale))
+                if !(scale > 0.0 && scale.is_finite()) => "The scales must be positive numbers.",
+            _ => return None
+        };
+        Some(String::from(problem))
+    }
+
+    /// Moves the field in the given config to its next choice,
+    /// wrapping around after the last one. Does nothing to the fields
+    /// without choices.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let conf: &mut config::Config = &mut config::get_config()?;
+    /// // Switches to the relative mode from the absolute one.
+    /// config::ConfigField::MappingMode.cycle(conf);
+    /// ```
+    pub fn cycle(&self, config: &mut Config) {
+        if let ConfigField::MappingMode = self {
+            let i = MappingMode::ALL.iter().position(|mode| *mode == config.mapping.mode).unwrap_or_default();
+            config.mapping.mode = MappingMode::ALL[(i + 1) % MappingMode::ALL.len()];
+        }
+    }
+
+    /// Returns the value of the field in the default config,
+    /// or `None` if it has none, like the input names.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// assert_eq!(config::ConfigField::MappingFlipX.default_value(), Some(config::ConfigValue::Bool(false)));
+    /// ```
+    pub fn default_value(&self) -> Option<ConfigValue> {
+        self.get(&Config::default())
+    }
+
+    /// Resets the field in the given config to its default,
+    /// returning the value it had if that changed it.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let conf: &mut config::Config = &mut config::get_config()?;
+    /// if let Some(before) = config::ConfigField::MappingScaleX.reset(conf) {
+    ///     println!("Reset the scale from {}.", before);
+    /// }
+    /// ```
+    pub fn reset(&self, config: &mut Config) -> Option<ConfigValue> {
+        let before = self.get(config)?;
+        self.set(config, self.default_value()?);
+        Some(before).filter(|before| self.get(config).as_ref() != Some(before))
+    }
+
+    /// Removes the field from the given config, returning whether it was there.
+    /// Unsetting the screen removes the whole display section,
+    /// as the screen isn't optional. Fields that can't be unset are kept.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let conf: &mut config::Config = &mut config::get_config()?;
+    /// // Detects the display at runtime instead.
+    /// config::ConfigField::DisplayDisplay.unset(conf);
+    /// ```
+    pub fn unset(&self, config: &mut Config) -> bool {
+        match self {
+            ConfigField::InputName(i) if *i < config.input.names.len() => {
+                config.input.names.remove(*i);
+                true
+            },
+            ConfigField::InputId => config.input.id.take().is_some(),
+            ConfigField::InputPath => config.input.path.take().is_some(),
+            ConfigField::DisplayDisplay => config.display.as_mut()
+                .and_then(|display| display.display.take())
+                .is_some(),
+            ConfigField::DisplayScreen => config.display.take().is_some(),
+            _ => false
+        }
+    }
+}
+
+/// Information about the config key.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// ConfigKey {
+///     key_type: ConfigKeyType::String,
+///     field: Some(ConfigField::InputName(0)),
+///     name: "Input Name",
+///     ypos: -1
+/// }
+/// ```
+struct ConfigKey<'a> {
+    key_type: ConfigKeyType,
+    field: Option<ConfigField>,
+    name: &'a str,
+    ypos: i32
+}
+
+impl ConfigKey<'_> {
+    fn val_xpos(&self) -> i32 {
+        value_column(self.name)
+    }
+}
+
+/// Returns the number of terminal columns the text takes,
+/// counting wide characters as two and combining ones as none.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(config::text_width("ペン"), 4);
+/// ```
+pub fn text_width(text: &str) -> i32 {
+    text.width() as i32
+}
+
+/// Returns the column the value of the key with the name starts at in the editor.
+pub fn value_column(name: &str) -> i32 {
+    text_width(format!(" => {} = ", name).as_str())
+}
+
+/// Returns the column of the cursor while editing the value of the key,
+/// after the text or before the closing quote of a quoted string.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(config::edit_column("Name", "Élan", true), 16);
+/// ```
+pub fn edit_column(name: &str, buf: &str, quoted: bool) -> i32 {
+    value_column(name) + quoted as i32 + text_width(buf)
+}
+
+/// Returns whether the character is typed into the value of a key of the type.
+/// Numbers only take digits and floats a single decimal point too,
+/// the other keys take any character.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert!(!config::accepts_char(&config::ConfigKeyType::Number, "25", 'a'));
+/// ```
+pub fn accepts_char(key_type: &ConfigKeyType, buf: &str, c: char) -> bool {
+    match key_type {
+        ConfigKeyType::Number => c.is_ascii_digit(),
+        ConfigKeyType::Float => c.is_ascii_digit() || (c == '.' && !buf.contains('.')),
+        _ => true
+    }
+}
+
+/// Returns why the value typed so far for a key of the type and the field can't be set,
+/// if it can't, shown on the status line while it's typed.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(config::edit_problem(&config::ConfigKeyType::Float, Some(config::ConfigField::MappingScaleX), "0"), Some(String::from("The number must be positive.")));
+/// ```
+pub fn edit_problem(key_type: &ConfigKeyType, field: Option<ConfigField>, buf: &str) -> Option<String> {
+    let max = field.map_or(u32::MAX, |field| field.max());
+    match key_type {
+        ConfigKeyType::Number | ConfigKeyType::Float if buf.trim_start_matches('.').is_empty() => Some(String::from("Type a number.")),
+        ConfigKeyType::Number if !buf.parse::<u32>().is_ok_and(|n| n <= max) => Some(format!("The number can't be larger than {}.", max)),
+        ConfigKeyType::Float if !buf.parse::<f32>().is_ok_and(|n| n > 0.0 && n.is_finite()) => Some(String::from("The number must be positive.")),
+        _ => None
+    }
+}
+
+/// Returns the string as the editor shows it, in quotes. The quotes are only
+/// shown, the quotes and backslashes within are left as they are.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(config::quote(r#"Pen "A" \ B"#), r#""Pen "A" \ B""#);
+/// ```
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value)
+}
+
+/// Config section.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// ConfigKeySection {
+///     name: "Input",
+///     keys: vec![
+///         ConfigKey {
+///             key_type: ConfigKeyType::String,
+///             field: Some(ConfigField::InputName(0)),
+///             name: "Input Name",
+///             ypos: -1
+///         }
+///     ]
+/// }
+/// ```
+struct ConfigKeySection<'a> {
+    name: &'a str,
+    keys: Vec<ConfigKey<'a>>
+}
+
+/// The location of the config key.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::ConfigKeyLocation {
+///     section: 0,
+///     key: 0
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigKeyLocation {
+    pub section: usize,
+    pub key: usize
+}
+
+/// The behaviour of the interactive editor, as opposed to the config it edits.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::config_interactive(config::EditorOptions {
+///     wrap: false,
+///     ..Default::default()
+/// });
+/// ```
+pub struct EditorOptions {
+    /// Whether moving past the first or the last key
+    /// wraps around to the other end.
+    pub wrap: bool,
+    /// Whether the configuration is only viewed,
+    /// so nothing can be edited and nothing is saved.
+    pub read_only: bool,
+    /// How often the unsaved changes are autosaved in seconds,
+    /// or `0` to autosave after every change.
+    pub autosave_secs: u64
+}
+
+impl Default for EditorOptions {
+    fn default() -> Self {
+        EditorOptions {
+            wrap: true,
+            read_only: false,
+            autosave_secs: 0
+        }
+    }
+}
+
+/// Returns the path of the autosave file kept next to the config file
+/// while the interactive editor has unsaved changes,
+/// like `.egawari.toml.autosave` for `egawari.toml`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let autosave = config::autosave_path(Path::new("/tmp/egawari.toml"));
+/// assert_eq!(autosave, PathBuf::from("/tmp/.egawari.toml.autosave"));
+/// ```
+pub fn autosave_path(file: &Path) -> PathBuf {
+    let name = file.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    file.with_file_name(format!(".{}.autosave", name))
+}
+
+/// Removes the autosave file of the config file, if there's one.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::remove_autosave(&config::config_path()?)?;
+/// ```
+pub fn remove_autosave(file: &Path) -> Result<()> {
+    let autosave = autosave_path(file);
+    match fs::remove_file(autosave.as_path()) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e)
+            .with_context(|| format!("Couldn't remove the autosave file: {}", autosave.display())),
+        _ => Ok(())
+    }
+}
+
+/// Offers to recover the changes of an editor that didn't exit cleanly
+/// from the autosave file, returning whether they were recovered.
+/// The autosave file is removed if they aren't.
+fn recover_autosave(conf: &mut Config, file: &Path) -> Result<bool> {
+    let autosave = autosave_path(file);
+    if !autosave.exists() {
+        return Ok(false);
+    }
+
+    let question = format!("Found unsaved changes at \x1b[0;39m{}\x1b[1;39m, recover them?", autosave.display());
+    if !stdout::confirm(question.as_str()) {
+        remove_autosave(file)?;
+        return Ok(false);
+    }
+    *conf = read_config(autosave.as_path())?;
+    Ok(true)
+}
+
+/// The most changes the interactive editor can undo.
+const UNDO_LIMIT: usize = 100;
+
+/// The changes of the interactive editor that can be undone,
+/// as the location of the changed key and the value it had.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut undo = config::UndoStack::default();
+/// let before = field.get(conf).unwrap();
+/// field.set(conf, config::ConfigValue::Bool(true));
+/// undo.push(cur, field, before);
+/// 
+/// // Restores the value and returns where it was changed.
+/// let cur = undo.undo(conf).unwrap();
+/// ```
+#[derive(Default)]
+pub struct UndoStack {
+    entries: Vec<(ConfigKeyLocation, ConfigField, ConfigValue)>
+}
+
+impl UndoStack {
+    /// Remembers the value the field at the location had before a change,
+    /// forgetting the oldest change past the limit.
+    pub fn push(&mut self, location: ConfigKeyLocation, field: ConfigField, old: ConfigValue) {
+        if self.entries.len() == UNDO_LIMIT {
+            self.entries.remove(0);
+        }
+        self.entries.push((location, field, old));
+    }
+
+    /// Restores the value the last changed field had,
+    /// returning its location or `None` if there's nothing to undo.
+    pub fn undo(&mut self, config: &mut Config) -> Option<ConfigKeyLocation> {
+        let (location, field, old) = self.entries.pop()?;
+        field.set(config, old);
+        Some(location)
+    }
+
+    /// Forgets the changes, like when the keys move.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns whether there's nothing to undo.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Returns the location of the key above or below the cursor,
+/// given the number of keys in each section. Without `wrap`
+/// the cursor stops at the first and the last key.
+/// Sections without keys are skipped and a cursor past the end
+/// of the sections is moved back within them first.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let top = config::ConfigKeyLocation { section: 0, key: 0 };
+/// // Wraps around to the last key of the last section.
+/// assert_eq!(config::move_cursor(top, &[2, 3], true, true), config::ConfigKeyLocation { section: 1, key: 2 });
+/// ```
+pub fn move_cursor(cur: ConfigKeyLocation, section_lens: &[usize], up: bool, wrap: bool) -> ConfigKeyLocation {
+    // Without any keys there's nowhere to move.
+    if section_lens.iter().all(|len| *len == 0) {
+        return cur;
+    }
+
+    let last_section = section_lens.len() - 1;
+    let section = cur.section.min(last_section);
+    let cur = ConfigKeyLocation {
+        section,
+        key: cur.key.min(section_lens[section].saturating_sub(1))
+    };
+    if up && cur.key > 0 {
+        return ConfigKeyLocation { key: cur.key - 1, ..cur };
+    }
+    if !up && cur.key + 1 < section_lens[cur.section] {
+        return ConfigKeyLocation { key: cur.key + 1, ..cur };
+    }
+
+    let mut section = cur.section;
+    loop {
+        section = match (up, section) {
+            (true, 0) if wrap => last_section,
+            (true, 0) => return cur,
+            (true, _) => section - 1,
+            (false, _) if section == last_section && wrap => 0,
+            (false, _) if section == last_section => return cur,
+            (false, _) => section + 1
+        };
+        if section_lens[section] > 0 {
+            let key = if up { section_lens[section] - 1 } else { 0 };
+            return ConfigKeyLocation { section, key };
+        }
+    }
+}
+
+/// Lays out the lines of the interactive editor and
+/// sets the `ypos` of every key to the line it's on.
+/// The header is marked with `*` while there are unsaved changes
+/// and with `(read-only)` if nothing can be edited.
+fn layout_lines(conf: &Config, key_sections: &mut [ConfigKeySection], profile: Option<&str>, dirty: bool, read_only: bool) -> Vec<String> {
+    let mut header = String::from("---===egawari=Configuration===---");
+    if let Some(name) = profile {
+        header.push_str(format!(" ({})", name).as_str());
+    }
+    if read_only {
+        header.push_str(" (read-only)");
+    }
+    if dirty {
+        header.push_str(" *");
+    }
+    let mut lines = vec![header];
+
+    for section in key_sections {
+        lines.push(String::new());
+        lines.push(format!(r"=\[{}\]=", section.name));
+
+        for key in &mut section.keys {
+            key.ypos = lines.len() as i32;
+            let value = key.field.and_then(|f| f.get(conf));
+            let mut line = match &value {
+                Some(val) => format!(" => {} = \x1b[0;39m{}", key.name, shown_value(val)),
+                None => format!(" => \x1b[0;39m{{{{{}}}}}", key.name)
+            };
+            // The changed values show the default "d" resets them to.
+            if let Some(default) = key.field.and_then(|f| f.default_value()).filter(|default| value.is_some() && value.as_ref() != Some(default)) {
+                match default {
+                    ConfigValue::String(val) if val.is_empty() => line.push_str(" (default unset)"),
+                    default => line.push_str(format!(" (default {})", shown_value(&default)).as_str())
+                }
+            }
+            // The invalid values are marked, their problem is on the status line.
+            if key.field.is_some_and(|f| f.problem(conf).is_some()) {
+                line.push_str(format!(" {}!", stdout::palette().error.escape(false)).as_str());
+            }
+            lines.push(line);
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("---===========================---"));
+    lines.push(String::new());
+    if read_only {
+        lines.push(String::from(r#" => Use "Up" and "Down" to move and "Enter" or "q" to exit."#));
+    } else {
+        lines.push(String::from(r#" => Use "Up" and "Down" to move, "Space" to edit, "Delete" to unset, "d" to reset to the default, "u" to undo, "Enter" to exit and "q" to quit without saving."#));
+    }
+    lines
 }
 
-/// The input configuration struct.
-/// 
-/// ## Example
-/// 
-/// ```rust
-/// config::Input {
-///     name: String::from("SynPS/2 Synaptics TouchPad")
-/// }
-/// ```
-#[derive(Serialize, Deserialize)]
-pub struct Input {
-    pub name: String
+/// Returns the value as it's shown in the editor, with the strings quoted.
+fn shown_value(value: &ConfigValue) -> String {
+    match value {
+        ConfigValue::String(val) => quote(val),
+        ConfigValue::Number(val) => format!("{:?}", val),
+        ConfigValue::Float(val) => format!("{:?}", val),
+        ConfigValue::Bool(val) => format!("{:?}", val),
+        ConfigValue::Choice(val) => val.to_string()
+    }
 }
 
-/// The display configuration struct.
-/// 
-/// ## Example
-/// 
-/// ```rust
-/// config::Display {
-///     display: Some(String::from(":0")),
-///     screen: 0
-/// }
-/// ```
-#[derive(Serialize, Deserialize)]
-pub struct Display {
-    pub display: Option<String>,
-    pub screen: u8
+/// What the automatic setup of the input does with the touchpads it found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSetup {
+    /// No device could be the touchpad.
+    NotFound,
+    /// The only touchpad found is used, as it doesn't replace another name.
+    Use(String),
+    /// The touchpad is picked from the ones found, starting at the selected one.
+    Choose {
+        candidates: Vec<String>,
+        selected: usize
+    }
 }
 
-/// Returns the configuration in the config file as struct.
-/// Config file is located at `$CONFIG_DIR/egawari/egawari.toml`
+/// Decides what the automatic setup does with the touchpads found, given the
+/// input names set already. Replacing a name is always confirmed, starting
+/// at the current one if it's among the touchpads found.
 /// 
 /// ## Example
 /// 
 /// ```rust
-/// let conf: config::Config = config::get_config().unwrap();
+/// let candidates = vec![String::from("SynPS/2 Synaptics TouchPad")];
+/// assert_eq!(config::input_setup(&[], candidates), config::InputSetup::Use(String::from("SynPS/2 Synaptics TouchPad")));
 /// ```
-pub fn get_config() -> Result<Config> {
-    let file = config_dir().unwrap().join("egawari").join("egawari.toml");
-
-    match fs::read_to_string(file.as_path()) {
-        Ok(s) => {
-            let config: Config = toml::from_str(s.as_str()).context("Couldn't parse the config file.")?;
-            Ok(config)
-        },
-        Err(_) => {
-            let config = match env::consts::OS {
-                "linux" => Config {
-                    input: Input {
-                        name: String::new()
-                    },
-                    display: Some(Display {
-                        display: Some(":0".to_string()),
-                        screen: 0
-                    })
-                },
-                _ => Config {
-                    input: Input {
-                        name: String::new()
-                    },
-                    display: None
-                }
-            };
-
-            Ok(config)
+pub fn input_setup(names: &[String], candidates: Vec<String>) -> InputSetup {
+    let current = names.first().filter(|name| !name.is_empty());
+    match (current, candidates.len()) {
+        (_, 0) => InputSetup::NotFound,
+        (None, 1) => InputSetup::Use(candidates[0].clone()),
+        (Some(name), 1) if *name == candidates[0] => InputSetup::Use(candidates[0].clone()),
+        _ => {
+            let selected = current.and_then(|name| candidates.iter().position(|candidate| candidate == name)).unwrap_or(0);
+            InputSetup::Choose { candidates, selected }
         }
     }
 }
 
-/// Saves the given config struct to the config file.
-/// Config file is located at `$CONFIG_DIR/egawari/egawari.toml`
+/// Makes the name the first of the input names in priority order,
+/// replacing the first one and keeping the others as fallbacks.
+/// Returns whether the names changed.
 /// 
 /// ## Example
 /// 
 /// ```rust
-/// let conf = config::Config {
-///     input: config::Input {
-///         name: String::new()
-///     },
-///     display: None
-/// };
-/// 
-/// config::save_config(conf).unwrap();
+/// config::use_input_name(&mut conf.input.names, "SynPS/2 Synaptics TouchPad");
 /// ```
-pub fn save_config(config: &Config) -> Result<()> {
-    let dir = config_dir().unwrap().join("egawari");
-    let file = dir.join("egawari.toml");
-    let raw = toml::to_string_pretty(config).context("Couldn't convert the config to TOML.")?;
-    
-    fs::create_dir_all(dir.as_path()).context("Couldn't create the config directory.")?;
-    fs::write(file.as_path(), raw).context("Couldn't write to the config file.")?;
+pub fn use_input_name(names: &mut Vec<String>, name: &str) -> bool {
+    let before = names.clone();
+    match names.first_mut() {
+        Some(first) => *first = name.to_string(),
+        None => names.push(name.to_string())
+    }
+    let mut i = 1;
+    while i < names.len() {
+        if names[i] == name {
+            names.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    *names != before
+}
 
-    Ok(())
+/// Lists the touchpads found by the automatic setup in the window to pick
+/// the one to use, starting at the selected one and showing the name it
+/// replaces. Returns `None` if the list is cancelled with "Esc".
+fn choose_input(window: &pancurses::Window, candidates: &[String], selected: usize, current: Option<&str>) -> Option<String> {
+    let mut lines = vec![
+        String::from("---===egawari=Automatic=Setup===---"),
+        String::new(),
+        String::from(r"=\[Touchpads\]=")
+    ];
+    for candidate in candidates {
+        match current {
+            Some(name) if name == candidate => lines.push(format!(" => \x1b[0;39m{} (current)", quote(candidate))),
+            _ => lines.push(format!(" => \x1b[0;39m{}", quote(candidate)))
+        }
+    }
+    lines.push(String::new());
+    if let Some(name) = current.filter(|name| !name.is_empty()) {
+        lines.push(format!(" => The touchpad replaces the name \x1b[0;39m{}", quote(name)));
+    }
+    lines.push(String::from(r#" => Use "Up" and "Down" to move, "Enter" to use the touchpad and "Esc" to cancel."#));
+
+    let mut selected = selected.min(candidates.len() - 1);
+    let mut scroll = 0;
+    loop {
+        scroll = render(window, &lines, 3 + selected as i32, scroll, None);
+        match window.getch() {
+            Some(pancurses::Input::KeyResize) => {
+                pancurses::resize_term(0, 0);
+                window.clear();
+            },
+            Some(pancurses::Input::KeyUp) => selected = selected.saturating_sub(1),
+            Some(pancurses::Input::KeyDown) => selected = (selected + 1).min(candidates.len() - 1),
+            Some(pancurses::Input::KeyEnter) | Some(pancurses::Input::Character('\n')) => return Some(candidates[selected].clone()),
+            Some(pancurses::Input::Character('\u{1b}')) => return None,
+            _ => ()
+        }
+    }
 }
 
-/// The behaviour of the config key.
-#[derive(PartialEq, Eq)]
-enum ConfigKeyType {
-    Button,
-    String,
-    Number
+/// Shows the problem on the last line of the window until a key is pressed.
+fn show_problem(window: &pancurses::Window, problem: &str) {
+    window.mv(window.get_max_y() - 1, 0);
+    window.clrtoeol();
+    errw!(window, problem);
+    window.refresh();
+    while window.getch().is_none() {}
 }
 
-/// The pointer types of the config keys.
-#[derive(Debug)]
-enum ConfigKeyPointer {
-    String(*mut String),
-    Number(*mut u8)
+/// Asks whether to save the changes on the last line of the window,
+/// returning `None` if the question is cancelled with "Esc".
+/// The question warns if some of the values are invalid.
+fn ask_save(window: &pancurses::Window, invalid: bool) -> Option<bool> {
+    window.mv(window.get_max_y() - 1, 0);
+    window.clrtoeol();
+    if invalid {
+        warnw!(window, r#"Some values are invalid, save changes anyway? (y/n, "Esc" to cancel)"#);
+    } else {
+        colwaddstr!(window, r#" => Save changes? (y/n, "Esc" to cancel)"#);
+    }
+    window.refresh();
+
+    loop {
+        match window.getch() {
+            Some(pancurses::Input::Character('y')) | Some(pancurses::Input::Character('Y')) => return Some(true),
+            Some(pancurses::Input::Character('n')) | Some(pancurses::Input::Character('N')) => return Some(false),
+            Some(pancurses::Input::Character('\u{1b}')) => return None,
+            _ => ()
+        }
+    }
 }
 
-/// Information about the config key.
+/// Returns the scroll offset that keeps the line at `ypos` visible
+/// in a view `height` lines tall showing `total` lines,
+/// moving the previous offset `scroll` as little as possible.
 /// 
 /// ## Example
 /// 
 /// ```rust
-/// let conf: &mut Config = &mut get_config()?;
-/// ConfigKey {
-///     key_type: ConfigKeyType::String,
-///     ptr: Some(ConfigKeyPointer::String(&mut conf.input.name)),
-///     name: "Input Name",
-///     ypos: -1
-/// }
+/// // The cursor moved below the bottom of a 10 lines tall window.
+/// assert_eq!(config::scroll_offset(12, 0, 10, 20), 3);
 /// ```
-struct ConfigKey<'a> {
-    key_type: ConfigKeyType,
-    ptr: Option<ConfigKeyPointer>,
-    name: &'a str,
-    ypos: i32
+pub fn scroll_offset(ypos: i32, scroll: i32, height: i32, total: i32) -> i32 {
+    if total <= height || height <= 0 {
+        return 0;
+    }
+
+    let mut scroll = scroll;
+    if ypos < scroll {
+        scroll = ypos;
+    } else if ypos >= scroll + height {
+        scroll = ypos - height + 1;
+    }
+
+    scroll.max(0).min(total - height)
 }
 
-impl ConfigKey<'_> {
-    fn val_xpos(&self) -> i32 {
-        format!(" => {} = ", self.name).len() as i32
+/// Draws the lines of the editor visible in the window and
+/// the cursor at the line `ypos`, returning the new scroll offset.
+/// The status, like the problem of the value under the cursor,
+/// is drawn on the last line of the window.
+fn render(window: &pancurses::Window, lines: &[String], ypos: i32, scroll: i32, status: Option<&str>) -> i32 {
+    let height = window.get_max_y() - status.is_some() as i32;
+    let scroll = scroll_offset(ypos, scroll, height, lines.len() as i32);
+
+    window.erase();
+    for (i, line) in lines.iter().enumerate() {
+        let y = i as i32 - scroll;
+        if y >= 0 && y < height {
+            colwmvaddstr!(window, y, 0, line.as_str());
+        }
+    }
+
+    window.attroff(pancurses::A_BOLD);
+    stdout::curses_color(window, 5);
+    window.mvaddstr(ypos - scroll, 0, " >> ");
+    window.attron(pancurses::A_BOLD);
+
+    if let Some(status) = status {
+        window.mv(height, 0);
+        errw!(window, status);
     }
+
+    window.mv(0, 0);
+    window.refresh();
+    scroll
 }
 
-/// Config section.
-/// 
-/// ## Example
-/// 
-/// ```rust
-/// let conf: &mut Config = &mut get_config()?;
-/// 
-/// ConfigKeySection {
-///     name: "Input",
-///     keys: vec![
-///         ConfigKey {
-///             key_type: ConfigKeyType::String,
-///             ptr: Some(ConfigKeyPointer::String(&mut conf.input.name)),
-///             name: "Input Name",
-///             ypos: -1
-///         }
-///     ]
-/// }
-/// ```
-struct ConfigKeySection<'a> {
-    name: &'a str,
-    keys: Vec<ConfigKey<'a>>
+/// Returns the number of keys in each section.
+fn section_lens(key_sections: &[ConfigKeySection]) -> Vec<usize> {
+    key_sections.iter().map(|section| section.keys.len()).collect()
 }
 
-/// The location of the config key.
-/// 
-/// ## Example
-/// 
-/// ```rust
-/// ConfigKeyLocation {
-///     section: 0,
-///     key: 0
-/// }
-/// ```
-struct ConfigKeyLocation {
-    section: usize,
-    key: usize
+/// Returns the editor section of the input, with a key
+/// for each of the names in priority order.
+fn input_section(conf: &Config) -> ConfigKeySection<'static> {
+    let mut keys = vec![
+        ConfigKey {
+            key_type: ConfigKeyType::Button(ConfigButton::AutomaticSetup),
+            field: None,
+            name: "Automatic Setup",
+            ypos: -1
+        }
+    ];
+
+    for i in 0..conf.input.names.len() {
+        keys.push(ConfigKey {
+            key_type: ConfigKeyType::String,
+            field: Some(ConfigField::InputName(i)),
+            name: "Name",
+            ypos: -1
+        });
+    }
+
+    keys.push(ConfigKey {
+        key_type: ConfigKeyType::Button(ConfigButton::AddInput),
+        field: None,
+        name: "Add Name",
+        ypos: -1
+    });
+    keys.push(ConfigKey {
+        key_type: ConfigKeyType::String,
+        field: Some(ConfigField::InputId),
+        name: "ID",
+        ypos: -1
+    });
+    keys.push(ConfigKey {
+        key_type: ConfigKeyType::String,
+        field: Some(ConfigField::InputPath),
+        name: "Path",
+        ypos: -1
+    });
+    keys.push(ConfigKey {
+        key_type: ConfigKeyType::Bool,
+        field: Some(ConfigField::InputGrab),
+        name: "Grab",
+        ypos: -1
+    });
+
+    ConfigKeySection {
+        name: "Input",
+        keys
+    }
 }
 
-/// Edit the config keys and values interactively using curses.
-/// Automatically loads and saves the config.
-/// 
-/// ## Example
-/// 
-/// ```rust
-/// config::config_interactive();
-/// ```
-pub fn config_interactive() -> Result<()> {
-    let conf: &mut Config = &mut get_config()?;
-    let mut key_sections: Vec<ConfigKeySection> = vec![
-        ConfigKeySection {
-            name: "Input",
+/// Returns the editor section of the display, with buttons
+/// to add the section or the display when they're unset.
+fn display_section(conf: &Config) -> ConfigKeySection<'static> {
+    let display = match &conf.display {
+        Some(display) => display,
+        None => return ConfigKeySection {
+            name: "Display",
             keys: vec![
                 ConfigKey {
-                    key_type: ConfigKeyType::Button,
-                    ptr: None,
-                    name: "Automatic Setup",
-                    ypos: -1
-                },
-                ConfigKey {
-                    key_type: ConfigKeyType::String,
-                    ptr: Some(ConfigKeyPointer::String(&mut conf.input.name)),
-                    name: "Name",
+                    key_type: ConfigKeyType::Button(ConfigButton::AddDisplay),
+                    field: None,
+                    name: "Add Display",
                     ypos: -1
                 }
             ]
         }
+    };
+
+    let mut keys = vec![
+        ConfigKey {
+            key_type: ConfigKeyType::Button(ConfigButton::AutomaticSetup),
+            field: None,
+            name: "Automatic Setup",
+            ypos: -1
+        }
     ];
 
-    if let Some(display) = &mut conf.display {
-        let mut arr: Vec<ConfigKey> = vec![
+    keys.push(match display.display {
+        Some(_) => ConfigKey {
+            key_type: ConfigKeyType::String,
+            field: Some(ConfigField::DisplayDisplay),
+            name: "Display",
+            ypos: -1
+        },
+        None => ConfigKey {
+            key_type: ConfigKeyType::Button(ConfigButton::SetDisplay),
+            field: None,
+            name: "Set Display",
+            ypos: -1
+        }
+    });
+
+    keys.push(ConfigKey {
+        key_type: ConfigKeyType::Number,
+        field: Some(ConfigField::DisplayScreen),
+        name: "Screen",
+        ypos: -1
+    });
+
+    ConfigKeySection {
+        name: "Display",
+        keys
+    }
+}
+
+/// Returns the sections of the interactive editor for the config.
+/// The input and the display sections come first,
+/// as they're rebuilt when their keys change.
+fn editor_sections(conf: &Config) -> Vec<ConfigKeySection<'static>> {
+    let mut key_sections = vec![input_section(conf), display_section(conf)];
+
+    key_sections.push(ConfigKeySection {
+        name: "Tablet",
+        keys: vec![
+            ConfigKey {
+                key_type: ConfigKeyType::Bool,
+                field: Some(ConfigField::TabletStartPaused),
+                name: "Start Paused",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Bool,
+                field: Some(ConfigField::TabletHoverOnLift),
+                name: "Hover On Lift",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Number,
+                field: Some(ConfigField::TabletReportRate),
+                name: "Report Rate",
+                ypos: -1
+            },
             ConfigKey {
-                key_type: ConfigKeyType::Button,
-                ptr: None,
-                name: "Automatic Setup",
+                key_type: ConfigKeyType::Bool,
+                field: Some(ConfigField::TabletPassthroughGestures),
+                name: "Passthrough Gestures",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Bool,
+                field: Some(ConfigField::TabletHideCursor),
+                name: "Hide Cursor",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Number,
+                field: Some(ConfigField::TabletTouchSettleMs),
+                name: "Touch Settle (ms)",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Number,
+                field: Some(ConfigField::TabletTouchThreshold),
+                name: "Touch Threshold",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::String,
+                field: Some(ConfigField::TabletVirtualName),
+                name: "Virtual Name",
                 ypos: -1
             }
-        ];
+        ]
+    });
 
-        if let Some(dp) = &mut display.display {
-            arr.push(ConfigKey {
-                key_type: ConfigKeyType::String,
-                ptr: Some(ConfigKeyPointer::String(dp)),
-                name: "Display",
+    key_sections.push(ConfigKeySection {
+        name: "Mapping",
+        keys: vec![
+            ConfigKey {
+                key_type: ConfigKeyType::Cycle,
+                field: Some(ConfigField::MappingMode),
+                name: "Mode",
                 ypos: -1
-            });
-        }
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Bool,
+                field: Some(ConfigField::MappingFlipX),
+                name: "Flip X",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Bool,
+                field: Some(ConfigField::MappingFlipY),
+                name: "Flip Y",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Float,
+                field: Some(ConfigField::MappingScaleX),
+                name: "Scale X",
+                ypos: -1
+            },
+            ConfigKey {
+                key_type: ConfigKeyType::Float,
+                field: Some(ConfigField::MappingScaleY),
+                name: "Scale Y",
+                ypos: -1
+            }
+        ]
+    });
 
-        arr.push(ConfigKey {
-            key_type: ConfigKeyType::Number,
-            ptr: Some(ConfigKeyPointer::Number(&mut display.screen)),
-            name: "Screen",
-            ypos: -1
-        });
+    key_sections
+}
 
-        key_sections.push(ConfigKeySection {
-            name: "Display",
-            keys: arr
-        });
-    }
+/// Returns the number of keys in each section of the interactive editor
+/// for the config, which the cursor moves across.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let lens = config::editor_section_lens(&conf);
+/// let cur = config::move_cursor(cur, &lens, false, true);
+/// ```
+pub fn editor_section_lens(conf: &Config) -> Vec<usize> {
+    section_lens(&editor_sections(conf))
+}
+
+/// Edit the config keys and values interactively using curses.
+/// Automatically loads the config and saves it on exit if it was changed,
+/// unless the editor is quit without saving or opened read-only.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// config::config_interactive(config::EditorOptions::default());
+/// ```
+pub fn config_interactive(options: EditorOptions) -> Result<()> {
+    let conf: &mut Config = &mut get_file_config()?;
+    let file = config_path()?;
+    let recovered = !options.read_only && recover_autosave(conf, file.as_path())?;
+    let mut key_sections = editor_sections(conf);
 
     let window = init_curses_wcolors();
     window.keypad(true);
     pancurses::noecho();
-    colwln!(&window, "---===egawari=Configuration===---");
+    if options.autosave_secs > 0 {
+        // Wakes up even without a key, so the changes are autosaved on time.
+        window.timeout(1000);
+    }
 
     let mut cur = ConfigKeyLocation {
         section: 0,
         key: 0
     };
     let mut edit = false;
+    let mut dirty = recovered;
+    let mut save = false;
+    let mut rebuild = false;
+    let mut scroll = 0;
+    let mut undo = UndoStack::default();
+    let mut autosaved = toml::to_string(conf).ok();
+    let mut autosaved_at = Instant::now();
 
-    let mut line_buf = 1;
-    for section in &mut key_sections {
-        window.printw("\n");
-        colwln!(&window, r"=\[{}\]=", section.name);
-        line_buf += 2;
-
-        for mut key in &mut section.keys {
-            if key.key_type == ConfigKeyType::Button {
-                colwln!(&window, " => \x1b[0;39m{{{{{}}}}}", key.name);
-            } else {
-                match key.ptr.as_ref().unwrap() {
-                    ConfigKeyPointer::String(val) => unsafe {
-                        colwln!(&window, " => {} = \x1b[0;39m{:?}", key.name, **val);
-                    },
-                    ConfigKeyPointer::Number(val) => unsafe {
-                        colwln!(&window, " => {} = \x1b[0;39m{:?}", key.name, **val);
-                    }
-                }
+    let profile = active_profile();
+    let mut buf = String::new();
+    loop {
+        if rebuild {
+            // The keys move, so the locations of the changes are stale.
+            undo.clear();
+            key_sections[0] = input_section(conf);
+            key_sections[1] = display_section(conf);
+            if cur.section <= 1 {
+                cur.key = cur.key.min(key_sections[cur.section].keys.len() - 1);
             }
-
-            key.ypos = line_buf;
-            line_buf += 1;
+            rebuild = false;
         }
-    }
-
-    window.printw("\n");
-    colwln!(&window, "---===========================---");
-    window.printw("\n");
-    logwln!(&window, r#"Use "Up" and "Down" to move, "Space" to edit and "Enter" to exit."#);
 
-    let mut buf = String::new();
-    loop {
+        let lines = layout_lines(conf, &mut key_sections, profile.as_deref(), dirty, options.read_only);
         let cur_key = &key_sections[cur.section].keys[cur.key];
         let mut cur_val_str = String::new();
-        if cur_key.key_type != ConfigKeyType::Button {
-            cur_val_str = match cur_key.ptr.as_ref().unwrap() {
-                ConfigKeyPointer::String(val) => unsafe {
-                    format!("{}", &**val)
-                },
-                ConfigKeyPointer::Number(val) => unsafe {
-                    format!("{}", **val)
-                }
-            };
+        if !matches!(cur_key.key_type, ConfigKeyType::Button(_)) {
+            cur_val_str = cur_key.field.unwrap().get(conf).unwrap().to_string();
         }
+        let problem = cur_key.field.and_then(|field| field.problem(conf));
 
         if !edit {
-            for section in &key_sections {
-                for key in &section.keys {
-                    colwmvaddstr!(&window, key.ypos, 0, " => ");
-                }
-            }
-
-            window.attroff(pancurses::A_BOLD);
-            window.attron(pancurses::ColorPair(5));
-            window.mvaddstr(cur_key.ypos, 0, " >> ");
-            window.attron(pancurses::A_BOLD);
-
-            window.mv(0, 0);
-            window.refresh();
+            scroll = render(&window, &lines, cur_key.ypos, scroll, problem.as_deref());
         }
 
         match window.getch() {
+            Some(pancurses::Input::KeyResize) => {
+                pancurses::resize_term(0, 0);
+                window.clear();
+                scroll = render(&window, &lines, cur_key.ypos, scroll, problem.as_deref());
+            },
             Some(pancurses::Input::Character('\u{1b}')) => {
                 edit = false;
             },
             Some(pancurses::Input::KeyEnter) | Some(pancurses::Input::Character('\n')) => {
                 if !edit {
-                    break;
+                    if !dirty {
+                        break;
+                    }
+                    let invalid = !conf.validate().is_empty() || key_sections.iter()
+                        .flat_map(|section| section.keys.iter())
+                        .any(|key| key.field.is_some_and(|field| field.problem(conf).is_some()));
+                    if let Some(answer) = ask_save(&window, invalid) {
+                        save = answer;
+                        break;
+                    }
                 } else {
-                    match cur_key.ptr.as_ref().unwrap() {
-                        ConfigKeyPointer::String(ptr) => unsafe {
-                            **ptr = buf.clone();
-                        },
-                        ConfigKeyPointer::Number(ptr) => unsafe {
-                            let digits: String = buf.clone().chars().filter(|c| c.is_digit(10)).collect();
-                            **ptr = digits.parse::<u8>().unwrap();
+                    // The keystrokes are checked as they're typed, so only a value with
+                    // a problem shown on the status line is left unset.
+                    let value = match cur_key.key_type {
+                        _ if edit_problem(&cur_key.key_type, cur_key.field, &buf).is_some() => None,
+                        ConfigKeyType::Number => buf.parse::<u32>().ok().map(ConfigValue::Number),
+                        ConfigKeyType::Float => buf.parse::<f32>().ok().map(ConfigValue::Float),
+                        _ => Some(ConfigValue::String(buf.clone()))
+                    };
+                    if let Some(value) = value {
+                        let field = cur_key.field.unwrap();
+                        let before = field.get(conf);
+                        field.set(conf, value);
+                        if let Some(before) = before.filter(|before| field.get(conf).as_ref() != Some(before)) {
+                            undo.push(cur, field, before);
+                            dirty = true;
                         }
                     }
                     edit = false;
                 }
             },
-            Some(pancurses::Input::Character(' ')) => {
+            Some(pancurses::Input::Character(' ')) if !options.read_only => {
                 if !edit {
-                    if cur_key.key_type == ConfigKeyType::Button {
+                    if cur_key.key_type == ConfigKeyType::Button(ConfigButton::AutomaticSetup) && cur.section == 0 {
+                        let candidates = device::touchpad_candidates(&device::list_devices(), conf.tablet.virtual_name.as_str());
+                        let name = match input_setup(&conf.input.names, candidates) {
+                            InputSetup::NotFound => {
+                                show_problem(&window, "No touchpad found, check the permissions of /dev/input.");
+                                None
+                            },
+                            InputSetup::Use(name) => Some(name),
+                            InputSetup::Choose { candidates, selected } => {
+                                choose_input(&window, &candidates, selected, conf.input.names.first().map(String::as_str))
+                            }
+                        };
+                        if let Some(name) = name {
+                            if use_input_name(&mut conf.input.names, name.as_str()) {
+                                cur.key = 1;
+                                rebuild = true;
+                                dirty = true;
+                            }
+                        }
+                    } else if cur_key.key_type == ConfigKeyType::Button(ConfigButton::AutomaticSetup) {
                         // TODO: Initialize auto setup.
+                    } else if cur_key.key_type == ConfigKeyType::Button(ConfigButton::AddInput) {
+                        conf.input.names.push(String::new());
+                        cur.key = conf.input.names.len();
+                        rebuild = true;
+                        dirty = true;
+                    } else if cur_key.key_type == ConfigKeyType::Button(ConfigButton::AddDisplay) {
+                        conf.display = Some(Display { display: None, screen: 0 });
+                        cur.key = 1;
+                        rebuild = true;
+                        dirty = true;
+                    } else if cur_key.key_type == ConfigKeyType::Button(ConfigButton::SetDisplay) {
+                        if let Some(display) = &mut conf.display {
+                            display.display = Some(String::new());
+                        }
+                        rebuild = true;
+                        dirty = true;
+                    } else if cur_key.key_type == ConfigKeyType::Cycle {
+                        let field = cur_key.field.unwrap();
+                        if let Some(before) = field.get(conf) {
+                            field.cycle(conf);
+                            undo.push(cur, field, before);
+                            dirty = true;
+                        }
+                    } else if let Some(ConfigValue::Bool(val)) = cur_key.field.unwrap().get(conf) {
+                        cur_key.field.unwrap().set(conf, ConfigValue::Bool(!val));
+                        undo.push(cur, cur_key.field.unwrap(), ConfigValue::Bool(val));
+                        dirty = true;
                     } else {
                         edit = true;
                         buf = cur_val_str.clone();
                     }
-                } else {
+                } else if accepts_char(&cur_key.key_type, &buf, ' ') {
                     buf.push(' ');
+                } else {
+                    pancurses::beep();
                 }
             },
-            Some(pancurses::Input::KeyUp) => {
-                if !edit {
-                    if cur.key == 0 {
-                        if cur.section == 0 {
-                            cur.section = key_sections.len() - 1;
-                        } else {
-                            cur.section -= 1;
-                        }
-
-                        cur.key = key_sections[cur.section].keys.len() - 1;
-                    } else {
-                        cur.key -= 1;
-                    }
+            Some(pancurses::Input::Character('q')) if !edit => {
+                break;
+            },
+            // Ctrl-Z suspends the terminal, so undo is on "u" like in vi.
+            Some(pancurses::Input::Character('u')) if !edit && !options.read_only => {
+                if let Some(location) = undo.undo(conf) {
+                    cur = location;
                 }
             },
-            Some(pancurses::Input::KeyDown) => {
-                if !edit {
-                    if cur.key == key_sections[cur.section].keys.len() - 1 {
-                        if cur.section == key_sections.len() - 1 {
-                            cur.section = 0;
-                        } else {
-                            cur.section += 1;
-                        }
-
-                        cur.key = 0;
-                    } else {
-                        cur.key += 1;
+            Some(pancurses::Input::Character('d')) if !edit && !options.read_only => {
+                if let Some(field) = cur_key.field {
+                    if let Some(before) = field.reset(conf) {
+                        undo.push(cur, field, before);
+                        dirty = true;
                     }
                 }
             },
+            Some(pancurses::Input::KeyDC) if !edit && !options.read_only => {
+                let unset = cur_key.field.is_some_and(|field| field.unset(conf));
+                rebuild |= unset;
+                dirty |= unset;
+            },
+            Some(pancurses::Input::KeyUp) if !edit => {
+                cur = move_cursor(cur, &section_lens(&key_sections), true, options.wrap);
+            },
+            Some(pancurses::Input::KeyDown) if !edit => {
+                cur = move_cursor(cur, &section_lens(&key_sections), false, options.wrap);
+            },
             Some(pancurses::Input::KeyBackspace) | Some(pancurses::Input::Character('\u{7f}')) => {
                 buf.pop();
             },
+            Some(pancurses::Input::Character(c)) if edit && !accepts_char(&cur_key.key_type, &buf, c) => {
+                pancurses::beep();
+            },
             Some(pancurses::Input::Character(c)) => {
                 buf.push(c);
             }
@@ -427,22 +3868,46 @@ pub fn config_interactive() -> Result<()> {
         }
 
         if edit {
-            window.mv(cur_key.ypos, cur_key.val_xpos());
+            scroll = render(&window, &lines, cur_key.ypos, scroll, edit_problem(&cur_key.key_type, cur_key.field, &buf).as_deref());
+            let y = cur_key.ypos - scroll;
+            window.mv(y, cur_key.val_xpos());
             window.clrtoeol();
-            match cur_key.key_type {
-                ConfigKeyType::String => {
-                    colwaddstr!(&window, "\x1b[0;39m{:?}", &buf);
-                    window.mv(window.get_cur_y(), window.get_cur_x() - 1);
-                },
-                _ => {
-                    colwaddstr!(&window, "\x1b[0;39m{}", &buf);
-                }
+            let quoted = cur_key.key_type == ConfigKeyType::String;
+            if quoted {
+                colwaddstr!(&window, "\x1b[0;39m{}", quote(&buf));
+            } else {
+                colwaddstr!(&window, "\x1b[0;39m{}", &buf);
+            }
+            window.mv(y, edit_column(cur_key.name, buf.as_str(), quoted));
+        }
+
+        if dirty && !options.read_only && autosaved_at.elapsed().as_secs() >= options.autosave_secs {
+            let raw = toml::to_string(conf).ok();
+            // A failed autosave is retried, the real save reports its errors.
+            if raw != autosaved && write_config(autosave_path(file.as_path()).as_path(), conf).is_ok() {
+                autosaved = raw;
             }
+            autosaved_at = Instant::now();
         }
     }
 
     pancurses::endwin();
-    save_config(conf)?;
-    successln!("Successfully saved the configuration.");
+    conf.input.names.retain(|name| !name.is_empty());
+    if let Some(display) = &mut conf.display {
+        display.display = display.display.take().filter(|dp| !dp.is_empty());
+    }
+    if options.read_only {
+        logln!("Closed the configuration without saving, as it was opened with --no-save.");
+    } else if save {
+        save_config(conf)?;
+        remove_autosave(file.as_path())?;
+        successln!("Successfully saved the configuration.");
+    } else if dirty {
+        remove_autosave(file.as_path())?;
+        warnln!("Discarded the changes to the configuration.");
+    } else {
+        remove_autosave(file.as_path())?;
+        logln!("No changes to save.");
+    }
     Ok(())
 }
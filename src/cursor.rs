@@ -0,0 +1,152 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::env;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_ulong, c_void};
+use std::ptr;
+use anyhow::{anyhow, Result};
+
+/// The Xlib library the display connection is opened with.
+pub const X11_LIBRARY: &[u8] = b"libX11.so.6\0";
+/// The XFixes library hiding and showing the cursor.
+const XFIXES_LIBRARY: &[u8] = b"libXfixes.so.3\0";
+
+type OpenDisplay = unsafe extern "C" fn(*const c_char) -> *mut c_void;
+type RootWindow = unsafe extern "C" fn(*mut c_void) -> c_ulong;
+type DisplayCall = unsafe extern "C" fn(*mut c_void) -> c_int;
+type CursorCall = unsafe extern "C" fn(*mut c_void, c_ulong);
+
+/// The X11 cursor hidden with XFixes, shown again when it's dropped.
+/// The X server shows it again by itself if egawari dies without dropping it,
+/// as the hiding only lasts while the display connection is open.
+/// The libraries are loaded at runtime and stay loaded, so egawari
+/// doesn't need them unless the cursor is hidden.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let cursor = cursor::HiddenCursor::hide(None)?;
+/// // Drawing without the cursor.
+/// drop(cursor);
+/// ```
+pub struct HiddenCursor {
+    display: *mut c_void,
+    root: c_ulong,
+    show: CursorCall,
+    flush: DisplayCall,
+    close: DisplayCall
+}
+
+impl HiddenCursor {
+    /// Hides the cursor on the display of the environment, or on the given
+    /// display if the environment doesn't have one. Fails on Wayland,
+    /// where the compositor decides whether the cursor is shown.
+    pub fn hide(display: Option<&str>) -> Result<HiddenCursor> {
+        if wayland() {
+            return Err(anyhow!("The cursor can't be hidden on Wayland, the compositor decides whether it's shown."));
+        }
+
+        let name = match env::var_os("DISPLAY") {
+            Some(_) => None,
+            None => display.map(CString::new).transpose()?
+        };
+        unsafe {
+            let x11 = open(X11_LIBRARY)?;
+            let xfixes = open(XFIXES_LIBRARY)?;
+            let open_display: OpenDisplay = symbol(x11, b"XOpenDisplay\0")?;
+            let root_window: RootWindow = symbol(x11, b"XDefaultRootWindow\0")?;
+            let flush: DisplayCall = symbol(x11, b"XFlush\0")?;
+            let close: DisplayCall = symbol(x11, b"XCloseDisplay\0")?;
+            let hide: CursorCall = symbol(xfixes, b"XFixesHideCursor\0")?;
+            let show: CursorCall = symbol(xfixes, b"XFixesShowCursor\0")?;
+
+            let display = open_display(name.as_ref().map_or(ptr::null(), |name| name.as_ptr()));
+            if display.is_null() {
+                return Err(anyhow!("Couldn't open the X display."));
+            }
+            let root = root_window(display);
+            hide(display, root);
+            flush(display);
+            Ok(HiddenCursor { display, root, show, flush, close })
+        }
+    }
+}
+
+impl Drop for HiddenCursor {
+    fn drop(&mut self) {
+        unsafe {
+            (self.show)(self.display, self.root);
+            (self.flush)(self.display);
+            (self.close)(self.display);
+        }
+    }
+}
+
+/// Returns whether the session runs on Wayland.
+pub fn wayland() -> bool {
+    env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// Returns why the cursor can't be hidden, if it can't.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(problem) = cursor::problem() {
+///     warnln!("{}", problem);
+/// }
+/// ```
+pub fn problem() -> Option<&'static str> {
+    if wayland() {
+        return Some("On Wayland the compositor decides whether the cursor is shown, so egawari can't hide it.");
+    }
+
+    let loaded = unsafe { open(X11_LIBRARY).and_then(|_| open(XFIXES_LIBRARY)) };
+    match loaded {
+        Ok(_) => None,
+        Err(_) => Some("Hiding the cursor needs the X11 libraries libX11 and libXfixes.")
+    }
+}
+
+/// Loads the shared library with the nul-terminated name.
+/// 
+/// # Safety
+/// 
+/// The library runs its initializers when it's loaded.
+pub unsafe fn open(name: &[u8]) -> Result<*mut c_void> {
+    let library = libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_NOW | libc::RTLD_LOCAL);
+    if library.is_null() {
+        return Err(anyhow!("Couldn't load {}.", String::from_utf8_lossy(&name[..name.len() - 1])));
+    }
+
+    Ok(library)
+}
+
+/// Looks up the function with the nul-terminated name in the library.
+/// 
+/// # Safety
+/// 
+/// The function has to have the type `T`.
+pub unsafe fn symbol<T: Copy>(library: *mut c_void, name: &[u8]) -> Result<T> {
+    let function = libc::dlsym(library, name.as_ptr() as *const c_char);
+    if function.is_null() {
+        return Err(anyhow!("Couldn't find {}.", String::from_utf8_lossy(&name[..name.len() - 1])));
+    }
+
+    Ok(std::mem::transmute_copy(&function))
+}
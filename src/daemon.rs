@@ -0,0 +1,618 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::{env, fs, process, thread};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Result};
+use dirs::runtime_dir;
+use serde::{Serialize, Deserialize};
+
+use crate::stats::Summary;
+use crate::stdout;
+
+/// Set by the `SIGUSR1` handler when the mapping should be toggled.
+static TOGGLE: AtomicBool = AtomicBool::new(false);
+
+/// Set by the `SIGINT` and `SIGTERM` handler when the daemon should stop.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// How long the daemon waits for the rest of a request
+/// and the client waits for the reply.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The longest request line the daemon reads.
+const REQUEST_LIMIT: u64 = 256;
+
+/// The state of the running daemon.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Active,
+    Paused
+}
+
+impl State {
+    /// Returns the state as it's written to the state file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            State::Active => "active",
+            State::Paused => "paused"
+        }
+    }
+
+    /// Parses the state as it's written to the state file.
+    pub fn parse(s: &str) -> Option<State> {
+        match s.trim() {
+            "active" => Some(State::Active),
+            "paused" => Some(State::Paused),
+            _ => None
+        }
+    }
+}
+
+/// Returns the directory the runtime files are kept in.
+/// Located at `$XDG_RUNTIME_DIR`, or the temporary directory if it's unset.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let pid = daemon::runtime_path().join("egawari.pid");
+/// ```
+pub fn runtime_path() -> PathBuf {
+    runtime_dir().unwrap_or_else(env::temp_dir)
+}
+
+/// Returns the path of the PID file of the running daemon.
+pub fn pid_path() -> PathBuf {
+    runtime_path().join("egawari.pid")
+}
+
+/// Returns the path of the file holding the state of the running daemon.
+pub fn state_path() -> PathBuf {
+    runtime_path().join("egawari.state")
+}
+
+/// Returns the path of the file holding the name of the device
+/// the running daemon is bound to.
+pub fn device_path() -> PathBuf {
+    runtime_path().join("egawari.device")
+}
+
+/// Returns the path of the socket the running daemon accepts requests on.
+pub fn socket_path() -> PathBuf {
+    runtime_path().join("egawari.sock")
+}
+
+/// Returns the PID of the running daemon, if there's one.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(pid) = daemon::running_pid() {
+///     println!("{}", pid);
+/// }
+/// ```
+pub fn running_pid() -> Option<i32> {
+    live_pid(&pid_path())
+}
+
+/// Returns the PID in the PID file at the path if that process is alive.
+fn live_pid(path: &Path) -> Option<i32> {
+    let pid: i32 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    if pid > 0 && unsafe { libc::kill(pid, 0) } == 0 {
+        Some(pid)
+    } else {
+        None
+    }
+}
+
+/// Returns the PID of another running instance, from the PID file at the path
+/// if that process is alive, or else as reported by a daemon answering
+/// on the socket at the path, like after its PID file was removed.
+/// The current process isn't another instance.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(pid) = daemon::other_instance(&daemon::pid_path(), &daemon::socket_path()) {
+///     println!("egawari is already running with the PID: {}", pid);
+/// }
+/// ```
+pub fn other_instance(pid_file: &Path, socket: &Path) -> Option<i32> {
+    let current = process::id() as i32;
+    live_pid(pid_file)
+        .or_else(|| request_at(socket, Request::Status).ok()?.status?.pid)
+        .filter(|pid| *pid != current)
+}
+
+/// Returns the state of the running daemon, if there's one.
+pub fn running_state() -> Option<State> {
+    running_pid()?;
+    State::parse(fs::read_to_string(state_path()).ok()?.as_str())
+}
+
+/// Returns the name of the device the running daemon is bound to, if there's one.
+pub fn running_device() -> Option<String> {
+    running_pid()?;
+    fs::read_to_string(device_path()).ok()
+}
+
+/// Writes the PID, the state and the bound device
+/// of the current process as the running daemon.
+pub fn write_runtime_files(state: State, device: &str) -> Result<()> {
+    fs::write(pid_path(), process::id().to_string()).context("Couldn't write the PID file.")?;
+    fs::write(device_path(), device).context("Couldn't write the device file.")?;
+    write_state(state)
+}
+
+/// Writes the state of the running daemon.
+pub fn write_state(state: State) -> Result<()> {
+    fs::write(state_path(), state.as_str()).context("Couldn't write the state file.")?;
+
+    Ok(())
+}
+
+/// Returns the paths of the runtime files of the daemon.
+pub fn runtime_files() -> Vec<PathBuf> {
+    vec![pid_path(), state_path(), device_path(), socket_path()]
+}
+
+/// Removes the runtime files of the running daemon.
+pub fn remove_runtime_files() {
+    for path in runtime_files() {
+        fs::remove_file(path).ok();
+    }
+}
+
+extern "C" fn handle_toggle(_: libc::c_int) {
+    TOGGLE.store(true, Ordering::SeqCst);
+}
+
+/// Installs the `SIGUSR1` handler requesting a toggle.
+/// Blocking reads are interrupted rather than restarted,
+/// so the request is noticed without waiting for an event.
+pub fn install_toggle_handler() -> Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_toggle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        if libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Couldn't install the signal handler.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns whether a toggle was requested since the last call.
+pub fn take_toggle() -> bool {
+    TOGGLE.swap(false, Ordering::SeqCst)
+}
+
+extern "C" fn handle_stop(_: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+/// Installs the `SIGINT` and `SIGTERM` handler requesting a stop,
+/// so the daemon can release its devices before exiting.
+pub fn install_stop_handler() -> Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(std::io::Error::last_os_error()).context("Couldn't install the signal handler.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Requests a stop after the number of seconds with `SIGALRM`,
+/// handled like `SIGINT` and `SIGTERM` so the daemon stops the same way.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// daemon::stop_after(60)?;
+/// ```
+pub fn stop_after(seconds: u32) -> Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        // The other calls carry on, the wait of the event loop is interrupted anyway.
+        action.sa_flags = libc::SA_RESTART;
+        if libc::sigaction(libc::SIGALRM, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Couldn't install the signal handler.");
+        }
+        libc::alarm(seconds);
+    }
+
+    Ok(())
+}
+
+/// Returns whether a stop was requested.
+pub fn stop_requested() -> bool {
+    STOP.load(Ordering::SeqCst)
+}
+
+/// Forgets the stop requested, so a test requesting one
+/// doesn't stop the loops of the tests after it.
+#[cfg(test)]
+pub fn clear_stop() {
+    STOP.store(false, Ordering::SeqCst);
+}
+
+/// A request to the running daemon. The protocol is a line with
+/// the name of the request sent to the socket, answered by a line
+/// with the `Reply` as JSON, after which the connection is closed.
+/// 
+/// ## Example
+/// 
+/// ```sh
+/// $ echo pause | nc -U "$XDG_RUNTIME_DIR/egawari.sock"
+/// {"ok":true,"error":null,"status":{"running":true,"pid":1234,"state":"paused","device":"SynPS/2 Synaptics TouchPad"}}
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    Status,
+    Pause,
+    Resume,
+    Toggle,
+    Reload
+}
+
+impl Request {
+    /// Parses the request line, ignoring the surrounding whitespace.
+    pub fn parse(s: &str) -> Option<Request> {
+        match s.trim() {
+            "status" => Some(Request::Status),
+            "pause" => Some(Request::Pause),
+            "resume" => Some(Request::Resume),
+            "toggle" => Some(Request::Toggle),
+            "reload" => Some(Request::Reload),
+            _ => None
+        }
+    }
+
+    /// Returns the request as it's sent to the socket.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Request::Status => "status",
+            Request::Pause => "pause",
+            Request::Resume => "resume",
+            Request::Toggle => "toggle",
+            Request::Reload => "reload"
+        }
+    }
+}
+
+/// The answer of the running daemon to a request,
+/// with its status after handling it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// daemon::Reply {
+///     ok: false,
+///     error: Some(String::from("Unknown request: stop")),
+///     status: None
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub ok: bool,
+    pub error: Option<String>,
+    pub status: Option<Status>
+}
+
+/// The socket the running daemon accepts the requests on.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let server = daemon::Server::bind(&daemon::socket_path())?;
+/// if let Some((request, client)) = server.accept() {
+///     client.reply(&daemon::Reply { ok: true, error: None, status: Some(daemon::status_info()) });
+/// }
+/// ```
+pub struct Server {
+    listener: UnixListener,
+    requests: Receiver<(Request, Client)>,
+    /// Readable while requests are waiting, a byte for each of them.
+    wake: UnixStream,
+    closed: Arc<AtomicBool>
+}
+
+/// A connection the request came from, to send the reply to.
+pub struct Client {
+    stream: UnixStream
+}
+
+impl Server {
+    /// Listens on the socket at the path, replacing a socket left behind.
+    pub fn bind(path: &Path) -> Result<Server> {
+        if path.exists() {
+            fs::remove_file(path).with_context(|| format!("Couldn't remove the old socket: {}", path.display()))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Couldn't create the socket: {}", path.display()))?;
+        let (wake, woken) = UnixStream::pair().context("Couldn't set up the socket.")?;
+        wake.set_nonblocking(true).context("Couldn't set up the socket.")?;
+
+        // The requests are read on a thread of their own, so a slow client
+        // never holds up the events of the touchpad.
+        let (sender, requests) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        let accepting = listener.try_clone().context("Couldn't set up the socket.")?;
+        let accept_closed = closed.clone();
+        thread::spawn(move || accept_requests(accepting, sender, woken, accept_closed));
+
+        Ok(Server { listener, requests, wake, closed })
+    }
+
+    /// Returns the file descriptor to wait for the requests on.
+    pub fn fd(&self) -> RawFd {
+        self.wake.as_raw_fd()
+    }
+
+    /// Returns a request read from a connection with its client,
+    /// or `None` if there's none waiting. Invalid requests are answered
+    /// with an error before they get here.
+    pub fn accept(&self) -> Option<(Request, Client)> {
+        let _ = (&self.wake).read(&mut [0]);
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for Server {
+    /// Stops accepting the connections, which ends the thread reading them.
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        unsafe { libc::shutdown(self.listener.as_raw_fd(), libc::SHUT_RDWR) };
+    }
+}
+
+/// Accepts the connections and reads their requests until the server is closed,
+/// sending the valid ones to the server with a byte to wake it up.
+fn accept_requests(listener: UnixListener, requests: Sender<(Request, Client)>, mut woken: UnixStream, closed: Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if closed.load(Ordering::SeqCst) {
+            return;
+        }
+        let request = match stream {
+            Ok(stream) => read_request(stream),
+            Err(_) => continue
+        };
+        if let Some(request) = request {
+            if requests.send(request).is_err() || woken.write_all(&[0]).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Reads the request of the connection, answering the invalid ones with an error.
+fn read_request(stream: UnixStream) -> Option<(Request, Client)> {
+    stream.set_read_timeout(Some(SOCKET_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(SOCKET_TIMEOUT)).ok()?;
+
+    // The line may come in pieces, so it's read until the newline or the end.
+    let mut line = String::new();
+    let read = BufReader::new((&stream).take(REQUEST_LIMIT)).read_line(&mut line);
+
+    let client = Client { stream };
+    let error = match (read, Request::parse(line.as_str())) {
+        (Ok(_), Some(request)) => return Some((request, client)),
+        (Ok(_), None) => format!("Unknown request: {}", line.trim()),
+        (Err(_), _) => String::from("The request didn't end with a newline in time.")
+    };
+    client.reply(&Reply { ok: false, error: Some(error), status: None });
+    None
+}
+
+impl Client {
+    /// Sends the reply and closes the connection.
+    /// A client that went away isn't an error of the daemon.
+    pub fn reply(mut self, reply: &Reply) {
+        if let Ok(json) = serde_json::to_string(reply) {
+            let _ = writeln!(self.stream, "{}", json);
+        }
+    }
+}
+
+/// Sends the request to the socket at the path and returns the reply.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let reply = daemon::request_at(&daemon::socket_path(), daemon::Request::Status)?;
+/// ```
+pub fn request_at(path: &Path, request: Request) -> Result<Reply> {
+    let mut stream = UnixStream::connect(path).context("Couldn't connect to the running daemon.")?;
+    stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+    stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+    writeln!(stream, "{}", request.as_str()).context("Couldn't send the request to the running daemon.")?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).context("Couldn't read the reply of the running daemon.")?;
+    serde_json::from_str(line.as_str()).context("Couldn't parse the reply of the running daemon.")
+}
+
+/// Sends the request to the running daemon and returns the reply,
+/// or an error if it couldn't be reached or refused the request.
+pub fn request(request: Request) -> Result<Reply> {
+    let reply = request_at(&socket_path(), request)?;
+    match &reply.error {
+        Some(error) if !reply.ok => Err(anyhow!("{}", error)),
+        _ => Ok(reply)
+    }
+}
+
+/// Pauses or resumes the mapping of the running daemon
+/// and reports its new state. Asks through the socket,
+/// or with a signal if the daemon can't be reached on it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// daemon::toggle().unwrap();
+/// ```
+pub fn toggle() -> Result<()> {
+    let pid = match running_pid() {
+        Some(pid) => pid,
+        None => {
+            errln!("egawari isn't running.");
+            logln!("See: \x1b[0;39megawari run");
+            process::exit(1);
+        }
+    };
+
+    if let Ok(reply) = request(Request::Toggle) {
+        match reply.status.and_then(|status| status.state) {
+            Some(State::Active) => successln!("Resumed the mapping."),
+            Some(State::Paused) => successln!("Paused the mapping."),
+            None => warnln!("The daemon didn't report its new state.")
+        }
+        return Ok(());
+    }
+
+    let before = running_state();
+    if unsafe { libc::kill(pid, libc::SIGUSR1) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Couldn't signal the running daemon.");
+    }
+
+    // Wait for the daemon to report its new state.
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        match running_state() {
+            Some(State::Active) if before != Some(State::Active) => {
+                successln!("Resumed the mapping.");
+                return Ok(());
+            },
+            Some(State::Paused) if before != Some(State::Paused) => {
+                successln!("Paused the mapping.");
+                return Ok(());
+            },
+            _ => thread::sleep(Duration::from_millis(10))
+        }
+    }
+
+    warnln!("The daemon didn't report its new state.");
+    Ok(())
+}
+
+/// The status of the daemon as reported by the `status` command.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// daemon::Status {
+///     running: true,
+///     pid: Some(1234),
+///     state: Some(daemon::State::Active),
+///     device: Some(String::from("SynPS/2 Synaptics TouchPad")),
+///     virtual_name: Some(String::from("egawari virtual tablet")),
+///     stats: None
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub running: bool,
+    pub pid: Option<i32>,
+    pub state: Option<State>,
+    pub device: Option<String>,
+    /// The name of the virtual tablet, or `None` if it's unknown
+    /// or there's none, as with `--dry-run`.
+    #[serde(default)]
+    pub virtual_name: Option<String>,
+    /// The event statistics since the daemon started, or since
+    /// the last logged summary with the `stats` option.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Summary>
+}
+
+/// Returns the status of the daemon, asking it through the socket
+/// or else reading its runtime files.
+pub fn status_info() -> Status {
+    let pid = running_pid();
+    if pid.is_some() {
+        if let Some(status) = request(Request::Status).ok().and_then(|reply| reply.status) {
+            return status;
+        }
+    }
+
+    Status {
+        running: pid.is_some(),
+        pid,
+        state: running_state(),
+        device: running_device(),
+        virtual_name: None,
+        stats: None
+    }
+}
+
+/// Runs the `status` command, reporting the state of the daemon.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// daemon::status().unwrap();
+/// ```
+pub fn status() -> Result<()> {
+    let status = status_info();
+    if stdout::json_enabled() {
+        println!("{}", serde_json::to_string_pretty(&status).context("Couldn't convert the status to JSON.")?);
+        return Ok(());
+    }
+
+    match status.pid {
+        Some(pid) => successln!("egawari is running with the PID: \x1b[0;39m{}", pid),
+        None => {
+            warnln!("egawari isn't running.");
+            return Ok(());
+        }
+    }
+
+    if let Some(state) = status.state {
+        logln!("State: \x1b[0;39m{}", state.as_str());
+    }
+    if let Some(device) = status.device {
+        logln!("Device: \x1b[0;39m{}", device);
+    }
+    if let Some(virtual_name) = status.virtual_name {
+        logln!("Virtual tablet: \x1b[0;39m{}", virtual_name);
+    }
+    if let Some(stats) = status.stats {
+        logln!("Stats over {}s: \x1b[0;39m{}", stats.seconds, stats.describe());
+    }
+
+    Ok(())
+}
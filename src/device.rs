@@ -0,0 +1,931 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use anyhow::{anyhow, Context, Result};
+use evdev::{AbsInfo, AbsoluteAxisType, AttributeSet, Device, Key, RelativeAxisType, UinputAbsSetup};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use serde::{Serialize, Deserialize};
+
+use crate::args::Options;
+use crate::config::{self, DeviceId, Input, RouteTarget};
+use crate::cursor;
+use crate::stdout;
+
+/// The name of the virtual tablet device egawari creates.
+pub const VIRTUAL_NAME: &str = "egawari virtual tablet";
+/// The longest name of a virtual device in bytes uinput accepts.
+pub const VIRTUAL_NAME_MAX: usize = 79;
+/// The most buttons the virtual pad can have, `BTN_0` to `BTN_9`.
+pub const PAD_BUTTONS_MAX: u8 = 10;
+/// The device node the virtual tablet is created with.
+pub const UINPUT_PATH: &str = "/dev/uinput";
+/// The udev rule letting the `input` group create virtual devices.
+pub const UINPUT_RULE: &str = r#"KERNEL=="uinput", SUBSYSTEM=="misc", GROUP="input", MODE="0660", OPTIONS+="static_node=uinput""#;
+/// The file `udev-rule --install` installs the udev rule to.
+pub const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/99-egawari.rules";
+
+/// The pressure range reported when the touchpad has no pressure axis.
+pub const DEFAULT_PRESSURE_MAX: i32 = 255;
+/// The resolution of the tilt axes of the virtual tablet, as they're in degrees.
+const TILT_RESOLUTION: i32 = 57;
+/// The keys of the virtual tablet, the pen and the eraser with their buttons.
+const TABLET_KEYS: [Key; 5] = [Key::BTN_TOUCH, Key::BTN_TOOL_PEN, Key::BTN_TOOL_RUBBER, Key::BTN_STYLUS, Key::BTN_STYLUS2];
+
+/// The ranges of the absolute axes of a device.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::Bounds {
+///     min_x: 0,
+///     max_x: 1920,
+///     min_y: 0,
+///     max_y: 1080,
+///     pressure: None,
+///     orientation: None
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min_x: i32,
+    pub max_x: i32,
+    pub min_y: i32,
+    pub max_y: i32,
+    pub pressure: Option<(i32, i32)>,
+    /// The orientation of a contact turned a quarter turn clockwise,
+    /// if the device reports the orientation of the contacts.
+    pub orientation: Option<i32>
+}
+
+/// An input device as listed by the `devices` command.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::DeviceInfo {
+///     path: String::from("/dev/input/event5"),
+///     name: String::from("SynPS/2 Synaptics TouchPad"),
+///     id: config::DeviceId { vendor: 0x0002, product: 0x0007 },
+///     capabilities: device::Capabilities::default()
+/// }
+/// ```
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub path: String,
+    pub name: String,
+    pub id: DeviceId,
+    pub capabilities: Capabilities
+}
+
+/// What an input device reports of what egawari maps.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::Capabilities {
+///     position: true,
+///     pressure: true,
+///     touch_major: false,
+///     slots: 5
+/// }
+/// ```
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether it reports absolute positions and when they're touched.
+    pub position: bool,
+    /// Whether it reports the force of the contacts.
+    pub pressure: bool,
+    /// Whether it reports the size of the contacts, the other pressure source.
+    pub touch_major: bool,
+    /// How many contacts it tracks at once, or 0 without multitouch.
+    pub slots: u32
+}
+
+impl Capabilities {
+    /// Returns how well the device would work as the touchpad.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let capabilities = device::Capabilities { position: true, ..Default::default() };
+    /// assert_eq!(capabilities.verdict(), device::Verdict::PositionOnly);
+    /// ```
+    pub fn verdict(&self) -> Verdict {
+        match (self.position, self.pressure || self.touch_major) {
+            (false, _) => Verdict::Unsuitable,
+            (true, false) => Verdict::PositionOnly,
+            (true, true) => Verdict::Suitable
+        }
+    }
+}
+
+/// How well an input device would work as the touchpad.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// It reports positions and a pressure source.
+    Suitable,
+    /// It reports positions, drawing with the full pressure.
+    PositionOnly,
+    /// It doesn't report absolute positions, like a mouse or a keyboard.
+    Unsuitable
+}
+
+impl Verdict {
+    /// Returns the verdict as the `devices` command prints it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Suitable => "suitable",
+            Verdict::PositionOnly => "position only",
+            Verdict::Unsuitable => "unsuitable"
+        }
+    }
+}
+
+/// Returns what the input device reports of what egawari maps.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (_, device) = device::open_input("SynPS/2 Synaptics TouchPad").unwrap();
+/// let suitable = device::capabilities(&device).verdict() == device::Verdict::Suitable;
+/// ```
+pub fn capabilities(device: &Device) -> Capabilities {
+    let axes = match device.supported_absolute_axes() {
+        Some(axes) => axes,
+        None => return Capabilities::default()
+    };
+    let touch = device.supported_keys().is_some_and(|keys| keys.contains(Key::BTN_TOUCH));
+    let slots = match axes.contains(AbsoluteAxisType::ABS_MT_SLOT) {
+        true => device.get_abs_state().ok()
+            .map_or(0, |state| (state[AbsoluteAxisType::ABS_MT_SLOT.0 as usize].maximum + 1).max(0) as u32),
+        false => 0
+    };
+
+    Capabilities {
+        position: touch && axes.contains(AbsoluteAxisType::ABS_X) && axes.contains(AbsoluteAxisType::ABS_Y),
+        pressure: axes.contains(AbsoluteAxisType::ABS_PRESSURE),
+        touch_major: axes.contains(AbsoluteAxisType::ABS_MT_TOUCH_MAJOR),
+        slots
+    }
+}
+
+/// Returns the vendor and product IDs of the device.
+pub fn device_id(device: &Device) -> DeviceId {
+    let id = device.input_id();
+    DeviceId { vendor: id.vendor(), product: id.product() }
+}
+
+/// What the input device is matched by among the present ones.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::InputMatch::Name(String::from("SynPS/2 Synaptics TouchPad"))
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMatch {
+    Name(String),
+    Id(DeviceId),
+    Path(PathBuf)
+}
+
+impl InputMatch {
+    /// Returns whether the device at the event node is the one matched.
+    fn matches(&self, path: &Path, name: Option<&str>, id: DeviceId) -> bool {
+        match self {
+            InputMatch::Name(expected) => name == Some(expected.as_str()),
+            InputMatch::Id(expected) => id == *expected,
+            InputMatch::Path(expected) => path == expected
+        }
+    }
+}
+
+impl std::fmt::Display for InputMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            InputMatch::Name(name) => write!(f, "{}", name),
+            InputMatch::Id(id) => write!(f, "{}", id),
+            InputMatch::Path(path) => write!(f, "{}", path.display())
+        }
+    }
+}
+
+/// Returns the readable input devices, sorted by their event node.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for device in device::list_devices() {
+///     println!("{}", device.name);
+/// }
+/// ```
+pub fn list_devices() -> Vec<DeviceInfo> {
+    let mut devices: Vec<DeviceInfo> = evdev::enumerate()
+        .map(|(path, device)| DeviceInfo {
+            path: path.display().to_string(),
+            name: device.name().unwrap_or_default().to_string(),
+            id: device_id(&device),
+            capabilities: capabilities(&device)
+        })
+        .collect();
+
+    devices.sort_by(|a, b| (a.path.len(), &a.path).cmp(&(b.path.len(), &b.path)));
+    devices
+}
+
+/// Returns the names of the devices that could be the touchpad, the suitable
+/// ones before the ones reporting positions only, leaving out the virtual
+/// tablet and pad of egawari with the name.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let candidates = device::touchpad_candidates(&device::list_devices(), device::VIRTUAL_NAME);
+/// ```
+pub fn touchpad_candidates(devices: &[DeviceInfo], virtual_name: &str) -> Vec<String> {
+    let pad = pad_name(virtual_name);
+    let mut found: Vec<&DeviceInfo> = devices.iter()
+        .filter(|device| device.capabilities.verdict() != Verdict::Unsuitable)
+        .filter(|device| device.name != virtual_name && device.name != pad)
+        .collect();
+    found.sort_by_key(|device| device.capabilities.verdict() != Verdict::Suitable);
+
+    let mut names: Vec<String> = vec![];
+    for device in found {
+        if !names.contains(&device.name) {
+            names.push(device.name.clone());
+        }
+    }
+    names
+}
+
+/// Runs the `devices` command, listing the input devices
+/// with a matrix of what they report and how well they'd work as the touchpad.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::devices().unwrap();
+/// ```
+pub fn devices() -> Result<()> {
+    let devices = list_devices();
+    if stdout::json_enabled() {
+        println!("{}", serde_json::to_string_pretty(&devices).context("Couldn't convert the devices to JSON.")?);
+        return Ok(());
+    }
+
+    if devices.is_empty() {
+        warnln!("No input devices found, check the permissions of /dev/input.");
+        return Ok(());
+    }
+
+    colln!("Position  Pressure  Touch Major  Slots  Verdict        Device");
+    for device in devices {
+        let capabilities = device.capabilities;
+        logln!("{}  {}  {}  {:>5}  {}  {} \x1b[0;39m({}, {})",
+            capability_cell(capabilities.position, 8),
+            capability_cell(capabilities.pressure, 8),
+            capability_cell(capabilities.touch_major, 11),
+            capabilities.slots,
+            verdict_cell(capabilities.verdict(), 13),
+            device.name, device.id, device.path);
+    }
+
+    Ok(())
+}
+
+/// Returns the cell of the capability matrix telling whether
+/// the capability is supported, padded to the width.
+fn capability_cell(supported: bool, width: usize) -> String {
+    let palette = stdout::palette();
+    let (color, text) = match supported {
+        true => (palette.success, "yes"),
+        false => (palette.error, "no")
+    };
+    format!("{}{:<width$}\x1b[1;39m", color.escape(stdout::truecolor_enabled()), text, width = width)
+}
+
+/// Returns the cell of the capability matrix with the verdict, padded to the width.
+fn verdict_cell(verdict: Verdict, width: usize) -> String {
+    let palette = stdout::palette();
+    let color = match verdict {
+        Verdict::Suitable => palette.success.escape(stdout::truecolor_enabled()),
+        Verdict::PositionOnly => String::from("\x1b[1;33m"),
+        Verdict::Unsuitable => palette.error.escape(stdout::truecolor_enabled())
+    };
+    format!("{}{:<width$}\x1b[1;39m", color, verdict.name(), width = width)
+}
+
+/// Finds and opens the input device with the given name,
+/// returning its event node path and the device.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (path, device) = device::open_input("SynPS/2 Synaptics TouchPad").unwrap();
+/// ```
+pub fn open_input(name: &str) -> Result<(PathBuf, Device)> {
+    open_matching(&InputMatch::Name(name.to_string()))
+}
+
+/// Finds and opens the input device matched by its name or its IDs,
+/// or opens the event node directly if it's matched by its path,
+/// returning its event node path and the device.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let id = "1267:abcd".parse().unwrap();
+/// let (path, device) = device::open_matching(&device::InputMatch::Id(id)).unwrap();
+/// ```
+pub fn open_matching(input: &InputMatch) -> Result<(PathBuf, Device)> {
+    if let InputMatch::Path(path) = input {
+        return open_path(path).map(|device| (path.clone(), device));
+    }
+
+    evdev::enumerate()
+        .find(|(path, device)| input.matches(path, device.name(), device_id(device)))
+        .ok_or_else(|| anyhow!("Couldn't find the input device: {}", input))
+}
+
+/// Opens the event node as an input device, failing if it isn't one.
+fn open_path(path: &Path) -> Result<Device> {
+    if !path.exists() {
+        return Err(anyhow!("The input device doesn't exist: {}", path.display()));
+    }
+    Device::open(path).with_context(|| format!("Not a readable input device: {}", path.display()))
+}
+
+/// Returns the first of the names, in priority order,
+/// that is among the present input devices.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let present = vec![String::from("SynPS/2 Synaptics TouchPad")];
+/// let names = vec![String::from("Apple Inc. Magic Trackpad"), String::from("SynPS/2 Synaptics TouchPad")];
+/// assert_eq!(device::select_input(&names, &present), Some("SynPS/2 Synaptics TouchPad"));
+/// ```
+pub fn select_input<'a>(names: &'a [String], present: &[String]) -> Option<&'a str> {
+    names.iter()
+        .find(|name| !name.is_empty() && present.contains(name))
+        .map(|name| name.as_str())
+}
+
+/// Returns what the configured input device is matched by among the present devices.
+/// The path wins over the IDs and the IDs over the names if they're set,
+/// so only the first set one is matched.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let present = device::list_devices();
+/// let input = device::select_match(&conf.input, &present).unwrap();
+/// ```
+pub fn select_match(input: &Input, present: &[DeviceInfo]) -> Option<InputMatch> {
+    if let Some(path) = &input.path {
+        return present.iter()
+            .any(|device| Path::new(&device.path) == path)
+            .then(|| InputMatch::Path(path.clone()));
+    }
+
+    match input.id {
+        Some(id) => present.iter()
+            .any(|device| device.id == id)
+            .then_some(InputMatch::Id(id)),
+        None => {
+            let names: Vec<String> = present.iter().map(|device| device.name.clone()).collect();
+            select_input(&input.names, &names).map(|name| InputMatch::Name(name.to_string()))
+        }
+    }
+}
+
+/// Opens the configured input device, by its event node if it's set,
+/// by its IDs if they're set or else by the first present one of its names,
+/// in priority order. The event node is opened without looking through the devices.
+/// Returns what it's matched by, its event node path and the device.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (input, path, device) = device::open_first_input(&conf.input).unwrap();
+/// ```
+pub fn open_first_input(input: &Input) -> Result<(InputMatch, PathBuf, Device)> {
+    if let Some(path) = &input.path {
+        let matched = InputMatch::Path(path.clone());
+        let (path, device) = open_matching(&matched)?;
+        return Ok((matched, path, device));
+    }
+
+    let matched = match (select_match(input, &list_devices()), input.id) {
+        (Some(matched), _) => matched,
+        (None, Some(id)) => return Err(anyhow!("Couldn't find the input device with the ID: {}", id)),
+        (None, None) => return Err(anyhow!("Couldn't find any of the input devices: {}", input.names.join(", ")))
+    };
+    let (path, device) = open_matching(&matched)?;
+    Ok((matched, path, device))
+}
+
+/// Grabs the input device exclusively, so its events stop moving the pointer,
+/// or releases the grab. The grab is also released when the device is closed.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (_, mut device) = device::open_input("SynPS/2 Synaptics TouchPad").unwrap();
+/// device::set_grab(&mut device, true).unwrap();
+/// ```
+pub fn set_grab(device: &mut Device, grab: bool) -> Result<()> {
+    if grab {
+        device.grab().context("Couldn't grab the input device, another program may have grabbed it. \
+            Set grab to false in the input section to map it without grabbing.")
+    } else {
+        device.ungrab().context("Couldn't release the grab of the input device.")
+    }
+}
+
+/// Returns whether another program holds a grab of the input device,
+/// like another instance of egawari. Checks by grabbing it for a moment.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (_, mut device) = device::open_input("SynPS/2 Synaptics TouchPad").unwrap();
+/// if device::grabbed_elsewhere(&mut device) {
+///     println!("Another program grabbed the touchpad.");
+/// }
+/// ```
+pub fn grabbed_elsewhere(device: &mut Device) -> bool {
+    match device.grab() {
+        Ok(()) => {
+            let _ = device.ungrab();
+            false
+        },
+        Err(e) => e.raw_os_error() == Some(libc::EBUSY)
+    }
+}
+
+/// Returns the ranges of the absolute axes of the input device.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (_, device) = device::open_input("SynPS/2 Synaptics TouchPad").unwrap();
+/// let bounds = device::input_bounds(&device, AbsoluteAxisType::ABS_PRESSURE).unwrap();
+/// ```
+pub fn input_bounds(device: &Device, pressure_axis: AbsoluteAxisType) -> Result<Bounds> {
+    let axes = device.supported_absolute_axes()
+        .ok_or_else(|| anyhow!("The input device has no absolute axes."))?;
+    if !axes.contains(AbsoluteAxisType::ABS_X) || !axes.contains(AbsoluteAxisType::ABS_Y) {
+        return Err(anyhow!("The input device doesn't report absolute positions."));
+    }
+
+    let state = device.get_abs_state().context("Couldn't read the input device axes.")?;
+    let x = state[AbsoluteAxisType::ABS_X.0 as usize];
+    let y = state[AbsoluteAxisType::ABS_Y.0 as usize];
+    let pressure = if axes.contains(pressure_axis) {
+        let p = state[pressure_axis.0 as usize];
+        Some((p.minimum, p.maximum))
+    } else {
+        None
+    };
+    let orientation = Some(state[AbsoluteAxisType::ABS_MT_ORIENTATION.0 as usize].maximum)
+        .filter(|max| axes.contains(AbsoluteAxisType::ABS_MT_ORIENTATION) && *max > 0);
+
+    Ok(Bounds {
+        min_x: x.minimum,
+        max_x: x.maximum,
+        min_y: y.minimum,
+        max_y: y.maximum,
+        pressure,
+        orientation
+    })
+}
+
+/// Returns the resolution of the X and Y axes of the input device
+/// in units per millimeter, or `None` if the device doesn't report it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (_, device) = device::open_input("SynPS/2 Synaptics TouchPad").unwrap();
+/// let resolution = device::input_resolution(&device).unwrap();
+/// ```
+pub fn input_resolution(device: &Device) -> Result<Option<(i32, i32)>> {
+    let state = device.get_abs_state().context("Couldn't read the input device axes.")?;
+    let x = state[AbsoluteAxisType::ABS_X.0 as usize].resolution;
+    let y = state[AbsoluteAxisType::ABS_Y.0 as usize].resolution;
+    if x <= 0 || y <= 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((x, y)))
+}
+
+/// Why the virtual tablet can't be created with /dev/uinput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UinputProblem {
+    /// The uinput module isn't loaded.
+    Missing,
+    /// The user can't write to /dev/uinput.
+    PermissionDenied
+}
+
+impl UinputProblem {
+    /// Returns the problem the error of opening /dev/uinput means,
+    /// or `None` if it's another error.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// if let Err(e) = device::create_tablet(device::VIRTUAL_NAME, &bounds, (4, 4), false, false, &[]) {
+    ///     let problem = device::UinputProblem::from_error(&e);
+    /// }
+    /// ```
+    pub fn from_error(e: &anyhow::Error) -> Option<UinputProblem> {
+        let errno = e.chain()
+            .find_map(|cause| cause.downcast_ref::<io::Error>())
+            .and_then(|e| e.raw_os_error())?;
+        match errno {
+            libc::EACCES | libc::EPERM => Some(UinputProblem::PermissionDenied),
+            libc::ENOENT | libc::ENODEV => Some(UinputProblem::Missing),
+            _ => None
+        }
+    }
+
+    /// Returns the error message of the problem.
+    pub fn message(&self) -> &'static str {
+        match self {
+            UinputProblem::Missing => "/dev/uinput doesn't exist.",
+            UinputProblem::PermissionDenied => "No permission to create a virtual tablet with /dev/uinput."
+        }
+    }
+
+    /// Returns the lines telling how to fix the problem.
+    pub fn hints(&self) -> Vec<String> {
+        match self {
+            UinputProblem::Missing => vec![String::from("Load the uinput module with: \x1b[0;39mmodprobe uinput")],
+            UinputProblem::PermissionDenied => {
+                let mut hints = vec![
+                    format!("Let the input group use /dev/uinput with the udev rule in {}: \x1b[0;39m{}", UDEV_RULES_PATH, UINPUT_RULE),
+                    String::from("Or install it with: \x1b[0;39msudo egawari udev-rule --install")
+                ];
+                hints.extend(udev_steps());
+                hints
+            }
+        }
+    }
+
+    /// Prints the problem with how to fix it.
+    pub fn report(&self) {
+        errln!("{}", self.message());
+        for hint in self.hints() {
+            logln!("{}", hint);
+        }
+    }
+}
+
+/// Returns why the virtual tablet can't be created, if it can't,
+/// by opening /dev/uinput without creating anything.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(problem) = device::check_uinput() {
+///     problem.report();
+/// }
+/// ```
+pub fn check_uinput() -> Option<UinputProblem> {
+    let opened = OpenOptions::new()
+        .write(true)
+        .open(UINPUT_PATH)
+        .context("Couldn't open /dev/uinput.");
+    UinputProblem::from_error(&opened.err()?)
+}
+
+/// Runs the `doctor` command, checking what egawari needs
+/// from the system before it's run. Tells why the cursor can't be hidden
+/// too if the configuration hides it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::doctor().unwrap();
+/// ```
+pub fn doctor() -> Result<()> {
+    let hide_cursor = config::get_config().is_ok_and(|conf| conf.tablet.hide_cursor);
+    if let Some(problem) = cursor::problem().filter(|_| hide_cursor) {
+        warnln!("{}", problem);
+    }
+
+    if let Some(problem) = check_uinput() {
+        problem.report();
+        process::exit(1);
+    }
+
+    successln!("The virtual tablet can be created with: \x1b[0;39m{}", UINPUT_PATH);
+    Ok(())
+}
+
+/// Returns the steps left after installing the udev rule
+/// for the user to create virtual devices.
+fn udev_steps() -> Vec<String> {
+    vec![
+        String::from("Add your user to the input group with: \x1b[0;39msudo usermod -aG input $USER"),
+        String::from("Then log in again after reloading the rules with: \x1b[0;39msudo udevadm control --reload-rules && sudo udevadm trigger")
+    ]
+}
+
+/// Returns the contents of the udev rules file egawari installs.
+pub fn udev_rules_file() -> String {
+    format!("# Lets the input group create the virtual tablet of egawari.\n{}\n", UINPUT_RULE)
+}
+
+/// Writes the udev rules file to the path.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::install_udev_rule(Path::new(device::UDEV_RULES_PATH)).unwrap();
+/// ```
+pub fn install_udev_rule(path: &Path) -> Result<()> {
+    fs::write(path, udev_rules_file())
+        .with_context(|| format!("Couldn't write the udev rule to {}.", path.display()))
+}
+
+/// Runs the `udev-rule` command, printing the udev rule `doctor` asks for
+/// or installing it with `--install`, which needs root.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// device::udev_rule(&args::Options { install: true, ..Default::default() }).unwrap();
+/// ```
+pub fn udev_rule(opts: &Options) -> Result<()> {
+    if !opts.install {
+        println!("{}", UINPUT_RULE);
+        logln!("Install it to {} with: \x1b[0;39msudo egawari udev-rule --install", UDEV_RULES_PATH);
+        for step in udev_steps() {
+            logln!("{}", step);
+        }
+        return Ok(());
+    }
+
+    if unsafe { libc::geteuid() } != 0 {
+        errln!("Installing the udev rule needs root.");
+        logln!("Run: \x1b[0;39msudo egawari udev-rule --install");
+        process::exit(1);
+    }
+
+    install_udev_rule(Path::new(UDEV_RULES_PATH))?;
+    successln!("Installed the udev rule to: \x1b[0;39m{}", UDEV_RULES_PATH);
+    for step in udev_steps() {
+        logln!("{}", step);
+    }
+    Ok(())
+}
+
+/// The capabilities of a tablet device, as the keys, the ranges
+/// of the absolute axes and the relative axes it reports.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let required = device::tablet_capabilities(&bounds, false, false, &[]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabletCapabilities {
+    pub keys: Vec<Key>,
+    pub absolute: Vec<(AbsoluteAxisType, i32, i32)>,
+    pub relative: Vec<RelativeAxisType>
+}
+
+/// What `run` writes the tablet events into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TabletTarget {
+    /// A virtual tablet of its own, created and removed by `run`.
+    Create,
+    /// The existing device, which its creator keeps and removes.
+    Reuse,
+    /// The existing device, which can't be used for what it's missing.
+    Incompatible(Vec<String>)
+}
+
+/// Returns the capabilities of the virtual tablet `create_tablet` creates
+/// with the bounds, the tilt axes, the relative axes and the targets of the routes.
+pub fn tablet_capabilities(bounds: &Bounds, tilt: bool, relative: bool, routes: &[RouteTarget]) -> TabletCapabilities {
+    let (min_pressure, max_pressure) = bounds.pressure.unwrap_or((0, DEFAULT_PRESSURE_MAX));
+    let mut absolute = vec![
+        (AbsoluteAxisType::ABS_X, bounds.min_x, bounds.max_x),
+        (AbsoluteAxisType::ABS_Y, bounds.min_y, bounds.max_y),
+        (AbsoluteAxisType::ABS_PRESSURE, min_pressure, max_pressure)
+    ];
+    if tilt {
+        absolute.push((AbsoluteAxisType::ABS_TILT_X, -90, 90));
+        absolute.push((AbsoluteAxisType::ABS_TILT_Y, -90, 90));
+    }
+    let mut keys = TABLET_KEYS.to_vec();
+    let mut relative = match relative {
+        true => vec![RelativeAxisType::REL_X, RelativeAxisType::REL_Y],
+        false => vec![]
+    };
+    for target in routes {
+        match *target {
+            RouteTarget::Relative(axis) if !relative.contains(&axis) => relative.push(axis),
+            RouteTarget::Key(key) if !keys.contains(&key) => keys.push(key),
+            _ => ()
+        }
+    }
+    TabletCapabilities { keys, absolute, relative }
+}
+
+/// Returns the capabilities the input device reports.
+pub fn device_capabilities(device: &Device) -> TabletCapabilities {
+    let absolute = match (device.supported_absolute_axes(), device.get_abs_state()) {
+        (Some(axes), Ok(ranges)) => axes.iter()
+            .map(|axis| (axis, ranges[axis.0 as usize].minimum, ranges[axis.0 as usize].maximum))
+            .collect(),
+        _ => vec![]
+    };
+    TabletCapabilities {
+        keys: device.supported_keys().map_or(vec![], |keys| keys.iter().collect()),
+        absolute,
+        relative: device.supported_relative_axes().map_or(vec![], |axes| axes.iter().collect())
+    }
+}
+
+/// Decides whether `run` creates a virtual tablet of its own or writes into
+/// the existing device, given what it reports if it's present. The device has
+/// to report the keys and the axes of the virtual tablet with the same ranges,
+/// as the positions are in the ranges of the virtual tablet.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let required = device::tablet_capabilities(&bounds, false, false, &[]);
+/// assert_eq!(device::tablet_target(None, &required), device::TabletTarget::Create);
+/// ```
+pub fn tablet_target(existing: Option<&TabletCapabilities>, required: &TabletCapabilities) -> TabletTarget {
+    let existing = match existing {
+        Some(existing) => existing,
+        None => return TabletTarget::Create
+    };
+
+    let mut missing: Vec<String> = required.keys.iter()
+        .filter(|key| !existing.keys.contains(key))
+        .map(|key| format!("{:?}", key))
+        .collect();
+    for (axis, min, max) in &required.absolute {
+        match existing.absolute.iter().find(|(a, ..)| a == axis) {
+            Some((_, found_min, found_max)) if (found_min, found_max) != (min, max) => {
+                missing.push(format!("{:?} from {} to {}", axis, min, max));
+            },
+            Some(_) => (),
+            None => missing.push(format!("{:?}", axis))
+        }
+    }
+    missing.extend(required.relative.iter()
+        .filter(|axis| !existing.relative.contains(axis))
+        .map(|axis| format!("{:?}", axis)));
+
+    match missing.is_empty() {
+        true => TabletTarget::Reuse,
+        false => TabletTarget::Incompatible(missing)
+    }
+}
+
+/// Creates the virtual tablet device reporting positions within the bounds,
+/// with the resolution of the X and Y axes in units per millimeter.
+/// With `tilt`, the tablet reports the tilt of the pen in degrees too,
+/// and with `relative` it reports the motion of the pen on the relative axes.
+/// The tablet reports the relative axes and the keys the routes target as well.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let tablet = device::create_tablet(device::VIRTUAL_NAME, &bounds, (4, 4), false, false, &[]).unwrap();
+/// ```
+pub fn create_tablet(name: &str, bounds: &Bounds, resolution: (i32, i32), tilt: bool, relative: bool, routes: &[RouteTarget]) -> Result<VirtualDevice> {
+    let capabilities = tablet_capabilities(bounds, tilt, relative, routes);
+    let mut keys = AttributeSet::<Key>::new();
+    for key in capabilities.keys {
+        keys.insert(key);
+    }
+
+    let (min_pressure, max_pressure) = bounds.pressure.unwrap_or((0, DEFAULT_PRESSURE_MAX));
+    let x = UinputAbsSetup::new(AbsoluteAxisType::ABS_X, AbsInfo::new(0, bounds.min_x, bounds.max_x, 0, 0, resolution.0));
+    let y = UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, AbsInfo::new(0, bounds.min_y, bounds.max_y, 0, 0, resolution.1));
+    let pressure = UinputAbsSetup::new(AbsoluteAxisType::ABS_PRESSURE, AbsInfo::new(0, min_pressure, max_pressure, 0, 0, 0));
+
+    let mut builder = VirtualDeviceBuilder::new()
+        .context("Couldn't open /dev/uinput.")?
+        .name(name)
+        .with_keys(&keys)?
+        .with_absolute_axis(&x)?
+        .with_absolute_axis(&y)?
+        .with_absolute_axis(&pressure)?;
+    if tilt {
+        // The resolution of the tilt axes is in units per radian.
+        for axis in [AbsoluteAxisType::ABS_TILT_X, AbsoluteAxisType::ABS_TILT_Y] {
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, AbsInfo::new(0, -90, 90, 0, 0, TILT_RESOLUTION)))?;
+        }
+    }
+    if !capabilities.relative.is_empty() {
+        let mut axes = AttributeSet::<RelativeAxisType>::new();
+        for axis in capabilities.relative {
+            axes.insert(axis);
+        }
+        builder = builder.with_relative_axes(&axes)?;
+    }
+    let device = builder.build().context("Couldn't create the virtual tablet.")?;
+
+    Ok(device)
+}
+
+/// Returns the key of the pad button, numbered from 0.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(device::pad_key(1), Key::BTN_1);
+/// ```
+pub fn pad_key(button: u8) -> Key {
+    Key::new(Key::BTN_0.code() + button as u16)
+}
+
+/// Returns the keys of a pad with the number of buttons,
+/// up to the most buttons a pad can have.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(device::pad_buttons(2), vec![Key::BTN_0, Key::BTN_1]);
+/// ```
+pub fn pad_buttons(count: u8) -> Vec<Key> {
+    (0..count.min(PAD_BUTTONS_MAX)).map(pad_key).collect()
+}
+
+/// Returns the name of the virtual pad of the virtual tablet with the name,
+/// shortened to the longest name uinput accepts.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(device::pad_name(device::VIRTUAL_NAME), "egawari virtual tablet pad");
+/// ```
+pub fn pad_name(name: &str) -> String {
+    let mut name = format!("{} pad", name);
+    while name.len() > VIRTUAL_NAME_MAX {
+        name.pop();
+    }
+    name
+}
+
+/// Creates the virtual pad of the virtual tablet with the name,
+/// with the number of buttons from `BTN_0` on.
+/// The pad has the X and Y axes too, like the pads of graphics tablets,
+/// so it's told apart from a keyboard.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let pad = device::create_pad(device::VIRTUAL_NAME, 4).unwrap();
+/// ```
+pub fn create_pad(name: &str, buttons: u8) -> Result<VirtualDevice> {
+    let mut keys = AttributeSet::<Key>::new();
+    for key in pad_buttons(buttons) {
+        keys.insert(key);
+    }
+    let x = UinputAbsSetup::new(AbsoluteAxisType::ABS_X, AbsInfo::new(0, 0, 1, 0, 0, 0));
+    let y = UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, AbsInfo::new(0, 0, 1, 0, 0, 0));
+
+    let device = VirtualDeviceBuilder::new()
+        .context("Couldn't open /dev/uinput.")?
+        .name(pad_name(name).as_str())
+        .with_keys(&keys)?
+        .with_absolute_axis(&x)?
+        .with_absolute_axis(&y)?
+        .build()
+        .context("Couldn't create the virtual pad.")?;
+
+    Ok(device)
+}
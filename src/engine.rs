@@ -0,0 +1,1202 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::time::{Duration, UNIX_EPOCH};
+use evdev::{AbsoluteAxisType, EventType, InputEvent, InputEventKind, Key, RelativeAxisType, Synchronization};
+
+use crate::config::{Config, HoverMode, MappingMode, RouteSource, RouteTarget, Smoothing, StylusButton, TiltSource};
+use crate::device::{self, Bounds, DEFAULT_PRESSURE_MAX};
+use crate::mapping::{self, Point};
+use crate::record::{RawEvent, Record, Recorder};
+
+/// How far apart two samples of a settling touch can be to be stable,
+/// as a fraction of the range of the touchpad.
+const SETTLE_TOLERANCE: f64 = 0.01;
+
+/// The most multitouch slots kept. Touchpads have a handful, the events
+/// of slots past it, only found in broken or crafted recordings, are ignored.
+const SLOTS_MAX: usize = 64;
+
+/// A multitouch slot of the touchpad, holding a contact while it's active.
+#[derive(Debug, Default, Clone, Copy)]
+struct Slot {
+    active: bool,
+    x: i32,
+    y: i32,
+    orientation: i32
+}
+
+/// The state of a route during a contact.
+#[derive(Debug, Default, Clone, Copy)]
+struct RouteState {
+    /// The value of the source last reported, as a fraction of its range.
+    last: Option<f64>,
+    /// The motion of a relative axis short of a whole step.
+    remainder: f64,
+    /// Whether the key is held.
+    held: bool
+}
+
+/// Translates the touchpad events into virtual tablet events.
+/// Events are collected until each `SYN_REPORT` and then
+/// reported together, like the kernel does.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut engine = engine::Engine::new(bounds, &conf);
+/// for event in touchpad.fetch_events()? {
+///     let out = engine.process(event);
+///     if !out.is_empty() {
+///         tablet.emit(&out)?;
+///     }
+/// }
+/// ```
+pub struct Engine {
+    bounds: Bounds,
+    config: Config,
+    paused: bool,
+    activated: bool,
+    x: i32,
+    y: i32,
+    pressure: i32,
+    tools: u8,
+    slot: usize,
+    slots: Vec<Slot>,
+    gesture: bool,
+    moved: bool,
+    touching: bool,
+    contact: bool,
+    hovering: bool,
+    down: bool,
+    proximity: bool,
+    erasing: bool,
+    rubber: bool,
+    eraser_tap: Option<Duration>,
+    pad_events: Vec<InputEvent>,
+    pad_pressed: u16,
+    pad_tap: Option<(usize, Duration)>,
+    stylus_keys: Vec<Key>,
+    stylus_pressed: [bool; 2],
+    routes: Vec<RouteState>,
+    pending: bool,
+    settling: bool,
+    settle_sample: Option<Point>,
+    touch_start: Duration,
+    dropped_at: Option<Duration>,
+    last_tap: Option<Duration>,
+    lifted_at: Option<Duration>,
+    last_report: Option<Duration>,
+    last_tilt: Option<(i32, i32)>,
+    anchor: Option<Point>,
+    remainder: (f64, f64),
+    smoothed: Option<(f64, f64)>,
+    pressure_lost: bool,
+    recorder: Option<Recorder>
+}
+
+impl Engine {
+    /// Creates the engine for a touchpad with the given bounds.
+    pub fn new(bounds: Bounds, config: &Config) -> Engine {
+        Engine {
+            bounds,
+            config: config.clone(),
+            paused: config.tablet.start_paused,
+            activated: config.activation.is_none(),
+            x: bounds.min_x,
+            y: bounds.min_y,
+            pressure: 0,
+            tools: 0,
+            slot: 0,
+            slots: vec![],
+            gesture: false,
+            moved: false,
+            touching: false,
+            contact: false,
+            hovering: false,
+            down: false,
+            proximity: false,
+            erasing: false,
+            rubber: false,
+            eraser_tap: None,
+            pad_events: vec![],
+            pad_pressed: 0,
+            pad_tap: None,
+            stylus_keys: vec![],
+            stylus_pressed: [false; 2],
+            routes: vec![],
+            pending: false,
+            settling: false,
+            settle_sample: None,
+            touch_start: Duration::default(),
+            dropped_at: None,
+            last_tap: None,
+            lifted_at: None,
+            last_report: None,
+            last_tilt: None,
+            anchor: None,
+            remainder: (0.0, 0.0),
+            smoothed: None,
+            pressure_lost: false,
+            recorder: None
+        }
+    }
+
+    /// Returns the bounds of the touchpad.
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Returns the config the events are translated with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Translates the following events with the config,
+    /// keeping the state of the fingers and the pen.
+    pub fn set_config(&mut self, config: &Config) {
+        self.config = config.clone();
+        self.write_config();
+    }
+
+    /// Records what the engine is given from now on with the recorder,
+    /// starting with the config, so it can be replayed.
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+        self.write_config();
+    }
+
+    /// Writes the config the events are translated with if the engine is recording.
+    fn write_config(&mut self) {
+        if self.recorder.is_none() {
+            return;
+        }
+        match Record::config(&self.config) {
+            Ok(record) => self.write_record(record),
+            Err(e) => {
+                warnln!("Stopped recording the input events: {:#}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Writes the record if the engine is recording.
+    /// Recording stops after a write fails, while the mapping goes on.
+    fn write_record(&mut self, record: Record) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.write(&record) {
+                warnln!("Stopped recording the input events: {:#}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Returns whether the mapping is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns whether the touchpad isn't mapped for now,
+    /// being paused or without the activation key held.
+    pub fn suspended(&self) -> bool {
+        self.paused || !self.activated
+    }
+
+    /// Pauses or resumes the mapping, returning the events to emit.
+    /// Pausing in the middle of a stroke lifts the pen
+    /// and takes it out of proximity.
+    pub fn set_paused(&mut self, paused: bool) -> Vec<InputEvent> {
+        self.write_record(Record::Paused { paused });
+        let out = self.suspend(paused);
+        self.paused = paused;
+        out
+    }
+
+    /// Lifts the pen when the mapping is suspended, or makes
+    /// the next report include the position when it's not.
+    fn suspend(&mut self, suspended: bool) -> Vec<InputEvent> {
+        if suspended {
+            return self.release();
+        }
+
+        self.moved = true;
+        self.anchor = None;
+        vec![]
+    }
+
+    /// Activates the mapping while the activation key is held,
+    /// returning the events to emit. Releasing the key in the middle
+    /// of a stroke lifts the pen and takes it out of proximity.
+    pub fn set_activated(&mut self, held: bool) -> Vec<InputEvent> {
+        self.write_record(Record::Activated { held });
+        self.activate(held)
+    }
+
+    /// Activates or deactivates the mapping, returning the events to emit.
+    fn activate(&mut self, held: bool) -> Vec<InputEvent> {
+        let out = self.suspend(!held);
+        self.activated = held;
+        out
+    }
+
+    /// Returns whether the event presses or releases the activation key,
+    /// or `None` if it's not the activation key. Repeats count as held.
+    pub fn activation_held(&self, event: &InputEvent) -> Option<bool> {
+        let key = self.config.activation.as_ref()?.key()?;
+        match event.kind() {
+            InputEventKind::Key(k) if k == key => Some(event.value() != 0),
+            _ => None
+        }
+    }
+
+    /// Uses the eraser while the eraser key is held and the pen otherwise,
+    /// returning the events to emit.
+    pub fn set_erasing(&mut self, erasing: bool) -> Vec<InputEvent> {
+        self.write_record(Record::Erasing { erasing });
+        self.switch_tool(erasing)
+    }
+
+    /// Returns whether the event presses or releases the eraser key,
+    /// or `None` if it's not the eraser key. Repeats count as held.
+    pub fn eraser_held(&self, event: &InputEvent) -> Option<bool> {
+        let key = self.config.eraser.as_ref()?.key()?;
+        match event.kind() {
+            InputEventKind::Key(k) if k == key => Some(event.value() != 0),
+            _ => None
+        }
+    }
+
+    /// Switches between the pen and the eraser, returning the events to emit.
+    /// A tool in proximity is lifted and leaves in a report of its own
+    /// before the other tool comes in at the same position,
+    /// touching down again if the contact still draws.
+    fn switch_tool(&mut self, erasing: bool) -> Vec<InputEvent> {
+        self.erasing = erasing;
+        if !self.proximity || self.rubber == erasing {
+            return vec![];
+        }
+
+        let lifted_at = self.lifted_at;
+        let mut out = self.release();
+        self.lifted_at = lifted_at;
+        out.push(syn());
+
+        self.rubber = erasing;
+        self.proximity = true;
+        self.moved = true;
+        out.push(key(self.tool(), 1));
+        let time = self.last_report.unwrap_or_default();
+        if self.touching {
+            out.extend(self.report(time));
+        } else {
+            out.extend(self.flush(time, false));
+        }
+        out
+    }
+
+    /// Returns the key of the tool in proximity, the pen or the eraser.
+    fn tool(&self) -> Key {
+        match self.rubber {
+            true => Key::BTN_TOOL_RUBBER,
+            false => Key::BTN_TOOL_PEN
+        }
+    }
+
+    /// Returns whether three fingers just tapped, if the eraser is switched that way.
+    /// The tap starts when the third finger lands and has to end with every finger
+    /// lifted within the double tap time.
+    fn eraser_tapped(&mut self, time: Duration) -> bool {
+        if self.config.eraser.as_ref().is_none_or(|eraser| eraser.key.is_some()) {
+            self.eraser_tap = None;
+            return false;
+        }
+
+        if self.touching {
+            if self.fingers() >= 3 && self.eraser_tap.is_none() {
+                self.eraser_tap = Some(time);
+            }
+            return false;
+        }
+
+        let double_tap = Duration::from_millis(self.config.tablet.double_tap_ms as u64);
+        self.eraser_tap.take().is_some_and(|tap| time.saturating_sub(tap) <= double_tap)
+    }
+
+    /// Returns the pad button the event presses or releases and whether it's held,
+    /// or `None` if no pad binding has the key. Repeats count as held.
+    pub fn pad_held(&self, event: &InputEvent) -> Option<(u8, bool)> {
+        let pad = self.config.pad.as_ref()?;
+        match event.kind() {
+            InputEventKind::Key(k) => Some((pad.key_button(k)?, event.value() != 0)),
+            _ => None
+        }
+    }
+
+    /// Presses or releases the pad button. The events are queued for the pad
+    /// until they're taken, each change in a report of its own.
+    pub fn set_pad_button(&mut self, button: u8, held: bool) {
+        let bit = match 1u16.checked_shl(button as u32) {
+            Some(bit) => bit,
+            None => return
+        };
+        if (self.pad_pressed & bit != 0) == held {
+            return;
+        }
+
+        self.pad_pressed ^= bit;
+        if !self.pad_events.is_empty() {
+            self.pad_events.push(syn());
+        }
+        self.pad_events.push(key(device::pad_key(button), held as i32));
+    }
+
+    /// Releases the held pad buttons, after the device of their keys is disconnected.
+    pub fn release_pad(&mut self) {
+        for button in 0..device::PAD_BUTTONS_MAX {
+            self.set_pad_button(button, false);
+        }
+    }
+
+    /// Returns the events for the pad queued since the last call.
+    pub fn take_pad(&mut self) -> Vec<InputEvent> {
+        std::mem::take(&mut self.pad_events)
+    }
+
+    /// Returns the key the event presses or releases and whether it's held,
+    /// or `None` if no stylus button binding has the key. Repeats count as held.
+    pub fn stylus_key_held(&self, event: &InputEvent) -> Option<(Key, bool)> {
+        let stylus = self.config.stylus_buttons.as_ref()?;
+        match event.kind() {
+            InputEventKind::Key(k) if stylus.binds(k) => Some((k, event.value() != 0)),
+            _ => None
+        }
+    }
+
+    /// Holds or releases a key of the stylus buttons, returning the events to emit.
+    /// A button is held while any key bound to it is, and only its changes are reported.
+    pub fn set_stylus_key(&mut self, key: Key, held: bool) -> Vec<InputEvent> {
+        self.write_record(Record::StylusKey { code: key.code(), held });
+        self.hold_stylus_key(key, held)
+    }
+
+    /// Holds or releases a key of the stylus buttons without recording it,
+    /// for the keys of the touchpad whose events are recorded already.
+    fn hold_stylus_key(&mut self, key: Key, held: bool) -> Vec<InputEvent> {
+        self.stylus_keys.retain(|k| *k != key);
+        if held {
+            self.stylus_keys.push(key);
+        }
+        let pressed = self.config.stylus_buttons.as_ref()
+            .map_or([false; 2], |stylus| stylus.held(&self.stylus_keys));
+        self.press_stylus(pressed)
+    }
+
+    /// Releases the stylus buttons, after the device of their keys is disconnected,
+    /// returning the events to emit.
+    pub fn release_stylus(&mut self) -> Vec<InputEvent> {
+        self.write_record(Record::StylusReleased);
+        self.clear_stylus()
+    }
+
+    /// Releases the stylus buttons without recording it, returning the events to emit.
+    fn clear_stylus(&mut self) -> Vec<InputEvent> {
+        self.stylus_keys.clear();
+        self.press_stylus([false; 2])
+    }
+
+    /// Reports the stylus buttons whose state changed, in a report of their own.
+    fn press_stylus(&mut self, pressed: [bool; 2]) -> Vec<InputEvent> {
+        let mut out: Vec<InputEvent> = StylusButton::ALL.iter().zip(pressed)
+            .zip(self.stylus_pressed)
+            .filter(|((_, now), before)| now != before)
+            .map(|((button, now), _)| key(button.key(), now as i32))
+            .collect();
+        self.stylus_pressed = pressed;
+        if !out.is_empty() {
+            out.push(syn());
+        }
+        out
+    }
+
+    /// Presses and releases the pad button of a quick tap of three or four fingers, if any.
+    /// The tap starts when the third finger lands and has to end with every finger
+    /// lifted within the double tap time, counting the most fingers in between.
+    fn pad_tapped(&mut self, time: Duration) {
+        if self.config.pad.is_none() {
+            self.pad_tap = None;
+            return;
+        }
+
+        if self.touching {
+            let fingers = self.fingers();
+            if fingers >= 3 {
+                let (most, start) = self.pad_tap.unwrap_or((fingers, time));
+                self.pad_tap = Some((most.max(fingers), start));
+            }
+            return;
+        }
+
+        let double_tap = Duration::from_millis(self.config.tablet.double_tap_ms as u64);
+        let tap = self.pad_tap.take().filter(|(_, start)| time.saturating_sub(*start) <= double_tap);
+        if let Some(button) = tap.and_then(|(fingers, _)| self.config.pad.as_ref()?.tap_button(fingers)) {
+            self.set_pad_button(button, true);
+            self.set_pad_button(button, false);
+        }
+    }
+
+    /// Forgets the fingers on the touchpad after it's disconnected,
+    /// lifting the pen and taking it out of proximity.
+    /// Returns the events to emit.
+    pub fn disconnect(&mut self) -> Vec<InputEvent> {
+        self.write_record(Record::Disconnected);
+        self.touching = false;
+        self.contact = false;
+        self.hovering = false;
+        self.gesture = false;
+        self.moved = false;
+        self.pending = false;
+        self.settling = false;
+        self.dropped_at = None;
+        self.tools = 0;
+        self.slots.clear();
+        self.anchor = None;
+        self.last_tap = None;
+        self.eraser_tap = None;
+        self.pad_tap = None;
+        if self.config.pad.as_ref().is_some_and(|pad| pad.device.is_none()) {
+            self.release_pad();
+        }
+        let mut out = match self.config.stylus_buttons.as_ref().is_some_and(|stylus| stylus.device.is_none()) {
+            true => self.clear_stylus(),
+            false => vec![]
+        };
+        out.extend(self.release());
+        out
+    }
+
+    /// Lifts the pen and takes it out of proximity, returning the events to emit.
+    fn release(&mut self) -> Vec<InputEvent> {
+        let mut out = self.release_routes();
+        if self.down {
+            out.push(abs(AbsoluteAxisType::ABS_PRESSURE, 0));
+            out.push(key(Key::BTN_TOUCH, 0));
+            self.down = false;
+        }
+        if self.proximity {
+            out.push(key(self.tool(), 0));
+            self.proximity = false;
+        }
+
+        self.lifted_at = None;
+        out
+    }
+
+    /// Returns the number of fingers on the touchpad,
+    /// from the multitouch slots or the finger count tools.
+    pub fn fingers(&self) -> usize {
+        let slots = self.slots.iter().filter(|slot| slot.active).count();
+        let tools = match self.tools {
+            0 => self.touching as usize,
+            tools => 2 + (7 - tools.leading_zeros() as usize)
+        };
+        slots.max(tools)
+    }
+
+    /// Returns the time the engine should be ticked at,
+    /// if it's holding back coalesced events, waiting for a touch
+    /// to settle, holding back a lift or hovering after a lift
+    /// with a lift-off timeout.
+    /// The time is since the Unix epoch, like the event timestamps.
+    pub fn deadline(&self) -> Option<Duration> {
+        [self.flush_deadline(), self.settle_deadline(), self.debounce_deadline(), self.lift_deadline()]
+            .iter()
+            .flatten()
+            .min()
+            .copied()
+    }
+
+    /// Returns the time the coalesced events should be reported at.
+    fn flush_deadline(&self) -> Option<Duration> {
+        if !self.pending || self.suspended() {
+            return None;
+        }
+
+        Some(self.last_report? + self.interval()?)
+    }
+
+    /// Returns the time a settling touch is reported at, even if it isn't stable.
+    fn settle_deadline(&self) -> Option<Duration> {
+        if !self.settling || self.suspended() {
+            return None;
+        }
+
+        Some(self.touch_start + Duration::from_millis(self.config.tablet.touch_settle_ms as u64))
+    }
+
+    /// Returns the time a held back lift is reported at
+    /// if the finger doesn't touch down again.
+    fn debounce_deadline(&self) -> Option<Duration> {
+        let debounce = Duration::from_millis(self.config.tablet.contact_debounce_ms as u64);
+        self.dropped_at.map(|dropped| dropped + debounce)
+    }
+
+    /// Returns the time the hovering pen should leave proximity at.
+    fn lift_deadline(&self) -> Option<Duration> {
+        if !self.proximity {
+            return None;
+        }
+
+        let timeout = Duration::from_millis(self.config.tablet.lift_timeout_ms as u64);
+        self.lifted_at.map(|lifted| lifted + timeout)
+    }
+
+    /// Handles the passing of time, returning the events to emit.
+    /// Reports the coalesced events once they're due, settling touches
+    /// once they time out and takes the pen out of proximity
+    /// once the lift-off timeout passes.
+    pub fn tick(&mut self, now: Duration) -> Vec<InputEvent> {
+        self.write_record(Record::Tick { time: now.as_micros() as u64 });
+        let mut out = vec![];
+        if self.settle_deadline().is_some_and(|deadline| now >= deadline) {
+            out.extend(self.report(now));
+        }
+
+        if self.debounce_deadline().is_some_and(|deadline| now >= deadline) {
+            out.extend(self.report(now));
+        }
+
+        if self.flush_deadline().is_some_and(|deadline| now >= deadline) {
+            out.extend(self.flush(now, self.down));
+        }
+
+        if self.lift_deadline().is_some_and(|deadline| now >= deadline) {
+            out.push(key(self.tool(), 0));
+            self.proximity = false;
+            self.lifted_at = None;
+        }
+
+        out
+    }
+
+    /// Returns the minimum time between the position reports,
+    /// or `None` if every event is reported.
+    fn interval(&self) -> Option<Duration> {
+        match self.config.tablet.report_rate {
+            0 => None,
+            rate => Some(Duration::from_secs(1) / rate as u32)
+        }
+    }
+
+    /// Returns the position and the pressure events,
+    /// marking the time they're reported at.
+    fn flush(&mut self, time: Duration, drawing: bool) -> Vec<InputEvent> {
+        let mut out = vec![];
+        if self.moved {
+            match self.config.mapping.mode {
+                MappingMode::Absolute => {
+                    let point = mapping::map_point(Point { x: self.x, y: self.y }, &self.config, self.bounds);
+                    let point = self.smooth(point);
+                    out.push(abs(AbsoluteAxisType::ABS_X, point.x));
+                    out.push(abs(AbsoluteAxisType::ABS_Y, point.y));
+                },
+                MappingMode::Relative => out.extend(self.relative_motion())
+            }
+            self.moved = false;
+        }
+
+        if drawing {
+            let pressure = self.reported_pressure().unwrap_or(DEFAULT_PRESSURE_MAX);
+            out.push(abs(AbsoluteAxisType::ABS_PRESSURE, pressure));
+        }
+
+        if let Some(tilt) = self.tilt().filter(|tilt| self.last_tilt != Some(*tilt)) {
+            out.push(abs(AbsoluteAxisType::ABS_TILT_X, tilt.0));
+            out.push(abs(AbsoluteAxisType::ABS_TILT_Y, tilt.1));
+            self.last_tilt = Some(tilt);
+        }
+
+        if self.touching {
+            out.extend(self.routed());
+        }
+
+        self.pending = false;
+        self.last_report = Some(time);
+        out
+    }
+
+    /// Returns the value of the source of a route as a fraction of its range,
+    /// or `None` if the touchpad doesn't report it.
+    fn route_source(&self, source: RouteSource) -> Option<f64> {
+        let fraction = |value: i32, min: i32, max: i32| match max > min {
+            true => Some(((value as f64 - min as f64) / (max as f64 - min as f64)).clamp(0.0, 1.0)),
+            false => None
+        };
+        match source {
+            RouteSource::Pressure => self.pressure_fraction(),
+            RouteSource::X => fraction(self.x, self.bounds.min_x, self.bounds.max_x),
+            RouteSource::Y => fraction(self.y, self.bounds.min_y, self.bounds.max_y)
+        }
+    }
+
+    /// Returns the events of the routes for the contact. A relative axis moves by the change
+    /// of the source since the last report, carrying the fractions of steps over, and the first
+    /// value of a contact only anchors the motion. A key is held from the threshold on.
+    fn routed(&mut self) -> Vec<InputEvent> {
+        let mut states = std::mem::take(&mut self.routes);
+        states.resize(self.config.routes.len(), RouteState::default());
+
+        let mut out = vec![];
+        for (route, state) in self.config.routes.iter().zip(states.iter_mut()) {
+            let (target, value) = match (route.target(), self.route_source(route.from)) {
+                (Some(target), Some(value)) => (target, value),
+                _ => continue
+            };
+            match target {
+                RouteTarget::Relative(axis) => {
+                    if let Some(last) = state.last.replace(value) {
+                        let moved = state.remainder + (value - last) * 100.0 * route.scale as f64;
+                        state.remainder = moved.fract();
+                        if moved.trunc() != 0.0 {
+                            out.push(rel(axis, moved.trunc() as i32));
+                        }
+                    }
+                },
+                RouteTarget::Key(k) => {
+                    let held = value * 100.0 >= route.threshold as f64;
+                    if held != state.held {
+                        state.held = held;
+                        out.push(key(k, held as i32));
+                    }
+                }
+            }
+        }
+
+        self.routes = states;
+        out
+    }
+
+    /// Releases the keys of the routes and ends their motion, returning the events to emit.
+    fn release_routes(&mut self) -> Vec<InputEvent> {
+        let mut out = vec![];
+        for (route, state) in self.config.routes.iter().zip(self.routes.iter()) {
+            if let (true, Some(RouteTarget::Key(k))) = (state.held, route.target()) {
+                out.push(key(k, 0));
+            }
+        }
+        self.routes.clear();
+        out
+    }
+
+    /// Pulls the mapped position towards the previously reported one by the smoothing
+    /// factor, which the pressure lessens with the pressure-aware smoothing.
+    /// The first position of a contact is reported as it is.
+    fn smooth(&mut self, point: Point) -> Point {
+        let smoothing = match self.config.smoothing {
+            Some(smoothing) => smoothing,
+            None => return point
+        };
+
+        let factor = smoothing_factor(&smoothing, self.pressure_fraction());
+        let (x, y) = match self.smoothed {
+            Some((x, y)) => (
+                x * factor + point.x as f64 * (1.0 - factor),
+                y * factor + point.y as f64 * (1.0 - factor)
+            ),
+            None => (point.x as f64, point.y as f64)
+        };
+        self.smoothed = Some((x, y));
+        Point { x: x.round() as i32, y: y.round() as i32 }
+    }
+
+    /// Returns the relative motion events since the last reported position,
+    /// carrying the fractions of pixels over to the next motion.
+    /// The first position of a contact only anchors the motion.
+    fn relative_motion(&mut self) -> Vec<InputEvent> {
+        let point = Point { x: self.x, y: self.y };
+        let anchor = match self.anchor.replace(point) {
+            Some(anchor) => anchor,
+            None => return vec![]
+        };
+
+        let (dx, dy) = mapping::relative_motion((point.x - anchor.x, point.y - anchor.y), &self.config, self.bounds);
+        let (dx, dy) = (self.remainder.0 + dx, self.remainder.1 + dy);
+        self.remainder = (dx.fract(), dy.fract());
+
+        let mut out = vec![];
+        if dx.trunc() != 0.0 {
+            out.push(rel(RelativeAxisType::REL_X, dx.trunc() as i32));
+        }
+        if dy.trunc() != 0.0 {
+            out.push(rel(RelativeAxisType::REL_Y, dy.trunc() as i32));
+        }
+        out
+    }
+
+    /// Processes a touchpad event, returning the events to emit.
+    pub fn process(&mut self, event: InputEvent) -> Vec<InputEvent> {
+        self.write_record(Record::Touchpad(RawEvent::new(&event)));
+        match event.kind() {
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => {
+                self.x = event.value();
+                self.moved = true;
+            },
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => {
+                self.y = event.value();
+                self.moved = true;
+            },
+            InputEventKind::AbsAxis(axis) if axis == self.config.tablet.pressure_source.axis()
+                && self.config.tablet.pressure_device.is_none() => {
+                self.pressure = event.value();
+            },
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_SLOT) => {
+                self.slot = event.value().max(0) as usize;
+            },
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+                if let Some(slot) = self.current_slot() {
+                    slot.active = event.value() != -1;
+                }
+            },
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                if let Some(slot) = self.current_slot() {
+                    slot.x = event.value();
+                }
+            },
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                if let Some(slot) = self.current_slot() {
+                    slot.y = event.value();
+                }
+            },
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_ORIENTATION) => {
+                if let Some(slot) = self.current_slot() {
+                    slot.orientation = event.value();
+                }
+            },
+            InputEventKind::Key(Key::BTN_TOUCH) => {
+                self.touching = event.value() != 0;
+            },
+            InputEventKind::Key(_) if self.config.pad.as_ref().is_some_and(|pad| pad.device.is_none())
+                && self.pad_held(&event).is_some() => {
+                if let Some((button, held)) = self.pad_held(&event) {
+                    self.set_pad_button(button, held);
+                }
+            },
+            InputEventKind::Key(_) if self.config.stylus_buttons.as_ref().is_some_and(|stylus| stylus.device.is_none())
+                && self.stylus_key_held(&event).is_some() => {
+                if let Some((key, held)) = self.stylus_key_held(&event) {
+                    return self.hold_stylus_key(key, held);
+                }
+            },
+            InputEventKind::Key(_) if self.config.eraser.as_ref().is_some_and(|eraser| eraser.device.is_none())
+                && self.eraser_held(&event).is_some() => {
+                let erasing = self.eraser_held(&event).unwrap_or_default();
+                return self.switch_tool(erasing);
+            },
+            InputEventKind::Key(_) if self.config.activation.as_ref().is_some_and(|a| a.device.is_none())
+                && self.activation_held(&event).is_some() => {
+                let held = self.activation_held(&event).unwrap_or_default();
+                return self.activate(held);
+            },
+            InputEventKind::Key(Key::BTN_TOOL_DOUBLETAP) => self.set_tool(0, event.value()),
+            InputEventKind::Key(Key::BTN_TOOL_TRIPLETAP) => self.set_tool(1, event.value()),
+            InputEventKind::Key(Key::BTN_TOOL_QUADTAP) => self.set_tool(2, event.value()),
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                let time = event.timestamp().duration_since(UNIX_EPOCH).unwrap_or_default();
+                return self.report(time);
+            },
+            _ => ()
+        }
+
+        vec![]
+    }
+
+    /// Processes an event of the separate pressure device, returning the events to emit.
+    /// The latest pressure is used by the following touchpad reports, as the touchpad
+    /// leads the stream. A pressure report while a settled contact is on the touchpad
+    /// is merged with the touchpad state and reported right away, so the pressure
+    /// changes even while the finger rests.
+    pub fn process_pressure(&mut self, event: InputEvent) -> Vec<InputEvent> {
+        self.write_record(Record::Pressure(RawEvent::new(&event)));
+        match event.kind() {
+            InputEventKind::AbsAxis(axis) if axis == self.config.tablet.pressure_source.axis() => {
+                self.pressure = event.value();
+                self.pressure_lost = false;
+            },
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) if self.contact && !self.settling => {
+                let time = event.timestamp().duration_since(UNIX_EPOCH).unwrap_or_default();
+                return self.report(time);
+            },
+            _ => ()
+        }
+
+        vec![]
+    }
+
+    /// Draws with the full pressure after the separate pressure device is disconnected,
+    /// returning the events to emit.
+    pub fn lose_pressure(&mut self) -> Vec<InputEvent> {
+        self.write_record(Record::PressureLost);
+        self.pressure_lost = true;
+        if self.down {
+            return self.flush(self.last_report.unwrap_or_default(), true);
+        }
+
+        vec![]
+    }
+
+    /// Returns the slot the multitouch events are about, adding it if it's new,
+    /// or `None` if it's past the most slots kept.
+    fn current_slot(&mut self) -> Option<&mut Slot> {
+        if self.slot >= SLOTS_MAX {
+            return None;
+        }
+        if self.slots.len() <= self.slot {
+            self.slots.resize(self.slot + 1, Slot::default());
+        }
+        Some(&mut self.slots[self.slot])
+    }
+
+    /// Returns the emulated tilt of the pen on the output, upright if the touchpad
+    /// doesn't tell the direction, or `None` if the tilt isn't emulated.
+    fn tilt(&self) -> Option<(i32, i32)> {
+        let tilt = self.config.tilt?;
+        let mut contacts = self.slots.iter().filter(|slot| slot.active);
+        let first = contacts.next();
+        let direction = match tilt.source {
+            TiltSource::Orientation => first.zip(self.bounds.orientation)
+                .map(|(first, max)| orientation_direction(first.orientation, max)),
+            TiltSource::TwoFinger => first.zip(contacts.next())
+                .map(|(first, second)| ((second.x - first.x) as f64, (second.y - first.y) as f64))
+        };
+
+        match direction {
+            Some(direction) => Some(tilt_axes(mapping::map_direction(direction, &self.config), tilt.angle)),
+            None => Some((0, 0))
+        }
+    }
+
+    /// Returns whether the settling touch is stable or out of time,
+    /// remembering the sample to compare the next one with otherwise.
+    /// The position is still reported as moved once it settles.
+    fn settled(&mut self, time: Duration) -> bool {
+        let sample = Point { x: self.x, y: self.y };
+        let tolerance_x = (self.bounds.max_x - self.bounds.min_x) as f64 * SETTLE_TOLERANCE;
+        let tolerance_y = (self.bounds.max_y - self.bounds.min_y) as f64 * SETTLE_TOLERANCE;
+        let stable = self.settle_sample.is_some_and(|last| {
+            ((sample.x - last.x).abs() as f64) <= tolerance_x && ((sample.y - last.y).abs() as f64) <= tolerance_y
+        });
+
+        if stable || self.settle_deadline().is_some_and(|deadline| time >= deadline) {
+            self.settling = false;
+            self.moved = true;
+            return true;
+        }
+
+        self.settle_sample = Some(sample);
+        false
+    }
+
+    /// Returns the pressure of the contact within the pressure range of the touchpad,
+    /// turned around if it's inverted, or `None` if the touchpad has no pressure.
+    /// The pressure is full once the separate pressure device is lost.
+    fn pressure(&self) -> Option<i32> {
+        let (min, max) = self.bounds.pressure?;
+        if self.pressure_lost {
+            return Some(min.max(max));
+        }
+        match self.config.tablet.invert_pressure {
+            true => Some(min + max - self.pressure.clamp(min.min(max), max.max(min))),
+            false => Some(self.pressure)
+        }
+    }
+
+    /// Returns the pressure the tablet reports for the contact, shaped by
+    /// the pressure curve within the pressure range, or `None` if the touchpad
+    /// has no pressure. The full pressure after losing the pressure device stays full.
+    fn reported_pressure(&self) -> Option<i32> {
+        let pressure = self.pressure()?;
+        let curve = &self.config.tablet.pressure_curve;
+        let (min, max) = self.bounds.pressure?;
+        if curve.is_empty() || self.pressure_lost || min == max {
+            return Some(pressure);
+        }
+
+        let (low, high) = (min.min(max) as f64, min.max(max) as f64);
+        let percent = (pressure as f64 - low) / (high - low) * 100.0;
+        Some((low + pressure_curve(curve, percent) / 100.0 * (high - low)).round() as i32)
+    }
+
+    /// Returns the pressure of the contact as a fraction of the pressure range,
+    /// or `None` if the touchpad has no pressure.
+    fn pressure_fraction(&self) -> Option<f64> {
+        let pressure = self.pressure()?;
+        let (min, max) = self.bounds.pressure?;
+        let (low, high) = (min.min(max) as f64, min.max(max) as f64);
+        if high == low {
+            return None;
+        }
+        Some(((pressure as f64 - low) / (high - low)).clamp(0.0, 1.0))
+    }
+
+    /// Returns whether the contact is pressed hard enough to draw.
+    /// Touchpads without pressure always are.
+    fn pressed(&self) -> bool {
+        self.pressure().is_none_or(|pressure| pressure >= self.config.tablet.touch_threshold as i32)
+    }
+
+    /// Records whether the touchpad reports the multi-finger tool bit.
+    fn set_tool(&mut self, bit: u8, value: i32) {
+        if value != 0 {
+            self.tools |= 1 << bit;
+        } else {
+            self.tools &= !(1 << bit);
+        }
+    }
+
+    /// Returns whether the lift of the contact is held back, as the finger
+    /// may touch down again within the debounce time, continuing the contact.
+    /// The lift is reported at the time it happened once the time passes.
+    fn debounced(&mut self, time: Duration) -> bool {
+        if self.touching || !self.contact || self.config.tablet.contact_debounce_ms == 0 {
+            self.dropped_at = None;
+            return false;
+        }
+
+        let debounce = Duration::from_millis(self.config.tablet.contact_debounce_ms as u64);
+        time < *self.dropped_at.get_or_insert(time) + debounce
+    }
+
+    /// Tracks the start and the end of the contacts,
+    /// deciding whether a new contact hovers instead of drawing.
+    fn track_contact(&mut self, time: Duration) {
+        let tablet = &self.config.tablet;
+        let double_tap = Duration::from_millis(tablet.double_tap_ms as u64);
+
+        if self.touching && !self.contact {
+            self.hovering = tablet.hover_mode == HoverMode::DoubleTap
+                && self.last_tap.is_some_and(|tap| time.saturating_sub(tap) <= double_tap);
+            self.touch_start = time;
+            self.anchor = None;
+            self.remainder = (0.0, 0.0);
+            self.smoothed = None;
+            self.last_tap = None;
+            self.lifted_at = None;
+            self.settling = tablet.touch_settle_ms > 0;
+            self.settle_sample = None;
+        } else if !self.touching && self.contact {
+            self.settling = false;
+            let quick = time.saturating_sub(self.touch_start) <= double_tap;
+            if tablet.hover_mode == HoverMode::DoubleTap && !self.hovering && quick {
+                self.last_tap = Some(time);
+            }
+            self.hovering = false;
+            if !tablet.hover_on_lift && tablet.lift_timeout_ms > 0 {
+                self.lifted_at = Some(time);
+            }
+        }
+
+        self.contact = self.touching;
+    }
+
+    /// Returns the events describing the changes since the last report.
+    /// The pen comes into proximity before the first position of a stroke
+    /// and leaves after it's lifted, unless it should hover on lift
+    /// or until the lift-off timeout. Hovering contacts move the pen
+    /// without touching down, as do the contacts lighter than the touch
+    /// threshold. A new touch isn't reported until its position
+    /// is stable or the settle time passes. Positions and pressures coming
+    /// faster than the report rate are held back, but touch changes
+    /// are never delayed, except for the lifts held back by the contact debounce.
+    /// In the relative mode the pen moves by the motion since the last report
+    /// instead of to the mapped position.
+    fn report(&mut self, time: Duration) -> Vec<InputEvent> {
+        if self.debounced(time) {
+            return vec![];
+        }
+        let time = self.dropped_at.take().unwrap_or(time);
+
+        self.track_contact(time);
+        let mut out = match self.eraser_tapped(time) {
+            true => self.switch_tool(!self.erasing),
+            false => vec![]
+        };
+        self.pad_tapped(time);
+        if self.suspended() {
+            self.moved = false;
+            self.pending = false;
+            return out;
+        }
+
+        // Multi-finger sequences are left to the system as gestures until every finger lifts.
+        if self.config.tablet.passthrough_gestures && self.touching && self.fingers() > 1 {
+            self.gesture = true;
+        }
+        if self.gesture {
+            self.gesture = self.touching;
+            self.moved = false;
+            self.pending = false;
+            return self.release();
+        }
+
+        if self.settling && !self.settled(time) {
+            return out;
+        }
+
+        let multi_finger = self.fingers() > 1 && self.config.tablet.hover_mode == HoverMode::TwoFinger;
+        let drawing = self.touching && !self.hovering && !multi_finger && self.pressed();
+
+        let changed = drawing != self.down || (self.touching && !self.proximity);
+        let due = match (self.last_report, self.interval()) {
+            (Some(last), Some(interval)) => time >= last + interval,
+            _ => true
+        };
+
+        if self.touching && !self.proximity {
+            self.rubber = self.erasing;
+            out.push(key(self.tool(), 1));
+            self.proximity = true;
+        }
+
+        if changed || due {
+            out.extend(self.flush(time, drawing));
+        } else if self.moved || drawing {
+            self.pending = true;
+        }
+
+        if drawing != self.down {
+            if !drawing {
+                out.push(abs(AbsoluteAxisType::ABS_PRESSURE, 0));
+            }
+            out.push(key(Key::BTN_TOUCH, drawing as i32));
+            self.down = drawing;
+        }
+
+        if !self.touching {
+            out.extend(self.release_routes());
+        }
+
+        if !self.touching && self.proximity && !self.config.tablet.hover_on_lift && self.lifted_at.is_none() {
+            out.push(key(self.tool(), 0));
+            self.proximity = false;
+        }
+
+        out
+    }
+}
+
+/// Returns the direction the pen leans in for a contact with the orientation,
+/// given the orientation a quarter turn clockwise from the Y axis reports.
+/// The pen leans along the finger, towards the user.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// // A finger turned to the right leans the pen to the bottom left.
+/// let (dx, dy) = engine::orientation_direction(45, 90);
+/// ```
+pub fn orientation_direction(orientation: i32, max: i32) -> (f64, f64) {
+    let angle = orientation as f64 / max as f64 * std::f64::consts::FRAC_PI_2;
+    (-angle.sin(), angle.cos())
+}
+
+/// Returns the values of the tilt axes for a pen leaning by the angle
+/// in degrees in the direction, which is on the output, with Y going down.
+/// A zero direction leaves the pen upright.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(engine::tilt_axes((1.0, 0.0), 45), (45, 0));
+/// ```
+pub fn tilt_axes(direction: (f64, f64), angle: u8) -> (i32, i32) {
+    let (dx, dy) = direction;
+    let length = dx.hypot(dy);
+    if length == 0.0 || !length.is_finite() {
+        return (0, 0);
+    }
+
+    let angle = angle.min(90) as f64;
+    ((dx / length * angle).round() as i32, (dy / length * angle).round() as i32)
+}
+
+/// Returns the output percent of the pressure curve for the input percent,
+/// interpolating linearly between the control points and keeping
+/// the output of the first or the last point outside them.
+/// The outputs are clamped to 100, and no points leave the pressure as it is.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(engine::pressure_curve(&[[0, 0], [50, 25], [100, 100]], 25.0), 12.5);
+/// ```
+pub fn pressure_curve(points: &[[u8; 2]], input: f64) -> f64 {
+    let input = input.clamp(0.0, 100.0);
+    let point = |[x, y]: [u8; 2]| (x as f64, y.min(100) as f64);
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (point(*first), point(*last)),
+        _ => return input
+    };
+    if input <= first.0 {
+        return first.1;
+    }
+    if input >= last.0 {
+        return last.1;
+    }
+
+    points.windows(2)
+        .map(|pair| (point(pair[0]), point(pair[1])))
+        .find(|(_, end)| input <= end.0)
+        .map_or(last.1, |(start, end)| start.1 + (input - start.0) / (end.0 - start.0) * (end.1 - start.1))
+}
+
+/// Returns how much of the previous position the smoothing keeps for the pressure,
+/// given as a fraction of the pressure range. The pressure-aware smoothing fades
+/// linearly from the factor without pressure to none at the full pressure.
+/// Without the pressure, the factor is kept.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let smoothing = config::Smoothing { factor: 0.5, pressure_aware: true };
+/// assert_eq!(engine::smoothing_factor(&smoothing, Some(0.5)), 0.25);
+/// ```
+pub fn smoothing_factor(smoothing: &Smoothing, pressure: Option<f64>) -> f64 {
+    let factor = smoothing.factor.clamp(0.0, 1.0) as f64;
+    match (smoothing.pressure_aware, pressure) {
+        (true, Some(pressure)) => factor * (1.0 - pressure.clamp(0.0, 1.0)),
+        _ => factor
+    }
+}
+
+/// Returns an absolute axis event.
+fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+}
+
+/// Returns a relative axis event.
+fn rel(axis: RelativeAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::RELATIVE, axis.0, value)
+}
+
+/// Returns the event ending a report.
+fn syn() -> InputEvent {
+    InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+}
+
+/// Returns a key event.
+fn key(key: Key, value: i32) -> InputEvent {
+    InputEvent::new(EventType::KEY, key.code(), value)
+}
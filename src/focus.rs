@@ -0,0 +1,198 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::time::Duration;
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
+
+use crate::config::FocusRule;
+use crate::monitor::{query, x_command};
+
+/// How often the focused window is checked.
+const FOCUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Matches the focused window id in the `xprop -root _NET_ACTIVE_WINDOW` output.
+static ACTIVE_WINDOW: Lazy<Regex> = Lazy::new(|| Regex::new(r"window id # (0x[0-9a-fA-F]+)").unwrap());
+
+/// Matches the instance and the class in the `xprop WM_CLASS` output.
+static WM_CLASS: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^WM_CLASS\(\w+\) = "((?:[^"\\]|\\.)*)", "((?:[^"\\]|\\.)*)""#).unwrap());
+
+/// Matches the title in the `xprop _NET_WM_NAME` output.
+static WM_NAME: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^_NET_WM_NAME\(\w+\) = "(.*)"$"#).unwrap());
+
+/// A window as the focus rules see it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// focus::Window {
+///     instance: String::from("krita"),
+///     class: String::from("krita"),
+///     title: String::from("Untitled - Krita")
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub instance: String,
+    pub class: String,
+    pub title: String
+}
+
+/// Returns the focused window id in the `xprop -root _NET_ACTIVE_WINDOW` output,
+/// or `None` if no window is focused.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(focus::parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007"), Some(String::from("0x3a00007")));
+/// ```
+pub fn parse_active_window(output: &str) -> Option<String> {
+    let caps = ACTIVE_WINDOW.captures(output).ok()??;
+    let id = caps[1].to_string();
+    if u64::from_str_radix(id.trim_start_matches("0x"), 16).ok()? == 0 {
+        return None;
+    }
+
+    Some(id)
+}
+
+/// Removes the backslashes escaping the characters of an `xprop` string.
+fn unescape(string: &str) -> String {
+    let mut result = String::new();
+    let mut chars = string.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            result.extend(chars.next());
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// Parses the `xprop -id <id> WM_CLASS _NET_WM_NAME` output.
+/// Missing properties are left empty.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let window = focus::parse_window("WM_CLASS(STRING) = \"krita\", \"krita\"\n_NET_WM_NAME(UTF8_STRING) = \"Untitled\"");
+/// ```
+pub fn parse_window(output: &str) -> Window {
+    let mut window = Window::default();
+    for line in output.lines() {
+        if let Ok(Some(caps)) = WM_CLASS.captures(line) {
+            window.instance = unescape(&caps[1]);
+            window.class = unescape(&caps[2]);
+        } else if let Ok(Some(caps)) = WM_NAME.captures(line) {
+            window.title = unescape(&caps[1]);
+        }
+    }
+
+    window
+}
+
+/// Returns the focused window of the X server, or `None`
+/// if no window is focused or the X server isn't reachable.
+pub fn active_window() -> Option<Window> {
+    let root = query(x_command("xprop").args(["-root", "_NET_ACTIVE_WINDOW"]))?;
+    let id = parse_active_window(root.as_str())?;
+    let output = query(x_command("xprop").args(["-id", id.as_str(), "WM_CLASS", "_NET_WM_NAME"]))?;
+    Some(parse_window(output.as_str()))
+}
+
+/// Returns whether the pattern is found in any of the values,
+/// or `true` if there's no pattern. Invalid patterns never match.
+fn matches(pattern: &Option<String>, values: &[&str]) -> bool {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return true
+    };
+
+    match Regex::new(pattern) {
+        Ok(re) => values.iter().any(|value| re.is_match(value).unwrap_or(false)),
+        Err(_) => false
+    }
+}
+
+/// Returns the profile of the first rule matching the window,
+/// or `None` if no rule matches it.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(window) = focus::active_window() {
+///     let profile = focus::matching_profile(&conf.focus_rules, &window);
+/// }
+/// ```
+pub fn matching_profile<'a>(rules: &'a [FocusRule], window: &Window) -> Option<&'a str> {
+    rules.iter()
+        .find(|rule| matches(&rule.class, &[window.class.as_str(), window.instance.as_str()])
+            && matches(&rule.title, &[window.title.as_str()]))
+        .map(|rule| rule.profile.as_str())
+}
+
+/// Checks the focused window from time to time, telling which profile
+/// it wants once it differs from the one in use. `None` stands for
+/// the config file, used when no rule matches.
+pub struct FocusWatcher {
+    rules: Vec<FocusRule>,
+    profile: Option<String>,
+    next_check: Duration
+}
+
+impl FocusWatcher {
+    /// Creates the watcher of the focus rules, starting with the config file in use.
+    pub fn new(rules: Vec<FocusRule>, now: Duration) -> FocusWatcher {
+        FocusWatcher {
+            rules,
+            profile: None,
+            next_check: now
+        }
+    }
+
+    /// Returns when the focused window should be checked next.
+    pub fn deadline(&self) -> Duration {
+        self.next_check
+    }
+
+    /// Returns the profile the window wants if it differs from the one in use,
+    /// taking it as in use, or `None` if nothing changes.
+    pub fn switch(&mut self, window: Option<&Window>) -> Option<Option<String>> {
+        let profile = window
+            .and_then(|window| matching_profile(&self.rules, window))
+            .map(String::from);
+        if profile == self.profile {
+            return None;
+        }
+
+        self.profile = profile.clone();
+        Some(profile)
+    }
+
+    /// Checks the focused window if it's time to, returning the profile
+    /// to switch to like `switch`.
+    pub fn check(&mut self, now: Duration) -> Option<Option<String>> {
+        if now < self.next_check {
+            return None;
+        }
+
+        self.next_check = now + FOCUS_INTERVAL;
+        self.switch(active_window().as_ref())
+    }
+}
@@ -0,0 +1,139 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+
+use crate::stdout;
+
+/// The importance of a message, from the most to the least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug
+}
+
+impl Level {
+    /// Returns the name of the level as it's written to the log file.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG"
+        }
+    }
+}
+
+/// The least important level that is printed and logged.
+static VERBOSITY: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// The file the messages are appended to, if any.
+static LOG_FILE: Lazy<Mutex<Option<File>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets the least important level that is printed and logged.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// log::set_verbosity(log::Level::Debug);
+/// ```
+pub fn set_verbosity(level: Level) {
+    VERBOSITY.store(level as u8, Ordering::SeqCst);
+}
+
+/// Returns whether the messages of the level are printed and logged.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if log::enabled(log::Level::Debug) {
+///     println!("{:?}", bounds);
+/// }
+/// ```
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= VERBOSITY.load(Ordering::SeqCst)
+}
+
+/// Appends the messages to the file in addition to printing them,
+/// or stops logging to a file with `None`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// log::set_log_file(Some(Path::new("/tmp/egawari.log"))).unwrap();
+/// ```
+pub fn set_log_file(path: Option<&Path>) -> Result<()> {
+    let file = match path {
+        Some(path) => Some(OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Couldn't open the log file: {}", path.display()))?),
+        None => None
+    };
+
+    *LOG_FILE.lock().unwrap() = file;
+    Ok(())
+}
+
+/// Appends the message to the log file with a timestamp and the level,
+/// without the color escapes. Does nothing if there's no log file
+/// or the level isn't enabled.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// // "2021-06-01 12:00:00.000 [ERROR] Couldn't read from the input device."
+/// log::write(log::Level::Error, "Couldn't read from the input device.");
+/// ```
+pub fn write(level: Level, msg: &str) {
+    if !enabled(level) {
+        return;
+    }
+
+    let mut file = LOG_FILE.lock().unwrap();
+    if let Some(file) = file.as_mut() {
+        let msg = stdout::strip_escapes(msg);
+        for line in msg.trim_end_matches('\n').lines() {
+            // A failing log file shouldn't stop the daemon.
+            let _ = writeln!(file, "{} [{}] {}", timestamp(), level.as_str(), line);
+        }
+    }
+}
+
+/// Returns the current local time, like `2021-06-01 12:00:00.000`.
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday,
+        tm.tm_hour, tm.tm_min, tm.tm_sec, now.subsec_millis()
+    )
+}
@@ -21,6 +21,10 @@ extern crate pancurses;
 extern crate anyhow;
 extern crate dirs;
 extern crate toml;
+extern crate once_cell;
+extern crate evdev;
+extern crate libc;
+extern crate serde_json;
 
 use std::env;
 use std::process;
@@ -28,7 +32,24 @@ use anyhow::Result;
 
 #[macro_use]
 pub mod stdout;
+pub mod args;
+pub mod log;
 pub mod config;
+pub mod profile;
+pub mod device;
+pub mod monitor;
+pub mod area;
+pub mod cursor;
+pub mod overlay;
+pub mod focus;
+pub mod mapping;
+pub mod engine;
+pub mod record;
+pub mod stats;
+pub mod daemon;
+pub mod run;
+pub mod watch;
+pub mod completions;
 
 #[cfg(test)]
 mod tests;
@@ -43,43 +64,127 @@ fn help() {
     colln!("---===Commands==---");
     logln!("help => Shows this text.");
     logln!("config => Edits or shows the egawari configuration interactively.");
+    logln!("init => Writes the default configuration to the config file to edit it.");
+    logln!("reset => Restores the default configuration.");
+    logln!("purge, uninstall => Removes the config directory with the profiles and the runtime files, after a confirmation.");
+    logln!("export <file> => Writes the configuration to the file.");
+    logln!("import <file> => Validates the configuration in the file and uses it.");
+    logln!("get [key] => Shows the configuration value at the key, like mapping.flip_x.");
+    logln!("list-keys => Lists the configuration keys with their type, default and description.");
+    logln!("print-config => Prints the configuration in effect as TOML, with the files it's read from and where each value comes from.");
+    logln!("devices => Lists the input devices with their vendor:product IDs and whether they report the positions, pressure and contacts a touchpad needs.");
+    logln!("monitors, screens => Lists the monitors and their geometry.");
+    logln!("select-area [monitor [left top right bottom]] => Maps the touchpad onto a monitor without the inset percents, chosen interactively without arguments.");
+    logln!("doctor => Checks that egawari can create the virtual tablet, telling how to fix it otherwise.");
+    logln!("udev-rule => Prints the udev rule letting the input group create the virtual tablet, installing it with --install.");
+    logln!("status => Shows the state of the running egawari.");
+    logln!("run => Maps the touchpad to a virtual graphics tablet. Try it first with --safe.");
+    logln!("replay <file> => Prints the tablet events of the input events recorded with --dump-events.");
+    logln!("map-point <x> <y> => Prints where the raw touchpad position is mapped on the output, with the stages of the mapping with --verbose.");
+    logln!("active-area => Prints the rectangle of the output in pixels the strokes land in, and the monitors it lies on.");
+    logln!("test-output => Draws a square with a virtual tablet to check the output without a touchpad.");
+    logln!("watch => Shows the touchpad values live with the positions they're mapped to.");
+    logln!("toggle => Pauses or resumes the mapping of the running egawari.");
+    logln!("profile list => Lists the saved configuration profiles.");
+    logln!("profile save <name> => Saves the configuration as the named profile.");
+    logln!("profile load <name> => Loads the named profile as the configuration.");
+    logln!("profile delete <name> => Deletes the named profile.");
+    logln!("completions <shell> => Prints the completion script for bash, zsh or fish.");
+    println!();
+    colln!("---===Options===---");
+    logln!("--config <path> => Uses the given config file instead of the user's, without the system-wide one.");
+    logln!("--no-wrap => Stops the cursor of config at the first and the last key.");
+    logln!("--no-save => Opens config to only view the configuration, never saving it.");
+    logln!("--autosave <seconds> => Autosaves the unsaved changes of config every given seconds instead of after every change.");
+    logln!("--no-watch => Keeps the configuration of run instead of reloading it when the file changes.");
+    logln!("--dry-run => Prints the tablet events of run instead of emitting them.");
+    logln!("--safe => Runs without grabbing the touchpad and only prints the tablet events, so the session is left alone.");
+    logln!("--stats => Logs the event rates, the latency and the dropped and coalesced reports of run every 5 seconds.");
+    logln!("--timeout <seconds> => Stops run after the seconds like Ctrl-C does, removing the virtual tablet, for scripts and demos.");
+    logln!("--overlay => Outlines the mapped area of run on the screen under X11, even without the overlay section in the configuration.");
+    logln!("--dump-events <file> => Records the input events of run to the file, to replay them later.");
+    logln!("--json => Prints get, list-keys, print-config, devices, monitors, status, map-point and active-area as JSON.");
+    logln!("--install => Installs the udev rule of udev-rule to /etc/udev/rules.d/, which needs root.");
+    logln!("--bounds <min_x,max_x,min_y,max_y> => Maps map-point and active-area within the given bounds instead of the touchpad's.");
+    logln!("--color <auto|always|never> => Colors the output when stdout is a terminal and NO_COLOR isn't set, always or never. It wins over NO_COLOR.");
+    logln!("-v, --verbose => Also prints and logs the debugging messages.");
+    logln!("-q, --quiet => Only prints and logs the warnings and errors.");
+    logln!("--force => Lets purge remove the files and run start while egawari is running or the touchpad is grabbed.");
+    logln!("-y, --yes => Skips the confirmation of reset, import and purge, and lets init overwrite the config file.");
+    logln!("-- => Makes the rest of the arguments not options.");
     println!();
     colln!("---=============---");
 }
 
 fn main() -> Result<()> {
-    let mut args: Vec<String> = vec![];
-    let mut opts: Vec<String> = vec![];
+    let raw_args: Vec<String> = env::args().skip(1).collect();
+    let parsed = match args::parse(&raw_args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            errln!("{}", e);
+            logln!("See: \x1b[0;39megawari help");
+            process::exit(1);
+        }
+    };
+    let opts = &parsed.options;
+    let args = &parsed.args;
 
-    let raw_args: Vec<String> = env::args().collect();
-    for raw in &raw_args[1..] {
-        if raw.starts_with("-") {
-            if raw.starts_with("--") {
-                opts.push(raw[2..].to_string());
-                continue;
-            }
+    stdout::set_json(opts.json);
+    if let Some(mode) = opts.color {
+        stdout::set_color_mode(mode);
+    }
+    if opts.verbose {
+        log::set_verbosity(log::Level::Debug);
+    } else if opts.quiet {
+        log::set_verbosity(log::Level::Warn);
+    }
 
-            for c in raw[1..].chars() {
-                opts.push(c.to_string());
-            }
-            continue;
+    let command = match &parsed.command {
+        Some(command) => command,
+        None => {
+            errln!("No command provided.");
+            logln!("See: \x1b[0;39megawari help");
+            process::exit(1);
         }
+    };
 
-        args.push(raw.to_string());
+    if opts.config.is_some() {
+        config::set_config_path(opts.config.clone());
     }
-
-    if args.len() < 1 {
-        errln!("No command provided.");
-        logln!("See: \x1b[0;39megawari help");
-        process::exit(1);
+    if let Some(theme) = config::get_config().ok().and_then(|conf| conf.theme) {
+        stdout::set_palette(theme.palette());
     }
 
-    let command = &args[0].to_string();
-    args.remove(0);
-
     match command.as_str() {
         "help" => help(),
-        "config" => config::config_interactive()?,
+        "config" => config::config_interactive(config::EditorOptions {
+            wrap: !opts.no_wrap,
+            read_only: opts.no_save,
+            autosave_secs: opts.autosave.unwrap_or(0)
+        })?,
+        "init" => config::init(opts)?,
+        "reset" => config::reset(opts)?,
+        "purge" | "uninstall" => config::purge(opts)?,
+        "export" => config::export(args)?,
+        "import" => config::import(args, opts)?,
+        "get" => config::get(args)?,
+        "list-keys" => config::list_keys()?,
+        "print-config" => config::print_config()?,
+        "devices" => device::devices()?,
+        "monitors" | "screens" => monitor::monitors()?,
+        "doctor" => device::doctor()?,
+        "udev-rule" => device::udev_rule(opts)?,
+        "status" => daemon::status()?,
+        "profile" => profile::profile(args)?,
+        "run" => run::run(opts)?,
+        "replay" => record::replay_file(args)?,
+        "map-point" => run::map_point(args, opts)?,
+        "active-area" => run::active_area(opts)?,
+        "test-output" => run::test_output()?,
+        "watch" => watch::watch()?,
+        "select-area" => area::select_area(args)?,
+        "toggle" => daemon::toggle()?,
+        "completions" => completions::completions(args)?,
         _ => {
             errln!("Unknown command: \x1b[0;39m{}", command);
             logln!("See: \x1b[0;39megawari help");
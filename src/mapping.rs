@@ -0,0 +1,403 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use serde::Serialize;
+use crate::config::{Area, Config, EdgeSnap, MappingTarget, Quad};
+use crate::device::Bounds;
+
+/// The tablet resolution in units per millimeter if the physical size is unknown,
+/// as the units are pixels and screens are assumed to be 96 DPI.
+pub const DEFAULT_RESOLUTION: i32 = 4;
+
+/// A position on the touchpad or on the output.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// mapping::Point { x: 100, y: 200 }
+/// ```
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32
+}
+
+/// Returns whether the touchpad reports an empty or inverted range on either axis.
+pub fn degenerate(bounds: Bounds) -> bool {
+    bounds.max_x <= bounds.min_x || bounds.max_y <= bounds.min_y
+}
+
+/// Returns the bounds to map the touchpad with: its own, or else
+/// the fallback bounds of the config if its ranges are degenerate.
+/// Returns `None` if neither is usable.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let bounds = mapping::usable_bounds(&conf, device::input_bounds(&input, conf.tablet.pressure_source.axis())?).unwrap();
+/// ```
+pub fn usable_bounds(cfg: &Config, bounds: Bounds) -> Option<Bounds> {
+    if !degenerate(bounds) {
+        return Some(bounds);
+    }
+
+    let fallback = cfg.input.fallback_bounds?;
+    let fallback = Bounds {
+        min_x: fallback.min_x,
+        max_x: fallback.max_x,
+        min_y: fallback.min_y,
+        max_y: fallback.max_y,
+        pressure: bounds.pressure,
+        orientation: bounds.orientation
+    };
+    Some(fallback).filter(|fallback| !degenerate(*fallback))
+}
+
+/// Returns the size of the output in pixels.
+/// Defaults to the resolution of the touchpad.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (width, height) = mapping::output_size(&conf, bounds);
+/// ```
+pub fn output_size(cfg: &Config, bounds: Bounds) -> (u32, u32) {
+    let size = |min: i32, max: i32| (max as i64 - min as i64 + 1).clamp(1, u32::MAX as i64) as u32;
+    let width = cfg.mapping.width.unwrap_or_else(|| size(bounds.min_x, bounds.max_x));
+    let height = cfg.mapping.height.unwrap_or_else(|| size(bounds.min_y, bounds.max_y));
+    (width, height)
+}
+
+/// Returns the bounds of the virtual tablet, covering the output.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let tablet = device::create_tablet(device::VIRTUAL_NAME, &mapping::output_bounds(&conf, bounds), (4, 4), false, false, &[]).unwrap();
+/// ```
+pub fn output_bounds(cfg: &Config, bounds: Bounds) -> Bounds {
+    let (width, height) = output_size(cfg, bounds);
+    Bounds {
+        min_x: 0,
+        max_x: width.saturating_sub(1) as i32,
+        min_y: 0,
+        max_y: height.saturating_sub(1) as i32,
+        pressure: bounds.pressure,
+        orientation: None
+    }
+}
+
+/// Returns the area of the output the touchpad is mapped onto,
+/// placed by its anchor unless the target monitors give it.
+/// With `keep_aspect` the area is shrunk and centered
+/// to match the aspect ratio of the (rotated) touchpad.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let area = mapping::target_area(&conf, bounds);
+/// println!("{}x{}+{}+{}", area.width, area.height, area.x, area.y);
+/// ```
+pub fn target_area(cfg: &Config, bounds: Bounds) -> Area {
+    let (width, height) = output_size(cfg, bounds);
+    let area = match cfg.mapping.area {
+        Some(area) if cfg.mapping.target == MappingTarget::Area => cfg.mapping.anchor.place(area, width, height),
+        Some(area) => area,
+        None => Area { x: 0, y: 0, width, height, width_mm: None, height_mm: None }
+    };
+
+    if !cfg.mapping.keep_aspect || area.width == 0 || area.height == 0 {
+        return area;
+    }
+
+    let (pad_width, pad_height) = match cfg.mapping.rotation % 360 {
+        90 | 270 => (bounds.max_y - bounds.min_y, bounds.max_x - bounds.min_x),
+        _ => (bounds.max_x - bounds.min_x, bounds.max_y - bounds.min_y)
+    };
+    if pad_width <= 0 || pad_height <= 0 {
+        return area;
+    }
+
+    let aspect = pad_width as f64 / pad_height as f64;
+    if area.width as f64 / area.height as f64 > aspect {
+        let letterboxed = (area.height as f64 * aspect).round() as u32;
+        Area {
+            x: area.x + (area.width - letterboxed) / 2,
+            width: letterboxed,
+            ..area
+        }
+    } else {
+        let letterboxed = (area.width as f64 / aspect).round() as u32;
+        Area {
+            y: area.y + (area.height - letterboxed) / 2,
+            height: letterboxed,
+            ..area
+        }
+    }
+}
+
+/// Returns the area of the output the pen can reach, which is
+/// the whole output for a quad and else the target area.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let area = mapping::reach_area(&conf, bounds);
+/// ```
+pub fn reach_area(cfg: &Config, bounds: Bounds) -> Area {
+    match cfg.mapping.quad {
+        Some(_) => {
+            let (width, height) = output_size(cfg, bounds);
+            Area { x: 0, y: 0, width, height, width_mm: None, height_mm: None }
+        },
+        None => target_area(cfg, bounds)
+    }
+}
+
+/// Returns the resolution of the virtual tablet in units per millimeter,
+/// from the physical size of the area or else of the (rotated) touchpad,
+/// given its resolution. Falls back to 96 DPI if neither is known.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (res_x, res_y) = mapping::tablet_resolution(&conf, bounds, device::input_resolution(&input)?);
+/// ```
+pub fn tablet_resolution(cfg: &Config, bounds: Bounds, pad_resolution: Option<(i32, i32)>) -> (i32, i32) {
+    let area = target_area(cfg, bounds);
+    let pad_mm = pad_resolution.map(|(res_x, res_y)| {
+        let width_mm = (bounds.max_x - bounds.min_x) as f32 / res_x as f32;
+        let height_mm = (bounds.max_y - bounds.min_y) as f32 / res_y as f32;
+        match cfg.mapping.rotation % 360 {
+            90 | 270 => (height_mm, width_mm),
+            _ => (width_mm, height_mm)
+        }
+    });
+
+    let resolution = |pixels: u32, mm: Option<f32>| match mm.filter(|mm| *mm > 0.0) {
+        Some(mm) => ((pixels as f32 / mm).round() as i32).max(1),
+        None => DEFAULT_RESOLUTION
+    };
+    (
+        resolution(area.width, area.width_mm.or(pad_mm.map(|(width, _)| width))),
+        resolution(area.height, area.height_mm.or(pad_mm.map(|(_, height)| height)))
+    )
+}
+
+/// The stages a raw touchpad position goes through while it's mapped,
+/// with the positions normalized from 0 to 1 until they land on the area.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// mapping::Trace {
+///     normalized: (0.1, 0.4),
+///     scaled: (0.1, 0.4),
+///     flipped: (0.1, 0.4),
+///     rotated: (0.1, 0.4),
+///     area: Area { x: 0, y: 0, width: 1920, height: 1080, width_mm: None, height_mm: None },
+///     unsnapped: mapping::Point { x: 192, y: 432 },
+///     point: mapping::Point { x: 192, y: 432 }
+/// }
+/// ```
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Trace {
+    pub normalized: (f64, f64),
+    pub scaled: (f64, f64),
+    pub flipped: (f64, f64),
+    pub rotated: (f64, f64),
+    /// The area the position is scaled onto, or the whole output with a quad.
+    pub area: Area,
+    pub unsnapped: Point,
+    pub point: Point
+}
+
+/// Maps a raw touchpad position onto the output.
+/// The position is normalized within the bounds, scaled by the gains,
+/// flipped, rotated clockwise and then scaled onto the target area,
+/// or transformed onto the quad if there's one.
+/// Scaling and flipping happen before rotating, so they are along
+/// the axes of the touchpad, not the output. Positions near the edges
+/// of the area, or of the output with a quad, snap onto them last.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let point = mapping::map_point(mapping::Point { x: 100, y: 200 }, &conf, bounds);
+/// ```
+pub fn map_point(raw: Point, cfg: &Config, bounds: Bounds) -> Point {
+    trace_point(raw, cfg, bounds).point
+}
+
+/// Maps a raw touchpad position onto the output like `map_point`,
+/// returning every stage along the way.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let trace = mapping::trace_point(mapping::Point { x: 100, y: 200 }, &conf, bounds);
+/// println!("{:?} -> {:?}", trace.normalized, trace.point);
+/// ```
+pub fn trace_point(raw: Point, cfg: &Config, bounds: Bounds) -> Trace {
+    let normalized = (
+        normalize(raw.x, bounds.min_x, bounds.max_x),
+        normalize(raw.y, bounds.min_y, bounds.max_y)
+    );
+    let scaled = (scale(normalized.0, cfg.mapping.scale_x), scale(normalized.1, cfg.mapping.scale_y));
+    let flipped = (
+        if cfg.mapping.flip_x { 1.0 - scaled.0 } else { scaled.0 },
+        if cfg.mapping.flip_y { 1.0 - scaled.1 } else { scaled.1 }
+    );
+    let rotated = rotate(flipped.0, flipped.1, cfg.mapping.rotation);
+    let (u, v) = rotated;
+
+    let (unsnapped, area) = match &cfg.mapping.quad {
+        Some(quad) => {
+            let (x, y) = project(quad, u, v);
+            let point = Point {
+                x: x.round().max(0.0) as i32,
+                y: y.round().max(0.0) as i32
+            };
+            let (width, height) = output_size(cfg, bounds);
+            (point, Area { x: 0, y: 0, width, height, width_mm: None, height_mm: None })
+        },
+        None => {
+            let area = target_area(cfg, bounds);
+            let point = Point {
+                x: area.x as i32 + (u * area.width.saturating_sub(1) as f64).round() as i32,
+                y: area.y as i32 + (v * area.height.saturating_sub(1) as f64).round() as i32
+            };
+            (point, area)
+        }
+    };
+
+    let point = match &cfg.mapping.edge_snap {
+        Some(edge_snap) => Point {
+            x: snap(unsnapped.x, area.x, area.width, edge_snap),
+            y: snap(unsnapped.y, area.y, area.height, edge_snap)
+        },
+        None => unsnapped
+    };
+
+    Trace { normalized, scaled, flipped, rotated, area, unsnapped, point }
+}
+
+/// Turns a direction on the touchpad into the direction on the output,
+/// flipping and rotating it like the positions. The gains and the shape
+/// of the area are left out, as they'd skew the direction.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (dx, dy) = mapping::map_direction((1.0, 0.0), &conf);
+/// ```
+pub fn map_direction(direction: (f64, f64), cfg: &Config) -> (f64, f64) {
+    let (dx, dy) = direction;
+    let dx = if cfg.mapping.flip_x { -dx } else { dx };
+    let dy = if cfg.mapping.flip_y { -dy } else { dy };
+    match cfg.mapping.rotation % 360 {
+        90 => (-dy, dx),
+        180 => (-dx, -dy),
+        270 => (dy, -dx),
+        _ => (dx, dy)
+    }
+}
+
+/// Returns how far the pen moves on the output in pixels for the motion
+/// on the touchpad in the relative mode. Crossing the touchpad crosses the output
+/// before the gains, and quick motions are sped up by the acceleration.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (dx, dy) = mapping::relative_motion((10, -4), &conf, bounds);
+/// ```
+pub fn relative_motion(motion: (i32, i32), cfg: &Config, bounds: Bounds) -> (f64, f64) {
+    let (width, height) = output_size(cfg, bounds);
+    let u = motion.0 as f64 / (bounds.max_x as f64 - bounds.min_x as f64).max(1.0);
+    let v = motion.1 as f64 / (bounds.max_y as f64 - bounds.min_y as f64).max(1.0);
+    let acceleration = 1.0 + cfg.mapping.acceleration as f64 * u.hypot(v) * 100.0;
+    (
+        u * width as f64 * cfg.mapping.scale_x as f64 * acceleration,
+        v * height as f64 * cfg.mapping.scale_y as f64 * acceleration
+    )
+}
+
+/// Snaps the coordinate onto the first or the last pixel of the span
+/// starting at `start` if it's within the snapping distance of it.
+fn snap(value: i32, start: u32, size: u32, edge_snap: &EdgeSnap) -> i32 {
+    let distance = (edge_snap.pixels as f64).max(size as f64 * edge_snap.percent as f64 / 100.0);
+    let (first, last) = (start as i64, start as i64 + size.saturating_sub(1) as i64);
+    let value = value as i64;
+
+    if ((value - first) as f64) <= distance && value >= first {
+        first as i32
+    } else if ((last - value) as f64) <= distance && value <= last {
+        last as i32
+    } else {
+        value as i32
+    }
+}
+
+/// Returns the position of the value within the range, from 0 to 1,
+/// or the middle if the range is empty or inverted.
+fn normalize(value: i32, min: i32, max: i32) -> f64 {
+    if max <= min {
+        return 0.5;
+    }
+
+    ((value as f64 - min as f64) / (max as f64 - min as f64)).clamp(0.0, 1.0)
+}
+
+/// Scales the normalized value around the center by the gain,
+/// clamping it so the position stays within the area.
+fn scale(value: f64, gain: f32) -> f64 {
+    (0.5 + (value - 0.5) * gain as f64).clamp(0.0, 1.0)
+}
+
+/// Projects the normalized position onto the quad, taking the corners
+/// of the unit square exactly onto the corners of the quad.
+/// The transform is the perspective one of Heckbert's square to quad mapping,
+/// which is affine when the quad is a parallelogram.
+fn project(quad: &Quad, u: f64, v: f64) -> (f64, f64) {
+    let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = quad.corners().map(|[x, y]| (x as f64, y as f64));
+    let (sx, sy) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+
+    let (g, h) = if sx == 0.0 && sy == 0.0 {
+        (0.0, 0.0)
+    } else {
+        let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+        let den = dx1 * dy2 - dx2 * dy1;
+        ((sx * dy2 - dx2 * sy) / den, (dx1 * sy - sx * dy1) / den)
+    };
+    let (a, b, c) = (x1 - x0 + g * x1, x3 - x0 + h * x3, x0);
+    let (d, e, f) = (y1 - y0 + g * y1, y3 - y0 + h * y3, y0);
+
+    let w = g * u + h * v + 1.0;
+    ((a * u + b * v + c) / w, (d * u + e * v + f) / w)
+}
+
+/// Rotates the normalized position clockwise by the given degrees.
+/// Rotations other than 90, 180 and 270 degrees are ignored.
+fn rotate(u: f64, v: f64, rotation: u16) -> (f64, f64) {
+    match rotation % 360 {
+        90 => (1.0 - v, u),
+        180 => (1.0 - u, 1.0 - v),
+        270 => (v, 1.0 - u),
+        _ => (u, v)
+    }
+}
@@ -0,0 +1,268 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::{env, process};
+use std::process::Command;
+use anyhow::{Context, Result};
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::config::{self, SessionType};
+use crate::stdout;
+
+/// Matches the enabled outputs in the `xrandr --query` output.
+static XRANDR_OUTPUT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\S+) connected( primary)? (\d+)x(\d+)\+(-?\d+)\+(-?\d+)").unwrap()
+});
+
+/// Matches the current mode in the `wlr-randr` output.
+static WLR_MODE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d+)x(\d+) px,.*current").unwrap());
+
+/// Matches the position in the `wlr-randr` output.
+static WLR_POSITION: Lazy<Regex> = Lazy::new(|| Regex::new(r"^Position: (-?\d+),(-?\d+)").unwrap());
+
+/// A display output as listed by the `monitors` command.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// monitor::Monitor {
+///     name: String::from("eDP-1"),
+///     width: 1920,
+///     height: 1080,
+///     x: 0,
+///     y: 0,
+///     primary: true
+/// }
+/// ```
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    pub primary: bool
+}
+
+/// Parses the enabled outputs from the `xrandr --query` output.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let monitors = monitor::parse_xrandr("eDP-1 connected primary 1920x1080+0+0 (normal) 344mm x 194mm");
+/// assert_eq!(monitors[0].name, "eDP-1");
+/// ```
+pub fn parse_xrandr(output: &str) -> Vec<Monitor> {
+    output.lines()
+        .filter_map(|line| XRANDR_OUTPUT.captures(line).ok().flatten())
+        .map(|caps| Monitor {
+            name: caps[1].to_string(),
+            width: caps[3].parse().unwrap_or(0),
+            height: caps[4].parse().unwrap_or(0),
+            x: caps[5].parse().unwrap_or(0),
+            y: caps[6].parse().unwrap_or(0),
+            primary: caps.get(2).is_some()
+        })
+        .collect()
+}
+
+/// Parses the enabled outputs from the `wlr-randr` output.
+/// Wayland has no primary output, so none of them are primary.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let monitors = monitor::parse_wlr_randr(&output);
+/// ```
+pub fn parse_wlr_randr(output: &str) -> Vec<Monitor> {
+    let mut monitors = vec![];
+    let mut current: Option<(Monitor, bool)> = None;
+
+    for line in output.lines() {
+        if !line.starts_with(' ') {
+            monitors.extend(current.take().filter(|(_, enabled)| *enabled).map(|(monitor, _)| monitor));
+            if let Some(name) = line.split_whitespace().next() {
+                current = Some((Monitor {
+                    name: name.to_string(),
+                    width: 0,
+                    height: 0,
+                    x: 0,
+                    y: 0,
+                    primary: false
+                }, true));
+            }
+            continue;
+        }
+
+        let (monitor, enabled) = match current.as_mut() {
+            Some(current) => current,
+            None => continue
+        };
+        let line = line.trim();
+        if line == "Enabled: no" {
+            *enabled = false;
+        } else if let Ok(Some(caps)) = WLR_MODE.captures(line) {
+            monitor.width = caps[1].parse().unwrap_or(0);
+            monitor.height = caps[2].parse().unwrap_or(0);
+        } else if let Ok(Some(caps)) = WLR_POSITION.captures(line) {
+            monitor.x = caps[1].parse().unwrap_or(0);
+            monitor.y = caps[2].parse().unwrap_or(0);
+        }
+    }
+
+    monitors.extend(current.filter(|(_, enabled)| *enabled).map(|(monitor, _)| monitor));
+    monitors
+}
+
+/// Runs the command and returns its output, or `None`
+/// if it's missing or fails, like when no display server is reachable.
+pub fn query(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Returns the command of the X client program, on the configured
+/// display if the environment doesn't have one.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let output = monitor::query(monitor::x_command("xrandr").arg("--query"));
+/// ```
+pub fn x_command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    if env::var_os("DISPLAY").is_none() {
+        let display = config::get_config().ok()
+            .and_then(|conf| conf.display)
+            .and_then(|display| display.display);
+        if let Some(display) = display {
+            command.env("DISPLAY", display);
+        }
+    }
+
+    command
+}
+
+/// Returns the type of the graphical session from `$XDG_SESSION_TYPE`,
+/// or else from whether `$WAYLAND_DISPLAY` or `$DISPLAY` is set,
+/// or `None` outside a graphical session.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(monitor::detect_session(Some("tty"), false, true), Some(config::SessionType::X11));
+/// ```
+pub fn detect_session(xdg_session_type: Option<&str>, wayland_display: bool, display: bool) -> Option<SessionType> {
+    match xdg_session_type.map(|session| session.trim().to_lowercase()).as_deref() {
+        Some("wayland") => Some(SessionType::Wayland),
+        Some("x11") => Some(SessionType::X11),
+        _ if wayland_display => Some(SessionType::Wayland),
+        _ if display => Some(SessionType::X11),
+        _ => None
+    }
+}
+
+/// Returns the type of the graphical session egawari runs in, like `detect_session`.
+pub fn current_session() -> Option<SessionType> {
+    let set = |name: &str| env::var_os(name).is_some_and(|value| !value.is_empty());
+    let xdg_session_type = env::var("XDG_SESSION_TYPE").ok();
+    detect_session(xdg_session_type.as_deref(), set("WAYLAND_DISPLAY"), set("DISPLAY"))
+}
+
+/// Returns why egawari can't run in the session, if the required session type
+/// isn't the one it is.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(problem) = monitor::session_problem(conf.require_session, monitor::current_session()) {
+///     errln!("{}", problem);
+/// }
+/// ```
+pub fn session_problem(required: SessionType, session: Option<SessionType>) -> Option<String> {
+    match (required, session) {
+        (SessionType::Any, _) => None,
+        (required, Some(session)) if required == session => None,
+        (required, Some(session)) => Some(format!("egawari only runs in {} sessions, but this session is {}.", required.name(), session.name())),
+        (required, None) => Some(format!("egawari only runs in {} sessions, but no graphical session was found.", required.name()))
+    }
+}
+
+/// Returns the enabled outputs of the Wayland compositor,
+/// or of the X server on the configured display,
+/// or `None` if no display server is reachable.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for monitor in monitor::list_monitors().unwrap_or_default() {
+///     println!("{}", monitor.name);
+/// }
+/// ```
+pub fn list_monitors() -> Option<Vec<Monitor>> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        if let Some(output) = query(&mut Command::new("wlr-randr")) {
+            return Some(parse_wlr_randr(output.as_str()));
+        }
+    }
+
+    let mut xrandr = x_command("xrandr");
+    xrandr.arg("--query");
+    query(&mut xrandr).map(|output| parse_xrandr(output.as_str()))
+}
+
+/// Runs the `monitors` command, listing the display outputs
+/// and their geometry.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// monitor::monitors().unwrap();
+/// ```
+pub fn monitors() -> Result<()> {
+    let monitors = match list_monitors() {
+        Some(monitors) => monitors,
+        None => {
+            errln!("Couldn't reach a display server.");
+            logln!("Listing the monitors needs: \x1b[0;39mxrandr or wlr-randr");
+            process::exit(1);
+        }
+    };
+
+    if stdout::json_enabled() {
+        println!("{}", serde_json::to_string_pretty(&monitors).context("Couldn't convert the monitors to JSON.")?);
+        return Ok(());
+    }
+
+    if monitors.is_empty() {
+        warnln!("The display server reports no enabled monitors.");
+        return Ok(());
+    }
+
+    for monitor in monitors {
+        let primary = if monitor.primary { " [primary]" } else { "" };
+        logln!("{} \x1b[0;39m{}x{}+{}+{}{}", monitor.name, monitor.width, monitor.height, monitor.x, monitor.y, primary);
+    }
+
+    Ok(())
+}
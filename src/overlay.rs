@@ -0,0 +1,278 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::env;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::ptr;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use crate::config::{Area, Overlay};
+use crate::cursor::{self, X11_LIBRARY};
+
+/// The X11 extension library cutting the window down to the outline.
+const XEXT_LIBRARY: &[u8] = b"libXext.so.6\0";
+/// The color of the outline, as a 24-bit RGB pixel.
+const OUTLINE_PIXEL: c_ulong = 0xff3030;
+
+const CW_BACK_PIXEL: c_ulong = 1 << 1;
+const CW_OVERRIDE_REDIRECT: c_ulong = 1 << 9;
+const INPUT_OUTPUT: c_uint = 1;
+const SHAPE_BOUNDING: c_int = 0;
+const SHAPE_INPUT: c_int = 2;
+const SHAPE_SET: c_int = 0;
+const UNSORTED: c_int = 0;
+
+/// The `XSetWindowAttributes` of Xlib.
+#[repr(C)]
+#[derive(Default)]
+struct SetWindowAttributes {
+    background_pixmap: c_ulong,
+    background_pixel: c_ulong,
+    border_pixmap: c_ulong,
+    border_pixel: c_ulong,
+    bit_gravity: c_int,
+    win_gravity: c_int,
+    backing_store: c_int,
+    backing_planes: c_ulong,
+    backing_pixel: c_ulong,
+    save_under: c_int,
+    event_mask: c_long,
+    do_not_propagate_mask: c_long,
+    override_redirect: c_int,
+    colormap: c_ulong,
+    cursor: c_ulong
+}
+
+/// The `XRectangle` of Xlib.
+#[repr(C)]
+struct XRectangle {
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16
+}
+
+type OpenDisplay = unsafe extern "C" fn(*const c_char) -> *mut c_void;
+type DisplayCall = unsafe extern "C" fn(*mut c_void) -> c_int;
+type ScreenCall = unsafe extern "C" fn(*mut c_void, c_int) -> c_int;
+type RootWindow = unsafe extern "C" fn(*mut c_void, c_int) -> c_ulong;
+type CreateWindow = unsafe extern "C" fn(*mut c_void, c_ulong, c_int, c_int, c_uint, c_uint, c_uint, c_int, c_uint, *mut c_void, c_ulong, *mut SetWindowAttributes) -> c_ulong;
+type MoveResizeWindow = unsafe extern "C" fn(*mut c_void, c_ulong, c_int, c_int, c_uint, c_uint) -> c_int;
+type WindowCall = unsafe extern "C" fn(*mut c_void, c_ulong) -> c_int;
+type ShapeRectangles = unsafe extern "C" fn(*mut c_void, c_ulong, c_int, c_int, c_int, *mut XRectangle, c_int, c_int, c_int);
+
+/// A rectangle on the screen in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32
+}
+
+/// Returns where the area of the output is on the screen,
+/// as the output of the virtual tablet spans the whole screen.
+/// The rectangle is at least a pixel wide and high.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let rect = overlay::screen_rect(mapping::reach_area(&conf, bounds), mapping::output_size(&conf, bounds), (2560, 1440));
+/// ```
+pub fn screen_rect(area: Area, output: (u32, u32), screen: (u32, u32)) -> Rect {
+    let scale = |value: u32, output: u32, screen: u32| {
+        (value as f64 * screen as f64 / output.max(1) as f64).round() as i64
+    };
+    let (left, top) = (scale(area.x, output.0, screen.0), scale(area.y, output.1, screen.1));
+    let right = scale(area.x.saturating_add(area.width), output.0, screen.0);
+    let bottom = scale(area.y.saturating_add(area.height), output.1, screen.1);
+    Rect {
+        x: left.clamp(0, i32::MAX as i64) as i32,
+        y: top.clamp(0, i32::MAX as i64) as i32,
+        width: (right - left).clamp(1, u32::MAX as i64) as u32,
+        height: (bottom - top).clamp(1, u32::MAX as i64) as u32
+    }
+}
+
+/// Returns the top, bottom, left and right edges of the outline of
+/// a rectangle of the size, relative to its corner. The edges are
+/// at most as thick as the rectangle, overlapping at the corners.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(overlay::frame(100, 50, 4)[1], overlay::Rect { x: 0, y: 46, width: 100, height: 4 });
+/// ```
+pub fn frame(width: u32, height: u32, thickness: u32) -> Vec<Rect> {
+    let across = thickness.min(height);
+    let down = thickness.min(width);
+    vec![
+        Rect { x: 0, y: 0, width, height: across },
+        Rect { x: 0, y: (height - across) as i32, width, height: across },
+        Rect { x: 0, y: 0, width: down, height },
+        Rect { x: (width - down) as i32, y: 0, width: down, height }
+    ]
+}
+
+/// The outline of the mapped area, drawn as an X11 window without
+/// a frame that's cut down to the outline and lets the clicks through.
+/// It's shown when the area changes or the mapping resumes,
+/// and hidden after the seconds of the config unless they're 0.
+/// The window is destroyed when it's dropped.
+/// The libraries are loaded at runtime like the ones of the hidden cursor.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut overlay = overlay::AreaOverlay::open(None, config::Overlay::default())?;
+/// overlay.update(Some((area, (1920, 1080))), now);
+/// drop(overlay);
+/// ```
+pub struct AreaOverlay {
+    display: *mut c_void,
+    window: c_ulong,
+    /// The size of the screen in pixels.
+    screen: (u32, u32),
+    config: Overlay,
+    /// Where the outline is, if it's shown.
+    shown: Option<Rect>,
+    /// When the outline is hidden again, if it's shown for a while.
+    hide_at: Option<Duration>,
+    move_resize: MoveResizeWindow,
+    map: WindowCall,
+    unmap: WindowCall,
+    destroy: WindowCall,
+    shape: ShapeRectangles,
+    flush: DisplayCall,
+    close: DisplayCall
+}
+
+impl AreaOverlay {
+    /// Opens the display of the environment, or the given display if
+    /// the environment doesn't have one, with the outline hidden.
+    /// Fails on Wayland, where windows can't place themselves.
+    pub fn open(display: Option<&str>, config: Overlay) -> Result<AreaOverlay> {
+        if cursor::wayland() {
+            return Err(anyhow!("The area can't be outlined on Wayland, the compositor places the windows."));
+        }
+
+        let name = match env::var_os("DISPLAY") {
+            Some(_) => None,
+            None => display.map(CString::new).transpose()?
+        };
+        unsafe {
+            let x11 = cursor::open(X11_LIBRARY)?;
+            let xext = cursor::open(XEXT_LIBRARY)?;
+            let open_display: OpenDisplay = cursor::symbol(x11, b"XOpenDisplay\0")?;
+            let default_screen: DisplayCall = cursor::symbol(x11, b"XDefaultScreen\0")?;
+            let display_width: ScreenCall = cursor::symbol(x11, b"XDisplayWidth\0")?;
+            let display_height: ScreenCall = cursor::symbol(x11, b"XDisplayHeight\0")?;
+            let root_window: RootWindow = cursor::symbol(x11, b"XRootWindow\0")?;
+            let create_window: CreateWindow = cursor::symbol(x11, b"XCreateWindow\0")?;
+            let move_resize: MoveResizeWindow = cursor::symbol(x11, b"XMoveResizeWindow\0")?;
+            let map: WindowCall = cursor::symbol(x11, b"XMapRaised\0")?;
+            let unmap: WindowCall = cursor::symbol(x11, b"XUnmapWindow\0")?;
+            let destroy: WindowCall = cursor::symbol(x11, b"XDestroyWindow\0")?;
+            let flush: DisplayCall = cursor::symbol(x11, b"XFlush\0")?;
+            let close: DisplayCall = cursor::symbol(x11, b"XCloseDisplay\0")?;
+            let shape: ShapeRectangles = cursor::symbol(xext, b"XShapeCombineRectangles\0")?;
+
+            let display = open_display(name.as_ref().map_or(ptr::null(), |name| name.as_ptr()));
+            if display.is_null() {
+                return Err(anyhow!("Couldn't open the X display."));
+            }
+            let number = default_screen(display);
+            let screen = (display_width(display, number).max(1) as u32, display_height(display, number).max(1) as u32);
+            let mut attributes = SetWindowAttributes {
+                background_pixel: OUTLINE_PIXEL,
+                override_redirect: 1,
+                ..Default::default()
+            };
+            let window = create_window(
+                display, root_window(display, number), 0, 0, 1, 1, 0, 0, INPUT_OUTPUT, ptr::null_mut(),
+                CW_BACK_PIXEL | CW_OVERRIDE_REDIRECT, &mut attributes
+            );
+            // Without an input shape the clicks go through to the windows below.
+            shape(display, window, SHAPE_INPUT, 0, 0, ptr::null_mut(), 0, SHAPE_SET, UNSORTED);
+            flush(display);
+            Ok(AreaOverlay {
+                display, window, screen, config, shown: None, hide_at: None,
+                move_resize, map, unmap, destroy, shape, flush, close
+            })
+        }
+    }
+
+    /// Outlines the area of the output with the size, or hides the
+    /// outline without one. The outline is only moved and shown again
+    /// when the area changes, and hidden once its time is up.
+    pub fn update(&mut self, mapped: Option<(Area, (u32, u32))>, now: Duration) {
+        let rect = mapped.map(|(area, output)| screen_rect(area, output, self.screen));
+        if rect != self.shown {
+            self.shown = rect;
+            self.hide_at = match self.config.seconds {
+                0 => None,
+                seconds => rect.map(|_| now + Duration::from_secs(seconds as u64))
+            };
+            unsafe {
+                match rect {
+                    Some(rect) => self.show(rect),
+                    None => {
+                        (self.unmap)(self.display, self.window);
+                    }
+                }
+                (self.flush)(self.display);
+            }
+        } else if self.hide_at.is_some_and(|hide_at| now >= hide_at) {
+            self.hide_at = None;
+            unsafe {
+                (self.unmap)(self.display, self.window);
+                (self.flush)(self.display);
+            }
+        }
+    }
+
+    /// Returns when the outline is hidden, if it's shown for a while.
+    pub fn deadline(&self) -> Option<Duration> {
+        self.hide_at
+    }
+
+    /// Moves the window onto the rectangle, cuts it down to the outline and raises it.
+    unsafe fn show(&self, rect: Rect) {
+        let mut edges: Vec<XRectangle> = frame(rect.width, rect.height, self.config.thickness).into_iter()
+            .map(|edge| XRectangle {
+                x: edge.x.min(i16::MAX as i32) as i16,
+                y: edge.y.min(i16::MAX as i32) as i16,
+                width: edge.width.min(u16::MAX as u32) as u16,
+                height: edge.height.min(u16::MAX as u32) as u16
+            })
+            .collect();
+        (self.move_resize)(self.display, self.window, rect.x, rect.y, rect.width, rect.height);
+        (self.shape)(self.display, self.window, SHAPE_BOUNDING, 0, 0, edges.as_mut_ptr(), edges.len() as c_int, SHAPE_SET, UNSORTED);
+        (self.map)(self.display, self.window);
+    }
+}
+
+impl Drop for AreaOverlay {
+    fn drop(&mut self) {
+        unsafe {
+            (self.destroy)(self.display, self.window);
+            (self.flush)(self.display);
+            (self.close)(self.display);
+        }
+    }
+}
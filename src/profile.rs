@@ -0,0 +1,303 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::{fs, process};
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use evdev::{InputEvent, InputEventKind};
+
+use crate::config::{self, Config, ProfileSwitch};
+
+/// Returns the directory the profiles are saved in.
+/// Located at `$CONFIG_DIR/egawari/profiles`
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let sketch = profile::profiles_dir().unwrap().join("sketch.toml");
+/// ```
+pub fn profiles_dir() -> Result<PathBuf> {
+    Ok(config::egawari_dir()?.join("profiles"))
+}
+
+/// Returns the path of the file holding the name of the active profile.
+fn active_path() -> Result<PathBuf> {
+    Ok(profiles_dir()?.join(".active"))
+}
+
+/// Returns the path of the profile file with the given name.
+fn profile_path(name: &str) -> Result<PathBuf> {
+    Ok(profiles_dir()?.join(format!("{}.toml", name)))
+}
+
+/// Returns whether the name can be used as a profile file name.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert!(profile::is_valid_name("sketch"));
+/// assert!(!profile::is_valid_name("../sketch"));
+/// ```
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// Returns the names of the saved profiles, sorted.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for name in profile::list_profiles().unwrap() {
+///     println!("{}", name);
+/// }
+/// ```
+pub fn list_profiles() -> Result<Vec<String>> {
+    let entries = match fs::read_dir(profiles_dir()?) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(vec![])
+    };
+
+    let mut names: Vec<String> = vec![];
+    for entry in entries {
+        let path = entry.context("Couldn't read the profiles directory.")?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the name of the last loaded or saved profile, if any.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(name) = profile::active_profile() {
+///     println!("{}", name);
+/// }
+/// ```
+pub fn active_profile() -> Option<String> {
+    let name = fs::read_to_string(active_path().ok()?).ok()?;
+    let name = name.trim();
+
+    if profile_path(name).ok()?.exists() {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns the profile after the current one among the sorted names, wrapping
+/// around, or the first one if the current one isn't among them.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let names = vec![String::from("drawing"), String::from("sketch")];
+/// assert_eq!(profile::next_profile(&names, Some("sketch")), Some(String::from("drawing")));
+/// ```
+pub fn next_profile(names: &[String], current: Option<&str>) -> Option<String> {
+    let next = match current.and_then(|current| names.iter().position(|name| name == current)) {
+        Some(i) => (i + 1) % names.len(),
+        None => 0
+    };
+    names.get(next).cloned()
+}
+
+/// The keys of the profile switch with the profile they last switched to.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut keys = profile::ProfileKeys::new(switch, profile::active_profile());
+/// if let Some(name) = keys.press(&event, || profile::list_profiles().unwrap_or_default()) {
+///     println!("Switching to {}.", name);
+/// }
+/// ```
+pub struct ProfileKeys {
+    switch: ProfileSwitch,
+    current: Option<String>
+}
+
+impl ProfileKeys {
+    /// Creates the keys of the profile switch, starting from the given profile.
+    pub fn new(switch: ProfileSwitch, current: Option<String>) -> ProfileKeys {
+        ProfileKeys { switch, current }
+    }
+
+    /// Returns whether the keys are reported by the touchpad itself.
+    pub fn on_touchpad(&self) -> bool {
+        self.switch.device.is_none()
+    }
+
+    /// Returns whether the switches are shown as desktop notifications.
+    pub fn notify(&self) -> bool {
+        self.switch.notify
+    }
+
+    /// Returns the profile to switch to if the event presses one of the keys.
+    /// The saved profiles are only listed for the keys switching to the next one.
+    /// Releases and repeats switch nothing. The profile is taken as the current
+    /// one even if switching to it fails, so the next one is tried after it.
+    pub fn press(&mut self, event: &InputEvent, names: impl FnOnce() -> Vec<String>) -> Option<String> {
+        let key = match event.kind() {
+            InputEventKind::Key(key) if event.value() == 1 => key,
+            _ => return None
+        };
+        let profile = match &self.switch.binding(key)?.profile {
+            Some(profile) => profile.clone(),
+            None => next_profile(&names(), self.current.as_deref())?
+        };
+
+        self.current = Some(profile.clone());
+        Some(profile)
+    }
+}
+
+/// Saves the current configuration as the profile with the given name.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// profile::save_profile("sketch").unwrap();
+/// ```
+pub fn save_profile(name: &str) -> Result<()> {
+    let conf = config::get_file_config()?;
+    config::write_config(profile_path(name)?.as_path(), &conf)?;
+    fs::write(active_path()?, name).context("Couldn't write the active profile.")?;
+
+    Ok(())
+}
+
+/// Reads the profile with the given name.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let conf = profile::read_profile("sketch").unwrap();
+/// ```
+pub fn read_profile(name: &str) -> Result<Config> {
+    config::read_config(profile_path(name)?.as_path())
+        .with_context(|| format!("Couldn't read the profile: {}", name))
+}
+
+/// Copies the profile with the given name into the configuration.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// profile::load_profile("sketch").unwrap();
+/// ```
+pub fn load_profile(name: &str) -> Result<()> {
+    let conf = read_profile(name)?;
+    config::save_config(&conf)?;
+    fs::write(active_path()?, name).context("Couldn't write the active profile.")?;
+
+    Ok(())
+}
+
+/// Deletes the profile with the given name.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// profile::delete_profile("sketch").unwrap();
+/// ```
+pub fn delete_profile(name: &str) -> Result<()> {
+    fs::remove_file(profile_path(name)?).context("Couldn't delete the profile.")?;
+
+    Ok(())
+}
+
+/// Runs the `profile` command with the given arguments.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// profile::profile(&[String::from("save"), String::from("sketch")]).unwrap();
+/// ```
+pub fn profile(args: &[String]) -> Result<()> {
+    if args.is_empty() {
+        errln!("No profile command provided.");
+        logln!("See: \x1b[0;39megawari help");
+        process::exit(1);
+    }
+
+    let command = args[0].as_str();
+    if command == "list" {
+        let names = list_profiles()?;
+        if names.is_empty() {
+            warnln!("There are no saved profiles.");
+            return Ok(());
+        }
+
+        let active = active_profile();
+        for name in names {
+            if active.as_deref() == Some(name.as_str()) {
+                successln!("{} (active)", name);
+            } else {
+                logln!("{}", name);
+            }
+        }
+        return Ok(());
+    }
+
+    if !matches!(command, "save" | "load" | "delete") {
+        errln!("Unknown profile command: \x1b[0;39m{}", command);
+        logln!("See: \x1b[0;39megawari help");
+        process::exit(1);
+    }
+
+    let name = match args.get(1) {
+        Some(name) if is_valid_name(name) => name.as_str(),
+        Some(name) => {
+            errln!("Invalid profile name: \x1b[0;39m{}", name);
+            process::exit(1);
+        },
+        None => {
+            errln!("No profile name provided.");
+            logln!("See: \x1b[0;39megawari help");
+            process::exit(1);
+        }
+    };
+
+    if command != "save" && !profile_path(name)?.exists() {
+        errln!("No such profile: \x1b[0;39m{}", name);
+        process::exit(1);
+    }
+
+    match command {
+        "save" => {
+            save_profile(name)?;
+            successln!("Saved the configuration as the profile: \x1b[0;39m{}", name);
+        },
+        "load" => {
+            load_profile(name)?;
+            successln!("Loaded the profile: \x1b[0;39m{}", name);
+        },
+        _ => {
+            delete_profile(name)?;
+            successln!("Deleted the profile: \x1b[0;39m{}", name);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::fs::{self, File};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
+use anyhow::{anyhow, Context, Result};
+use evdev::{EventType, InputEvent, Key};
+use serde::{Serialize, Deserialize};
+
+use crate::area;
+use crate::config::{self, Config};
+use crate::device::Bounds;
+use crate::engine::Engine;
+use crate::run;
+
+/// The first line of a recording, describing the touchpad the events came from.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// record::Header {
+///     device: String::from("SynPS/2 Synaptics TouchPad"),
+///     bounds
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub device: String,
+    pub bounds: Bounds
+}
+
+/// A raw input event with its time in microseconds since the Unix epoch.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// record::RawEvent { time: 1632000000000000, kind: 3, code: 0, value: 100 }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEvent {
+    pub time: u64,
+    #[serde(rename = "type")]
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32
+}
+
+impl RawEvent {
+    /// Returns the raw event of the input event.
+    pub fn new(event: &InputEvent) -> RawEvent {
+        let time = event.timestamp().duration_since(UNIX_EPOCH).unwrap_or_default();
+        RawEvent {
+            time: time.as_micros() as u64,
+            kind: event.event_type().0,
+            code: event.code(),
+            value: event.value()
+        }
+    }
+
+    /// Returns the input event with the time of the raw event.
+    pub fn event(&self) -> InputEvent {
+        InputEvent::from(libc::input_event {
+            time: libc::timeval {
+                tv_sec: (self.time / 1_000_000) as libc::time_t,
+                tv_usec: (self.time % 1_000_000) as libc::suseconds_t
+            },
+            type_: EventType(self.kind).0,
+            code: self.code,
+            value: self.value
+        })
+    }
+}
+
+/// What happened to the engine, as a line of a recording after the header.
+/// Each line is a JSON object with the `source` it's from, like
+/// `{"source":"touchpad","time":1632000000000000,"type":3,"code":0,"value":100}`.
+/// The config is recorded at the start and after every reload or profile switch,
+/// so a replay maps the events as the live run did.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum Record {
+    /// An event of the touchpad.
+    Touchpad(RawEvent),
+    /// An event of the separate pressure device.
+    Pressure(RawEvent),
+    /// The engine was ticked at the time in microseconds since the Unix epoch.
+    Tick { time: u64 },
+    /// The mapping was paused or resumed.
+    Paused { paused: bool },
+    /// The activation key was pressed or released on another device.
+    Activated { held: bool },
+    /// The eraser key was pressed or released on another device.
+    Erasing { erasing: bool },
+    /// A key of the stylus buttons was pressed or released on another device.
+    StylusKey { code: u16, held: bool },
+    /// The device of the stylus button keys was disconnected.
+    StylusReleased,
+    /// The events are translated with the config from now on, serialized as TOML
+    /// with the mapping target resolved.
+    Config { config: String },
+    /// The touchpad was disconnected.
+    Disconnected,
+    /// The separate pressure device was disconnected.
+    PressureLost
+}
+
+impl Record {
+    /// Returns the record of switching to the config.
+    pub fn config(conf: &Config) -> Result<Record> {
+        let config = toml::to_string(conf).context("Couldn't convert the config to TOML.")?;
+        Ok(Record::Config { config })
+    }
+}
+
+/// Writes the records to a file as JSON lines, one line at a time
+/// so a crash loses nothing already recorded.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut recorder = record::Recorder::create(Path::new("events.jsonl"), &header)?;
+/// recorder.write(&record::Record::Paused { paused: true })?;
+/// ```
+pub struct Recorder {
+    writer: LineWriter<File>
+}
+
+impl Recorder {
+    /// Creates the recording file, writing the header.
+    pub fn create(path: &Path, header: &Header) -> Result<Recorder> {
+        let file = File::create(path)
+            .with_context(|| format!("Couldn't create the recording file: {}", path.display()))?;
+        let mut recorder = Recorder { writer: LineWriter::new(file) };
+        recorder.write_line(serde_json::to_string(header)?)?;
+        Ok(recorder)
+    }
+
+    /// Appends the record to the file.
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        self.write_line(serde_json::to_string(record)?)
+    }
+
+    /// Appends the line to the file.
+    fn write_line(&mut self, line: String) -> Result<()> {
+        writeln!(self.writer, "{}", line).context("Couldn't write to the recording file.")
+    }
+}
+
+/// Reads the header and the records of a recording.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (header, records) = record::read_recording(Path::new("events.jsonl"))?;
+/// ```
+pub fn read_recording(path: &Path) -> Result<(Header, Vec<Record>)> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read the recording file: {}", path.display()))?;
+    let mut lines = raw.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+
+    let header = match lines.next() {
+        Some((_, line)) => serde_json::from_str(line).context("Couldn't parse the header of the recording.")?,
+        None => return Err(anyhow!("The recording is empty."))
+    };
+    let records = lines
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Couldn't parse line {} of the recording.", i + 1)))
+        .collect::<Result<Vec<Record>>>()?;
+
+    Ok((header, records))
+}
+
+/// Feeds the records to a new engine with the config,
+/// returning the events it emits for each of them but the configs,
+/// as a live run given the same events would.
+/// The configs in the recording replace it as they come.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for events in record::replay(&header, &records, &conf) {
+///     println!("{}", run::describe(&events));
+/// }
+/// ```
+pub fn replay(header: &Header, records: &[Record], conf: &Config) -> Vec<Vec<InputEvent>> {
+    let mut engine = Engine::new(header.bounds, conf);
+    records.iter()
+        .filter_map(|record| Some(match record {
+            Record::Touchpad(raw) => engine.process(raw.event()),
+            Record::Pressure(raw) => engine.process_pressure(raw.event()),
+            Record::Tick { time } => engine.tick(Duration::from_micros(*time)),
+            Record::Paused { paused } => engine.set_paused(*paused),
+            Record::Activated { held } => engine.set_activated(*held),
+            Record::Erasing { erasing } => engine.set_erasing(*erasing),
+            Record::StylusKey { code, held } => engine.set_stylus_key(Key::new(*code), *held),
+            Record::StylusReleased => engine.release_stylus(),
+            Record::Config { config } => {
+                match toml::from_str::<Config>(config) {
+                    Ok(conf) => engine.set_config(&conf),
+                    Err(e) => warnln!("Couldn't parse a config of the recording: {}", e)
+                }
+                return None;
+            },
+            Record::Disconnected => engine.disconnect(),
+            Record::PressureLost => engine.lose_pressure()
+        }))
+        .collect()
+}
+
+/// Runs the `replay` command, feeding a recording made with `run --dump-events`
+/// through the mapping with the configuration and printing the tablet events,
+/// like `run --dry-run` would have.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// record::replay_file(&[String::from("events.jsonl")]).unwrap();
+/// ```
+pub fn replay_file(args: &[String]) -> Result<()> {
+    let file = match args.first() {
+        Some(file) => PathBuf::from(file),
+        None => {
+            errln!("No file provided.");
+            logln!("See: \x1b[0;39megawari help");
+            process::exit(1);
+        }
+    };
+
+    // Recordings start with the config of the live run, this maps the older ones the same way.
+    let conf = area::resolve_target(config::get_config()?)?;
+    let problems = conf.validate();
+    if !problems.is_empty() {
+        for problem in problems {
+            errln!("{}", problem);
+        }
+        logln!("See: \x1b[0;39megawari config");
+        process::exit(1);
+    }
+
+    let (header, records) = read_recording(file.as_path())?;
+    successln!("Replaying the events of \"{}\" from: \x1b[0;39m{}", header.device, file.display());
+    for events in replay(&header, &records, &conf).iter().filter(|events| !events.is_empty()) {
+        logln!("{}", run::describe(events));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1453 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::{fs, io, process, thread};
+use std::process::{Command, Stdio};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Context, Result};
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, RelativeAxisType};
+use evdev::uinput::VirtualDevice;
+
+use crate::area;
+use crate::args::Options;
+use crate::config::{self, Area, Config, MappingMode, Overlay};
+use crate::cursor::HiddenCursor;
+use crate::daemon::{self, Client, Reply, Request, Server, State, Status};
+use crate::device::{self, InputMatch, TabletTarget, UinputProblem};
+use crate::engine::Engine;
+use crate::focus::{self, FocusWatcher};
+use crate::log;
+use crate::mapping;
+use crate::monitor;
+use crate::overlay::AreaOverlay;
+use crate::profile::{self, ProfileKeys};
+use crate::record::{Header, Recorder};
+use crate::stats::Stats;
+use crate::stdout;
+
+/// Where the translated events go.
+pub enum Output {
+    /// The virtual tablet, with its virtual pad if there's one.
+    Tablet(VirtualDevice, Option<VirtualDevice>),
+    /// The existing output device, with the virtual pad if there's one.
+    /// The device is only closed when the output is dropped, as its creator removes it.
+    Existing(Box<evdev::Device>, Option<VirtualDevice>),
+    Print
+}
+
+impl Output {
+    /// Emits the events to the virtual tablet, or prints them.
+    fn emit(&mut self, events: &[InputEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            Output::Tablet(tablet, _) => {
+                tablet.emit(events).context("Couldn't write to the virtual tablet.")?;
+            },
+            Output::Existing(device, _) => {
+                // Like the virtual tablet, the batch ends with a report of its own.
+                let mut events = events.to_vec();
+                events.push(InputEvent::new(EventType::SYNCHRONIZATION, 0, 0));
+                device.send_events(&events).context("Couldn't write to the output device.")?;
+            },
+            Output::Print => {
+                logln!("{}", describe(events));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Emits the events to the virtual pad, or prints them.
+    /// The events are dropped if there's no pad.
+    fn emit_pad(&mut self, events: &[InputEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        match self {
+            Output::Tablet(_, Some(pad)) | Output::Existing(_, Some(pad)) => {
+                pad.emit(events).context("Couldn't write to the virtual pad.")?;
+            },
+            Output::Tablet(_, None) | Output::Existing(_, None) => (),
+            Output::Print => {
+                logln!("Pad: {}", describe(events));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the running mapping holds and has to release when it stops,
+/// which `teardown` does when called or else when the session is dropped,
+/// so the error paths release it too.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut session = run::Session { output: Some(run::Output::Print), engine, cursor: None, runtime_files: false };
+/// session.teardown();
+/// ```
+pub struct Session {
+    pub output: Option<Output>,
+    pub engine: Engine,
+    /// The cursor hidden while the mapping runs, if it is.
+    pub cursor: Option<HiddenCursor>,
+    /// Whether the runtime files of the daemon were written by this session.
+    pub runtime_files: bool
+}
+
+impl Session {
+    /// Lifts the pen, removes the virtual tablet, shows the cursor again
+    /// and removes the runtime files. An existing output device is only
+    /// closed, as it's removed by its creator.
+    /// Only the first call releases anything, and returns `true`.
+    pub fn teardown(&mut self) -> bool {
+        let mut output = match self.output.take() {
+            Some(output) => output,
+            None => return false
+        };
+
+        if let Err(e) = output.emit(&self.engine.disconnect()) {
+            debugln!("Couldn't lift the pen: {:#}", e);
+        }
+        drop(output);
+        self.cursor = None;
+        if self.runtime_files {
+            daemon::remove_runtime_files();
+        }
+        true
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
+
+/// Describes the tablet events as `name = value` pairs.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// // "ABS_X = 100, ABS_Y = 200"
+/// println!("{}", run::describe(&events));
+/// ```
+pub fn describe(events: &[InputEvent]) -> String {
+    events.iter()
+        .map(|e| match e.event_type() {
+            EventType::ABSOLUTE => format!("{:?} = {}", AbsoluteAxisType(e.code()), e.value()),
+            EventType::KEY => format!("{:?} = {}", Key::new(e.code()), e.value()),
+            EventType::RELATIVE => format!("{:?} = {}", RelativeAxisType(e.code()), e.value()),
+            _ => format!("{:?} {} = {}", e.event_type(), e.code(), e.value())
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Maps the first present configured touchpad to a virtual
+/// graphics tablet until the process is killed.
+/// With the `dry-run` option the tablet events are printed instead.
+/// The `safe` option prints them too and never grabs the touchpad.
+/// With the `dump-events` option the input events are recorded to the file for `replay`.
+/// The config file is reloaded when it changes, unless the `no-watch` option is given.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// run::run(&args::Options { dry_run: true, ..Default::default() }).unwrap();
+/// ```
+pub fn run(opts: &Options) -> Result<()> {
+    let dry_run = opts.dry_run || opts.safe;
+    SAFE.store(opts.safe, Ordering::SeqCst);
+
+    let conf = config::get_config()?;
+    if conf.input.names.iter().all(|name| name.is_empty()) && conf.input.id.is_none() && conf.input.path.is_none() {
+        errln!("No input device is configured.");
+        logln!("See: \x1b[0;39megawari config");
+        process::exit(1);
+    }
+
+    let problems = conf.validate();
+    if !problems.is_empty() {
+        for problem in problems {
+            errln!("{}", problem);
+        }
+        logln!("See: \x1b[0;39megawari config");
+        process::exit(1);
+    }
+
+    if let Some(problem) = monitor::session_problem(conf.require_session, monitor::current_session()) {
+        errln!("{}", problem);
+        logln!("See: \x1b[0;39megawari get require_session");
+        process::exit(1);
+    }
+
+    if let Some(path) = &conf.log_file {
+        log::set_log_file(Some(path))?;
+    }
+    let conf = safe_config(area::resolve_target(conf)?);
+
+    // Two instances would fight over the touchpad and draw with two cursors.
+    if let Some(pid) = daemon::other_instance(&daemon::pid_path(), &daemon::socket_path()) {
+        if !opts.force {
+            errln!("egawari is already running with the PID: \x1b[0;39m{}", pid);
+            logln!("Stop it first, or run anyway with: \x1b[0;39megawari run --force");
+            process::exit(1);
+        }
+        warnln!("egawari is already running with the PID {}, running anyway.", pid);
+    }
+
+    let (matched, path, mut input, mut bounds) = open_touchpad(&conf)?;
+    if device::grabbed_elsewhere(&mut input) {
+        if !opts.force {
+            errln!("The input device is grabbed by another program: \x1b[0;39m{}", path.display());
+            logln!("Close it first, or run anyway with: \x1b[0;39megawari run --force");
+            process::exit(1);
+        }
+        warnln!("The input device is grabbed by another program, running anyway.");
+    }
+    let name = input.name().unwrap_or_default().to_string();
+    let pressure = open_pressure(&conf)?;
+    if let Some((_, range)) = &pressure {
+        bounds.pressure = Some(*range);
+    }
+    let output = if dry_run {
+        Output::Print
+    } else {
+        let tablet_bounds = mapping::output_bounds(&conf, bounds);
+        let resolution = mapping::tablet_resolution(&conf, bounds, device::input_resolution(&input)?);
+        let relative = conf.mapping.mode == MappingMode::Relative;
+        let pad = match &conf.pad {
+            Some(pad) => {
+                let device = uinput_device(device::create_pad(conf.tablet.virtual_name.as_str(), pad.buttons))?;
+                debugln!("Created the virtual pad \"{}\" with {} buttons.", device::pad_name(conf.tablet.virtual_name.as_str()), pad.buttons);
+                Some(device)
+            },
+            None => None
+        };
+        match open_output_device(&conf, &tablet_bounds) {
+            Some(existing) => Output::Existing(Box::new(existing), pad),
+            None => {
+                let tablet = uinput_device(device::create_tablet(conf.tablet.virtual_name.as_str(), &tablet_bounds, resolution, conf.tilt.is_some(), relative, &conf.route_targets()))?;
+                debugln!("Created the virtual tablet \"{}\" with the bounds {:?} and the resolution: {:?}", conf.tablet.virtual_name, tablet_bounds, resolution);
+                Output::Tablet(tablet, pad)
+            }
+        }
+    };
+    let mut engine = Engine::new(bounds, &conf);
+    if let Some(file) = &opts.dump_events {
+        engine.record(Recorder::create(file, &Header { device: name.clone(), bounds })?);
+        logln!("Recording the input events to: \x1b[0;39m{}", file.display());
+    }
+    let devices = OtherDevices {
+        activation: open_activation(&conf, &input, &mut engine)?,
+        pressure: pressure.map(|(device, _)| device),
+        eraser: open_eraser(&conf, &input, &mut engine)?,
+        pad: open_pad(&conf)?,
+        stylus: open_stylus(&conf)?,
+        profile_switch: open_profile_switch(&conf)?
+    };
+    let cursor = match conf.tablet.hide_cursor && !dry_run {
+        true => {
+            let display = conf.display.as_ref().and_then(|display| display.display.as_deref());
+            match HiddenCursor::hide(display) {
+                Ok(cursor) => Some(cursor),
+                Err(e) => {
+                    warnln!("Couldn't hide the cursor: {:#}", e);
+                    None
+                }
+            }
+        },
+        false => None
+    };
+    let overlay = match conf.overlay.or_else(|| opts.overlay.then(Overlay::default)) {
+        Some(overlay) if !dry_run => {
+            let display = conf.display.as_ref().and_then(|display| display.display.as_deref());
+            match AreaOverlay::open(display, overlay) {
+                Ok(overlay) => Some(overlay),
+                Err(e) => {
+                    warnln!("Couldn't outline the area: {:#}", e);
+                    None
+                }
+            }
+        },
+        _ => None
+    };
+    let mut session = Session { output: Some(output), engine, cursor, runtime_files: false };
+
+    daemon::install_toggle_handler()?;
+    daemon::install_stop_handler()?;
+    if let Some(seconds) = opts.timeout {
+        daemon::stop_after(seconds)?;
+        logln!("Stopping after \x1b[0;39m{}\x1b[1;39m seconds.", seconds);
+    }
+    session.runtime_files = true;
+    daemon::write_runtime_files(state(&session.engine), name.as_str())?;
+    let server = Server::bind(&daemon::socket_path())?;
+    debugln!("Accepting the requests on: {}", daemon::socket_path().display());
+    if opts.safe {
+        warnln!("Safe mode: the touchpad isn't grabbed and no virtual tablet is created.");
+        warnln!("The tablet events are only printed, without --safe they're emitted for real.");
+    }
+    let shared = matches!(session.output, Some(Output::Existing(..)));
+    if dry_run {
+        successln!("Printing the tablet events of \"{}\" ({}).", name, path.display());
+    } else if shared {
+        successln!("Mapping \"{}\" ({}) to the existing output device.", name, path.display());
+    } else {
+        successln!("Mapping \"{}\" ({}) to a virtual tablet.", name, path.display());
+    }
+    if session.engine.paused() {
+        warnln!("The mapping is paused, see: \x1b[0;39megawari toggle");
+    }
+
+    let mut watchers = Watchers {
+        config: if opts.no_watch { None } else { Some(Watcher::new(config::config_path()?)) },
+        focus: None,
+        profile_keys: conf.profile_switch.clone().map(|switch| ProfileKeys::new(switch, profile::active_profile())),
+        stats: Stats::new(now()),
+        log_stats: opts.stats,
+        overlay
+    };
+    watchers.reloaded(&conf);
+    if watchers.focus.is_some() && focus::active_window().is_none() {
+        warnln!("Couldn't find the focused window, the focus rules need xprop and an X server.");
+    }
+    let Session { output, engine, .. } = &mut session;
+    let output = output.as_mut().expect("The session is only torn down after the loop.");
+    let result = event_loop(&matched, input, output, engine, &mut watchers, &server, devices);
+    watchers.overlay = None;
+    session.teardown();
+    match &result {
+        Ok(()) if shared => successln!("Stopped mapping \"{}\" and left the output device to its creator.", name),
+        Ok(()) => successln!("Stopped mapping \"{}\" and removed the virtual tablet.", name),
+        Err(e) => {
+            log::write(log::Level::Error, format!("{:#}", e).as_str());
+            logln!("Closed the input device \"{}\".", name);
+        }
+    }
+    result
+}
+
+/// Whether `run` is in safe mode, so the configs it switches to never grab the touchpad.
+static SAFE: AtomicBool = AtomicBool::new(false);
+
+/// The delay before retrying to open a disconnected input device.
+const RECONNECT_MIN: Duration = Duration::from_millis(100);
+/// The longest delay between the attempts to open a disconnected input device.
+const RECONNECT_MAX: Duration = Duration::from_secs(5);
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notices the changes of a file by its modification time,
+/// checking it at most once per second.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut watcher = run::Watcher::new(config::config_path()?);
+/// if watcher.changed(SystemTime::now().duration_since(UNIX_EPOCH)?) {
+///     println!("The config file changed.");
+/// }
+/// ```
+pub struct Watcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    next_check: Duration
+}
+
+impl Watcher {
+    /// Creates the watcher of the file, remembering its current modification time.
+    pub fn new(path: PathBuf) -> Watcher {
+        let modified = Watcher::modified(&path);
+        Watcher {
+            path,
+            modified,
+            next_check: now() + WATCH_INTERVAL
+        }
+    }
+
+    /// Returns the modification time of the file, or `None` if it doesn't exist.
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Returns when the file should be checked next.
+    pub fn deadline(&self) -> Duration {
+        self.next_check
+    }
+
+    /// Returns whether the file was modified since the last check,
+    /// or `false` if it's too early to check again.
+    pub fn changed(&mut self, now: Duration) -> bool {
+        if now < self.next_check {
+            return false;
+        }
+
+        self.next_check = now + WATCH_INTERVAL;
+        let modified = Watcher::modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        modified.is_some()
+    }
+}
+
+/// Returns the reasons the running mapping can't switch to the new config, if any.
+/// The output size and the pressure source can't change as the virtual tablet
+/// is made for them, nor can the pad buttons, the activation key, the pressure device,
+/// the eraser device or the pad device as their devices are already open.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for problem in run::reload_problems(engine.config(), &conf, engine.bounds()) {
+///     warnln!("{}", problem);
+/// }
+/// ```
+pub fn reload_problems(current: &Config, new: &Config, bounds: device::Bounds) -> Vec<String> {
+    let mut problems = new.validate();
+    if mapping::output_size(current, bounds) != mapping::output_size(new, bounds) {
+        problems.push(String::from("The output size can't change while egawari is running."));
+    }
+    if current.tablet.pressure_source != new.tablet.pressure_source {
+        problems.push(String::from("The pressure source can't change while egawari is running."));
+    }
+    if current.tablet.pressure_device != new.tablet.pressure_device {
+        problems.push(String::from("The pressure device can't change while egawari is running."));
+    }
+    if current.tablet.hide_cursor != new.tablet.hide_cursor {
+        problems.push(String::from("Hiding the cursor can't be turned on or off while egawari is running."));
+    }
+    if current.tablet.output_device != new.tablet.output_device {
+        problems.push(String::from("The output device can't change while egawari is running."));
+    }
+    if current.tablet.virtual_name != new.tablet.virtual_name {
+        problems.push(String::from("The virtual tablet name can't change while egawari is running."));
+    }
+    if current.mapping.mode != new.mapping.mode {
+        problems.push(String::from("The mapping mode can't change while egawari is running."));
+    }
+    if current.tilt.is_some() != new.tilt.is_some() {
+        problems.push(String::from("The tilt emulation can't be turned on or off while egawari is running."));
+    }
+    if current.eraser.as_ref().and_then(|eraser| eraser.device.as_ref()) != new.eraser.as_ref().and_then(|eraser| eraser.device.as_ref()) {
+        problems.push(String::from("The eraser device can't change while egawari is running."));
+    }
+    if current.pad.as_ref().map(|pad| (pad.buttons, pad.device.as_ref())) != new.pad.as_ref().map(|pad| (pad.buttons, pad.device.as_ref())) {
+        problems.push(String::from("The pad buttons and the pad device can't change while egawari is running."));
+    }
+    if current.activation != new.activation {
+        problems.push(String::from("The activation key can't change while egawari is running."));
+    }
+    if current.route_targets() != new.route_targets() {
+        problems.push(String::from("The targets of the routes can't change while egawari is running."));
+    }
+
+    problems
+}
+
+/// What the event loop checks from time to time, besides the devices.
+struct Watchers {
+    config: Option<Watcher>,
+    focus: Option<FocusWatcher>,
+    /// The keys of the profile switch, which stay the ones egawari started with.
+    profile_keys: Option<ProfileKeys>,
+    stats: Stats,
+    /// Whether the summary of the stats is logged periodically.
+    log_stats: bool,
+    /// The outline of the mapped area, which keeps the settings egawari started with.
+    overlay: Option<AreaOverlay>
+}
+
+impl Watchers {
+    /// Returns when the next check is due, if any.
+    fn deadline(&self) -> Option<Duration> {
+        let config = self.config.as_ref().map(|watcher| watcher.deadline());
+        let focus = self.focus.as_ref().map(|watcher| watcher.deadline());
+        let stats = Some(self.stats.deadline()).filter(|_| self.log_stats);
+        let overlay = self.overlay.as_ref().and_then(|overlay| overlay.deadline());
+        config.into_iter().chain(focus).chain(stats).chain(overlay).min()
+    }
+
+    /// Logs the summary of the stats once it's due, starting a new window.
+    fn log_stats(&mut self, now: Duration) {
+        if self.log_stats && now >= self.stats.deadline() {
+            logln!("Stats: \x1b[0;39m{}", self.stats.take(now).describe());
+        }
+    }
+
+    /// Takes the focus rules of the config file now in use,
+    /// checking the focused window again right away.
+    fn reloaded(&mut self, conf: &Config) {
+        self.focus = match conf.focus_rules.is_empty() {
+            true => None,
+            false => Some(FocusWatcher::new(conf.focus_rules.clone(), now()))
+        };
+    }
+}
+
+/// Returns the config as `run` uses it, never grabbing the touchpad in safe mode.
+fn safe_config(mut conf: Config) -> Config {
+    if SAFE.load(Ordering::SeqCst) {
+        conf.input.grab = false;
+    }
+    conf
+}
+
+/// Returns the area of the output the pen can reach and the size of the output,
+/// unless the touchpad isn't mapped for now or moves the pen relatively.
+fn mapped_area(engine: &Engine) -> Option<(Area, (u32, u32))> {
+    let conf = engine.config();
+    if engine.suspended() || conf.mapping.mode == MappingMode::Relative {
+        return None;
+    }
+    Some((mapping::reach_area(conf, engine.bounds()), mapping::output_size(conf, engine.bounds())))
+}
+
+/// Switches the engine to the config, returning the problems if it can't be used.
+fn use_config(conf: Result<Config>, engine: &mut Engine) -> Result<()> {
+    let conf = safe_config(area::resolve_target(conf?)?);
+    let problems = reload_problems(engine.config(), &conf, engine.bounds());
+    if !problems.is_empty() {
+        return Err(anyhow!("{}", problems.join(" ")));
+    }
+
+    engine.set_config(&conf);
+    Ok(())
+}
+
+/// Switches the engine to the config file, keeping the previous
+/// config and returning the problems if the new one can't be used.
+fn reload(engine: &mut Engine, watchers: &mut Watchers) -> Result<()> {
+    let result = use_config(config::get_config().context("Couldn't reload the configuration."), engine);
+
+    match &result {
+        Ok(()) => {
+            watchers.reloaded(engine.config());
+            logln!("Reloaded the configuration.");
+        },
+        Err(e) => {
+            warnln!("{:#}", e);
+            warnln!("Kept the previous configuration.");
+        }
+    }
+    result
+}
+
+/// Switches the engine to the profile the focused window wants,
+/// or back to the config file for `None`, keeping the previous
+/// config if the new one can't be used.
+fn switch_profile(name: Option<&str>, engine: &mut Engine) -> Result<()> {
+    let conf = match name {
+        Some(name) => profile::read_profile(name),
+        None => config::get_config().context("Couldn't reload the configuration.")
+    };
+    let result = use_config(conf, engine);
+
+    match (&result, name) {
+        (Ok(()), Some(name)) => logln!("Switched to the profile \"{}\" for the focused window.", name),
+        (Ok(()), None) => logln!("Switched back to the configuration."),
+        (Err(e), _) => {
+            warnln!("{:#}", e);
+            warnln!("Kept the previous configuration.");
+        }
+    }
+    result
+}
+
+/// Switches the engine to the profile if the event presses a key of the profile switch,
+/// keeping the previous config if the profile can't be used.
+fn press_profile_key(event: &InputEvent, engine: &mut Engine, watchers: &mut Watchers) {
+    let keys = match watchers.profile_keys.as_mut() {
+        Some(keys) => keys,
+        None => return
+    };
+    let name = match keys.press(event, || profile::list_profiles().unwrap_or_default()) {
+        Some(name) => name,
+        None => return
+    };
+
+    match use_config(profile::read_profile(name.as_str()), engine) {
+        Ok(()) => {
+            logln!("Switched to the profile \"{}\".", name);
+            if keys.notify() {
+                notify(format!("Switched to the profile \"{}\".", name).as_str());
+            }
+        },
+        Err(e) => {
+            warnln!("{:#}", e);
+            warnln!("Kept the previous configuration.");
+        }
+    }
+}
+
+/// Shows the message as a desktop notification with `notify-send`, if it's installed.
+/// The notification is sent in the background, as it can take a while without a notification daemon.
+fn notify(message: &str) {
+    let child = Command::new("notify-send")
+        .args(["--app-name=egawari", "egawari", message])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match child {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        },
+        Err(e) => debugln!("Couldn't show the notification with notify-send: {}", e)
+    }
+}
+
+/// Pauses or resumes the mapping, emitting the events it causes.
+fn set_paused(paused: bool, output: &mut Output, engine: &mut Engine) -> Result<()> {
+    if paused == engine.paused() {
+        return Ok(());
+    }
+
+    output.emit(&engine.set_paused(paused))?;
+    daemon::write_state(state(engine))?;
+    match state(engine) {
+        State::Active => logln!("Resumed the mapping."),
+        State::Paused => logln!("Paused the mapping.")
+    }
+    Ok(())
+}
+
+/// Handles the request from the socket and replies with the status after it.
+/// Requests that fail are answered with the error instead of stopping the daemon.
+fn handle_request(
+    request: Request,
+    client: Client,
+    name: &str,
+    output: &mut Output,
+    engine: &mut Engine,
+    watchers: &mut Watchers
+) -> Result<()> {
+    debugln!("Received the request: {}", request.as_str());
+    let paused = match request {
+        Request::Pause => Some(true),
+        Request::Resume => Some(false),
+        Request::Toggle => Some(!engine.paused()),
+        Request::Status | Request::Reload => None
+    };
+    if let Some(paused) = paused {
+        set_paused(paused, output, engine)?;
+    }
+    let error = match request {
+        Request::Reload => reload(engine, watchers).err().map(|e| format!("{:#}", e)),
+        _ => None
+    };
+
+    client.reply(&Reply {
+        ok: error.is_none(),
+        error,
+        status: Some(Status {
+            running: true,
+            pid: Some(process::id() as i32),
+            state: Some(state(engine)),
+            device: Some(name.to_string()),
+            virtual_name: match output {
+                Output::Tablet(..) => Some(engine.config().tablet.virtual_name.clone()),
+                Output::Existing(device, _) => device.name().map(String::from),
+                Output::Print => None
+            },
+            stats: Some(watchers.stats.summary(now()))
+        })
+    });
+    Ok(())
+}
+
+/// Returns the current time since the Unix epoch, like the event timestamps.
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Waits until any of the files is readable or the deadline passes,
+/// returning whether each of them is readable.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let readable = run::wait_readable(&[input.as_raw_fd()], None)?;
+/// ```
+pub fn wait_readable(fds: &[RawFd], deadline: Option<Duration>) -> io::Result<Vec<bool>> {
+    let timeout = match deadline {
+        Some(deadline) => deadline.saturating_sub(now()).as_millis().min(i32::MAX as u128) as i32,
+        None => -1
+    };
+
+    let mut fds: Vec<libc::pollfd> = fds.iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0
+        })
+        .collect();
+    match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(fds.iter().map(|fd| fd.revents != 0).collect())
+    }
+}
+
+/// Opens the configured touchpad, returning what it's matched by, its event node path,
+/// the device and the bounds to map it with. Exits if its ranges are unusable.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (matched, path, input, bounds) = run::open_touchpad(&config::get_config()?)?;
+/// ```
+pub fn open_touchpad(conf: &Config) -> Result<(InputMatch, PathBuf, evdev::Device, device::Bounds)> {
+    let (matched, path, input) = device::open_first_input(&conf.input)?;
+    let reported = device::input_bounds(&input, conf.tablet.pressure_source.axis())?;
+    debugln!("Opened the input device {} with the bounds: {:?}", path.display(), reported);
+    let bounds = match mapping::usable_bounds(conf, reported) {
+        Some(bounds) => bounds,
+        None => {
+            errln!("The input device reports an empty or inverted range: \x1b[0;39mX from {} to {}, Y from {} to {}",
+                reported.min_x, reported.max_x, reported.min_y, reported.max_y);
+            logln!("Set input.fallback_bounds in: \x1b[0;39m{}", config::config_path()?.display());
+            process::exit(1);
+        }
+    };
+    if bounds != reported {
+        warnln!("The input device reports an empty or inverted range, using the fallback bounds.");
+    }
+
+    Ok((matched, path, input, bounds))
+}
+
+/// Opens the separate pressure device if there's one, returning it
+/// with the range of its pressure axis. Exits if it has no such axis.
+fn open_pressure(conf: &Config) -> Result<Option<(evdev::Device, (i32, i32))>> {
+    let name = match &conf.tablet.pressure_device {
+        Some(name) => name,
+        None => return Ok(None)
+    };
+
+    let (path, device) = device::open_input(name)?;
+    let axis = conf.tablet.pressure_source.axis();
+    if !device.supported_absolute_axes().is_some_and(|axes| axes.contains(axis)) {
+        errln!("The pressure device \"{}\" doesn't report the axis: \x1b[0;39m{:?}", name, axis);
+        logln!("Set tablet.pressure_source in: \x1b[0;39m{}", config::config_path()?.display());
+        process::exit(1);
+    }
+    let state = device.get_abs_state().context("Couldn't read the pressure device axes.")?;
+    let range = (state[axis.0 as usize].minimum, state[axis.0 as usize].maximum);
+    logln!("Reading the pressure from \"{}\" ({}).", name, path.display());
+    Ok(Some((device, range)))
+}
+
+/// The devices read along with the touchpad, if they're configured.
+struct OtherDevices {
+    activation: Option<evdev::Device>,
+    pressure: Option<evdev::Device>,
+    eraser: Option<evdev::Device>,
+    pad: Option<evdev::Device>,
+    stylus: Option<evdev::Device>,
+    profile_switch: Option<evdev::Device>
+}
+
+/// Opens the device of the activation key unless it's the touchpad itself,
+/// activating the mapping if the key is already held.
+fn open_activation(conf: &Config, input: &evdev::Device, engine: &mut Engine) -> Result<Option<evdev::Device>> {
+    let (activation, key) = match conf.activation.as_ref().and_then(|activation| Some((activation, activation.key()?))) {
+        Some(activation) => activation,
+        None => return Ok(None)
+    };
+
+    let device = match &activation.device {
+        Some(name) => Some(device::open_input(name)?.1),
+        None => None
+    };
+    let held = device.as_ref().unwrap_or(input).get_key_state()
+        .is_ok_and(|keys| keys.contains(key));
+    engine.set_activated(held);
+    logln!("Mapping only while \x1b[0;39m{}\x1b[1;39m is held.", activation.key);
+    Ok(device)
+}
+
+/// Opens the device of the eraser key unless it's the touchpad itself,
+/// starting with the eraser if the key is already held.
+fn open_eraser(conf: &Config, input: &evdev::Device, engine: &mut Engine) -> Result<Option<evdev::Device>> {
+    let eraser = match &conf.eraser {
+        Some(eraser) => eraser,
+        None => return Ok(None)
+    };
+    let key = match eraser.key() {
+        Some(key) => key,
+        None => {
+            logln!("Switching between the pen and the eraser with a three-finger tap.");
+            return Ok(None);
+        }
+    };
+
+    let device = match &eraser.device {
+        Some(name) => Some(device::open_input(name)?.1),
+        None => None
+    };
+    let held = device.as_ref().unwrap_or(input).get_key_state()
+        .is_ok_and(|keys| keys.contains(key));
+    engine.set_erasing(held);
+    logln!("Erasing while \x1b[0;39m{:?}\x1b[1;39m is held.", key);
+    Ok(device)
+}
+
+/// Opens the device of the pad keys unless it's the touchpad itself.
+fn open_pad(conf: &Config) -> Result<Option<evdev::Device>> {
+    let pad = match &conf.pad {
+        Some(pad) => pad,
+        None => return Ok(None)
+    };
+
+    logln!("Pressing the {} buttons of the virtual pad with {} bindings.", pad.buttons, pad.bindings.len());
+    match &pad.device {
+        Some(name) => Ok(Some(device::open_input(name)?.1)),
+        None => Ok(None)
+    }
+}
+
+/// Opens the existing output device the tablet events are written into, if it's
+/// configured and present. Without it a virtual tablet is created instead, but
+/// a device missing what the virtual tablet would report is refused.
+fn open_output_device(conf: &Config, bounds: &device::Bounds) -> Option<evdev::Device> {
+    let name = conf.tablet.output_device.as_ref()?;
+    let matched = match name.starts_with('/') {
+        true => InputMatch::Path(PathBuf::from(name)),
+        false => InputMatch::Name(name.clone())
+    };
+    let existing = match device::open_matching(&matched) {
+        Ok((_, device)) => Some(device),
+        Err(e) => {
+            debugln!("Couldn't open the output device: {:#}", e);
+            None
+        }
+    };
+
+    let required = device::tablet_capabilities(bounds, conf.tilt.is_some(), conf.mapping.mode == MappingMode::Relative, &conf.route_targets());
+    match device::tablet_target(existing.as_ref().map(device::device_capabilities).as_ref(), &required) {
+        TabletTarget::Create => {
+            warnln!("The output device \"{}\" isn't there, creating a virtual tablet instead.", name);
+            None
+        },
+        TabletTarget::Reuse => {
+            logln!("Writing the tablet events into the existing device: \x1b[0;39m{}", name);
+            existing
+        },
+        TabletTarget::Incompatible(missing) => {
+            errln!("The output device \"{}\" doesn't report: \x1b[0;39m{}", name, missing.join(", "));
+            logln!("See: \x1b[0;39megawari get tablet.output_device");
+            process::exit(1);
+        }
+    }
+}
+
+/// Opens the device of the stylus button keys unless it's the touchpad itself.
+fn open_stylus(conf: &Config) -> Result<Option<evdev::Device>> {
+    let stylus = match &conf.stylus_buttons {
+        Some(stylus) => stylus,
+        None => return Ok(None)
+    };
+
+    logln!("Holding the stylus buttons with {} keys.", stylus.bindings.len());
+    match &stylus.device {
+        Some(name) => Ok(Some(device::open_input(name)?.1)),
+        None => Ok(None)
+    }
+}
+
+/// Opens the device of the profile switch keys unless it's the touchpad itself.
+fn open_profile_switch(conf: &Config) -> Result<Option<evdev::Device>> {
+    let switch = match &conf.profile_switch {
+        Some(switch) => switch,
+        None => return Ok(None)
+    };
+
+    logln!("Switching the profiles with {} keys.", switch.bindings.len());
+    match &switch.device {
+        Some(name) => Ok(Some(device::open_input(name)?.1)),
+        None => Ok(None)
+    }
+}
+
+/// Reads the events of the profile switch device, switching the profiles when their keys
+/// are pressed. A disconnected device isn't read anymore.
+fn read_profile_switch(switch: &mut Option<evdev::Device>, engine: &mut Engine, watchers: &mut Watchers) -> Result<()> {
+    let device = match switch.as_mut() {
+        Some(device) => device,
+        None => return Ok(())
+    };
+
+    let fetched = device.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+    match fetched {
+        Ok(events) => {
+            for event in events {
+                press_profile_key(&event, engine, watchers);
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) if disconnected(&e) => {
+            *switch = None;
+            watchers.profile_keys = None;
+            warnln!("The profile switch device was disconnected, its keys don't switch the profiles anymore.");
+        },
+        Err(e) => return Err(e).context("Couldn't read from the profile switch device.")
+    }
+
+    Ok(())
+}
+
+/// Reads the events of the pad device, pressing the pad buttons while their keys are held.
+/// A disconnected device releases the buttons and isn't read anymore.
+fn read_pad(pad: &mut Option<evdev::Device>, output: &mut Output, engine: &mut Engine) -> Result<()> {
+    let device = match pad.as_mut() {
+        Some(device) => device,
+        None => return Ok(())
+    };
+
+    let fetched = device.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+    match fetched {
+        Ok(events) => {
+            for event in events {
+                if let Some((button, held)) = engine.pad_held(&event) {
+                    engine.set_pad_button(button, held);
+                }
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) if disconnected(&e) => {
+            engine.release_pad();
+            *pad = None;
+            warnln!("The pad device was disconnected, its keys don't press the pad buttons anymore.");
+        },
+        Err(e) => return Err(e).context("Couldn't read from the pad device.")
+    }
+
+    output.emit_pad(&engine.take_pad())
+}
+
+/// Reads the events of the stylus buttons device, holding the buttons while their keys are.
+/// A disconnected device releases the buttons and isn't read anymore.
+fn read_stylus(stylus: &mut Option<evdev::Device>, output: &mut Output, engine: &mut Engine) -> Result<()> {
+    let device = match stylus.as_mut() {
+        Some(device) => device,
+        None => return Ok(())
+    };
+
+    let fetched = device.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+    match fetched {
+        Ok(events) => {
+            for event in events {
+                if let Some((key, held)) = engine.stylus_key_held(&event) {
+                    output.emit(&engine.set_stylus_key(key, held))?;
+                }
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) if disconnected(&e) => {
+            output.emit(&engine.release_stylus())?;
+            *stylus = None;
+            warnln!("The stylus buttons device was disconnected, its keys don't hold the buttons anymore.");
+        },
+        Err(e) => return Err(e).context("Couldn't read from the stylus buttons device.")
+    }
+
+    Ok(())
+}
+
+/// Reads the events of the eraser device, using the eraser while the key is held.
+/// A disconnected device switches back to the pen and isn't read anymore.
+fn read_eraser(eraser: &mut Option<evdev::Device>, output: &mut Output, engine: &mut Engine) -> Result<()> {
+    let device = match eraser.as_mut() {
+        Some(device) => device,
+        None => return Ok(())
+    };
+
+    let fetched = device.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+    match fetched {
+        Ok(events) => {
+            for event in events {
+                if let Some(held) = engine.eraser_held(&event) {
+                    output.emit(&engine.set_erasing(held))?;
+                }
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) if disconnected(&e) => {
+            output.emit(&engine.set_erasing(false))?;
+            *eraser = None;
+            warnln!("The eraser device was disconnected, drawing with the pen.");
+        },
+        Err(e) => return Err(e).context("Couldn't read from the eraser device.")
+    }
+
+    Ok(())
+}
+
+/// Reads the events of the activation device, activating the mapping
+/// while the key is held. A disconnected device deactivates the mapping
+/// and isn't read anymore.
+fn read_activation(activation: &mut Option<evdev::Device>, output: &mut Output, engine: &mut Engine) -> Result<()> {
+    let device = match activation.as_mut() {
+        Some(device) => device,
+        None => return Ok(())
+    };
+
+    let fetched = device.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+    match fetched {
+        Ok(events) => {
+            for event in events {
+                if let Some(held) = engine.activation_held(&event) {
+                    output.emit(&engine.set_activated(held))?;
+                }
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) if disconnected(&e) => {
+            output.emit(&engine.set_activated(false))?;
+            *activation = None;
+            warnln!("The activation device was disconnected, the mapping stays inactive.");
+        },
+        Err(e) => return Err(e).context("Couldn't read from the activation device.")
+    }
+
+    Ok(())
+}
+
+/// Reads the events of the separate pressure device, merging them into
+/// the touchpad reports. A disconnected device isn't read anymore
+/// and the pen draws with the full pressure instead.
+fn read_pressure(pressure: &mut Option<evdev::Device>, output: &mut Output, engine: &mut Engine) -> Result<()> {
+    let device = match pressure.as_mut() {
+        Some(device) => device,
+        None => return Ok(())
+    };
+
+    let fetched = device.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+    match fetched {
+        Ok(events) => {
+            for event in events {
+                output.emit(&engine.process_pressure(event))?;
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+        Err(e) if disconnected(&e) => {
+            output.emit(&engine.lose_pressure())?;
+            *pressure = None;
+            warnln!("The pressure device was disconnected, drawing with the full pressure.");
+        },
+        Err(e) => return Err(e).context("Couldn't read from the pressure device.")
+    }
+
+    Ok(())
+}
+
+/// Reads the touchpad events and emits the translated events
+/// to the output, handling toggle signals, socket requests,
+/// the activation key, the pressure device, the eraser key, the pad keys, the stylus button keys, the profile switch keys,
+/// config and focus changes and timeouts in between.
+/// A disconnected touchpad is waited for and reopened by what it was matched by,
+/// as the virtual tablet is made for its ranges. The touchpad is grabbed
+/// while it's mapped, unless the grab is off. Returns once a stop is requested.
+fn event_loop(
+    matched: &InputMatch,
+    mut input: evdev::Device,
+    output: &mut Output,
+    engine: &mut Engine,
+    watchers: &mut Watchers,
+    server: &Server,
+    mut devices: OtherDevices
+) -> Result<()> {
+    let mut grabbed = false;
+    loop {
+        if daemon::stop_requested() {
+            return Ok(());
+        }
+
+        // The problems are already reported.
+        if watchers.config.as_mut().is_some_and(|watcher| watcher.changed(now())) {
+            let _ = reload(engine, watchers);
+        }
+        if let Some(profile) = watchers.focus.as_mut().and_then(|watcher| watcher.check(now())) {
+            let _ = switch_profile(profile.as_deref(), engine);
+        }
+        watchers.log_stats(now());
+
+        if daemon::take_toggle() {
+            set_paused(!engine.paused(), output, engine)?;
+        }
+
+        // The touchpad is only grabbed while it's mapped, so it works as usual otherwise.
+        let grab = engine.config().input.grab && !engine.suspended();
+        if grab != grabbed {
+            device::set_grab(&mut input, grab)?;
+            grabbed = grab;
+        }
+        if let Some(overlay) = watchers.overlay.as_mut() {
+            overlay.update(mapped_area(engine), now());
+        }
+
+        // The pad buttons pressed by the touchpad since the last wait.
+        output.emit_pad(&engine.take_pad())?;
+
+        let deadline = engine.deadline().into_iter().chain(watchers.deadline()).min();
+        let mut fds = vec![input.as_raw_fd(), server.fd()];
+        fds.extend(devices.activation.as_ref().map(|device| device.as_raw_fd()));
+        let pressure_index = fds.len();
+        fds.extend(devices.pressure.as_ref().map(|device| device.as_raw_fd()));
+        let eraser_index = fds.len();
+        fds.extend(devices.eraser.as_ref().map(|device| device.as_raw_fd()));
+        let pad_index = fds.len();
+        fds.extend(devices.pad.as_ref().map(|device| device.as_raw_fd()));
+        let stylus_index = fds.len();
+        fds.extend(devices.stylus.as_ref().map(|device| device.as_raw_fd()));
+        let profile_index = fds.len();
+        fds.extend(devices.profile_switch.as_ref().map(|device| device.as_raw_fd()));
+        match wait_readable(&fds, deadline) {
+            Ok(readable) => {
+                if readable[1] {
+                    if let Some((request, client)) = server.accept() {
+                        let name = input.name().unwrap_or_default().to_string();
+                        handle_request(request, client, name.as_str(), output, engine, watchers)?;
+                    }
+                }
+                if devices.activation.is_some() && readable[2] {
+                    read_activation(&mut devices.activation, output, engine)?;
+                }
+                if devices.pressure.is_some() && readable[pressure_index] {
+                    read_pressure(&mut devices.pressure, output, engine)?;
+                }
+                if readable.get(eraser_index) == Some(&true) {
+                    read_eraser(&mut devices.eraser, output, engine)?;
+                }
+                if readable.get(pad_index) == Some(&true) {
+                    read_pad(&mut devices.pad, output, engine)?;
+                }
+                if readable.get(stylus_index) == Some(&true) {
+                    read_stylus(&mut devices.stylus, output, engine)?;
+                }
+                if readable.get(profile_index) == Some(&true) {
+                    read_profile_switch(&mut devices.profile_switch, engine, watchers)?;
+                }
+                if !readable[0] {
+                    let out = engine.tick(now());
+                    output.emit(&out)?;
+                    watchers.stats.output(out.len());
+                    continue;
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if disconnected(&e) => {
+                input = match reopen_input(matched, output, engine)? {
+                    Some(input) => input,
+                    None => return Ok(())
+                };
+                grabbed = false;
+                continue;
+            },
+            Err(e) => return Err(e).context("Couldn't wait for the input device.")
+        }
+
+        let fetched = input.fetch_events().map(|events| events.collect::<Vec<InputEvent>>());
+        let events = match fetched {
+            Ok(events) => events,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if disconnected(&e) => {
+                input = match reopen_input(matched, output, engine)? {
+                    Some(input) => input,
+                    None => return Ok(())
+                };
+                grabbed = false;
+                continue;
+            },
+            Err(e) => return Err(e).context("Couldn't read from the input device.")
+        };
+
+        for event in events {
+            if watchers.profile_keys.as_ref().is_some_and(|keys| keys.on_touchpad()) {
+                press_profile_key(&event, engine, watchers);
+            }
+            let out = engine.process(event);
+            output.emit(&out)?;
+            watchers.stats.input(&event, out.len(), now());
+        }
+    }
+}
+
+/// Returns whether the error means the input device is gone.
+fn disconnected(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENODEV)
+}
+
+/// Lifts the pen and waits for the disconnected input device to come back,
+/// returning the reopened device, or `None` if a stop was requested meanwhile.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(reopened) = run::reopen_input(&matched, &mut output, &mut engine)? {
+///     input = reopened;
+/// }
+/// ```
+pub fn reopen_input(matched: &InputMatch, output: &mut Output, engine: &mut Engine) -> Result<Option<evdev::Device>> {
+    output.emit(&engine.disconnect())?;
+    warnln!("The input device \"{}\" was disconnected, waiting for it to come back.", matched);
+
+    let reopened = reconnect(|| match daemon::stop_requested() {
+        true => Some(None),
+        false => device::open_matching(matched).ok().map(Some)
+    }, thread::sleep);
+    let (path, input) = match reopened {
+        Some(reopened) => reopened,
+        None => return Ok(None)
+    };
+    successln!("Reconnected to \"{}\" ({}).", input.name().unwrap_or_default(), path.display());
+    Ok(Some(input))
+}
+
+/// Calls `open` until it returns the device, calling `sleep`
+/// between the attempts with a delay doubling from 100ms up to 5s.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let (path, input) = run::reconnect(|| device::open_input(name).ok(), thread::sleep);
+/// ```
+pub fn reconnect<T>(mut open: impl FnMut() -> Option<T>, mut sleep: impl FnMut(Duration)) -> T {
+    let mut delay = RECONNECT_MIN;
+    loop {
+        if let Some(device) = open() {
+            return device;
+        }
+
+        debugln!("The input device isn't back yet, retrying in {}ms.", delay.as_millis());
+        sleep(delay);
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+/// The bounds of the virtual tablet made by `test-output`.
+const TEST_BOUNDS: device::Bounds = device::Bounds {
+    min_x: 0,
+    max_x: 1000,
+    min_y: 0,
+    max_y: 1000,
+    pressure: None,
+    orientation: None
+};
+/// The number of reports drawing each side of the test square.
+const TEST_STEPS: i32 = 50;
+/// The delay between the reports of the test square.
+const TEST_STEP_DELAY: Duration = Duration::from_millis(10);
+
+/// Returns the reports drawing a square over the middle half of the bounds
+/// with the pen down, starting and ending at the top left corner.
+/// Each side is walked in `steps` reports.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// for report in run::square(&bounds, 50) {
+///     tablet.emit(&report)?;
+/// }
+/// ```
+pub fn square(bounds: &device::Bounds, steps: i32) -> Vec<Vec<InputEvent>> {
+    let abs = |axis: AbsoluteAxisType, value| InputEvent::new(EventType::ABSOLUTE, axis.0, value);
+    let key = |key: Key, value| InputEvent::new(EventType::KEY, key.code(), value);
+    let (width, height) = (bounds.max_x - bounds.min_x, bounds.max_y - bounds.min_y);
+    let (left, top) = (bounds.min_x + width / 4, bounds.min_y + height / 4);
+    let (right, bottom) = (bounds.max_x - width / 4, bounds.max_y - height / 4);
+    let corners = [(left, top), (right, top), (right, bottom), (left, bottom), (left, top)];
+    let (_, max_pressure) = bounds.pressure.unwrap_or((0, device::DEFAULT_PRESSURE_MAX));
+
+    let mut reports = vec![
+        vec![key(Key::BTN_TOOL_PEN, 1), abs(AbsoluteAxisType::ABS_X, left), abs(AbsoluteAxisType::ABS_Y, top)],
+        vec![abs(AbsoluteAxisType::ABS_PRESSURE, max_pressure), key(Key::BTN_TOUCH, 1)]
+    ];
+    for side in corners.windows(2) {
+        let ((x0, y0), (x1, y1)) = (side[0], side[1]);
+        for step in 1..=steps {
+            reports.push(vec![
+                abs(AbsoluteAxisType::ABS_X, x0 + (x1 - x0) * step / steps),
+                abs(AbsoluteAxisType::ABS_Y, y0 + (y1 - y0) * step / steps)
+            ]);
+        }
+    }
+    reports.push(vec![abs(AbsoluteAxisType::ABS_PRESSURE, 0), key(Key::BTN_TOUCH, 0)]);
+    reports.push(vec![key(Key::BTN_TOOL_PEN, 0)]);
+    reports
+}
+
+/// Creates a virtual tablet without an input device and draws a square
+/// over the middle of the screen with it, then removes it.
+/// Checks that the output works regardless of the touchpad.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// run::test_output().unwrap();
+/// ```
+pub fn test_output() -> Result<()> {
+    let resolution = (mapping::DEFAULT_RESOLUTION, mapping::DEFAULT_RESOLUTION);
+    let mut tablet = uinput_device(device::create_tablet(device::VIRTUAL_NAME, &TEST_BOUNDS, resolution, false, false, &[]))?;
+    logln!("Created the virtual tablet \"{}\".", device::VIRTUAL_NAME);
+
+    // Gives the system time to pick up the new device.
+    thread::sleep(Duration::from_secs(1));
+    logln!("Drawing a square in the middle of the screen.");
+    for report in square(&TEST_BOUNDS, TEST_STEPS) {
+        tablet.emit(&report).context("Couldn't write to the virtual tablet.")?;
+        thread::sleep(TEST_STEP_DELAY);
+    }
+
+    drop(tablet);
+    successln!("Drew the square and removed the virtual tablet.");
+    Ok(())
+}
+
+/// Runs the `map-point` command, mapping the raw touchpad position given as
+/// the arguments onto the output with the configuration and printing where it lands.
+/// The bounds are the ones given with `--bounds`, or else the touchpad's.
+/// With `--verbose` the stages of the mapping are printed too.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// run::map_point(&[String::from("100"), String::from("200")], &args::Options::default()).unwrap();
+/// ```
+pub fn map_point(args: &[String], opts: &Options) -> Result<()> {
+    let raw = match args {
+        [x, y] => match (x.parse(), y.parse()) {
+            (Ok(x), Ok(y)) => mapping::Point { x, y },
+            _ => {
+                errln!("Invalid position: \x1b[0;39m{} {}", x, y);
+                logln!("See: \x1b[0;39megawari help");
+                process::exit(1);
+            }
+        },
+        _ => {
+            errln!("The position must be the raw X and Y of the touchpad.");
+            logln!("See: \x1b[0;39megawari help");
+            process::exit(1);
+        }
+    };
+
+    let conf = area::resolve_target(config::get_config()?)?;
+    let bounds = given_bounds(&conf, opts)?;
+    let trace = mapping::trace_point(raw, &conf, bounds);
+    if stdout::json_enabled() {
+        println!("{}", serde_json::to_string_pretty(&trace).context("Couldn't convert the mapped position to JSON.")?);
+        return Ok(());
+    }
+
+    debugln!("Bounds: X from {} to {}, Y from {} to {}", bounds.min_x, bounds.max_x, bounds.min_y, bounds.max_y);
+    debugln!("Normalized: {:.4}, {:.4}", trace.normalized.0, trace.normalized.1);
+    debugln!("Scaled by the gains: {:.4}, {:.4}", trace.scaled.0, trace.scaled.1);
+    debugln!("Flipped: {:.4}, {:.4}", trace.flipped.0, trace.flipped.1);
+    debugln!("Rotated: {:.4}, {:.4}", trace.rotated.0, trace.rotated.1);
+    debugln!("Area: {}x{}+{}+{}", trace.area.width, trace.area.height, trace.area.x, trace.area.y);
+    debugln!("Before the edge snapping: {}, {}", trace.unsnapped.x, trace.unsnapped.y);
+    successln!("{}, {} maps to: \x1b[0;39m{}, {}", raw.x, raw.y, trace.point.x, trace.point.y);
+    Ok(())
+}
+
+/// Runs the `active-area` command, printing the rectangle of the output
+/// the strokes land in and the monitors it lies on.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// run::active_area(&opts).unwrap();
+/// ```
+pub fn active_area(opts: &Options) -> Result<()> {
+    let conf = config::get_config()?;
+    let (conf, monitors) = match monitor::list_monitors() {
+        Some(monitors) => (area::apply_target(conf, &monitors)?, monitors),
+        None => (area::resolve_target(conf)?, vec![])
+    };
+    let bounds = given_bounds(&conf, opts)?;
+    let active = area::active_area(&conf, bounds, &monitors);
+    if stdout::json_enabled() {
+        println!("{}", serde_json::to_string_pretty(&active).context("Couldn't convert the active area to JSON.")?);
+        return Ok(());
+    }
+
+    successln!("Active area: \x1b[0;39m{}x{}+{}+{}", active.width, active.height, active.x, active.y);
+    if active.monitors.is_empty() {
+        warnln!("The active area lies on no known monitor.");
+    } else {
+        logln!("Monitors: \x1b[0;39m{}", active.monitors.join(", "));
+    }
+    Ok(())
+}
+
+/// Returns the bounds given with `--bounds`, or else those of the touchpad.
+/// Exits if they're empty or inverted.
+fn given_bounds(conf: &Config, opts: &Options) -> Result<device::Bounds> {
+    let bounds = match opts.bounds {
+        Some(given) => device::Bounds {
+            min_x: given.min_x,
+            max_x: given.max_x,
+            min_y: given.min_y,
+            max_y: given.max_y,
+            pressure: None,
+            orientation: None
+        },
+        None => open_touchpad(conf)?.3
+    };
+    if mapping::degenerate(bounds) {
+        errln!("The bounds are empty or inverted: \x1b[0;39mX from {} to {}, Y from {} to {}",
+            bounds.min_x, bounds.max_x, bounds.min_y, bounds.max_y);
+        process::exit(1);
+    }
+    Ok(bounds)
+}
+
+/// Returns the created virtual device, or exits explaining
+/// how to fix the problem if /dev/uinput can't be used.
+fn uinput_device(created: Result<VirtualDevice>) -> Result<VirtualDevice> {
+    match created {
+        Ok(device) => Ok(device),
+        Err(e) => match UinputProblem::from_error(&e) {
+            Some(problem) => {
+                problem.report();
+                process::exit(1);
+            },
+            None => Err(e)
+        }
+    }
+}
+
+/// Returns the daemon state matching the engine.
+fn state(engine: &Engine) -> State {
+    if engine.paused() {
+        State::Paused
+    } else {
+        State::Active
+    }
+}
@@ -0,0 +1,155 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::time::{Duration, UNIX_EPOCH};
+use evdev::{EventType, InputEvent, Synchronization};
+use serde::{Deserialize, Serialize};
+
+/// How often the summary is logged with the `stats` option.
+pub const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The counts of the events passing through egawari since the window started.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut stats = stats::Stats::new(now);
+/// let out = engine.process(event);
+/// tablet.emit(&out)?;
+/// stats.input(&event, out.len(), now);
+/// println!("{:?}", stats.summary(now));
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    start: Duration,
+    events_in: u64,
+    events_out: u64,
+    reports: u64,
+    latency: Duration,
+    dropped: u64,
+    coalesced: u64
+}
+
+/// The statistics of a window, as reported by the `status` command.
+/// The latency is from the kernel timestamp of a touchpad report
+/// until its tablet events are emitted, averaged over the reports
+/// emitting any. The coalesced reports emit nothing, being held back
+/// or merged into later ones, and the dropped ones are the reports
+/// the kernel threw away because they weren't read in time.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// stats::Summary {
+///     seconds: 5,
+///     events_in: 1500,
+///     events_out: 600,
+///     in_rate: 300,
+///     out_rate: 120,
+///     latency_us: 850,
+///     dropped: 0,
+///     coalesced: 100
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub seconds: u64,
+    pub events_in: u64,
+    pub events_out: u64,
+    /// The events read per second.
+    pub in_rate: u64,
+    /// The events emitted per second.
+    pub out_rate: u64,
+    pub latency_us: u64,
+    pub dropped: u64,
+    pub coalesced: u64
+}
+
+impl Stats {
+    /// Creates the statistics with a window starting at the time.
+    pub fn new(start: Duration) -> Stats {
+        Stats { start, ..Default::default() }
+    }
+
+    /// Counts the touchpad event and the number of tablet events it emitted, at the time.
+    pub fn input(&mut self, event: &InputEvent, emitted: usize, now: Duration) {
+        self.events_in += 1;
+        self.events_out += emitted as u64;
+        if event.event_type() != EventType::SYNCHRONIZATION {
+            return;
+        }
+
+        match Synchronization(event.code()) {
+            Synchronization::SYN_DROPPED => self.dropped += 1,
+            Synchronization::SYN_REPORT if emitted == 0 => self.coalesced += 1,
+            Synchronization::SYN_REPORT => {
+                let time = event.timestamp().duration_since(UNIX_EPOCH).unwrap_or_default();
+                self.latency += now.saturating_sub(time);
+                self.reports += 1;
+            },
+            _ => ()
+        }
+    }
+
+    /// Counts the tablet events emitted without a touchpad event, like when ticking.
+    pub fn output(&mut self, emitted: usize) {
+        self.events_out += emitted as u64;
+    }
+
+    /// Returns when the summary should be logged next.
+    pub fn deadline(&self) -> Duration {
+        self.start + STATS_INTERVAL
+    }
+
+    /// Returns the summary of the window until the time.
+    pub fn summary(&self, now: Duration) -> Summary {
+        let elapsed = now.saturating_sub(self.start);
+        let rate = |count: u64| match elapsed.as_millis() {
+            0 => 0,
+            millis => (count as u128 * 1000 / millis) as u64
+        };
+        Summary {
+            seconds: elapsed.as_secs(),
+            events_in: self.events_in,
+            events_out: self.events_out,
+            in_rate: rate(self.events_in),
+            out_rate: rate(self.events_out),
+            latency_us: match self.reports {
+                0 => 0,
+                reports => (self.latency.as_micros() / reports as u128) as u64
+            },
+            dropped: self.dropped,
+            coalesced: self.coalesced
+        }
+    }
+
+    /// Returns the summary of the window until the time, starting a new one.
+    pub fn take(&mut self, now: Duration) -> Summary {
+        let summary = self.summary(now);
+        *self = Stats::new(now);
+        summary
+    }
+}
+
+impl Summary {
+    /// Returns the summary as a line of the log.
+    pub fn describe(&self) -> String {
+        format!("{} events in ({}/s), {} out ({}/s), {:.2}ms latency, {} dropped, {} coalesced",
+            self.events_in, self.in_rate, self.events_out, self.out_rate,
+            self.latency_us as f64 / 1000.0, self.dropped, self.coalesced)
+    }
+}
@@ -15,11 +15,336 @@
 ** You should have received a copy of the GNU General Public License
 ** along with this program.  If not, see <https://www.gnu.org/licenses/>.
 ****************************************************************************/
+use std::env;
+use std::io::{self, IsTerminal, Write};
 use std::str::Chars;
-use fancy_regex::{Regex, Captures};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use fancy_regex::Regex;
+use once_cell::sync::Lazy;
 use pancurses;
 
-/// Colors the string using ANSI escape codes according to some rules.
+use crate::log::{self, Level};
+
+/// A highlight color, with both its classic ANSI palette
+/// index and its 24-bit truecolor representation.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let cyan = stdout::Color {
+///     bold: true,
+///     ansi: 6,
+///     rgb: (86, 182, 194)
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub bold: bool,
+    pub ansi: u8,
+    pub rgb: (u8, u8, u8)
+}
+
+impl Color {
+    /// Returns the ANSI escape sequence enabling the color,
+    /// using the truecolor representation if `truecolor` is true.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// print!("{}Hi!", stdout::PALETTE.operator.escape(stdout::truecolor_enabled()));
+    /// ```
+    pub fn escape(&self, truecolor: bool) -> String {
+        let weight = if self.bold { 1 } else { 0 };
+        if truecolor {
+            format!("\x1b[{};38;2;{};{};{}m", weight, self.rgb.0, self.rgb.1, self.rgb.2)
+        } else {
+            format!("\x1b[{};3{}m", weight, self.ansi)
+        }
+    }
+}
+
+impl Color {
+    /// Returns the color of the classic ANSI palette index,
+    /// with its truecolor representation from `ANSI_RGB`.
+    /// 
+    /// ## Example
+    /// 
+    /// ```rust
+    /// let blue = stdout::Color::from_ansi(true, 4);
+    /// ```
+    pub fn from_ansi(bold: bool, ansi: u8) -> Color {
+        Color {
+            bold,
+            ansi,
+            rgb: ANSI_RGB[ansi as usize % ANSI_RGB.len()]
+        }
+    }
+}
+
+/// The truecolor representations of the eight classic ANSI colors.
+pub const ANSI_RGB: [(u8, u8, u8); 8] = [
+    (40, 44, 52),
+    (224, 108, 117),
+    (152, 195, 121),
+    (229, 192, 123),
+    (97, 175, 239),
+    (198, 120, 221),
+    (86, 182, 194),
+    (171, 178, 191)
+];
+
+/// The highlight colors of each part of the colored strings
+/// and of the arrows the error and success messages start with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub plus: Color,
+    pub operator: Color,
+    pub separator: Color,
+    pub asterisk: Color,
+    pub brace: Color,
+    pub arrow: Color,
+    pub bracket: Color,
+    pub paren: Color,
+    pub string: Color,
+    pub quote: Color,
+    pub angle: Color,
+    pub error: Color,
+    pub success: Color
+}
+
+/// The default highlight colors.
+pub const PALETTE: Palette = Palette {
+    plus: Color { bold: true, ansi: 6, rgb: (86, 182, 194) },
+    operator: Color { bold: true, ansi: 2, rgb: (152, 195, 121) },
+    separator: Color { bold: false, ansi: 2, rgb: (152, 195, 121) },
+    asterisk: Color { bold: true, ansi: 1, rgb: (224, 108, 117) },
+    brace: Color { bold: true, ansi: 5, rgb: (198, 120, 221) },
+    arrow: Color { bold: true, ansi: 6, rgb: (86, 182, 194) },
+    bracket: Color { bold: true, ansi: 2, rgb: (152, 195, 121) },
+    paren: Color { bold: false, ansi: 2, rgb: (152, 195, 121) },
+    string: Color { bold: true, ansi: 2, rgb: (152, 195, 121) },
+    quote: Color { bold: false, ansi: 2, rgb: (152, 195, 121) },
+    angle: Color { bold: true, ansi: 2, rgb: (152, 195, 121) },
+    error: Color { bold: true, ansi: 1, rgb: (224, 108, 117) },
+    success: Color { bold: true, ansi: 2, rgb: (152, 195, 121) }
+};
+
+/// The highlight colors in use, the default ones unless a theme is set.
+static ACTIVE_PALETTE: Lazy<RwLock<Palette>> = Lazy::new(|| RwLock::new(PALETTE));
+
+/// Highlights with the colors of the palette from now on,
+/// like the ones of the theme in the config.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if let Some(theme) = &conf.theme {
+///     stdout::set_palette(theme.palette());
+/// }
+/// ```
+pub fn set_palette(palette: Palette) {
+    *ACTIVE_PALETTE.write().unwrap() = palette;
+}
+
+/// Returns the highlight colors in use.
+pub fn palette() -> Palette {
+    *ACTIVE_PALETTE.read().unwrap()
+}
+
+/// Returns whether the terminal supports 24-bit colors,
+/// according to the `$COLORTERM` environment variable.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if stdout::truecolor_enabled() {
+///     println!("\x1b[1;38;2;86;182;194mHi!");
+/// }
+/// ```
+pub fn truecolor_enabled() -> bool {
+    matches!(env::var("COLORTERM").as_deref(), Ok("truecolor") | Ok("24bit"))
+}
+
+/// When the output is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Colored if stdout is a terminal and `$NO_COLOR` isn't set.
+    Auto,
+    /// Always colored, even if stdout isn't a terminal or `$NO_COLOR` is set.
+    Always,
+    /// Never colored.
+    Never
+}
+
+impl ColorMode {
+    /// Every mode, in the order `--color` lists them.
+    pub const ALL: [ColorMode; 3] = [ColorMode::Auto, ColorMode::Always, ColorMode::Never];
+
+    /// Returns the name of the mode as `--color` takes it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Auto => "auto",
+            ColorMode::Always => "always",
+            ColorMode::Never => "never"
+        }
+    }
+
+    /// Returns the mode with the name, `auto`, `always` or `never`.
+    pub fn parse(name: &str) -> Option<ColorMode> {
+        ColorMode::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+}
+
+/// When the output is colored, detected unless `--color` is given.
+static COLOR_MODE: Lazy<RwLock<ColorMode>> = Lazy::new(|| RwLock::new(ColorMode::Auto));
+
+/// Colors the output in the mode from now on.
+/// An explicit mode wins over `$NO_COLOR` and the terminal detection.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// stdout::set_color_mode(stdout::ColorMode::Always);
+/// ```
+pub fn set_color_mode(mode: ColorMode) {
+    *COLOR_MODE.write().unwrap() = mode;
+}
+
+/// Returns whether the output is colored in the mode, given whether
+/// `$NO_COLOR` is set to something and whether stdout is a terminal.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert!(stdout::colored(stdout::ColorMode::Always, true, false));
+/// ```
+pub fn colored(mode: ColorMode, no_color: bool, terminal: bool) -> bool {
+    match mode {
+        ColorMode::Auto => !no_color && terminal,
+        ColorMode::Always => true,
+        ColorMode::Never => false
+    }
+}
+
+/// Returns whether the output is colored now.
+pub fn colors_enabled() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    colored(*COLOR_MODE.read().unwrap(), no_color, io::stdout().is_terminal())
+}
+
+/// Returns the string as it is if the output is colored,
+/// or else without its escape sequences.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// print!("{}", stdout::paint(String::from("\x1b[1;32mHi!\x1b[;m")));
+/// ```
+pub fn paint(string: String) -> String {
+    match colors_enabled() {
+        true => string,
+        false => strip_escapes(string.as_str())
+    }
+}
+
+/// Set when the commands print JSON instead of the colored output.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Makes the commands print JSON, suppressing the colored output macros.
+/// Errors are still printed to stderr, without colors.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// stdout::set_json(opts.json);
+/// ```
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::SeqCst);
+}
+
+/// Returns whether the commands print JSON.
+pub fn json_enabled() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// Returns whether the colored output macros print the messages
+/// of the level, which they don't in JSON mode or below the verbosity.
+/// Errors are always printed.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if stdout::printed(log::Level::Info) {
+///     println!();
+/// }
+/// ```
+pub fn printed(level: Level) -> bool {
+    level == Level::Error || (!json_enabled() && log::enabled(level))
+}
+
+/// Removes the ANSI escape sequences from the string.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(stdout::strip_escapes("\x1b[1;32mHi!\x1b[;m"), "Hi!");
+/// ```
+pub fn strip_escapes(string: &str) -> String {
+    ANY_ESCAPE.replace_all(string, "").to_string()
+}
+
+/// Approximates the 24-bit color to the closest of the eight
+/// classic ANSI colors by its hue, returning the palette index.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(stdout::approximate_ansi((86, 182, 194)), 6);
+/// ```
+pub fn approximate_ansi(rgb: (u8, u8, u8)) -> u8 {
+    let (r, g, b) = (rgb.0 as u16, rgb.1 as u16, rgb.2 as u16);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+
+    // Grays become black or white depending on their brightness.
+    if max - min < 32 {
+        return if max < 128 { 0 } else { 7 };
+    }
+
+    let mid = (max + min) / 2;
+    let mut ansi = 0;
+    if r > mid {
+        ansi |= 1;
+    }
+    if g > mid {
+        ansi |= 2;
+    }
+    if b > mid {
+        ansi |= 4;
+    }
+
+    ansi
+}
+
+/// The regular expressions of the basic coloring rules, compiled once.
+static CHARACTER_RULES: Lazy<[Regex; 5]> = Lazy::new(|| [
+    Regex::new(r#"[+]+"#).unwrap(),
+    Regex::new(r#"[:/=]+"#).unwrap(),
+    Regex::new(r#"[,\-|]+"#).unwrap(),
+    Regex::new(r#"[*]+"#).unwrap(),
+    Regex::new(r#"[{}]+"#).unwrap()
+]);
+
+/// The regular expression of an ANSI escape color, compiled once.
+static ESCAPED_COLOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?<=\[)\d*(;\d+)+(?=m)").unwrap());
+
+/// Matches any color escape sequence.
+static ANY_ESCAPE: Lazy<Regex> = Lazy::new(|| Regex::new("\x1b\\[[\\d;]*m").unwrap());
+
+/// Colors the string using ANSI escape codes according to some rules,
+/// highlighting with the colors in use. If the output isn't colored,
+/// the escape sequences already in the string are removed instead.
 /// 
 /// ## Example
 /// 
@@ -27,59 +352,234 @@ use pancurses;
 /// println!("{}", stdout::color_str_escape(" => 'Hi!'"));
 /// ```
 pub fn color_str_escape(string: &str) -> String {
-    let mut result = string.to_string();
+    if !colors_enabled() {
+        return strip_escapes(string);
+    }
+
+    color_str_escape_with(string, &palette(), truecolor_enabled())
+}
+
+/// Colors the string using ANSI escape codes according to some rules,
+/// highlighting with the colors of the palette.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// println!("{}", stdout::color_str_escape_with(" => 'Hi!'", &stdout::PALETTE, true));
+/// ```
+pub fn color_str_escape_with(string: &str, palette: &Palette, truecolor: bool) -> String {
+    // Most lines have nothing to color, and are left as they are.
+    if !string.contains(|c| HIGHLIGHTED.contains(c)) {
+        return string.to_string();
+    }
 
+    color_rules(string, palette, truecolor)
+}
+
+/// The characters the coloring rules act on, including the `[` of escape sequences.
+const HIGHLIGHTED: &str = "+:/=,-|*{}[]()\"'<>";
+
+/// Colors the string like `color_str_escape_with`, running every rule
+/// even if there's nothing to color.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// assert_eq!(stdout::color_rules("plain", &stdout::PALETTE, true), "plain");
+/// ```
+pub fn color_rules(string: &str, palette: &Palette, truecolor: bool) -> String {
     // Basic regular expressions and replacements.
-    let rules: Vec<(&str, &str)> = vec![
+    let rules: [&Color; 5] = [
         // Characters
-        (r#"[+]+"#, "\x1b[1;36m${0}\x1b[1;39m"),
-        (r#"[:/=]+"#, "\x1b[1;32m${0}\x1b[1;39m"),
-        (r#"[,\-|]+"#, "\x1b[0;32m${0}\x1b[1;39m"),
-        (r#"[*]+"#, "\x1b[1;31m${0}\x1b[1;39m"),
-        (r#"[{}]+"#, "\x1b[1;35m${0}\x1b[1;39m"),
-
-        // Exceptions
-        ("\x1b\\[\\d*;\\d+m=\x1b\\[\\d*;\\d+m>", "\x1b[1;36m=>\x1b[1;39m")
+        &palette.plus,
+        &palette.operator,
+        &palette.separator,
+        &palette.asterisk,
+        &palette.brace
     ];
-    for tuple in rules {
-        let re = Regex::new(tuple.0).unwrap();
-        result = re.replace_all(result.as_str(), tuple.1).to_string();
-    }
-    
+
+    // Exceptions
+    // The arrows are split off first, so the operators next to them
+    // can't take their "=" and leave them half colored.
+    let arrow = format!("{}=>\x1b[1;39m", palette.arrow.escape(truecolor));
+    let result = string.split("=>").map(|part| {
+        let mut part = part.to_string();
+        for (re, color) in CHARACTER_RULES.iter().zip(rules.iter()) {
+            part = re.replace_all(part.as_str(), format!("{}${{0}}\x1b[1;39m", color.escape(truecolor)).as_str()).to_string();
+        }
+        part
+    }).collect::<Vec<String>>().join(arrow.as_str());
+
     // The surrounding characters rules.
-    let surrounding: Vec<(&str, &str)> = vec![
-        (r#"([\[])(?:(?=(\\?))\2.)*?([\]])"#, "\x1b[1;32m"),
-        (r#"([\(])(?:(?=(\\?))\2.)*?([\)])"#, "\x1b[0;32m"),
-        (r#"(["])(?:(?=(\\?))\2.)*?(["])"#, "\x1b[1;32m"),
-        (r#"(['])(?:(?=(\\?))\2.)*?(['])"#, "\x1b[0;32m"),
-        (r#"([<])(?:(?=(\\?))\2.)*?([>])"#, "\x1b[1;32m")
-    ];
-    // Color the surrounding colors and remove the color between them.
-    for tuple in surrounding {
-        let re = Regex::new(tuple.0).unwrap();
-        result = re.replace_all(result.as_str(), |caps: &Captures| {
-            let buf = &mut caps[0].chars();
-            buf.next();
-            buf.next_back();
-            format!("{}{}\x1b[0;39m{}{}{}\x1b[1;39m", tuple.1, &caps[1], buf.as_str().replace("\x1b[1;39m", "\x1b[0;39m"), tuple.1, &caps[3])
-        }).to_string();
+    color_surrounding(result.as_str(), palette, truecolor)
+}
+
+/// A piece of the string the surrounding characters are matched in.
+enum Token {
+    /// An ANSI escape sequence, never matched.
+    Escape(String),
+    /// A surrounding character escaped with a backslash.
+    Escaped(char),
+    /// An arrow `=>`, whose `>` never closes a `<`.
+    Arrow,
+    Char(char)
+}
+
+/// Whether a character opens or closes a matched pair.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Plain,
+    Open,
+    Close
+}
+
+/// Splits the string into characters, escape sequences, arrows and escaped surrounding characters.
+fn tokenize(string: &str) -> Vec<Token> {
+    let chars: Vec<char> = string.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == '\x1b' && chars.get(i + 1) == Some(&'[') {
+            let end = chars[i + 2..].iter()
+                .position(|c| !c.is_ascii_digit() && *c != ';')
+                .map(|p| i + 2 + p)
+                .filter(|&end| chars[end] == 'm');
+            if let Some(end) = end {
+                tokens.push(Token::Escape(chars[i..=end].iter().collect()));
+                i = end + 1;
+                continue;
+            }
+        }
+
+        if chars[i] == '=' && chars.get(i + 1) == Some(&'>') {
+            tokens.push(Token::Arrow);
+            i += 2;
+            continue;
+        }
+
+        if chars[i] == '\\' && chars.get(i + 1).is_some_and(|c| "[]()\"'<>".contains(*c)) {
+            tokens.push(Token::Escaped(chars[i + 1]));
+            i += 2;
+            continue;
+        }
+
+        tokens.push(Token::Char(chars[i]));
+        i += 1;
     }
 
-    // The surrounding character escapes.
-    let sur_escape: Vec<(&str, &str)> = vec![
-        (r#"\\([\[\]"<>])"#, "\x1b[1;32m${1}\x1b[1;39m"),
-        (r#"\\([\(\)'])"#, "\x1b[0;32m${1}\x1b[1;39m")
-    ];
-    // Delete the escape character if the surrounding character was escaped.
-    for tuple in sur_escape {
-        let re = Regex::new(tuple.0).unwrap();
-        result = re.replace_all(result.as_str(), tuple.1).to_string();
+    tokens
+}
+
+/// Matches the surrounding characters like nested brackets.
+/// A closing character closes the innermost open one of its kind,
+/// leaving the ones opened after it unmatched.
+/// Quotes close the same quote if it's open and open otherwise.
+fn match_pairs(tokens: &[Token]) -> Vec<Role> {
+    let mut roles = vec![Role::Plain; tokens.len()];
+    let mut stack: Vec<(char, usize)> = vec![];
+
+    for (i, token) in tokens.iter().enumerate() {
+        let c = match token {
+            Token::Char(c) => *c,
+            _ => continue
+        };
+
+        let opener = match c {
+            ']' => '[',
+            ')' => '(',
+            '>' => '<',
+            '"' | '\'' => c,
+            '[' | '(' | '<' => {
+                stack.push((c, i));
+                continue;
+            },
+            _ => continue
+        };
+
+        if let Some(pos) = stack.iter().rposition(|&(open, _)| open == opener) {
+            roles[stack[pos].1] = Role::Open;
+            roles[i] = Role::Close;
+            stack.truncate(pos);
+        } else if opener == c {
+            stack.push((c, i));
+        }
+    }
+
+    roles
+}
+
+/// Colors the matched surrounding characters and dims the text between them.
+/// Unmatched ones are left uncolored, so they can't bleed into the rest of the line.
+fn color_surrounding(string: &str, palette: &Palette, truecolor: bool) -> String {
+    let tokens = tokenize(string);
+    let roles = match_pairs(&tokens);
+    let color = |c: char| match c {
+        '[' | ']' => &palette.bracket,
+        '(' | ')' => &palette.paren,
+        '"' => &palette.string,
+        '\'' => &palette.quote,
+        _ => &palette.angle
+    }.escape(truecolor);
+    let base = |depth: usize| if depth == 0 { "\x1b[1;39m" } else { "\x1b[0;39m" };
+
+    let mut result = String::new();
+    let mut depth = 0;
+    for (token, role) in tokens.iter().zip(roles) {
+        match (token, role) {
+            (Token::Escape(escape), _) => {
+                if depth > 0 && escape == "\x1b[1;39m" {
+                    result.push_str("\x1b[0;39m");
+                } else {
+                    result.push_str(escape);
+                }
+            },
+            // Escaped characters keep the color of their kind, unmatched.
+            (Token::Escaped(c), _) => {
+                let color = match c {
+                    '(' | ')' | '\'' => palette.paren.escape(truecolor),
+                    _ => palette.bracket.escape(truecolor)
+                };
+                result.push_str(format!("{}{}{}", color, c, base(depth)).as_str());
+            },
+            (Token::Char(c), Role::Open) => {
+                result.push_str(format!("{}{}\x1b[0;39m", color(*c), c).as_str());
+                depth += 1;
+            },
+            (Token::Char(c), Role::Close) => {
+                depth -= 1;
+                result.push_str(format!("{}{}{}", color(*c), c, base(depth)).as_str());
+            },
+            (Token::Arrow, _) => result.push_str("=>"),
+            (Token::Char(c), Role::Plain) => result.push(*c)
+        }
     }
 
     result
 }
 
+/// Cleared when the terminal of the curses window has no colors.
+static CURSES_COLORS: AtomicBool = AtomicBool::new(true);
+
+/// Makes the curses output use the color pairs, or plain text without them.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// stdout::set_curses_colors(false);
+/// ```
+pub fn set_curses_colors(colors: bool) {
+    CURSES_COLORS.store(colors, Ordering::SeqCst);
+}
+
+/// Returns whether the curses output uses the color pairs.
+pub fn curses_colors_enabled() -> bool {
+    CURSES_COLORS.load(Ordering::SeqCst)
+}
+
 /// Initializes a curses window with colors using `pancurses`.
+/// On terminals without colors the color pairs are left out
+/// and the colored output is drawn as plain text.
 /// 
 /// ## Example
 /// 
@@ -89,6 +589,12 @@ pub fn color_str_escape(string: &str) -> String {
 pub fn init_curses_wcolors() -> pancurses::Window {
     let window = pancurses::initscr();
 
+    let colors = pancurses::has_colors();
+    set_curses_colors(colors);
+    if !colors {
+        return window;
+    }
+
     pancurses::use_default_colors();
     pancurses::start_color();
     pancurses::init_pair(0, pancurses::COLOR_BLACK, -1);
@@ -106,7 +612,96 @@ pub fn init_curses_wcolors() -> pancurses::Window {
     window
 }
 
+/// Switches the window to the color pair, unless the terminal has no colors.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// stdout::curses_color(&window, 5);
+/// ```
+pub fn curses_color(window: &pancurses::Window, pair: u8) {
+    if curses_colors_enabled() {
+        window.attron(pancurses::ColorPair(pair));
+    }
+}
+
+/// The curses attributes an escape sequence switches to.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// stdout::CursesStyle { bold: true, pair: Some(2) }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursesStyle {
+    pub bold: bool,
+    /// The color pair, or `None` to keep the current one.
+    pub pair: Option<u8>
+}
+
+/// Parses the escape sequence at the start of the part of an escaped string
+/// between two escapes, returning its style if there's one and the remaining characters.
+/// Returns `None` if the part is to be left out.
+fn parse_style(s: &str) -> Option<(Option<CursesStyle>, Chars<'_>)> {
+    let mat = ESCAPED_COLOR.find(s).unwrap();
+    let mut chars = s.chars();
+
+    let mat = match mat {
+        Some(mat) => mat,
+        None => return Some((None, chars))
+    };
+    if mat.start() > 1 {
+        return None;
+    }
+
+    let colors: Vec<&str> = mat.as_str().split(";").collect();
+    let bold = colors[0] == "1";
+
+    // Truecolor is approximated to the closest color pair.
+    let pair = if colors.len() == 6 && colors[1] == "38" && colors[2] == "2" {
+        let rgb: Vec<u8> = colors[3..].iter().map(|c| c.parse::<u8>().unwrap()).collect();
+        Some(approximate_ansi((rgb[0], rgb[1], rgb[2])))
+    } else {
+        let mut color_chars = colors[1].chars();
+        color_chars.next();
+        let color = color_chars.as_str();
+        (!color.is_empty()).then(|| color.parse::<u8>().unwrap())
+    };
+
+    chars.nth(mat.end());
+    Some((Some(CursesStyle { bold, pair }), chars))
+}
+
+/// Turns the attributes of the style on in the window.
+fn apply_style(window: &pancurses::Window, style: CursesStyle) {
+    match style.bold {
+        true => { window.attron(pancurses::A_BOLD); },
+        false => { window.attroff(pancurses::A_BOLD); }
+    }
+    if let Some(pair) = style.pair {
+        window.attron(pancurses::ColorPair(pair));
+    }
+}
+
+/// Splits the escaped string into the texts to draw with curses,
+/// each with the style to switch to before it. Without colors
+/// there are no styles, so the texts are drawn as they are.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let segments = stdout::curses_segments("\x1b[1;32mHi!", true);
+/// assert_eq!(segments, vec![("", None), ("Hi!", Some(stdout::CursesStyle { bold: true, pair: Some(2) }))]);
+/// ```
+pub fn curses_segments(escaped: &str, colors: bool) -> Vec<(&str, Option<CursesStyle>)> {
+    escaped.split("\x1b")
+        .filter_map(parse_style)
+        .map(|(style, chars)| (chars.as_str(), style.filter(|_| colors)))
+        .collect()
+}
+
 /// Parses the escaped string, enables the colors and returns the remaining characters.
+/// The colors are left as they are if the terminal has none.
 /// 
 /// ## Example
 /// 
@@ -121,29 +716,9 @@ pub fn init_curses_wcolors() -> pancurses::Window {
 /// }
 /// ```
 pub fn parse_escaped<'a>(window: &pancurses::Window, s: &'a str) -> Option<Chars<'a>> {
-    let re = Regex::new(r"(?<=\[)\d*;\d+(?=m)").unwrap();
-    let mat = re.find(s).unwrap();
-    let mut chars = s.chars();
-
-    if let Some(mat) = mat {
-        if mat.start() > 1 {
-            return None;
-        }
-
-        let colors: Vec<&str> = mat.as_str().split(";").collect();
-        match colors[0] {
-            "1" => { window.attron(pancurses::A_BOLD); },
-            _ => { window.attroff(pancurses::A_BOLD); }
-        }
-
-        let mut color_chars = colors[1].chars();
-        color_chars.next();
-        let color = color_chars.as_str();
-        if color != "" {
-            window.attron(pancurses::ColorPair(color.parse::<u8>().unwrap()));
-        }
-            
-        chars.nth(mat.end());
+    let (style, chars) = parse_style(s)?;
+    if let Some(style) = style.filter(|_| curses_colors_enabled()) {
+        apply_style(window, style);
     }
 
     Some(chars)
@@ -151,6 +726,7 @@ pub fn parse_escaped<'a>(window: &pancurses::Window, s: &'a str) -> Option<Chars
 
 /// Converts the ANSI escape colored string to a sequence of
 /// curses attributes and `printw` methods and runs them.
+/// Without colors the text is printed without attribute changes.
 /// 
 /// ## Example
 /// 
@@ -161,25 +737,28 @@ pub fn parse_escaped<'a>(window: &pancurses::Window, s: &'a str) -> Option<Chars
 /// stdout::escaped_to_printw(&window, "\x1b[1;32mHi!");
 /// ```
 pub fn escaped_to_printw(window: &pancurses::Window, escaped: String) {
-    window.attron(pancurses::ColorPair(9));
-    window.attron(pancurses::A_BOLD);
-
-    for s in escaped.split("\x1b") {
-        let parsed = parse_escaped(window, s);
+    let colors = curses_colors_enabled();
+    if colors {
+        window.attron(pancurses::ColorPair(9));
+        window.attron(pancurses::A_BOLD);
+    }
 
-        if let Some(chars) = parsed {
-            window.printw(chars.as_str());
-        } else {
-            continue;
+    for (text, style) in curses_segments(escaped.as_str(), colors) {
+        if let Some(style) = style {
+            apply_style(window, style);
         }
+        window.printw(text);
     }
 
-    window.attron(pancurses::ColorPair(9));
-    window.attroff(pancurses::A_BOLD);
+    if colors {
+        window.attron(pancurses::ColorPair(9));
+        window.attroff(pancurses::A_BOLD);
+    }
 }
 
 /// Converts the ANSI escape colored string to a sequence of
 /// curses attributes and `addstr` methods and runs them.
+/// Without colors the text is added without attribute changes.
 /// 
 /// ## Example
 /// 
@@ -190,17 +769,17 @@ pub fn escaped_to_printw(window: &pancurses::Window, escaped: String) {
 /// stdout::escaped_to_addstr(&window, "\x1b[1;32mHi!");
 /// ```
 pub fn escaped_to_addstr(window: &pancurses::Window, escaped: String) {
-    window.attron(pancurses::ColorPair(9));
-    window.attron(pancurses::A_BOLD);
-
-    for s in escaped.split("\x1b") {
-        let parsed = parse_escaped(window, s);
+    let colors = curses_colors_enabled();
+    if colors {
+        window.attron(pancurses::ColorPair(9));
+        window.attron(pancurses::A_BOLD);
+    }
 
-        if let Some(chars) = parsed {
-            window.addstr(chars.as_str());
-        } else {
-            continue;
+    for (text, style) in curses_segments(escaped.as_str(), colors) {
+        if let Some(style) = style {
+            apply_style(window, style);
         }
+        window.addstr(text);
     }
 }
 
@@ -213,11 +792,15 @@ pub fn escaped_to_addstr(window: &pancurses::Window, escaped: String) {
 #[macro_export]
 macro_rules! col {
     ($fmt:expr) => ({
-        print!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape($fmt));
+        if $crate::stdout::printed($crate::log::Level::Info) {
+            print!("{}", $crate::stdout::paint(format!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape($fmt))));
+        }
     });
 
     ($fmt:expr, $($arg:tt)*) => ({
-        print!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
+        if $crate::stdout::printed($crate::log::Level::Info) {
+            print!("{}", $crate::stdout::paint(format!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()))));
+        }
     });
 }
 
@@ -235,11 +818,18 @@ macro_rules! colln {
 #[macro_export]
 macro_rules! log {
     ($fmt:expr) => ({
-        print!(" \x1b[1;36m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt));
+        $crate::log::write($crate::log::Level::Info, $fmt);
+        if $crate::stdout::printed($crate::log::Level::Info) {
+            print!("{}", $crate::stdout::paint(format!(" \x1b[1;36m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt))));
+        }
     });
 
     ($fmt:expr, $($arg:tt)*) => ({
-        print!("\x1b[1;36m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
+        let msg = format!($fmt, $($arg)*);
+        $crate::log::write($crate::log::Level::Info, msg.as_str());
+        if $crate::stdout::printed($crate::log::Level::Info) {
+            print!("{}", $crate::stdout::paint(format!("\x1b[1;36m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(msg.as_str()))));
+        }
     });
 }
 
@@ -257,11 +847,22 @@ macro_rules! logln {
 #[macro_export]
 macro_rules! err {
     ($fmt:expr) => ({
-        print!(" \x1b[1;31m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt));
+        $crate::log::write($crate::log::Level::Error, $fmt);
+        if $crate::stdout::json_enabled() {
+            eprint!("{}", $crate::stdout::strip_escapes($fmt));
+        } else {
+            print!("{}", $crate::stdout::paint(format!(" {}=>\x1b[1;39m {}\x1b[;m", $crate::stdout::palette().error.escape(false), $crate::stdout::color_str_escape($fmt))));
+        }
     });
 
     ($fmt:expr, $($arg:tt)*) => ({
-        print!("\x1b[1;31m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
+        let msg = format!($fmt, $($arg)*);
+        $crate::log::write($crate::log::Level::Error, msg.as_str());
+        if $crate::stdout::json_enabled() {
+            eprint!("{}", $crate::stdout::strip_escapes(msg.as_str()));
+        } else {
+            print!("{}", $crate::stdout::paint(format!("{}=>\x1b[1;39m {}\x1b[;m", $crate::stdout::palette().error.escape(false), $crate::stdout::color_str_escape(msg.as_str()))));
+        }
     });
 }
 
@@ -279,11 +880,18 @@ macro_rules! errln {
 #[macro_export]
 macro_rules! success {
     ($fmt:expr) => ({
-        print!(" \x1b[1;32m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt));
+        $crate::log::write($crate::log::Level::Info, $fmt);
+        if $crate::stdout::printed($crate::log::Level::Info) {
+            print!("{}", $crate::stdout::paint(format!(" {}=>\x1b[1;39m {}\x1b[;m", $crate::stdout::palette().success.escape(false), $crate::stdout::color_str_escape($fmt))));
+        }
     });
 
     ($fmt:expr, $($arg:tt)*) => ({
-        print!("\x1b[1;32m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
+        let msg = format!($fmt, $($arg)*);
+        $crate::log::write($crate::log::Level::Info, msg.as_str());
+        if $crate::stdout::printed($crate::log::Level::Info) {
+            print!("{}", $crate::stdout::paint(format!("{}=>\x1b[1;39m {}\x1b[;m", $crate::stdout::palette().success.escape(false), $crate::stdout::color_str_escape(msg.as_str()))));
+        }
     });
 }
 
@@ -301,11 +909,18 @@ macro_rules! successln {
 #[macro_export]
 macro_rules! warn {
     ($fmt:expr) => ({
-        print!(" \x1b[1;33m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt));
+        $crate::log::write($crate::log::Level::Warn, $fmt);
+        if $crate::stdout::printed($crate::log::Level::Warn) {
+            print!("{}", $crate::stdout::paint(format!(" \x1b[1;33m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt))));
+        }
     });
 
     ($fmt:expr, $($arg:tt)*) => ({
-        print!("\x1b[1;33m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
+        let msg = format!($fmt, $($arg)*);
+        $crate::log::write($crate::log::Level::Warn, msg.as_str());
+        if $crate::stdout::printed($crate::log::Level::Warn) {
+            print!("{}", $crate::stdout::paint(format!("\x1b[1;33m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(msg.as_str()))));
+        }
     });
 }
 
@@ -320,6 +935,35 @@ macro_rules! warnln {
     });
 }
 
+#[macro_export]
+macro_rules! debug {
+    ($fmt:expr) => ({
+        $crate::log::write($crate::log::Level::Debug, $fmt);
+        if $crate::stdout::printed($crate::log::Level::Debug) {
+            print!("{}", $crate::stdout::paint(format!(" \x1b[1;35m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape($fmt))));
+        }
+    });
+
+    ($fmt:expr, $($arg:tt)*) => ({
+        let msg = format!($fmt, $($arg)*);
+        $crate::log::write($crate::log::Level::Debug, msg.as_str());
+        if $crate::stdout::printed($crate::log::Level::Debug) {
+            print!("{}", $crate::stdout::paint(format!("\x1b[1;35m=>\x1b[1;39m {}\x1b[;m", $crate::stdout::color_str_escape(msg.as_str()))));
+        }
+    });
+}
+
+#[macro_export]
+macro_rules! debugln {
+    ($fmt:expr) => ({
+        debug!(format!("{}\n", $fmt).as_str());
+    });
+
+    ($fmt:expr, $($arg:tt)*) => ({
+        debug!(format!("{}\n", format!($fmt, $($arg)*).as_str()).as_str());
+    });
+}
+
 //
 // Macro rules that automatically print to a curses window after
 // coloring the string using ColorPair attribute sequences using
@@ -373,11 +1017,11 @@ macro_rules! logwln {
 #[macro_export]
 macro_rules! errw {
     ($window:expr, $fmt:expr) => ({
-        $crate::stdout::escaped_to_printw($window, format!(" \x1b[1;31m=>\x1b[1;39m {}", $crate::stdout::color_str_escape($fmt)));
+        $crate::stdout::escaped_to_printw($window, format!(" {}=>\x1b[1;39m {}", $crate::stdout::palette().error.escape(false), $crate::stdout::color_str_escape($fmt)));
     });
 
     ($window:expr, $fmt:expr, $($arg:tt)*) => ({
-        $crate::stdout::escaped_to_printw($window, format!(" \x1b[1;31m=>\x1b[1;39m {}", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str())));
+        $crate::stdout::escaped_to_printw($window, format!(" {}=>\x1b[1;39m {}", $crate::stdout::palette().error.escape(false), $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str())));
     });
 }
 
@@ -395,11 +1039,11 @@ macro_rules! errwln {
 #[macro_export]
 macro_rules! successw {
     ($window:expr, $fmt:expr) => ({
-        $crate::stdout::escaped_to_printw($window, format!(" \x1b[1;32m=>\x1b[1;39m {}", $crate::stdout::color_str_escape($fmt)));
+        $crate::stdout::escaped_to_printw($window, format!(" {}=>\x1b[1;39m {}", $crate::stdout::palette().success.escape(false), $crate::stdout::color_str_escape($fmt)));
     });
 
     ($window:expr, $fmt:expr, $($arg:tt)*) => ({
-        $crate::stdout::escaped_to_printw($window, format!(" \x1b[1;32m=>\x1b[1;39m {}", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str())));
+        $crate::stdout::escaped_to_printw($window, format!(" {}=>\x1b[1;39m {}", $crate::stdout::palette().success.escape(false), $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str())));
     });
 }
 
@@ -443,11 +1087,11 @@ macro_rules! warnwln {
 #[macro_export]
 macro_rules! colfmt {
     ($fmt:expr) => ({
-        format!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape($fmt));
+        $crate::stdout::paint(format!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape($fmt)));
     });
 
     ($fmt:expr, $($arg:tt)*) => ({
-        format!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
+        $crate::stdout::paint(format!("\x1b[1;39m{}\x1b[;m", $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str())));
     });
 }
 
@@ -480,3 +1124,27 @@ macro_rules! colwmvaddstr {
         $crate::stdout::escaped_to_addstr($window, $crate::stdout::color_str_escape(format!($fmt, $($arg)*).as_str()));
     });
 }
+
+/// Asks the yes or no question on stderr, returning whether it was answered yes.
+/// Anything but `y` or `yes`, including the end of the input, is a no.
+/// The question is shown whatever the verbosity, even with JSON on stdout,
+/// and isn't logged.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// if stdout::confirm("Reset the configuration?") {
+///     config::save_config(&config::Config::default()).unwrap();
+/// }
+/// ```
+pub fn confirm(question: &str) -> bool {
+    eprint!("{}", paint(format!(" \x1b[1;33m=>\x1b[1;39m {} [y/N] \x1b[;m", color_str_escape(question))));
+    io::stderr().flush().ok();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
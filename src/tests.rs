@@ -1,12 +1,3513 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use once_cell::sync::Lazy;
+
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, RelativeAxisType, Synchronization};
+
+use crate::area;
+use crate::args;
+use crate::completions;
 use crate::config;
+use crate::cursor;
+use crate::daemon;
+use crate::device;
+use crate::engine;
+use crate::focus;
+use crate::log;
+use crate::mapping::{self, Point};
+use crate::monitor::{self, Monitor};
+use crate::overlay::{self, Rect};
+use crate::profile;
+use crate::record;
+use crate::run;
+use crate::stats;
+use crate::stdout;
+use crate::watch;
+
+/// Serializes the tests that read and write the real config file.
+static CONFIG_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Serializes the tests that change the verbosity or the log file.
+static LOG_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Serializes the tests that change the color mode.
+static COLOR_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Serializes the tests that request a stop or run loops checking for one.
+static STOP_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Tests parsing representative command lines.
+#[test]
+fn args_parsing() {
+    let parse = |raw: &[&str]| args::parse(&raw.iter().map(|arg| arg.to_string()).collect::<Vec<String>>());
+    let strings = |strings: &[&str]| strings.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    let parsed = parse(&["--json", "profile", "load", "drawing"]).unwrap();
+    assert_eq!(parsed.command.as_deref(), Some("profile"));
+    assert_eq!(parsed.args, strings(&["load", "drawing"]));
+    assert!(parsed.options.json);
+
+    let parsed = parse(&["config", "--no-save"]).unwrap();
+    assert!(parsed.options.no_save && !parsed.options.no_wrap);
+
+    // Values are given after an equals sign or as the next argument.
+    let config = Some(PathBuf::from("/tmp/egawari.toml"));
+    assert_eq!(parse(&["--config=/tmp/egawari.toml", "run"]).unwrap().options.config, config);
+    assert_eq!(parse(&["run", "--config", "/tmp/egawari.toml"]).unwrap().options.config, config);
+    assert_eq!(parse(&["run", "--config", "/tmp/egawari.toml"]).unwrap().args, strings(&[]));
+    assert_eq!(parse(&["run", "--dump-events", "events.jsonl"]).unwrap().options.dump_events, Some(PathBuf::from("events.jsonl")));
+    assert_eq!(parse(&["config", "--autosave=30"]).unwrap().options.autosave, Some(30));
+    assert!(parse(&["config", "--autosave", "soon"]).is_err());
+    assert_eq!(parse(&["--color=never", "list"]).unwrap().options.color, Some(stdout::ColorMode::Never));
+    assert_eq!(parse(&["--color", "always", "list"]).unwrap().options.color, Some(stdout::ColorMode::Always));
+    assert!(parse(&["--color=sometimes", "list"]).is_err());
+    assert!(parse(&["udev-rule", "--install"]).unwrap().options.install);
+    assert!(parse(&["purge", "--force", "-y"]).unwrap().options.force);
+    assert!(parse(&["run", "--safe"]).unwrap().options.safe);
+    assert!(parse(&["run", "--overlay"]).unwrap().options.overlay);
+    assert_eq!(parse(&["run", "--timeout=5"]).unwrap().options.timeout, Some(5));
+    assert!(parse(&["run", "--timeout", "0"]).is_err());
+    assert_eq!(parse(&["map-point", "--bounds=0,1000, 0,500", "1", "2"]).unwrap().options.bounds,
+        Some(config::InputBounds { min_x: 0, max_x: 1000, min_y: 0, max_y: 500 }));
+    assert!(parse(&["map-point", "--bounds", "0,1000,0", "1", "2"]).is_err());
+
+    // Short flags are bundled.
+    let parsed = parse(&["-qy", "reset"]).unwrap();
+    assert!(parsed.options.quiet && parsed.options.yes && !parsed.options.verbose);
+
+    // Everything after "--" is an argument.
+    let parsed = parse(&["export", "--", "--json", "-y"]).unwrap();
+    assert_eq!(parsed.args, strings(&["--json", "-y"]));
+    assert!(!parsed.options.json && !parsed.options.yes);
+
+    // Negative numbers are arguments, among the options too.
+    let parsed = parse(&["map-point", "-5", "-q", "-10.5"]).unwrap();
+    assert_eq!(parsed.command.as_deref(), Some("map-point"));
+    assert_eq!(parsed.args, strings(&["-5", "-10.5"]));
+    assert!(parsed.options.quiet);
+
+    assert_eq!(parse(&[]).unwrap(), args::Args::default());
+    assert_eq!(parse(&["--colour"]).unwrap_err().to_string(), "Unknown option: --colour");
+    assert_eq!(parse(&["-x"]).unwrap_err().to_string(), "Unknown option: -x");
+    assert_eq!(parse(&["run", "--config"]).unwrap_err().to_string(), "The option --config needs a value.");
+    assert_eq!(parse(&["--json=yes"]).unwrap_err().to_string(), "The option --json doesn't take a value.");
+}
 
 /// Tests the creation and reading of the config file.
 #[test]
 fn config_file() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
     let mut conf = config::get_config().unwrap();
-    conf.input.name = String::from("SynPS/2 Synaptics TouchPad");
+    conf.input.names = vec![String::from("SynPS/2 Synaptics TouchPad")];
+    config::save_config(&conf).unwrap();
+
+    let input_names = &conf.input.names;
+    assert_eq!(config::get_config().unwrap().input.names, *input_names);
+}
+
+/// Tests that init writes the default configuration where `--config` points.
+#[test]
+fn config_init() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let dir = env::temp_dir().join("egawari-test-init");
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("egawari.toml");
+
+    config::set_config_path(Some(path.clone()));
+    let created = config::init(&args::Options::default());
+    std::fs::write(&path, "[input]\nnames = [\"Elan Touchpad\"]\n").unwrap();
+    let overwritten = config::init(&args::Options { yes: true, ..Default::default() });
+    config::set_config_path(None);
+
+    created.unwrap();
+    overwritten.unwrap();
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert!(!raw.contains("Elan Touchpad"));
+    assert_eq!(config::read_config(path.as_path()).unwrap().input.names, config::Config::default().input.names);
+}
+
+/// Tests that the autosave file sits next to the config file and is removed cleanly.
+#[test]
+fn config_autosave() {
+    let dir = env::temp_dir().join("egawari-test-autosave");
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("egawari.toml");
+    let autosave = config::autosave_path(path.as_path());
+    assert_eq!(autosave, dir.join(".egawari.toml.autosave"));
+
+    let mut conf = config::Config::default();
+    conf.input.names = vec![String::from("Elan Touchpad")];
+    config::write_config(autosave.as_path(), &conf).unwrap();
+    assert_eq!(config::read_config(autosave.as_path()).unwrap().input.names, conf.input.names);
+
+    config::remove_autosave(path.as_path()).unwrap();
+    assert!(!autosave.exists());
+    // There's nothing left to remove.
+    config::remove_autosave(path.as_path()).unwrap();
+}
+
+/// Tests that purging removes the config directory and the runtime files that exist,
+/// telling which, and nothing else.
+#[test]
+fn config_purge() {
+    let root = env::temp_dir().join("egawari-test-purge");
+    let _ = std::fs::remove_dir_all(&root);
+    let dir = root.join("egawari");
+    std::fs::create_dir_all(dir.join("profiles")).unwrap();
+    std::fs::write(dir.join("egawari.toml"), "[input]\nnames = []\n").unwrap();
+    std::fs::write(dir.join("profiles").join("drawing.toml"), "[input]\nnames = []\n").unwrap();
+    let pid = root.join("egawari.pid");
+    let socket = root.join("egawari.sock");
+    std::fs::write(&pid, "1").unwrap();
+    let other = root.join("other.toml");
+    std::fs::write(&other, "").unwrap();
+
+    let removed = config::remove_paths(dir.as_path(), &[pid.clone(), socket.clone()]).unwrap();
+    assert_eq!(removed, vec![dir.clone(), pid.clone()]);
+    assert!(!dir.exists() && !pid.exists());
+    assert!(other.exists());
+
+    // There's nothing left to remove.
+    assert!(config::remove_paths(dir.as_path(), &[pid, socket]).unwrap().is_empty());
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert!(daemon::runtime_files().contains(&daemon::pid_path()));
+    assert!(daemon::runtime_files().contains(&daemon::socket_path()));
+}
+
+/// Tests that resetting without a confirmation restores the defaults.
+#[test]
+fn config_reset() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let path = env::temp_dir().join("egawari-test-reset").join("egawari.toml");
+    config::set_config_path(Some(path.clone()));
+
+    let mut conf = config::Config::default();
+    conf.input.names = vec![String::from("SynPS/2 Synaptics TouchPad")];
     config::save_config(&conf).unwrap();
+    let result = config::reset(&args::Options {
+        yes: true,
+        ..Default::default()
+    });
+    let reset = config::read_config(path.as_path());
+    config::set_config_path(None);
+
+    result.unwrap();
+    assert!(reset.unwrap().input.names.is_empty());
+}
+
+/// Tests that resetting replaces a file with an include with the defaults,
+/// dropping the include.
+#[test]
+fn config_reset_include() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let dir = env::temp_dir().join("egawari-test-reset-include");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("base.toml"), "[pad]\nbuttons = 4\n").unwrap();
+    let path = dir.join("egawari.toml");
+    std::fs::write(&path, "include = \"base.toml\"\n\n[mapping]\nflip_x = true\n").unwrap();
+
+    config::set_config_path(Some(path.clone()));
+    let result = config::reset(&args::Options {
+        yes: true,
+        ..Default::default()
+    });
+    config::set_config_path(None);
+
+    result.unwrap();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("include"));
+    let reset = config::read_config(path.as_path()).unwrap();
+    assert!(reset.pad.is_none());
+    assert!(!reset.mapping.flip_x);
+}
+
+/// Tests that a config file is read on top of the file it includes,
+/// that saving keeps the include and that include cycles are rejected.
+#[test]
+fn config_include() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let dir = env::temp_dir().join("egawari-test-include");
+    std::fs::create_dir_all(dir.join("profiles")).unwrap();
+    std::fs::write(dir.join("base.toml"), "[input]\nnames = [\"Elan Touchpad\"]\n\n[mapping]\nrotation = 90\nflip_x = true\n").unwrap();
+    let path = dir.join("profiles").join("sketch.toml");
+    std::fs::write(&path, "include = \"../base.toml\"\n\n[mapping]\nflip_x = false\nscale_x = 2.0\n").unwrap();
+
+    let conf = config::read_config(path.as_path()).unwrap();
+    assert_eq!(conf.input.names, vec![String::from("Elan Touchpad")]);
+    assert_eq!(conf.mapping.rotation, 90);
+    assert!(!conf.mapping.flip_x);
+    assert_eq!(conf.mapping.scale_x, 2.0);
+
+    // Saving keeps only what differs from the included file.
+    config::set_config_path(Some(path.clone()));
+    let mut edited = conf.clone();
+    edited.mapping.flip_y = true;
+    let saved = config::save_config(&edited);
+    config::set_config_path(None);
+    saved.unwrap();
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert!(raw.contains("include = '../base.toml'"));
+    assert!(raw.contains("flip_y = true"));
+    assert!(!raw.contains("rotation"));
+    assert!(!raw.contains("Elan Touchpad"));
+    assert!(config::read_config(path.as_path()).unwrap().mapping.flip_y);
+
+    // What the included file sets and the config doesn't is unset.
+    std::fs::write(dir.join("base.toml"), "[pad]\nbuttons = 4\n\n[tablet]\npressure_device = \"Wacom\"\n").unwrap();
+    let mut unset = config::read_config(path.as_path()).unwrap();
+    assert!(unset.pad.is_some());
+    unset.pad = None;
+    unset.tablet.pressure_device = None;
+    config::set_config_path(Some(path.clone()));
+    let saved = config::save_config(&unset);
+    config::set_config_path(None);
+    saved.unwrap();
+    let raw = std::fs::read_to_string(&path).unwrap();
+    assert!(raw.contains("pad = false"), "{}", raw);
+    let reread = config::read_config(path.as_path()).unwrap();
+    assert!(reread.pad.is_none());
+    assert!(reread.tablet.pressure_device.is_none());
+
+    std::fs::write(dir.join("a.toml"), "include = \"b.toml\"\n").unwrap();
+    std::fs::write(dir.join("b.toml"), "include = \"a.toml\"\n").unwrap();
+    let error = config::read_config(dir.join("a.toml").as_path()).err().unwrap().to_string();
+    assert!(error.starts_with("The config files include each other: "), "{}", error);
+    assert!(error.ends_with("a.toml"), "{}", error);
+    assert!(config::read_config(dir.join("missing.toml").as_path()).is_err());
+}
+
+/// Tests that the configuration in effect is annotated with the sources of its values.
+#[test]
+fn config_sources() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let path = env::temp_dir().join("egawari-test-sources.toml");
+    std::fs::write(&path, "[input]\nnames = []\n\n[mapping]\nflip_x = true\n\n[[focus_rules]]\nclass = \"krita\"\nprofile = \"sketch\"\n").unwrap();
+
+    config::set_config_path(Some(path.clone()));
+    env::set_var("EGAWARI_DISPLAY", ":2");
+    let conf = config::get_config();
+    let sources = conf.as_ref().ok().map(config::config_sources);
+    env::remove_var("EGAWARI_DISPLAY");
+    config::set_config_path(None);
+
+    let conf = conf.unwrap();
+    let sources = sources.unwrap().unwrap();
+    let source = |path: &str| sources.iter().find(|(key, _)| key == path).map(|(_, source)| *source);
+    assert_eq!(source("mapping.flip_x"), Some(config::Source::File));
+    assert_eq!(source("mapping.flip_y"), Some(config::Source::Default));
+    assert_eq!(source("display.display"), Some(config::Source::Env));
+    assert_eq!(source("focus_rules"), Some(config::Source::File));
+
+    let raw = toml::to_string_pretty(&conf).unwrap();
+    let annotated = config::annotate_sources(raw.as_str(), &sources);
+    assert!(annotated.contains("flip_x = true # file\n"), "{}", annotated);
+    assert!(annotated.contains("flip_y = false # default\n"), "{}", annotated);
+    assert!(annotated.contains("display = ':2' # env\n"), "{}", annotated);
+    assert!(annotated.contains("[[focus_rules]] # file\n"), "{}", annotated);
+    assert!(annotated.contains("\n[mapping]\n"), "{}", annotated);
+
+    // The comments leave the TOML as it was.
+    let reparsed: toml::Value = toml::from_str(annotated.as_str()).unwrap();
+    assert_eq!(reparsed, toml::Value::try_from(&conf).unwrap());
+}
+
+/// Tests that the user's config file is read over the system-wide one.
+#[test]
+fn config_search_path() {
+    let dir = env::temp_dir().join("egawari-test-search");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("etc")).unwrap();
+    std::fs::create_dir_all(dir.join("home")).unwrap();
+    let system = dir.join("etc").join("egawari.toml");
+    let user = dir.join("home").join("egawari.toml");
+
+    // Neither, giving the defaults.
+    assert!(config::search_config_files(Some(system.as_path()), user.as_path()).is_empty());
+    assert_eq!(config::read_configs(&[]).unwrap().mapping.rotation, config::Config::default().mapping.rotation);
+
+    // The system-wide file only.
+    std::fs::write(&system, "[input]\nnames = [\"Elan Touchpad\"]\n\n[mapping]\nrotation = 90\nflip_x = true\n").unwrap();
+    let files = config::search_config_files(Some(system.as_path()), user.as_path());
+    assert_eq!(files, vec![system.clone()]);
+    let conf = config::read_configs(&files).unwrap();
+    assert_eq!(conf.input.names, vec![String::from("Elan Touchpad")]);
+    assert_eq!(conf.mapping.rotation, 90);
+
+    // Both, the user's values winning key by key.
+    std::fs::write(&user, "[input]\ngrab = false\n\n[mapping]\nflip_x = false\nscale_x = 2.0\n").unwrap();
+    let files = config::search_config_files(Some(system.as_path()), user.as_path());
+    assert_eq!(files, vec![system.clone(), user.clone()]);
+    let conf = config::read_configs(&files).unwrap();
+    assert_eq!(conf.input.names, vec![String::from("Elan Touchpad")]);
+    assert_eq!(conf.mapping.rotation, 90);
+    assert!(!conf.mapping.flip_x);
+    assert_eq!(conf.mapping.scale_x, 2.0);
+    assert!(!conf.input.grab);
+
+    // The user's file only, as with --config.
+    let files = config::search_config_files(None, user.as_path());
+    assert_eq!(files, vec![user.clone()]);
+    let conf = config::read_configs(&files).unwrap();
+    assert_eq!(conf.input.names, config::Config::default().input.names);
+    assert!(!conf.mapping.flip_x);
+
+    std::fs::remove_file(&system).unwrap();
+    assert_eq!(config::search_config_files(Some(system.as_path()), user.as_path()), vec![user.clone()]);
+
+    // Resetting unsets what the system-wide file sets, leaving the defaults in effect.
+    std::fs::write(&system, "[activation]\nkey = \"KEY_LEFTALT\"\n\n[[routes]]\nfrom = \"pressure\"\nto = \"REL_WHEEL\"\n\n[mapping]\nrotation = 90\n").unwrap();
+    config::replace_config(user.as_path(), std::slice::from_ref(&system), &config::Config::default()).unwrap();
+    let reset = config::read_configs(&[system.clone(), user.clone()]).unwrap();
+    assert!(reset.activation.is_none());
+    assert_eq!(toml::Value::try_from(&reset).unwrap(), toml::Value::try_from(config::Config::default()).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Tests the areas selected on the monitors with the inset percents.
+#[test]
+fn area_selection() {
+    let monitor = |name: &str, width, height, x, y| Monitor { name: name.to_string(), width, height, x, y, primary: false };
+    // The external monitor is on the left of the laptop, at negative positions.
+    let monitors = vec![monitor("eDP-1", 1920, 1080, 0, 0), monitor("HDMI-1", 1280, 1024, -1280, 0)];
+    assert_eq!(area::screen_size(&monitors), (3200, 1080));
+
+    let full = area::inset_area(&monitors, &monitors[0], area::Insets::default()).unwrap();
+    assert_eq!((full.x, full.y, full.width, full.height), (1280, 0, 1920, 1080));
+    let insets = area::Insets { left: 10, top: 0, right: 10, bottom: 50 };
+    let inset = area::inset_area(&monitors, &monitors[0], insets).unwrap();
+    assert_eq!((inset.x, inset.y, inset.width, inset.height), (1472, 0, 1536, 540));
+    let external = area::inset_area(&monitors, &monitors[1], area::Insets { left: 25, ..Default::default() }).unwrap();
+    assert_eq!((external.x, external.y, external.width, external.height), (320, 0, 960, 1024));
+    assert_eq!(area::inset_area(&monitors, &monitors[0], area::Insets { left: 50, right: 50, ..Default::default() }), None);
+
+    // The selected area fits the whole screen as the output.
+    let conf = config::Config {
+        mapping: config::Mapping { width: Some(3200), height: Some(1080), area: Some(inset), ..Default::default() },
+        ..Default::default()
+    };
+    assert!(conf.validate().is_empty());
+
+    let sketch = area::sketch(&monitors, Some(inset), 16, 4);
+    assert_eq!(sketch, vec![
+        String::from(".......########."),
+        String::from(".......########."),
+        String::from("................"),
+        String::from("................")
+    ]);
+}
+
+/// Tests the bounding box of several monitors the touchpad is mapped onto.
+#[test]
+fn area_union() {
+    use config::MappingTarget;
+    let monitor = |name: &str, width, height, x, y| Monitor { name: name.to_string(), width, height, x, y, primary: false };
+
+    // Side by side with different heights, leaving a dead zone under the smaller one.
+    let monitors = vec![monitor("DP-1", 2560, 1440, 0, 0), monitor("HDMI-1", 1920, 1080, 2560, 0)];
+    let both = area::union_area(&monitors, &[&monitors[0], &monitors[1]]).unwrap();
+    assert_eq!((both.x, both.y, both.width, both.height), (0, 0, 4480, 1440));
+    let right = area::union_area(&monitors, &[&monitors[1]]).unwrap();
+    assert_eq!((right.x, right.y, right.width, right.height), (2560, 0, 1920, 1080));
+    assert_eq!(area::union_area(&monitors, &[]), None);
+
+    // An L of three monitors, one on top at negative positions.
+    let monitors = vec![
+        monitor("eDP-1", 1920, 1080, 0, 0),
+        monitor("DP-1", 1920, 1080, 1920, 0),
+        monitor("DP-2", 1280, 1024, -640, -1024)
+    ];
+    let all: Vec<&Monitor> = monitors.iter().collect();
+    let whole = area::union_area(&monitors, &all).unwrap();
+    assert_eq!((whole.x, whole.y, whole.width, whole.height), (0, 0, 4480, 2104));
+    assert_eq!((whole.width, whole.height), area::screen_size(&monitors));
+    let pair = area::union_area(&monitors, &[&monitors[0], &monitors[2]]).unwrap();
+    assert_eq!((pair.x, pair.y, pair.width, pair.height), (0, 0, 2560, 2104));
+    let lower = area::union_area(&monitors, &[&monitors[1], &monitors[0]]).unwrap();
+    assert_eq!((lower.x, lower.y, lower.width, lower.height), (640, 1024, 3840, 1080));
+
+    // The targets replace the output size and the area, except for the area target.
+    let conf = |target| config::Config {
+        mapping: config::Mapping { target, width: Some(1920), height: Some(1080), ..Default::default() },
+        ..Default::default()
+    };
+    let applied = area::apply_target(conf(MappingTarget::All), &monitors).unwrap();
+    assert_eq!((applied.mapping.width, applied.mapping.height), (Some(4480), Some(2104)));
+    assert_eq!(applied.mapping.area, Some(whole));
+    assert!(applied.validate().is_empty());
+    let target = MappingTarget::Monitors(vec![String::from("DP-1"), String::from("eDP-1")]);
+    assert_eq!(area::apply_target(conf(target), &monitors).unwrap().mapping.area, Some(lower));
+    let applied = area::apply_target(conf(MappingTarget::Area), &monitors).unwrap();
+    assert_eq!((applied.mapping.width, applied.mapping.area), (Some(1920), None));
+    assert!(area::apply_target(conf(MappingTarget::Monitors(vec![String::from("VGA-1")])), &monitors).is_err());
+    assert!(area::apply_target(conf(MappingTarget::All), &[]).is_err());
+
+    // The targets are written as they're parsed.
+    for target in &["area", "all", "monitor:DP-1", "monitor:DP-1,eDP-1"] {
+        assert_eq!(target.parse::<MappingTarget>().unwrap().to_string(), *target);
+    }
+    assert_eq!("monitor: DP-1 , eDP-1".parse::<MappingTarget>().unwrap(), MappingTarget::Monitors(vec![String::from("DP-1"), String::from("eDP-1")]));
+    assert!("monitor:".parse::<MappingTarget>().is_err());
+    assert!("monitor:DP-1,".parse::<MappingTarget>().is_err());
+    assert!("screen".parse::<MappingTarget>().is_err());
+    let parsed: config::Mapping = toml::from_str("target = \"all\"\n").unwrap();
+    assert_eq!(parsed.target, MappingTarget::All);
+    assert!(toml::from_str::<config::Mapping>("target = \"half\"\n").is_err());
+}
+
+/// Tests the rectangle the strokes land in and the monitors it lies on.
+#[test]
+fn area_active() {
+    use config::MappingTarget;
+    let monitor = |name: &str, width, height, x, y| Monitor { name: name.to_string(), width, height, x, y, primary: false };
+    let monitors = vec![monitor("eDP-1", 1920, 1080, 0, 0), monitor("HDMI-1", 1920, 1080, 1920, 0)];
+    let active = |target, keep_aspect, rotation, area| {
+        let conf = config::Config {
+            mapping: config::Mapping { target, keep_aspect, rotation, area, ..Default::default() },
+            ..Default::default()
+        };
+        let active = area::active_area(&area::apply_target(conf, &monitors).unwrap(), test_bounds(), &monitors);
+        ((active.x, active.y, active.width, active.height), active.monitors)
+    };
+    let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+    // The whole target, or letterboxed to the aspect ratio of the (rotated) touchpad.
+    let left = MappingTarget::Monitors(vec![String::from("eDP-1")]);
+    assert_eq!(active(left.clone(), false, 0, None), ((0, 0, 1920, 1080), names(&["eDP-1"])));
+    assert_eq!(active(left.clone(), true, 0, None), ((0, 60, 1920, 960), names(&["eDP-1"])));
+    assert_eq!(active(left, true, 90, None), ((690, 0, 540, 1080), names(&["eDP-1"])));
+    assert_eq!(active(MappingTarget::All, false, 0, None), ((0, 0, 3840, 1080), names(&["eDP-1", "HDMI-1"])));
+
+    // A configured area lies on the monitors it overlaps.
+    let area = |x, width| Some(config::Area { x, y: 100, width, height: 500, width_mm: None, height_mm: None });
+    assert_eq!(active(MappingTarget::Area, false, 0, area(2000, 800)).1, names(&["HDMI-1"]));
+    assert_eq!(active(MappingTarget::Area, false, 0, area(1500, 800)).1, names(&["eDP-1", "HDMI-1"]));
+    assert_eq!(active(MappingTarget::Area, false, 0, area(1120, 800)).1, names(&["eDP-1"]));
+}
+
+/// Tests detecting the session type from the environment and refusing the wrong one.
+#[test]
+fn session_guard() {
+    use config::SessionType;
+
+    // `$XDG_SESSION_TYPE` wins, then the display variables tell.
+    assert_eq!(monitor::detect_session(Some("wayland"), false, true), Some(SessionType::Wayland));
+    assert_eq!(monitor::detect_session(Some("x11"), true, true), Some(SessionType::X11));
+    assert_eq!(monitor::detect_session(Some("X11 "), false, false), Some(SessionType::X11));
+    assert_eq!(monitor::detect_session(Some("tty"), true, true), Some(SessionType::Wayland));
+    assert_eq!(monitor::detect_session(None, false, true), Some(SessionType::X11));
+    assert_eq!(monitor::detect_session(Some(""), true, false), Some(SessionType::Wayland));
+    assert_eq!(monitor::detect_session(Some("tty"), false, false), None);
+    assert_eq!(monitor::detect_session(None, false, false), None);
+
+    assert_eq!(monitor::session_problem(SessionType::Any, None), None);
+    assert_eq!(monitor::session_problem(SessionType::Any, Some(SessionType::Wayland)), None);
+    assert_eq!(monitor::session_problem(SessionType::X11, Some(SessionType::X11)), None);
+    assert_eq!(monitor::session_problem(SessionType::X11, Some(SessionType::Wayland)),
+        Some(String::from("egawari only runs in X11 sessions, but this session is Wayland.")));
+    assert_eq!(monitor::session_problem(SessionType::Wayland, None),
+        Some(String::from("egawari only runs in Wayland sessions, but no graphical session was found.")));
+
+    let conf: config::Config = toml::from_str("require_session = \"x11\"\n[input]\nnames = []\n").unwrap();
+    assert_eq!(conf.require_session, SessionType::X11);
+    assert!(!toml::to_string(&config::Config::default()).unwrap().contains("require_session"));
+}
+
+/// Tests that the validation catches unusable mappings.
+#[test]
+fn config_validate() {
+    assert!(config::Config::default().validate().is_empty());
+
+    let conf = mapping_config(config::Mapping {
+        rotation: 45,
+        scale_y: 0.0,
+        area: Some(config::Area {
+            x: 960,
+            y: 0,
+            width: 1920,
+            height: 0,
+            width_mm: None,
+            height_mm: None
+        }),
+        ..Default::default()
+    });
+    assert_eq!(conf.validate(), vec![
+        String::from("The rotation must be 0, 90, 180 or 270 degrees, not 45."),
+        String::from("The scales must be positive numbers."),
+        String::from("The area width and height can't be 0."),
+        String::from("The area doesn't fit in the output.")
+    ]);
+
+    // An area ending past the largest number doesn't wrap around to fit.
+    for anchor in [config::Anchor::TopLeft, config::Anchor::Center] {
+        let conf = mapping_config(config::Mapping {
+            width: Some(1920),
+            height: Some(1080),
+            area: Some(config::Area {
+                x: 4294967000,
+                y: 0,
+                width: 1000,
+                height: 4294967000,
+                width_mm: None,
+                height_mm: None
+            }),
+            anchor,
+            ..Default::default()
+        });
+        assert_eq!(conf.validate(), vec![String::from("The area doesn't fit in the output.")]);
+    }
+
+    let mut conf = config::Config::default();
+    conf.tablet.virtual_name = String::from(" ");
+    assert_eq!(conf.validate(), vec![String::from("The virtual tablet name can't be empty.")]);
+    conf.tablet.virtual_name = "a".repeat(80);
+    assert_eq!(conf.validate(), vec![String::from("The virtual tablet name can't be longer than 79 bytes.")]);
+}
+
+/// Tests that the old single input name is read as a list and a missing input table as no device.
+#[test]
+fn config_input_names() {
+    let read = |toml: &str| toml::from_str::<config::Config>(toml).unwrap().input.names;
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+
+    assert_eq!(read("[input]\nname = \"SynPS/2 Synaptics TouchPad\""), names(&["SynPS/2 Synaptics TouchPad"]));
+    assert_eq!(read("[input]\nname = \"\""), names(&[]));
+    assert_eq!(read("[input]\nnames = [\"Apple Inc. Magic Trackpad\", \"Elan Touchpad\"]"), names(&["Apple Inc. Magic Trackpad", "Elan Touchpad"]));
+    assert_eq!(read("[input]\nname = \"Elan Touchpad\"\nnames = [\"Apple Inc. Magic Trackpad\"]"), names(&["Elan Touchpad", "Apple Inc. Magic Trackpad"]));
+
+    // The list is written back instead of the single name.
+    let conf: config::Config = toml::from_str("[input]\nname = \"Elan Touchpad\"").unwrap();
+    assert!(toml::to_string(&conf).unwrap().contains("names = [\"Elan Touchpad\"]"));
+
+    // Files without the table read as no device, grabbed once one is set.
+    let conf: config::Config = toml::from_str("[display]\ndisplay = \":1\"\nscreen = 0\n").unwrap();
+    assert!(conf.input.names.is_empty() && conf.input.id.is_none() && conf.input.path.is_none());
+    assert!(conf.input.grab);
+    assert_eq!(conf.display.unwrap().display.as_deref(), Some(":1"));
+}
+
+/// Tests that the input device is grabbed unless the grab is turned off.
+#[test]
+fn config_input_grab() {
+    let read = |toml: &str| toml::from_str::<config::Config>(toml).unwrap().input.grab;
+
+    assert!(read("[input]\nnames = [\"Elan Touchpad\"]"));
+    assert!(read("[input]\nname = \"Elan Touchpad\"\ngrab = true"));
+    assert!(!read("[input]\nnames = [\"Elan Touchpad\"]\ngrab = false"));
+
+    let mut conf = config::Config::default();
+    config::ConfigField::InputGrab.set(&mut conf, config::ConfigValue::Bool(false));
+    assert!(toml::to_string(&conf).unwrap().contains("grab = false"));
+}
+
+/// Tests that the listed keys are exactly the keys of a config with every section set.
+#[test]
+fn config_keys() {
+    fn leaves(path: String, value: &serde_json::Value, out: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => for (key, value) in map {
+                let path = if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+                leaves(path, value, out);
+            },
+            _ => out.push(path)
+        }
+    }
+
+    let conf = config::Config {
+        log_file: Some(PathBuf::from("/tmp/egawari.log")),
+        require_session: config::SessionType::Wayland,
+        input: config::Input {
+            names: vec![String::from("Elan Touchpad")],
+            id: Some(config::DeviceId { vendor: 0x04f3, product: 0x3140 }),
+            path: Some(PathBuf::from("/dev/input/event5")),
+            grab: true,
+            fallback_bounds: Some(config::InputBounds { min_x: 0, max_x: 1000, min_y: 0, max_y: 500 })
+        },
+        display: Some(config::Display { display: Some(String::from(":0")), screen: 0 }),
+        tablet: config::Tablet {
+            pressure_curve: vec![[0, 0], [100, 100]],
+            pressure_device: Some(String::from("Pressure Pad")),
+            output_device: Some(String::from("Shared Tablet")),
+            ..Default::default()
+        },
+        tilt: Some(config::Tilt::default()),
+        smoothing: Some(config::Smoothing::default()),
+        mapping: config::Mapping {
+            width: Some(1920),
+            height: Some(1080),
+            area: Some(config::Area { x: 0, y: 0, width: 960, height: 540, width_mm: Some(100.0), height_mm: Some(60.0) }),
+            quad: Some(config::Quad { top_left: [0, 0], top_right: [959, 0], bottom_right: [959, 539], bottom_left: [0, 539] }),
+            edge_snap: Some(config::EdgeSnap { pixels: 4, percent: 0.5 }),
+            ..Default::default()
+        },
+        overlay: Some(config::Overlay::default()),
+        activation: Some(config::Activation { key: String::from("KEY_LEFTALT"), device: Some(String::from("Keyboard")) }),
+        eraser: Some(config::Eraser { key: Some(String::from("KEY_LEFTCTRL")), device: Some(String::from("Keyboard")) }),
+        pad: Some(config::Pad {
+            buttons: 1,
+            device: Some(String::from("Keyboard")),
+            bindings: vec![config::PadBinding { button: 0, key: Some(String::from("KEY_F13")), gesture: None }]
+        }),
+        stylus_buttons: Some(config::StylusButtons {
+            device: Some(String::from("Keyboard")),
+            bindings: vec![config::StylusBinding { key: String::from("KEY_LEFTSHIFT"), button: config::StylusButton::Lower }]
+        }),
+        routes: vec![config::Route { from: config::RouteSource::Pressure, to: String::from("REL_WHEEL"), scale: 0.1, threshold: 50 }],
+        focus_rules: vec![config::FocusRule { class: Some(String::from("krita")), title: None, profile: String::from("drawing") }],
+        profile_switch: Some(config::ProfileSwitch {
+            device: Some(String::from("Keyboard")),
+            notify: true,
+            bindings: vec![config::ProfileBinding { key: String::from("KEY_F9"), profile: None }]
+        }),
+        theme: Some(config::Theme::default())
+    };
+
+    let mut paths = vec![];
+    leaves(String::new(), &serde_json::to_value(&conf).unwrap(), &mut paths);
+    let mut keys: Vec<String> = config::KEYS.iter().map(|(path, _, _)| path.to_string()).collect();
+    paths.sort();
+    keys.sort();
+    assert_eq!(keys, paths);
+}
+
+/// Tests that the editor columns count the terminal width of the characters instead of their bytes.
+#[test]
+fn config_edit_column() {
+    assert_eq!(config::value_column("Name"), 11);
+    assert_eq!(config::edit_column("Name", "Elan", true), 16);
+    assert_eq!(config::edit_column("Name", "Élan", true), 16);
+    // Wide characters take two columns.
+    assert_eq!(config::edit_column("Name", "ペンタブ", true), 20);
+    assert_eq!(config::edit_column("Screen", "12", false), 15);
+    // The quotes and backslashes within a string are shown and edited as they are.
+    let name = r#"Pen "A" \ B"#;
+    assert_eq!(config::quote(name), r#""Pen "A" \ B""#);
+    assert_eq!(config::edit_column("Name", name, true), 11 + 1 + 11);
+    assert_eq!(config::edit_column("Name", r"C:\", true), 11 + 1 + 3);
+    // Combining characters take none.
+    assert_eq!(config::text_width("Ta\u{301}blette"), 8);
+}
+
+/// Tests picking the touchpads for the automatic setup and confirming the name it replaces.
+#[test]
+fn config_input_setup() {
+    let device = |name: &str, position, pressure| device::DeviceInfo {
+        path: String::from("/dev/input/event0"),
+        name: String::from(name),
+        id: config::DeviceId { vendor: 0, product: 0 },
+        capabilities: device::Capabilities { position, pressure, ..Default::default() }
+    };
+    let devices = vec![
+        device("AT Translated Set 2 keyboard", false, false),
+        device("ELAN Touchscreen", true, false),
+        device("SynPS/2 Synaptics TouchPad", true, true),
+        device(device::VIRTUAL_NAME, true, true),
+        device(device::pad_name(device::VIRTUAL_NAME).as_str(), true, true),
+        device("SynPS/2 Synaptics TouchPad", true, true)
+    ];
+    let candidates = device::touchpad_candidates(&devices, device::VIRTUAL_NAME);
+    assert_eq!(candidates, vec![String::from("SynPS/2 Synaptics TouchPad"), String::from("ELAN Touchscreen")]);
+
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+    let touchpad = || vec![String::from("SynPS/2 Synaptics TouchPad")];
+    assert_eq!(config::input_setup(&[], vec![]), config::InputSetup::NotFound);
+    assert_eq!(config::input_setup(&[], touchpad()), config::InputSetup::Use(String::from("SynPS/2 Synaptics TouchPad")));
+    assert_eq!(config::input_setup(&names(&["SynPS/2 Synaptics TouchPad"]), touchpad()), config::InputSetup::Use(String::from("SynPS/2 Synaptics TouchPad")));
+    // A different name set already is only replaced once it's confirmed.
+    assert_eq!(config::input_setup(&names(&["ELAN Touchpad"]), touchpad()), config::InputSetup::Choose { candidates: touchpad(), selected: 0 });
+    assert_eq!(config::input_setup(&names(&["ELAN Touchscreen"]), candidates.clone()), config::InputSetup::Choose { candidates: candidates.clone(), selected: 1 });
+    assert_eq!(config::input_setup(&[], candidates.clone()), config::InputSetup::Choose { candidates, selected: 0 });
+
+    let mut input = names(&["ELAN Touchpad", "SynPS/2 Synaptics TouchPad", "Other"]);
+    assert!(config::use_input_name(&mut input, "SynPS/2 Synaptics TouchPad"));
+    assert_eq!(input, names(&["SynPS/2 Synaptics TouchPad", "Other"]));
+    assert!(!config::use_input_name(&mut input, "SynPS/2 Synaptics TouchPad"));
+    let mut empty = vec![];
+    assert!(config::use_input_name(&mut empty, "ELAN Touchscreen"));
+    assert_eq!(empty, names(&["ELAN Touchscreen"]));
+}
+
+/// Tests which keystrokes the editor takes for numbers, floats and strings, and the live problems.
+#[test]
+fn config_edit_keystrokes() {
+    use config::ConfigKeyType::{Float, Number, String as Text};
+
+    assert!("0123456789".chars().all(|c| config::accepts_char(&Number, "", c)));
+    assert!(['a', '.', '-', ' ', 'q'].iter().all(|c| !config::accepts_char(&Number, "12", *c)));
+    assert!(config::accepts_char(&Float, "1", '.') && config::accepts_char(&Float, "1.", '5'));
+    assert!(!config::accepts_char(&Float, "1.5", '.') && !config::accepts_char(&Float, "1", 'e'));
+    assert!(['a', '.', ' ', 'q', 'É'].iter().all(|c| config::accepts_char(&Text, "Pen", *c)));
+
+    let settle = Some(config::ConfigField::TabletTouchSettleMs);
+    let scale = Some(config::ConfigField::MappingScaleX);
+    assert_eq!(config::edit_problem(&Number, settle, "250"), None);
+    assert_eq!(config::edit_problem(&Number, settle, ""), Some(String::from("Type a number.")));
+    assert_eq!(config::edit_problem(&Number, settle, "4294967296"), Some(String::from("The number can't be larger than 4294967295.")));
+    assert_eq!(config::edit_problem(&Float, scale, "0.5"), None);
+    assert_eq!(config::edit_problem(&Float, scale, "."), Some(String::from("Type a number.")));
+    assert_eq!(config::edit_problem(&Float, scale, "0.0"), Some(String::from("The number must be positive.")));
+    assert_eq!(config::edit_problem(&Text, Some(config::ConfigField::TabletVirtualName), ""), None);
+
+    // The narrower numbers show their own limit, and what passes the check is set.
+    let mut conf = config::Config { display: Some(config::Display { display: None, screen: 0 }), ..Default::default() };
+    for (field, max) in [(config::ConfigField::DisplayScreen, 255), (config::ConfigField::TabletReportRate, 65535)] {
+        assert_eq!(config::edit_problem(&Number, Some(field), (max + 1).to_string().as_str()), Some(format!("The number can't be larger than {}.", max)));
+        assert_eq!(config::edit_problem(&Number, Some(field), max.to_string().as_str()), None);
+        field.set(&mut conf, config::ConfigValue::Number(max));
+        assert_eq!(field.get(&conf), Some(config::ConfigValue::Number(max)));
+    }
+}
+
+/// Tests that the undo stack restores the edits from the last one.
+#[test]
+fn config_undo() {
+    use config::{ConfigField, ConfigKeyLocation, ConfigValue};
+    let at = |section, key| ConfigKeyLocation { section, key };
+    let mut conf = config::Config::default();
+    let mut undo = config::UndoStack::default();
+    assert_eq!(undo.undo(&mut conf), None);
+
+    let edit = |conf: &mut config::Config, undo: &mut config::UndoStack, location, field: ConfigField, value| {
+        undo.push(location, field, field.get(conf).unwrap());
+        field.set(conf, value);
+    };
+    edit(&mut conf, &mut undo, at(2, 0), ConfigField::TabletStartPaused, ConfigValue::Bool(true));
+    edit(&mut conf, &mut undo, at(2, 2), ConfigField::TabletReportRate, ConfigValue::Number(120));
+    edit(&mut conf, &mut undo, at(2, 2), ConfigField::TabletReportRate, ConfigValue::Number(60));
+    edit(&mut conf, &mut undo, at(1, 1), ConfigField::DisplayDisplay, ConfigValue::String(String::from(":1")));
+
+    assert_eq!(undo.undo(&mut conf), Some(at(1, 1)));
+    assert_eq!(conf.display.as_ref().unwrap().display.as_deref(), Some(":0"));
+    assert_eq!(undo.undo(&mut conf), Some(at(2, 2)));
+    assert_eq!(conf.tablet.report_rate, 120);
+    assert_eq!(undo.undo(&mut conf), Some(at(2, 2)));
+    assert_eq!(conf.tablet.report_rate, 0);
+    assert_eq!(undo.undo(&mut conf), Some(at(2, 0)));
+    assert!(!conf.tablet.start_paused);
+    assert!(undo.is_empty());
+
+    // Only the latest changes are kept.
+    for rate in 1..=150 {
+        edit(&mut conf, &mut undo, at(2, 2), ConfigField::TabletReportRate, ConfigValue::Number(rate));
+    }
+    while undo.undo(&mut conf).is_some() {}
+    assert_eq!(conf.tablet.report_rate, 50);
+}
+
+/// Tests the problems the editor shows for the values of single fields.
+#[test]
+fn config_field_problems() {
+    use config::{ConfigField, ConfigValue};
+
+    let mut conf = config::Config {
+        display: Some(config::Display { display: Some(String::from(":0")), screen: 0 }),
+        ..Default::default()
+    };
+    conf.input.names = vec![String::from("Elan Touchpad"), String::new()];
+    assert_eq!(ConfigField::InputName(0).problem(&conf), None);
+    assert_eq!(ConfigField::InputName(1).problem(&conf), Some(String::from("The input name can't be empty.")));
+    // Fields of absent values have no problems.
+    assert_eq!(ConfigField::InputName(2).problem(&conf), None);
+
+    assert_eq!(ConfigField::DisplayDisplay.problem(&conf), None);
+    ConfigField::DisplayDisplay.set(&mut conf, ConfigValue::String(String::from(" ")));
+    assert!(ConfigField::DisplayDisplay.problem(&conf).is_some());
+
+    conf.mapping.scale_y = 0.0;
+    assert_eq!(ConfigField::MappingScaleX.problem(&conf), None);
+    assert_eq!(ConfigField::MappingScaleY.problem(&conf), Some(String::from("The scales must be positive numbers.")));
+    assert_eq!(ConfigField::TabletReportRate.problem(&conf), None);
+
+    assert_eq!(ConfigField::TabletVirtualName.get(&conf), Some(ConfigValue::String(String::from(device::VIRTUAL_NAME))));
+    ConfigField::TabletVirtualName.set(&mut conf, ConfigValue::String(String::new()));
+    assert_eq!(ConfigField::TabletVirtualName.problem(&conf), Some(String::from("The virtual tablet name can't be empty.")));
+}
+
+/// Tests that unsetting the optional fields leaves them out of the config file.
+#[test]
+fn config_unset() {
+    use config::ConfigField;
+    let mut conf = config::Config::default();
+    conf.input.names = vec![String::from("Elan Touchpad"), String::from("SynPS/2 Synaptics TouchPad")];
+
+    assert!(ConfigField::InputName(0).unset(&mut conf));
+    assert!(!ConfigField::InputName(1).unset(&mut conf));
+    assert_eq!(conf.input.names, vec![String::from("SynPS/2 Synaptics TouchPad")]);
+    assert!(!ConfigField::TabletReportRate.unset(&mut conf));
+
+    assert!(ConfigField::DisplayDisplay.unset(&mut conf));
+    assert!(!ConfigField::DisplayDisplay.unset(&mut conf));
+    let raw = toml::to_string(&conf).unwrap();
+    assert!(raw.contains("[display]") && !raw.contains("display = "));
+
+    // Unsetting the screen removes the whole section.
+    assert!(ConfigField::DisplayScreen.unset(&mut conf));
+    assert!(conf.display.is_none());
+    let raw = toml::to_string(&conf).unwrap();
+    assert!(!raw.contains("display"));
+    assert!(toml::from_str::<config::Config>(raw.as_str()).unwrap().display.is_none());
+}
+
+/// Tests that the first present input device is chosen in priority order.
+#[test]
+fn device_fallback() {
+    let names = vec![String::from("Apple Inc. Magic Trackpad"), String::from("SynPS/2 Synaptics TouchPad")];
+    let present = |present: &[&str]| present.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+
+    assert_eq!(device::select_input(&names, &present(&["Apple Inc. Magic Trackpad", "SynPS/2 Synaptics TouchPad"])), Some("Apple Inc. Magic Trackpad"));
+    assert_eq!(device::select_input(&names, &present(&["AT Translated Set 2 keyboard", "SynPS/2 Synaptics TouchPad"])), Some("SynPS/2 Synaptics TouchPad"));
+    assert_eq!(device::select_input(&names, &present(&["AT Translated Set 2 keyboard"])), None);
+    assert_eq!(device::select_input(&[String::new()], &present(&[""])), None);
+}
+
+/// Tests matching the input device by its vendor and product IDs,
+/// which win over the names when both are set.
+#[test]
+fn device_id_matching() {
+    let id: config::DeviceId = "1267:ABCD".parse().unwrap();
+    assert_eq!(id, config::DeviceId { vendor: 0x1267, product: 0xabcd });
+    assert_eq!(id.to_string(), "1267:abcd");
+    assert!("1267".parse::<config::DeviceId>().is_err());
+    assert!("1267:12345".parse::<config::DeviceId>().is_err());
+    assert!("wxyz:abcd".parse::<config::DeviceId>().is_err());
+
+    let conf: config::Config = toml::from_str("[input]\nnames = [\"Elan Touchpad\"]\nid = \"1267:abcd\"\n").unwrap();
+    assert_eq!(conf.input.id, Some(id));
+    assert!(toml::to_string(&conf).unwrap().contains("id = \"1267:abcd\""));
+    assert!(toml::from_str::<config::Config>("[input]\nid = \"touchpad\"\n").is_err());
+
+    let info = |name: &str, id: &str| device::DeviceInfo {
+        path: String::from("/dev/input/event5"),
+        name: name.to_string(),
+        id: id.parse().unwrap(),
+        capabilities: device::Capabilities::default()
+    };
+    let present = vec![info("Elan Touchpad", "04f3:3140"), info("Elan Touchpad", "1267:abcd")];
+    assert_eq!(device::select_match(&conf.input, &present), Some(device::InputMatch::Id(id)));
+    assert_eq!(device::select_match(&conf.input, &present[..1]), None);
+
+    let by_name = config::Input { id: None, ..conf.input.clone() };
+    assert_eq!(device::select_match(&by_name, &present), Some(device::InputMatch::Name(String::from("Elan Touchpad"))));
+}
+
+/// Tests opening the input device by its event node, which wins
+/// over the IDs and the names when they're set too.
+#[test]
+fn device_path_matching() {
+    let conf: config::Config = toml::from_str("[input]\nnames = [\"Elan Touchpad\"]\nid = \"04f3:3140\"\npath = \"/dev/input/event7\"\n").unwrap();
+    assert_eq!(conf.input.path, Some(PathBuf::from("/dev/input/event7")));
+    assert!(toml::to_string(&conf).unwrap().contains("path = \"/dev/input/event7\""));
+
+    let info = |path: &str| device::DeviceInfo {
+        path: path.to_string(),
+        name: String::from("Elan Touchpad"),
+        id: "04f3:3140".parse().unwrap(),
+        capabilities: device::Capabilities::default()
+    };
+    let present = vec![info("/dev/input/event5"), info("/dev/input/event7")];
+    assert_eq!(device::select_match(&conf.input, &present), Some(device::InputMatch::Path(PathBuf::from("/dev/input/event7"))));
+    assert_eq!(device::select_match(&conf.input, &present[..1]), None);
+
+    let without_path = config::Input { path: None, ..conf.input.clone() };
+    assert_eq!(device::select_match(&without_path, &present), Some(device::InputMatch::Id("04f3:3140".parse().unwrap())));
+
+    // Nodes that aren't input devices are refused.
+    let input = config::Input { path: Some(PathBuf::from("/dev/null")), ..conf.input.clone() };
+    assert!(device::open_first_input(&input).is_err());
+    let input = config::Input { path: Some(PathBuf::from("/dev/input/event-missing")), ..conf.input };
+    let error = device::open_first_input(&input).err().unwrap();
+    assert_eq!(error.to_string(), "The input device doesn't exist: /dev/input/event-missing");
+
+    let mut conf = config::Config::default();
+    config::ConfigField::InputPath.set(&mut conf, config::ConfigValue::String(String::from(" /dev/input/event7 ")));
+    assert_eq!(conf.input.path, Some(PathBuf::from("/dev/input/event7")));
+    config::ConfigField::InputPath.set(&mut conf, config::ConfigValue::String(String::new()));
+    assert_eq!(conf.input.path, None);
+}
+
+/// Tests the verdicts on the devices from what they report.
+#[test]
+fn device_capabilities() {
+    use device::{Capabilities, Verdict};
+
+    let touchpad = Capabilities { position: true, pressure: true, touch_major: true, slots: 5 };
+    assert_eq!(touchpad.verdict(), Verdict::Suitable);
+    assert_eq!(Capabilities { pressure: false, ..touchpad }.verdict(), Verdict::Suitable);
+    assert_eq!(Capabilities { touch_major: false, ..touchpad }.verdict(), Verdict::Suitable);
+    assert_eq!(Capabilities { pressure: false, touch_major: false, ..touchpad }.verdict(), Verdict::PositionOnly);
+    assert_eq!(Capabilities { position: false, ..touchpad }.verdict(), Verdict::Unsuitable);
+    assert_eq!(Capabilities::default().verdict(), Verdict::Unsuitable);
+    assert_eq!(Verdict::PositionOnly.name(), "position only");
+
+    // The JSON of the devices carries the matrix too.
+    let json = serde_json::to_value(touchpad).unwrap();
+    assert_eq!(json, serde_json::json!({ "position": true, "pressure": true, "touch_major": true, "slots": 5 }));
+    assert_eq!(serde_json::to_value(Verdict::PositionOnly).unwrap(), serde_json::json!("position_only"));
+}
+
+/// Tests that the environment variables override the config file.
+#[test]
+fn config_env_overrides() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let file = config::get_file_config().unwrap();
+
+    env::set_var("EGAWARI_INPUT_NAME", "Elan Touchpad");
+    env::set_var("EGAWARI_DISPLAY", ":2");
+    env::set_var("EGAWARI_SCREEN", "1");
+    let conf = config::get_config();
+    env::set_var("EGAWARI_SCREEN", "first");
+    let invalid = config::get_config();
+    env::remove_var("EGAWARI_INPUT_NAME");
+    env::remove_var("EGAWARI_DISPLAY");
+    env::remove_var("EGAWARI_SCREEN");
+
+    let conf = conf.unwrap();
+    assert_eq!(conf.input.names, vec![String::from("Elan Touchpad")]);
+    let display = conf.display.unwrap();
+    assert_eq!(display.display.as_deref(), Some(":2"));
+    assert_eq!(display.screen, 1);
+    assert!(invalid.is_err());
+
+    // Unset variables leave the file values intact.
+    let conf = config::get_config().unwrap();
+    assert_eq!(conf.input.names, file.input.names);
+    assert_eq!(conf.display.map(|d| d.screen), file.display.map(|d| d.screen));
+}
+
+/// Tests the JSON output of the config keys and the daemon status.
+#[test]
+fn json_output() {
+    let value = serde_json::to_value(config::Config::default()).unwrap();
+    assert_eq!(config::lookup(&value, "mapping.flip_x"), Some(&serde_json::Value::Bool(false)));
+    assert_eq!(config::lookup(&value, "input.names"), Some(&serde_json::Value::Array(vec![])));
+    assert_eq!(config::lookup(&value, "mapping.nope"), None);
+
+    let status = daemon::Status {
+        running: true,
+        pid: Some(1234),
+        state: Some(daemon::State::Paused),
+        device: Some(String::from("SynPS/2 Synaptics TouchPad")),
+        virtual_name: Some(String::from("egawari virtual tablet")),
+        stats: None
+    };
+    assert_eq!(
+        serde_json::to_string(&status).unwrap(),
+        r#"{"running":true,"pid":1234,"state":"paused","device":"SynPS/2 Synaptics TouchPad","virtual_name":"egawari virtual tablet"}"#
+    );
+    // Daemons not reporting the virtual tablet name are still understood.
+    let old: daemon::Status = serde_json::from_str(r#"{"running":true,"pid":1234,"state":"paused","device":null}"#).unwrap();
+    assert_eq!(old.virtual_name, None);
+
+    assert_eq!(stdout::strip_escapes("\x1b[1;31m=>\x1b[1;39m Unknown config key: \x1b[0;39mnope"), "=> Unknown config key: nope");
+}
+
+/// Tests the rates, the average latency and the dropped and coalesced counts of the stats.
+#[test]
+fn stats_accumulator() {
+    let ms = Duration::from_millis;
+    let report = |time| at(time, vec![InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)])[0];
+    let dropped = at(0, vec![InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_DROPPED.0, 0)])[0];
+    let x = InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, 100);
+    let start = ms(1_000_000);
+    let mut stats = stats::Stats::new(start);
+    assert_eq!(stats.summary(start), stats::Summary::default());
+
+    // Two reports emitted 1ms and 3ms after the kernel stamped them,
+    // one held back for the next and a dropped one, over two seconds.
+    stats.input(&x, 0, ms(1_000_100));
+    stats.input(&report(1_000_100), 3, ms(1_000_101));
+    stats.input(&x, 0, ms(1_000_200));
+    stats.input(&report(1_000_200), 0, ms(1_000_200));
+    stats.input(&report(1_000_300), 3, ms(1_000_303));
+    stats.input(&dropped, 0, ms(1_000_400));
+    stats.output(2);
+    let summary = stats.summary(ms(1_002_000));
+    assert_eq!(summary, stats::Summary {
+        seconds: 2,
+        events_in: 6,
+        events_out: 8,
+        in_rate: 3,
+        out_rate: 4,
+        latency_us: 2000,
+        dropped: 1,
+        coalesced: 1
+    });
+    assert_eq!(summary.describe(), "6 events in (3/s), 8 out (4/s), 2.00ms latency, 1 dropped, 1 coalesced");
+
+    // Taking the summary starts a new window, due after the interval.
+    assert_eq!(stats.take(ms(1_002_000)), summary);
+    assert_eq!(stats.summary(ms(1_002_000)), stats::Summary::default());
+    assert_eq!(stats.deadline(), ms(1_002_000) + stats::STATS_INTERVAL);
+
+    // Events stamped after the time don't count as negative latencies.
+    stats.input(&report(1_002_500), 1, ms(1_002_400));
+    assert_eq!(stats.summary(ms(1_003_000)).latency_us, 0);
+}
+
+/// Tests the requests to the daemon through its socket,
+/// including a request line arriving in pieces.
+#[test]
+fn daemon_socket() {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use daemon::{Reply, Request};
+
+    assert_eq!(Request::parse("pause\n"), Some(Request::Pause));
+    assert_eq!(Request::parse(Request::Reload.as_str()), Some(Request::Reload));
+    assert_eq!(Request::parse("stop"), None);
+
+    let path = env::temp_dir().join("egawari-test.sock");
+    let server = daemon::Server::bind(&path).unwrap();
+    let reply = |error: Option<&str>| Reply {
+        ok: error.is_none(),
+        error: error.map(String::from),
+        status: None
+    };
+
+    let client_path = path.clone();
+    let client = std::thread::spawn(move || daemon::request_at(&client_path, Request::Toggle).unwrap());
+    let accepted = loop {
+        if let Some(accepted) = server.accept() {
+            break accepted;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(accepted.0, Request::Toggle);
+    accepted.1.reply(&reply(None));
+    assert_eq!(client.join().unwrap(), reply(None));
+
+    // The request is read until its newline, and unknown ones are refused.
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(b"sto").unwrap();
+    let writer = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        stream.write_all(b"p\n").unwrap();
+        let mut line = String::new();
+        std::io::BufRead::read_line(&mut std::io::BufReader::new(stream), &mut line).unwrap();
+        serde_json::from_str::<Reply>(line.as_str()).unwrap()
+    });
+    while server.accept().is_none() && !writer.is_finished() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(writer.join().unwrap(), reply(Some("Unknown request: stop")));
+
+    // A client that never ends its line doesn't hold up the server, and is told so in the end.
+    let mut silent = UnixStream::connect(&path).unwrap();
+    silent.write_all(b"pau").unwrap();
+    let started = Instant::now();
+    assert!(server.accept().is_none());
+    assert!(started.elapsed() < Duration::from_millis(500));
+    let mut line = String::new();
+    std::io::BufRead::read_line(&mut std::io::BufReader::new(silent), &mut line).unwrap();
+    assert_eq!(serde_json::from_str::<Reply>(line.as_str()).unwrap(), reply(Some("The request didn't end with a newline in time.")));
+
+    // A socket left behind is replaced.
+    drop(server);
+    assert!(daemon::Server::bind(&path).is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Tests finding another running instance from a PID file or a daemon answering on the socket.
+#[test]
+fn daemon_other_instance() {
+    use daemon::{Reply, Request, State, Status};
+
+    let dir = env::temp_dir().join("egawari-test-instance");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let (pid_file, socket) = (dir.join("egawari.pid"), dir.join("egawari.sock"));
+    let other = |pid: &str| {
+        std::fs::write(&pid_file, pid).unwrap();
+        daemon::other_instance(&pid_file, &socket)
+    };
+
+    // A live process in the PID file is another instance, unless it's this one.
+    let parent = unsafe { libc::getppid() };
+    assert_eq!(other(format!("{}\n", parent).as_str()), Some(parent));
+    assert_eq!(other(std::process::id().to_string().as_str()), None);
+
+    // A PID file left behind by an exited process or garbage isn't.
+    let mut exited = std::process::Command::new("true").spawn().unwrap();
+    exited.wait().unwrap();
+    assert_eq!(other(exited.id().to_string().as_str()), None);
+    assert_eq!(other("egawari"), None);
+    assert_eq!(other("0"), None);
+    std::fs::remove_file(&pid_file).unwrap();
+    assert_eq!(daemon::other_instance(&pid_file, &socket), None);
+
+    // Without a PID file, a daemon answering on the socket is.
+    let server = daemon::Server::bind(&socket).unwrap();
+    let (client_pid, client_socket) = (pid_file.clone(), socket.clone());
+    let client = std::thread::spawn(move || daemon::other_instance(&client_pid, &client_socket));
+    let accepted = loop {
+        if let Some(accepted) = server.accept() {
+            break accepted;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    assert_eq!(accepted.0, Request::Status);
+    accepted.1.reply(&Reply {
+        ok: true,
+        error: None,
+        status: Some(Status { running: true, pid: Some(parent), state: Some(State::Active), device: None, virtual_name: None, stats: None })
+    });
+    assert_eq!(client.join().unwrap(), Some(parent));
+    drop(server);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Tests parsing the monitors from the xrandr and wlr-randr outputs.
+#[test]
+fn monitor_parsing() {
+    let monitor = |name: &str, width, height, x, y, primary| Monitor {
+        name: name.to_string(),
+        width,
+        height,
+        x,
+        y,
+        primary
+    };
+
+    let xrandr = "Screen 0: minimum 320 x 200, current 3840 x 1080, maximum 16384 x 16384
+eDP-1 connected primary 1920x1080+0+0 (normal left inverted right x axis y axis) 344mm x 194mm
+   1920x1080     60.02*+
+HDMI-1 connected 1920x1080+1920+0 (normal left inverted right x axis y axis) 527mm x 296mm
+   1920x1080     60.00*+
+DP-1 disconnected (normal left inverted right x axis y axis)
+DP-2 connected (normal left inverted right x axis y axis)";
+    assert_eq!(monitor::parse_xrandr(xrandr), vec![
+        monitor("eDP-1", 1920, 1080, 0, 0, true),
+        monitor("HDMI-1", 1920, 1080, 1920, 0, false)
+    ]);
+
+    let wlr_randr = "eDP-1 \"Sharp Corporation 0x1453 (eDP-1)\"
+  Enabled: yes
+  Modes:
+    1920x1080 px, 59.934002 Hz (preferred)
+    2560x1440 px, 60.000000 Hz (current)
+  Position: 0,-1440
+  Transform: normal
+HDMI-A-1 \"Dell Inc. DELL U2415 (HDMI-A-1)\"
+  Enabled: no
+  Modes:
+    1920x1200 px, 59.950001 Hz (preferred, current)
+  Position: 2560,0";
+    assert_eq!(monitor::parse_wlr_randr(wlr_randr), vec![monitor("eDP-1", 2560, 1440, 0, -1440, false)]);
+}
+
+/// Tests that the messages are appended to the log file with their levels.
+#[test]
+fn log_file() {
+    let _lock = LOG_LOCK.lock().unwrap();
+    let path = env::temp_dir().join("egawari-test.log");
+    let _ = std::fs::remove_file(&path);
+
+    log::set_log_file(Some(path.as_path())).unwrap();
+    errln!("Couldn't read from the \x1b[0;39minput device.");
+    log::write(log::Level::Info, "Opened the input device.");
+    log::write(log::Level::Debug, "Hidden without verbosity.");
+    log::set_verbosity(log::Level::Debug);
+    log::write(log::Level::Debug, "Shown with verbosity.");
+    log::set_verbosity(log::Level::Info);
+    log::set_log_file(None).unwrap();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(lines.iter().any(|l| l.ends_with(" [ERROR] Couldn't read from the input device.")));
+    assert!(lines.iter().any(|l| l.ends_with(" [INFO] Opened the input device.")));
+    assert!(lines.iter().any(|l| l.ends_with(" [DEBUG] Shown with verbosity.")));
+    assert!(!log.contains("Hidden without verbosity."));
+
+    // The lines start with the local time, like "2021-06-01 12:00:00.000".
+    let timestamp = lines[0].split(" [").next().unwrap();
+    assert_eq!(timestamp.len(), 23);
+    assert_eq!(&timestamp[4..5], "-");
+    assert_eq!(&timestamp[19..20], ".");
+
+    // Unopenable log files are reported.
+    assert!(log::set_log_file(Some(PathBuf::from("/proc/egawari/egawari.log").as_path())).is_err());
+}
+
+/// Tests moving the editor cursor across the sections, with and without wrapping.
+#[test]
+fn config_cursor() {
+    use config::ConfigKeyLocation;
+    let at = |section, key| ConfigKeyLocation { section, key };
+    let lens = [2, 1, 3];
+
+    assert_eq!(config::move_cursor(at(0, 0), &lens, false, true), at(0, 1));
+    assert_eq!(config::move_cursor(at(0, 1), &lens, false, true), at(1, 0));
+    assert_eq!(config::move_cursor(at(1, 0), &lens, true, true), at(0, 1));
+    assert_eq!(config::move_cursor(at(2, 0), &lens, true, true), at(1, 0));
+
+    // The ends wrap around by default.
+    assert_eq!(config::move_cursor(at(0, 0), &lens, true, true), at(2, 2));
+    assert_eq!(config::move_cursor(at(2, 2), &lens, false, true), at(0, 0));
+
+    // Without wrapping the cursor stops at the ends.
+    assert_eq!(config::move_cursor(at(0, 0), &lens, true, false), at(0, 0));
+    assert_eq!(config::move_cursor(at(2, 2), &lens, false, false), at(2, 2));
+    assert_eq!(config::move_cursor(at(0, 1), &lens, false, false), at(1, 0));
+
+    // A single section wraps within itself.
+    assert_eq!(config::move_cursor(at(0, 2), &[3], false, true), at(0, 0));
+    assert_eq!(config::move_cursor(at(0, 0), &[3], true, true), at(0, 2));
+    assert_eq!(config::move_cursor(at(0, 0), &[1], false, false), at(0, 0));
+
+    // Empty sections are skipped and stray cursors are brought back.
+    assert_eq!(config::move_cursor(at(0, 1), &[2, 0, 1], false, true), at(2, 0));
+    assert_eq!(config::move_cursor(at(2, 0), &[2, 0, 1], true, true), at(0, 1));
+    assert_eq!(config::move_cursor(at(5, 9), &lens, true, true), at(2, 1));
+    assert_eq!(config::move_cursor(at(0, 0), &[], false, true), at(0, 0));
+    assert_eq!(config::move_cursor(at(0, 0), &[0, 0], true, true), at(0, 0));
+
+    // Without a display section the cursor visits every key and stays in bounds.
+    let conf = config::Config { display: None, ..Default::default() };
+    let lens = config::editor_section_lens(&conf);
+    assert_eq!(lens[1], 1);
+    let keys: usize = lens.iter().sum();
+    for wrap in [true, false] {
+        for up in [true, false] {
+            let mut cur = at(0, 0);
+            let mut visited: std::collections::HashSet<(usize, usize)> = vec![(0, 0)].into_iter().collect();
+            for _ in 0..keys * 2 {
+                cur = config::move_cursor(cur, &lens, up, wrap);
+                assert!(cur.section < lens.len() && cur.key < lens[cur.section], "{:?}", cur);
+                visited.insert((cur.section, cur.key));
+            }
+            assert_eq!(visited.len(), if wrap || !up { keys } else { 1 }, "up: {}, wrap: {}", up, wrap);
+        }
+    }
+}
+
+/// Tests that the quiet verbosity only prints the warnings and the errors
+/// and doesn't change the outcome of the commands.
+#[test]
+fn quiet_output() {
+    let _config_lock = CONFIG_LOCK.lock().unwrap();
+    let _log_lock = LOG_LOCK.lock().unwrap();
+    let path = env::temp_dir().join("egawari-test-quiet.toml");
+
+    log::set_verbosity(log::Level::Warn);
+    let printed = [log::Level::Error, log::Level::Warn, log::Level::Info, log::Level::Debug].map(stdout::printed);
+    let result = config::export(&[path.display().to_string()]);
+    log::set_verbosity(log::Level::Info);
+
+    assert_eq!(printed, [true, true, false, false]);
+    assert!(result.is_ok());
+    assert!(path.exists());
+}
+
+/// Tests that an unwritable config location reports a friendly error.
+#[test]
+fn config_unwritable() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    config::set_config_path(Some(PathBuf::from("/proc/egawari/egawari.toml")));
+    let result = config::save_config(&config::Config::default());
+    config::set_config_path(None);
+
+    let err = result.unwrap_err().to_string();
+    assert_eq!(err, "Couldn't create the config directory: /proc/egawari");
+}
+
+/// Tests getting and setting every editable field through `ConfigField`.
+#[test]
+fn config_field_accessors() {
+    use config::{ConfigField, ConfigValue};
+
+    let mut conf = config::Config {
+        log_file: None,
+        require_session: config::SessionType::Any,
+        input: config::Input {
+            names: vec![String::new()],
+            id: None,
+            path: None,
+            grab: true,
+            fallback_bounds: None
+        },
+        display: Some(config::Display {
+            display: Some(String::from(":0")),
+            screen: 0
+        }),
+        tablet: config::Tablet::default(),
+        tilt: None,
+        smoothing: None,
+        mapping: config::Mapping::default(),
+        overlay: None,
+        activation: None,
+        eraser: None,
+        pad: None,
+        stylus_buttons: None,
+        routes: vec![],
+        focus_rules: vec![],
+        profile_switch: None,
+        theme: None
+    };
+
+    ConfigField::InputName(0).set(&mut conf, ConfigValue::String(String::from("SynPS/2 Synaptics TouchPad")));
+    assert_eq!(ConfigField::InputName(0).get(&conf), Some(ConfigValue::String(String::from("SynPS/2 Synaptics TouchPad"))));
+
+    // Names past the end of the list read as `None` and aren't set.
+    ConfigField::InputName(1).set(&mut conf, ConfigValue::String(String::from("Elan Touchpad")));
+    assert_eq!(ConfigField::InputName(1).get(&conf), None);
+
+    ConfigField::DisplayDisplay.set(&mut conf, ConfigValue::String(String::from(":1")));
+    assert_eq!(ConfigField::DisplayDisplay.get(&conf), Some(ConfigValue::String(String::from(":1"))));
+
+    ConfigField::DisplayScreen.set(&mut conf, ConfigValue::Number(2));
+    assert_eq!(ConfigField::DisplayScreen.get(&conf), Some(ConfigValue::Number(2)));
+
+    ConfigField::TabletStartPaused.set(&mut conf, ConfigValue::Bool(true));
+    assert_eq!(ConfigField::TabletStartPaused.get(&conf), Some(ConfigValue::Bool(true)));
+
+    ConfigField::TabletReportRate.set(&mut conf, ConfigValue::Number(120));
+    assert_eq!(ConfigField::TabletReportRate.get(&conf), Some(ConfigValue::Number(120)));
+
+    ConfigField::MappingFlipX.set(&mut conf, ConfigValue::Bool(true));
+    assert_eq!(ConfigField::MappingFlipX.get(&conf), Some(ConfigValue::Bool(true)));
+    assert_eq!(ConfigField::MappingFlipY.get(&conf), Some(ConfigValue::Bool(false)));
+
+    ConfigField::MappingScaleX.set(&mut conf, ConfigValue::Float(1.5));
+    assert_eq!(ConfigField::MappingScaleX.get(&conf), Some(ConfigValue::Float(1.5)));
+    assert_eq!(ConfigField::MappingScaleY.get(&conf), Some(ConfigValue::Float(1.0)));
+
+    // Mismatched value types are ignored.
+    ConfigField::DisplayScreen.set(&mut conf, ConfigValue::String(String::from("3")));
+    assert_eq!(ConfigField::DisplayScreen.get(&conf), Some(ConfigValue::Number(2)));
+
+    // Values out of the range of the field are ignored.
+    ConfigField::DisplayScreen.set(&mut conf, ConfigValue::Number(256));
+    assert_eq!(ConfigField::DisplayScreen.get(&conf), Some(ConfigValue::Number(2)));
+
+    // Fields of absent sections read as `None` and aren't set.
+    conf.display = None;
+    ConfigField::DisplayScreen.set(&mut conf, ConfigValue::Number(1));
+    assert_eq!(ConfigField::DisplayDisplay.get(&conf), None);
+    assert_eq!(ConfigField::DisplayScreen.get(&conf), None);
+}
+
+/// Tests resetting fields of each type to their defaults, which can be undone.
+#[test]
+fn config_field_reset() {
+    use config::{ConfigField, ConfigKeyLocation, ConfigValue, MappingMode};
+    let mut conf = config::Config::default();
+    conf.input.names = vec![String::from("Elan Touchpad")];
+    conf.input.id = Some("04f3:3140".parse().unwrap());
+    conf.tablet.start_paused = true;
+    conf.tablet.report_rate = 120;
+    conf.tablet.virtual_name = String::from("Pen");
+    conf.mapping.mode = MappingMode::Relative;
+    conf.mapping.scale_x = 1.5;
+
+    let defaults = config::Config::default();
+    for field in [ConfigField::InputId, ConfigField::TabletStartPaused, ConfigField::TabletReportRate,
+        ConfigField::TabletVirtualName, ConfigField::MappingMode, ConfigField::MappingScaleX] {
+        let before = field.get(&conf);
+        assert_eq!(field.reset(&mut conf), before);
+        assert_eq!(field.get(&conf), field.get(&defaults));
+        // Resetting again changes nothing.
+        assert_eq!(field.reset(&mut conf), None);
+    }
+    assert_eq!(conf.input.id, None);
+    assert_eq!(conf.mapping.mode, defaults.mapping.mode);
+
+    // Fields without a default and ones in absent sections are kept.
+    assert_eq!(ConfigField::InputName(0).default_value(), None);
+    assert_eq!(ConfigField::InputName(0).reset(&mut conf), None);
+    assert_eq!(conf.input.names, vec![String::from("Elan Touchpad")]);
+    conf.display = None;
+    assert_eq!(ConfigField::DisplayScreen.reset(&mut conf), None);
+    assert!(conf.display.is_none());
+
+    let mut undo = config::UndoStack::default();
+    let at = ConfigKeyLocation { section: 4, key: 3 };
+    conf.mapping.flip_y = true;
+    let before = ConfigField::MappingFlipY.reset(&mut conf).unwrap();
+    undo.push(at, ConfigField::MappingFlipY, before);
+    assert!(!conf.mapping.flip_y);
+    assert_eq!(undo.undo(&mut conf), Some(at));
+    assert!(conf.mapping.flip_y);
+    assert_eq!(ConfigField::MappingFlipY.default_value(), Some(ConfigValue::Bool(false)));
+}
+
+/// Tests that the editor view follows the cursor in a window
+/// too short to show every line.
+#[test]
+fn config_scroll_offset() {
+    // Everything fits, so there's no scrolling.
+    assert_eq!(config::scroll_offset(15, 0, 24, 20), 0);
+
+    // Moving below the bottom scrolls just enough to show the cursor.
+    assert_eq!(config::scroll_offset(5, 0, 5, 20), 1);
+    assert_eq!(config::scroll_offset(12, 1, 5, 20), 8);
+
+    // Moving inside the view keeps the offset.
+    assert_eq!(config::scroll_offset(10, 8, 5, 20), 8);
+
+    // Moving above the top scrolls back up.
+    assert_eq!(config::scroll_offset(3, 8, 5, 20), 3);
+
+    // The offset never goes past the last line.
+    assert_eq!(config::scroll_offset(19, 0, 5, 20), 15);
+    assert_eq!(config::scroll_offset(19, 18, 5, 20), 15);
+}
+
+/// Tests the truecolor output and its approximation to the classic palette.
+#[test]
+fn truecolor_palette() {
+    let palette = &stdout::PALETTE;
+    assert_eq!(stdout::color_str_escape_with("*", palette, false), "\x1b[1;31m*\x1b[1;39m");
+    assert_eq!(stdout::color_str_escape_with("*", palette, true), "\x1b[1;38;2;224;108;117m*\x1b[1;39m");
+
+    for color in &[palette.plus, palette.separator, palette.asterisk, palette.brace, palette.string] {
+        assert_eq!(stdout::approximate_ansi(color.rgb), color.ansi);
+    }
+    assert_eq!(stdout::approximate_ansi((20, 20, 20)), 0);
+    assert_eq!(stdout::approximate_ansi((250, 250, 250)), 7);
+}
+
+/// Tests that skipping the rules on plain strings gives the same output
+/// as running them, on random strings.
+#[test]
+fn color_plain_fast_path() {
+    let alphabet: Vec<char> = "ab Z09.;_\\é\x1b+:/=,-|*{}[]()\"'<>".chars().collect();
+    let mut state: u64 = 0x2545f4914f6cdd1d;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    for _ in 0..2000 {
+        let len = next() % 24;
+        // Half of the strings only use the characters that aren't highlighted.
+        let chars = if next() % 2 == 0 { 12 } else { alphabet.len() as u64 };
+        let string: String = (0..len).map(|_| alphabet[(next() % chars) as usize]).collect();
+        for truecolor in [false, true] {
+            assert_eq!(
+                stdout::color_str_escape_with(string.as_str(), &stdout::PALETTE, truecolor),
+                stdout::color_rules(string.as_str(), &stdout::PALETTE, truecolor),
+                "{:?}", string
+            );
+        }
+    }
+
+    assert_eq!(stdout::color_str_escape_with("Reloaded the configuration.", &stdout::PALETTE, false), "Reloaded the configuration.");
+}
+
+/// Tests that a theme changes the highlight colors of its parts only.
+#[test]
+fn theme_palette() {
+    let escape = |s: &str, palette| stdout::color_str_escape_with(s, palette, false);
+    assert_eq!(config::Theme::default().palette(), stdout::PALETTE);
+
+    let theme: config::Theme = toml::from_str("operator = 4\nbracket = 5\nerror = 3").unwrap();
+    assert_eq!(theme.string, stdout::PALETTE.string.ansi);
+    let palette = theme.palette();
+    assert_eq!(escape("a = b", &palette), "a \x1b[1;34m=\x1b[1;39m b");
+    assert_eq!(escape("[a]", &palette), "\x1b[1;35m[\x1b[0;39ma\x1b[1;35m]\x1b[1;39m");
+    assert_eq!(escape("*", &palette), escape("*", &stdout::PALETTE));
+    assert_eq!(palette.error.escape(false), "\x1b[1;33m");
+    assert_eq!(palette.operator.escape(true), "\x1b[1;38;2;97;175;239m");
+
+    let conf = config::Config {
+        theme: Some(config::Theme {
+            success: 9,
+            ..theme
+        }),
+        ..Default::default()
+    };
+    assert_eq!(conf.validate(), vec![String::from("The theme colors must be palette indices from 0 to 7.")]);
+}
+
+/// Pins the output of the coloring function for each of its rules.
+#[test]
+fn color_str_escape_rules() {
+    let escape = |s: &str| stdout::color_str_escape_with(s, &stdout::PALETTE, false);
+
+    assert_eq!(escape(" => Hi!"), " \x1b[1;36m=>\x1b[1;39m Hi!");
+    assert_eq!(escape("a + b"), "a \x1b[1;36m+\x1b[1;39m b");
+    assert_eq!(escape("key = value, other"), "key \x1b[1;32m=\x1b[1;39m value\x1b[0;32m,\x1b[1;39m other");
+    assert_eq!(escape("**bold** {{x}}"), "\x1b[1;31m**\x1b[1;39mbold\x1b[1;31m**\x1b[1;39m \x1b[1;35m{{\x1b[1;39mx\x1b[1;35m}}\x1b[1;39m");
+    assert_eq!(
+        escape(r#"[a] (b) "c" 'd' <e>"#),
+        "\x1b[1;32m[\x1b[0;39ma\x1b[1;32m]\x1b[1;39m \x1b[0;32m(\x1b[0;39mb\x1b[0;32m)\x1b[1;39m \x1b[1;32m\"\x1b[0;39mc\x1b[1;32m\"\x1b[1;39m \x1b[0;32m'\x1b[0;39md\x1b[0;32m'\x1b[1;39m \x1b[1;32m<\x1b[0;39me\x1b[1;32m>\x1b[1;39m"
+    );
+    assert_eq!(escape(r"\[x\]"), "\x1b[1;32m[\x1b[1;39mx\x1b[1;32m]\x1b[1;39m");
+}
+
+/// Tests that every arrow is fully in the arrow color,
+/// whatever operators are next to it.
+#[test]
+fn color_str_escape_arrows() {
+    let escape = |s: &str| stdout::color_str_escape_with(s, &stdout::PALETTE, false);
+    let arrow = "\x1b[1;36m=>\x1b[1;39m";
+
+    assert_eq!(escape("a => b => c"), format!("a {} b {} c", arrow, arrow));
+    assert_eq!(escape("==>"), format!("\x1b[1;32m=\x1b[1;39m{}", arrow));
+    assert_eq!(escape("=>="), format!("{}\x1b[1;32m=\x1b[1;39m", arrow));
+    assert_eq!(escape("a=>b:c"), format!("a{}b\x1b[1;32m:\x1b[1;39mc", arrow));
+
+    // The ">" of an arrow doesn't close a "<".
+    assert_eq!(escape("a < b => c"), format!("a < b {} c", arrow));
+}
+
+/// Tests that nested surrounding characters are matched
+/// and unbalanced ones are left uncolored.
+#[test]
+fn color_str_escape_nesting() {
+    let escape = |s: &str| stdout::color_str_escape_with(s, &stdout::PALETTE, false);
+
+    assert_eq!(escape("a [b (c) d"), "a [b \x1b[0;32m(\x1b[0;39mc\x1b[0;32m)\x1b[1;39m d");
+    assert_eq!(
+        escape("[[nested]]"),
+        "\x1b[1;32m[\x1b[0;39m\x1b[1;32m[\x1b[0;39mnested\x1b[1;32m]\x1b[0;39m\x1b[1;32m]\x1b[1;39m"
+    );
+    assert_eq!(
+        escape("(a (b) c)"),
+        "\x1b[0;32m(\x1b[0;39ma \x1b[0;32m(\x1b[0;39mb\x1b[0;32m)\x1b[0;39m c\x1b[0;32m)\x1b[1;39m"
+    );
+
+    // The brackets of the escape sequences aren't matched.
+    assert_eq!(escape("a = [b]"), "a \x1b[1;32m=\x1b[1;39m \x1b[1;32m[\x1b[0;39mb\x1b[1;32m]\x1b[1;39m");
+
+    // Every line is back to the default color at the end, with its text intact.
+    let lines = [
+        "a [b (c) d", "[[nested]]", "a (b [c] d) e", "x \"y\" [z", "(a (b) c)",
+        "a ] b ) c", "[a (b] c)", "a < b", "it's [a] test", "[key] = value] (x"
+    ];
+    for line in lines.iter() {
+        let escaped = escape(line);
+        assert_eq!(stdout::strip_escapes(escaped.as_str()), *line);
+        if let Some(last) = escaped.rfind('\x1b') {
+            assert!(escaped[last..].starts_with("\x1b[1;39m"), "{:?}", escaped);
+        }
+    }
+}
+
+/// Tests that coloring a line costs less than compiling its regexes,
+/// now that they're compiled once instead of on every call.
+#[test]
+fn color_str_escape_precompiled() {
+    use std::time::Instant;
+    use fancy_regex::Regex;
+
+    let _lock = COLOR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    stdout::set_color_mode(stdout::ColorMode::Always);
+    let line = r#" => Name = "SynPS/2 Synaptics TouchPad" (event5), [Input] <ok>"#;
+    stdout::color_str_escape(line);
+
+    let start = Instant::now();
+    for _ in 0..100 {
+        stdout::color_str_escape(line);
+    }
+    let escape = start.elapsed() / 100;
+
+    // Compiling every rule, as each call used to.
+    let patterns = [
+        r#"[+]+"#, r#"[:/=]+"#, r#"[,\-|]+"#, r#"[*]+"#, r#"[{}]+"#,
+        "\x1b\\[[\\d;]*m=\x1b\\[[\\d;]*m>",
+        r#"([\[])(?:(?=(\\?))\2.)*?([\]])"#,
+        r#"([\(])(?:(?=(\\?))\2.)*?([\)])"#,
+        r#"(["])(?:(?=(\\?))\2.)*?(["])"#,
+        r#"(['])(?:(?=(\\?))\2.)*?(['])"#,
+        r#"([<])(?:(?=(\\?))\2.)*?([>])"#,
+        r#"\\([\[\]"<>])"#, r#"\\([\(\)'])"#
+    ];
+    let start = Instant::now();
+    for _ in 0..100 {
+        for pattern in &patterns {
+            Regex::new(pattern).unwrap();
+        }
+    }
+    let compile = start.elapsed() / 100;
+    stdout::set_color_mode(stdout::ColorMode::Auto);
+
+    assert!(escape < compile);
+}
+
+/// Tests that the curses output keeps its text but drops the styles
+/// on terminals without colors.
+#[test]
+fn curses_monochrome() {
+    use stdout::CursesStyle;
+
+    let escaped = "\x1b[1;32mHi\x1b[0;39m, \x1b[1;38;2;86;182;194mthere!";
+    assert_eq!(stdout::curses_segments(escaped, true), vec![
+        ("", None),
+        ("Hi", Some(CursesStyle { bold: true, pair: Some(2) })),
+        (", ", Some(CursesStyle { bold: false, pair: Some(9) })),
+        ("there!", Some(CursesStyle { bold: true, pair: Some(6) }))
+    ]);
+
+    let plain = stdout::curses_segments(escaped, false);
+    assert!(plain.iter().all(|(_, style)| style.is_none()));
+    assert_eq!(plain.iter().map(|(text, _)| *text).collect::<String>(), "Hi, there!");
+    assert_eq!(stdout::curses_segments("plain", false), vec![("plain", None)]);
+}
+
+/// Tests when the output is colored in each color mode.
+#[test]
+fn color_mode() {
+    use stdout::ColorMode;
+
+    assert_eq!(ColorMode::parse("auto"), Some(ColorMode::Auto));
+    assert_eq!(ColorMode::parse("Always"), None);
+
+    // Only auto looks at `$NO_COLOR` and the terminal, so an explicit mode wins.
+    for &(no_color, terminal) in &[(false, false), (false, true), (true, false), (true, true)] {
+        assert_eq!(stdout::colored(ColorMode::Auto, no_color, terminal), !no_color && terminal);
+        assert!(stdout::colored(ColorMode::Always, no_color, terminal));
+        assert!(!stdout::colored(ColorMode::Never, no_color, terminal));
+    }
+
+    let _lock = COLOR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let line = " => Name = 'touchpad'";
+
+    // Always colors even though the tests' stdout isn't a terminal.
+    stdout::set_color_mode(ColorMode::Always);
+    assert!(stdout::colors_enabled());
+    assert!(stdout::color_str_escape(line).contains('\x1b'));
+    assert!(stdout::paint(String::from("\x1b[1;32mHi!\x1b[;m")).contains('\x1b'));
+
+    // Never leaves the text without any escape sequence.
+    stdout::set_color_mode(ColorMode::Never);
+    assert!(!stdout::colors_enabled());
+    assert_eq!(stdout::color_str_escape(line), line);
+    assert_eq!(stdout::color_str_escape("\x1b[1;31mred\x1b[;m"), "red");
+    assert_eq!(stdout::paint(String::from("\x1b[1;32mHi!\x1b[;m")), "Hi!");
+
+    // Auto follows the terminal and `$NO_COLOR`.
+    stdout::set_color_mode(ColorMode::Auto);
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let terminal = std::io::IsTerminal::is_terminal(&std::io::stdout());
+    assert_eq!(stdout::colors_enabled(), !no_color && terminal);
+}
+
+/// Tests saving, listing, loading and deleting a profile.
+#[test]
+fn profiles() {
+    let _lock = CONFIG_LOCK.lock().unwrap();
+    let name = "egawari-test-profile";
+    assert!(profile::is_valid_name(name));
+    assert!(!profile::is_valid_name("../egawari"));
+    assert!(!profile::is_valid_name(".active"));
+
+    profile::save_profile(name).unwrap();
+    assert!(profile::list_profiles().unwrap().contains(&String::from(name)));
+    assert_eq!(profile::active_profile().as_deref(), Some(name));
+
+    let conf = config::get_config().unwrap();
+    profile::load_profile(name).unwrap();
+    assert_eq!(config::get_config().unwrap().input.names, conf.input.names);
+
+    profile::delete_profile(name).unwrap();
+    assert!(!profile::list_profiles().unwrap().contains(&String::from(name)));
+    assert_eq!(profile::active_profile(), None);
+}
+
+/// Returns the touchpad bounds used by the engine tests.
+fn test_bounds() -> device::Bounds {
+    device::Bounds {
+        min_x: 0,
+        max_x: 1000,
+        min_y: 0,
+        max_y: 500,
+        pressure: Some((0, 255)),
+        orientation: None
+    }
+}
+
+/// Returns the touchpad events of a report touching the given point.
+fn touch(x: i32, y: i32, pressure: i32) -> Vec<InputEvent> {
+    vec![
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), (pressure > 0) as i32),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, x),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, y),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_PRESSURE.0, pressure),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+    ]
+}
+
+/// Returns the touchpad events of a report lifting the finger.
+fn lift() -> Vec<InputEvent> {
+    vec![
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), 0),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_PRESSURE.0, 0),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+    ]
+}
+
+/// Feeds the events to the engine, returning every event it emits.
+fn feed(engine: &mut engine::Engine, events: Vec<InputEvent>) -> Vec<(EventType, u16, i32)> {
+    events.into_iter()
+        .flat_map(|e| engine.process(e))
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect()
+}
+
+/// Stamps the events with the time in milliseconds since the Unix epoch.
+fn at(ms: u64, events: Vec<InputEvent>) -> Vec<InputEvent> {
+    events.into_iter()
+        .map(|e| InputEvent::from(libc::input_event {
+            time: libc::timeval {
+                tv_sec: (ms / 1000) as libc::time_t,
+                tv_usec: (ms % 1000 * 1000) as libc::suseconds_t
+            },
+            type_: e.event_type().0,
+            code: e.code(),
+            value: e.value()
+        }))
+        .collect()
+}
+
+/// Returns the emitted pen proximity event with the value, as `feed` summarizes it.
+fn pen(v: i32) -> (EventType, u16, i32) {
+    (EventType::KEY, Key::BTN_TOOL_PEN.code(), v)
+}
+
+/// Returns the emitted pen contact event with the value.
+fn touch_key(v: i32) -> (EventType, u16, i32) {
+    (EventType::KEY, Key::BTN_TOUCH.code(), v)
+}
+
+/// Returns the emitted horizontal position event with the value.
+fn x(v: i32) -> (EventType, u16, i32) {
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, v)
+}
+
+/// Returns the emitted vertical position event with the value.
+fn y(v: i32) -> (EventType, u16, i32) {
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, v)
+}
+
+/// Returns the emitted pressure event with the value.
+fn pressure(v: i32) -> (EventType, u16, i32) {
+    (EventType::ABSOLUTE, AbsoluteAxisType::ABS_PRESSURE.0, v)
+}
+
+/// Tests that pausing lifts the pen and stops the mapping until resumed.
+#[test]
+fn engine_pause() {
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    let out = feed(&mut engine, touch(100, 200, 50));
+    assert!(out.contains(&(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, 100)));
+    assert!(out.contains(&(EventType::KEY, Key::BTN_TOUCH.code(), 1)));
+
+    let out: Vec<(EventType, u16, i32)> = engine.set_paused(true).iter()
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect();
+    assert!(out.contains(&(EventType::KEY, Key::BTN_TOUCH.code(), 0)));
+    assert!(feed(&mut engine, touch(300, 400, 50)).is_empty());
+
+    assert!(engine.set_paused(false).is_empty());
+    let out = feed(&mut engine, touch(300, 400, 50));
+    assert!(out.contains(&(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, 400)));
+    assert!(out.contains(&(EventType::KEY, Key::BTN_TOUCH.code(), 1)));
+}
+
+/// Tests that the pen proximity brackets the strokes.
+#[test]
+fn engine_proximity() {
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    assert_eq!(feed(&mut engine, touch(100, 200, 50)), vec![pen(1), x(100), y(200), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, touch(110, 210, 60)), vec![x(110), y(210), pressure(60)]);
+    assert_eq!(feed(&mut engine, lift()), vec![pressure(0), touch_key(0), pen(0)]);
+
+    // Hovering keeps the pen in proximity between the strokes.
+    let conf = config::Config {
+        tablet: config::Tablet {
+            hover_on_lift: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(feed(&mut engine, touch(100, 200, 50)), vec![pen(1), x(100), y(200), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, lift()), vec![pressure(0), touch_key(0)]);
+    assert_eq!(feed(&mut engine, touch(120, 220, 40)), vec![x(120), y(220), pressure(40), touch_key(1)]);
+}
+
+/// Tests the event preview of the dry run.
+#[test]
+fn run_describe() {
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    let mut out = vec![];
+    for event in touch(100, 200, 50) {
+        out.extend(engine.process(event));
+    }
+
+    assert_eq!(run::describe(&out), "BTN_TOOL_PEN = 1, ABS_X = 100, ABS_Y = 200, ABS_PRESSURE = 50, BTN_TOUCH = 1");
+    assert_eq!(run::describe(&[]), "");
+}
+
+/// Tests the square drawn by the output self-test.
+#[test]
+fn run_square() {
+    let reports = run::square(&test_bounds(), 2);
+    assert_eq!(run::describe(&reports[0]), "BTN_TOOL_PEN = 1, ABS_X = 250, ABS_Y = 125");
+    assert_eq!(run::describe(&reports[1]), "ABS_PRESSURE = 255, BTN_TOUCH = 1");
+    let corners: Vec<String> = reports[2..10].iter().map(|report| run::describe(report)).collect();
+    assert_eq!(corners, vec![
+        "ABS_X = 500, ABS_Y = 125",
+        "ABS_X = 750, ABS_Y = 125",
+        "ABS_X = 750, ABS_Y = 250",
+        "ABS_X = 750, ABS_Y = 375",
+        "ABS_X = 500, ABS_Y = 375",
+        "ABS_X = 250, ABS_Y = 375",
+        "ABS_X = 250, ABS_Y = 250",
+        "ABS_X = 250, ABS_Y = 125"
+    ]);
+    assert_eq!(run::describe(&reports[10]), "ABS_PRESSURE = 0, BTN_TOUCH = 0");
+    assert_eq!(run::describe(&reports[11]), "BTN_TOOL_PEN = 0");
+    assert_eq!(reports.len(), 12);
+}
+
+/// Tests the hover emulation and the lift-off timeout with timed touches.
+#[test]
+fn engine_hover() {
+    let tablet = |tablet: config::Tablet| config::Config {
+        tablet,
+        ..Default::default()
+    };
+
+    // A touch right after a quick tap hovers, a touch after a slow one draws.
+    let conf = tablet(config::Tablet {
+        hover_mode: config::HoverMode::DoubleTap,
+        ..Default::default()
+    });
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(feed(&mut engine, at(0, touch(100, 200, 50))), vec![pen(1), x(100), y(200), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, at(100, lift())), vec![pressure(0), touch_key(0), pen(0)]);
+    assert_eq!(feed(&mut engine, at(200, touch(110, 210, 50))), vec![pen(1), x(110), y(210)]);
+    assert_eq!(feed(&mut engine, at(300, touch(120, 220, 60))), vec![x(120), y(220)]);
+    assert_eq!(feed(&mut engine, at(400, lift())), vec![pen(0)]);
+    assert_eq!(feed(&mut engine, at(500, touch(130, 230, 50))), vec![pen(1), x(130), y(230), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, at(1000, lift())), vec![pressure(0), touch_key(0), pen(0)]);
+    assert_eq!(feed(&mut engine, at(1100, touch(140, 240, 50))), vec![pen(1), x(140), y(240), pressure(50), touch_key(1)]);
+
+    // Two fingers hover, lifting one of them touches down.
+    let conf = tablet(config::Tablet {
+        hover_mode: config::HoverMode::TwoFinger,
+        ..Default::default()
+    });
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    let mut two_fingers = vec![InputEvent::new(EventType::KEY, Key::BTN_TOOL_DOUBLETAP.code(), 1)];
+    two_fingers.extend(touch(100, 200, 50));
+    assert_eq!(feed(&mut engine, two_fingers), vec![pen(1), x(100), y(200)]);
+    let mut one_finger = vec![InputEvent::new(EventType::KEY, Key::BTN_TOOL_DOUBLETAP.code(), 0)];
+    one_finger.extend(touch(110, 210, 50));
+    assert_eq!(feed(&mut engine, one_finger), vec![x(110), y(210), pressure(50), touch_key(1)]);
+
+    // The pen hovers until the lift-off timeout passes.
+    let conf = tablet(config::Tablet {
+        lift_timeout_ms: 500,
+        ..Default::default()
+    });
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    feed(&mut engine, at(0, touch(100, 200, 50)));
+    assert_eq!(feed(&mut engine, at(100, lift())), vec![pressure(0), touch_key(0)]);
+    assert_eq!(engine.deadline(), Some(Duration::from_millis(600)));
+    assert!(engine.tick(Duration::from_millis(599)).is_empty());
+    let out: Vec<(EventType, u16, i32)> = engine.tick(Duration::from_millis(600)).iter()
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect();
+    assert_eq!(out, vec![pen(0)]);
+    assert_eq!(engine.deadline(), None);
+
+    // Touching again before the timeout keeps the pen in proximity.
+    feed(&mut engine, at(1000, touch(100, 200, 50)));
+    feed(&mut engine, at(1100, lift()));
+    assert_eq!(feed(&mut engine, at(1200, touch(110, 210, 50))), vec![x(110), y(210), pressure(50), touch_key(1)]);
+    assert_eq!(engine.deadline(), None);
+}
+
+/// Tests that a burst of events faster than the report rate is coalesced.
+#[test]
+fn engine_report_rate() {
+    let conf = config::Config {
+        tablet: config::Tablet {
+            report_rate: 100,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // Touching down is reported right away.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(feed(&mut engine, at(0, touch(0, 0, 10))), vec![pen(1), x(0), y(0), pressure(10), touch_key(1)]);
+
+    // A 1000 Hz burst is reported at 100 Hz, with the latest values.
+    let mut out = vec![];
+    for ms in 1..=95 {
+        out.extend(feed(&mut engine, at(ms, touch(ms as i32, ms as i32, 10 + ms as i32))));
+    }
+    assert_eq!(out.iter().filter(|e| e.1 == AbsoluteAxisType::ABS_X.0).count(), 9);
+    assert_eq!(&out[out.len() - 3..], &[x(90), y(90), pressure(100)]);
+
+    // The held back values are reported once due, even without new events.
+    assert_eq!(engine.deadline(), Some(Duration::from_millis(100)));
+    assert!(engine.tick(Duration::from_millis(99)).is_empty());
+    let out: Vec<(EventType, u16, i32)> = engine.tick(Duration::from_millis(100)).iter()
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect();
+    assert_eq!(out, vec![x(95), y(95), pressure(105)]);
+    assert_eq!(engine.deadline(), None);
+
+    // Lifting is never delayed.
+    assert_eq!(feed(&mut engine, at(101, lift())), vec![pressure(0), touch_key(0), pen(0)]);
+}
+
+/// Tests that a new touch is held back until its position settles.
+#[test]
+fn engine_touch_settle() {
+    let conf = config::Config {
+        tablet: config::Tablet {
+            touch_settle_ms: 20,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // The noisy samples are dropped and the first stable one starts the stroke.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert!(feed(&mut engine, at(0, touch(100, 200, 50))).is_empty());
+    assert!(feed(&mut engine, at(2, touch(180, 260, 50))).is_empty());
+    assert_eq!(feed(&mut engine, at(4, touch(184, 262, 50))), vec![pen(1), x(184), y(262), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, at(6, touch(190, 262, 50))), vec![x(190), y(262), pressure(50)]);
+    feed(&mut engine, at(8, lift()));
+
+    // A touch that never settles is reported once the settle time passes.
+    assert!(feed(&mut engine, at(100, touch(100, 100, 50))).is_empty());
+    assert!(feed(&mut engine, at(110, touch(200, 200, 50))).is_empty());
+    assert_eq!(feed(&mut engine, at(120, touch(300, 300, 50)))[0], pen(1));
+    feed(&mut engine, at(130, lift()));
+
+    // A still finger is reported by the tick.
+    assert!(feed(&mut engine, at(200, touch(100, 100, 50))).is_empty());
+    assert_eq!(engine.deadline(), Some(Duration::from_millis(220)));
+    assert_eq!(engine.tick(Duration::from_millis(220)).len(), 5);
+    assert_eq!(engine.deadline(), None);
+}
+
+/// Tests that a short dropout of the contact doesn't lift the pen
+/// within the debounce time, but a longer one does.
+#[test]
+fn engine_contact_debounce() {
+    let conf = config::Config {
+        tablet: config::Tablet {
+            contact_debounce_ms: 30,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // The finger drops out for 15 ms in the middle of the stroke, which goes on.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(feed(&mut engine, at(0, touch(100, 200, 50))), vec![pen(1), x(100), y(200), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, at(10, touch(110, 200, 50))), vec![x(110), y(200), pressure(50)]);
+    assert!(feed(&mut engine, at(20, lift())).is_empty());
+    assert_eq!(engine.deadline(), Some(Duration::from_millis(50)));
+    assert!(engine.tick(Duration::from_millis(30)).is_empty());
+    assert_eq!(feed(&mut engine, at(35, touch(130, 200, 50))), vec![x(130), y(200), pressure(50)]);
+    assert_eq!(engine.deadline(), None);
+
+    // A lift outlasting the debounce time is reported by the tick.
+    assert!(feed(&mut engine, at(60, lift())).is_empty());
+    let out: Vec<_> = engine.tick(Duration::from_millis(90)).iter()
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect();
+    assert!(out.contains(&touch_key(0)));
+    assert!(out.contains(&pen(0)));
+    assert_eq!(engine.deadline(), None);
+
+    // Without a debounce time the lift is reported right away.
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    feed(&mut engine, touch(100, 200, 50));
+    assert!(feed(&mut engine, lift()).contains(&touch_key(0)));
+}
+
+/// Tests interpolating the pressure curves between their control points.
+#[test]
+fn engine_pressure_curve() {
+    // Two points from corner to corner leave the pressure linear, as do no points.
+    for input in &[0.0, 12.5, 50.0, 99.0, 100.0] {
+        assert_eq!(engine::pressure_curve(&[[0, 0], [100, 100]], *input), *input);
+        assert_eq!(engine::pressure_curve(&[], *input), *input);
+    }
+
+    // A soft curve rising quickly and flattening, like a logarithm.
+    let soft = [[0, 0], [10, 40], [40, 80], [100, 100]];
+    assert_eq!(engine::pressure_curve(&soft, 5.0), 20.0);
+    assert_eq!(engine::pressure_curve(&soft, 10.0), 40.0);
+    assert_eq!(engine::pressure_curve(&soft, 25.0), 60.0);
+    assert_eq!(engine::pressure_curve(&soft, 70.0), 90.0);
+
+    // Outside the points the curve is flat, and the outputs are clamped.
+    let narrow = [[20, 10], [80, 150]];
+    assert_eq!(engine::pressure_curve(&narrow, 0.0), 10.0);
+    assert_eq!(engine::pressure_curve(&narrow, 50.0), 55.0);
+    assert_eq!(engine::pressure_curve(&narrow, 100.0), 100.0);
+    assert_eq!(engine::pressure_curve(&narrow, -5.0), 10.0);
+
+    // The engine reports the shaped pressure within the pressure range.
+    let conf = config::Config {
+        tablet: config::Tablet { pressure_curve: vec![[0, 0], [50, 25], [100, 100]], ..Default::default() },
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert!(feed(&mut engine, touch(100, 200, 51)).contains(&pressure(26)));
+    assert!(feed(&mut engine, touch(100, 200, 255)).contains(&pressure(255)));
+
+    // The points must go from left to right within the range.
+    let problems = |curve: Vec<[u8; 2]>| config::Config {
+        tablet: config::Tablet { pressure_curve: curve, ..Default::default() },
+        ..Default::default()
+    }.validate();
+    assert!(problems(vec![[0, 0], [100, 100]]).is_empty());
+    assert!(problems(vec![[0, 0], [60, 200], [100, 100]]).is_empty());
+    assert_eq!(problems(vec![[50, 50]]), vec![String::from("The pressure curve needs at least two points.")]);
+    assert_eq!(problems(vec![[0, 0], [50, 20], [50, 80]]), vec![String::from("The inputs of the pressure curve must increase from point to point.")]);
+    assert_eq!(problems(vec![[0, 0], [120, 100]]), vec![String::from("The inputs of the pressure curve must be from 0 to 100.")]);
+}
+
+/// Tests that smoothing pulls positions toward the previous ones, less so under pressure.
+#[test]
+fn engine_smoothing() {
+    // Pressure-aware smoothing weakens as the pressure rises, down to none at full pressure.
+    let aware = config::Smoothing { factor: 0.8, pressure_aware: true };
+    let factors: Vec<f64> = [0.0, 0.25, 0.5, 1.0].iter()
+        .map(|p| engine::smoothing_factor(&aware, Some(*p)))
+        .collect();
+    assert!(factors.windows(2).all(|w| w[1] < w[0]));
+    assert_eq!(factors[0], 0.8f32 as f64);
+    assert_eq!(factors[3], 0.0);
+
+    // Otherwise, or without a pressure reading, the factor stays.
+    let plain = config::Smoothing { factor: 0.8, pressure_aware: false };
+    assert_eq!(engine::smoothing_factor(&plain, Some(1.0)), 0.8f32 as f64);
+    assert_eq!(engine::smoothing_factor(&aware, None), 0.8f32 as f64);
+
+    // The engine reports the second position halfway to the first, unless pressed fully.
+    let positions = |smoothing: Option<config::Smoothing>, pressure: i32| {
+        let conf = config::Config { smoothing, ..Default::default() };
+        let mut engine = engine::Engine::new(test_bounds(), &conf);
+        let x = |out: Vec<(EventType, u16, i32)>| out.into_iter()
+            .filter(|e| e.0 == EventType::ABSOLUTE && e.1 == AbsoluteAxisType::ABS_X.0)
+            .map(|e| e.2)
+            .next_back()
+            .unwrap();
+        (x(feed(&mut engine, touch(100, 200, pressure))), x(feed(&mut engine, touch(900, 200, pressure))))
+    };
+    let (first, second) = positions(None, 100);
+    let half = config::Smoothing { factor: 0.5, pressure_aware: true };
+    assert_eq!(positions(Some(half), 0).0, first);
+    assert_eq!(positions(Some(half), 0).1, (first + second) / 2);
+    assert_eq!(positions(Some(half), 255), (first, second));
+
+    // The factor must stay below 1 for the position to move at all.
+    let problems = |factor| config::Config {
+        smoothing: Some(config::Smoothing { factor, pressure_aware: false }),
+        ..Default::default()
+    }.validate();
+    assert!(problems(0.0).is_empty());
+    assert_eq!(problems(1.0), vec![String::from("The smoothing factor must be from 0 to below 1.")]);
+}
+
+/// Tests that contacts lighter than the touch threshold hover instead of drawing.
+#[test]
+fn engine_touch_threshold() {
+    let conf = config::Config {
+        tablet: config::Tablet {
+            touch_threshold: 30,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(feed(&mut engine, touch(100, 200, 10)), vec![pen(1), x(100), y(200)]);
+    assert_eq!(feed(&mut engine, touch(110, 210, 29)), vec![x(110), y(210)]);
+    assert_eq!(feed(&mut engine, touch(120, 220, 30)), vec![x(120), y(220), pressure(30), touch_key(1)]);
+    assert_eq!(feed(&mut engine, touch(130, 230, 20)), vec![x(130), y(230), pressure(0), touch_key(0)]);
+    assert_eq!(feed(&mut engine, lift()), vec![pen(0)]);
+
+    // Without a pressure axis every contact draws.
+    let bounds = device::Bounds {
+        pressure: None,
+        ..test_bounds()
+    };
+    let mut engine = engine::Engine::new(bounds, &conf);
+    let out = feed(&mut engine, touch(100, 200, 10));
+    assert!(out.contains(&touch_key(1)));
+}
+
+/// Tests turning the direction of the contacts into the tilt of the pen.
+#[test]
+fn engine_tilt() {
+    assert_eq!(engine::tilt_axes((1.0, 0.0), 45), (45, 0));
+    assert_eq!(engine::tilt_axes((0.0, -2.0), 90), (0, -90));
+    assert_eq!(engine::tilt_axes((1.0, 1.0), 60), (42, 42));
+    assert_eq!(engine::tilt_axes((0.0, 0.0), 45), (0, 0));
+    assert_eq!(engine::tilt_axes((1.0, 0.0), 120), (90, 0));
+
+    // An upright finger leans the pen towards the user, a finger turned to the right to the left.
+    assert_eq!(engine::tilt_axes(engine::orientation_direction(0, 90), 45), (0, 45));
+    assert_eq!(engine::tilt_axes(engine::orientation_direction(90, 90), 45), (-45, 0));
+    assert_eq!(engine::tilt_axes(engine::orientation_direction(-45, 90), 60), (42, 42));
+
+    // The direction is flipped and rotated like the positions.
+    let rotated = mapping_config(config::Mapping { rotation: 90, ..Default::default() });
+    assert_eq!(engine::tilt_axes(mapping::map_direction((1.0, 0.0), &rotated), 45), (0, 45));
+    let flipped = mapping_config(config::Mapping { flip_x: true, ..Default::default() });
+    assert_eq!(engine::tilt_axes(mapping::map_direction((1.0, 0.0), &flipped), 45), (-45, 0));
+
+    let contact = |slot: i32, id: i32, x: i32, y: i32| vec![
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_SLOT.0, slot),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_TRACKING_ID.0, id),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_POSITION_X.0, x),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_POSITION_Y.0, y)
+    ];
+    let tilt_x = |value| (EventType::ABSOLUTE, AbsoluteAxisType::ABS_TILT_X.0, value);
+    let tilt_y = |value| (EventType::ABSOLUTE, AbsoluteAxisType::ABS_TILT_Y.0, value);
+
+    // The pen leans towards the second finger, and stands upright without it.
+    let mut conf = config::Config {
+        tilt: Some(config::Tilt { source: config::TiltSource::TwoFinger, angle: 45 }),
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    let out = feed(&mut engine, contact(0, 1, 100, 100).into_iter().chain(touch(100, 100, 100)).collect());
+    assert!(out.contains(&tilt_x(0)) && out.contains(&tilt_y(0)), "{:?}", out);
+    let out = feed(&mut engine, contact(1, 2, 300, 100).into_iter().chain(touch(100, 100, 100)).collect());
+    assert!(out.contains(&tilt_x(45)) && out.contains(&tilt_y(0)), "{:?}", out);
+    // An unchanged tilt isn't reported again.
+    let out = feed(&mut engine, touch(110, 100, 100));
+    assert!(!out.contains(&tilt_x(45)), "{:?}", out);
+
+    // Without the tilt section the tablet has no tilt.
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    let out = feed(&mut engine, contact(0, 1, 100, 100).into_iter().chain(contact(1, 2, 300, 100)).chain(touch(100, 100, 100)).collect());
+    assert!(!out.contains(&tilt_x(0)) && !out.contains(&tilt_x(45)), "{:?}", out);
+
+    conf.tablet.hover_mode = config::HoverMode::TwoFinger;
+    assert_eq!(conf.validate(), vec![String::from("The two-finger tilt can't be used with two-finger hovering or passed through gestures.")]);
+    conf.tablet.hover_mode = config::HoverMode::Off;
+    conf.tilt = Some(config::Tilt { source: config::TiltSource::Orientation, angle: 91 });
+    assert_eq!(conf.validate(), vec![String::from("The tilt angle must be from 0 to 90 degrees.")]);
+}
+
+/// Tests that the relative mode moves the pen by the motion on the touchpad,
+/// leaving out the area and the rotation.
+#[test]
+fn engine_relative() {
+    let conf = mapping_config(config::Mapping {
+        mode: config::MappingMode::Relative,
+        rotation: 90,
+        area: Some(config::Area { x: 960, y: 0, width: 960, height: 540, width_mm: None, height_mm: None }),
+        ..Default::default()
+    });
+    assert!(conf.validate().is_empty());
+    let rel = |axis: RelativeAxisType, v| (EventType::RELATIVE, axis.0, v);
+    let motion = |out: Vec<(EventType, u16, i32)>| out.into_iter()
+        .filter(|e| e.0 == EventType::RELATIVE || e.1 == AbsoluteAxisType::ABS_X.0 || e.1 == AbsoluteAxisType::ABS_Y.0)
+        .collect::<Vec<(EventType, u16, i32)>>();
+
+    // The first position of a contact only anchors the motion.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(motion(feed(&mut engine, at(0, touch(100, 100, 100)))), vec![]);
+    // A tenth of the touchpad moves the pen by a tenth of the output.
+    assert_eq!(motion(feed(&mut engine, at(100, touch(200, 150, 100)))), vec![rel(RelativeAxisType::REL_X, 192), rel(RelativeAxisType::REL_Y, 108)]);
+    assert_eq!(motion(feed(&mut engine, at(200, touch(150, 150, 100)))), vec![rel(RelativeAxisType::REL_X, -96)]);
+
+    // Lifting and touching elsewhere doesn't move the pen.
+    feed(&mut engine, at(300, lift()));
+    assert_eq!(motion(feed(&mut engine, at(1000, touch(900, 400, 100)))), vec![]);
+    // The fractions of pixels carry over to the next motion.
+    assert_eq!(motion(feed(&mut engine, at(1100, touch(905, 400, 100)))), vec![rel(RelativeAxisType::REL_X, 9)]);
+    assert_eq!(motion(feed(&mut engine, at(1200, touch(910, 400, 100)))), vec![rel(RelativeAxisType::REL_X, 10)]);
+
+    // Quick motions are sped up by the acceleration.
+    let accelerated = mapping_config(config::Mapping { mode: config::MappingMode::Relative, acceleration: 0.1, ..Default::default() });
+    assert_eq!(mapping::relative_motion((100, 0), &accelerated, test_bounds()).0.round(), 384.0);
+    assert_eq!(mapping::relative_motion((0, 0), &accelerated, test_bounds()), (0.0, 0.0));
+    let negative = mapping_config(config::Mapping { acceleration: -1.0, ..Default::default() });
+    assert_eq!(negative.validate(), vec![String::from("The acceleration can't be negative.")]);
+
+    // The editor cycles through the modes.
+    let mut conf = config::Config::default();
+    config::ConfigField::MappingMode.cycle(&mut conf);
+    assert_eq!(conf.mapping.mode, config::MappingMode::Relative);
+    config::ConfigField::MappingMode.cycle(&mut conf);
+    assert_eq!(config::ConfigField::MappingMode.get(&conf), Some(config::ConfigValue::Choice("absolute")));
+    config::ConfigField::MappingMode.set(&mut conf, config::ConfigValue::Choice("relative"));
+    assert_eq!(conf.mapping.mode, config::MappingMode::Relative);
+}
+
+/// Tests switching between the pen and the eraser, with the tool leaving
+/// in a report of its own before the other one comes in.
+#[test]
+fn engine_eraser() {
+    let tool = |key: Key, v| (EventType::KEY, key.code(), v);
+    let syn = (EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+    let button = |v| vec![InputEvent::new(EventType::KEY, Key::BTN_RIGHT.code(), v)];
+    let conf = config::Config {
+        eraser: Some(config::Eraser { key: Some(String::from("BTN_RIGHT")), device: None }),
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+
+    assert_eq!(feed(&mut engine, touch(100, 200, 80)), vec![tool(Key::BTN_TOOL_PEN, 1), x(100), y(200), pressure(80), tool(Key::BTN_TOUCH, 1)]);
+    assert_eq!(feed(&mut engine, button(1)), vec![
+        pressure(0), tool(Key::BTN_TOUCH, 0), tool(Key::BTN_TOOL_PEN, 0), syn,
+        tool(Key::BTN_TOOL_RUBBER, 1), x(100), y(200), pressure(80), tool(Key::BTN_TOUCH, 1)
+    ]);
+    assert_eq!(feed(&mut engine, lift()), vec![pressure(0), tool(Key::BTN_TOUCH, 0), tool(Key::BTN_TOOL_RUBBER, 0)]);
+    // The eraser stays while the key is held.
+    assert_eq!(feed(&mut engine, touch(150, 200, 80))[0], tool(Key::BTN_TOOL_RUBBER, 1));
+    assert_eq!(feed(&mut engine, button(0))[..4], [pressure(0), tool(Key::BTN_TOUCH, 0), tool(Key::BTN_TOOL_RUBBER, 0), syn]);
+    feed(&mut engine, lift());
+
+    // Out of proximity, only the next tool changes.
+    assert!(engine.set_erasing(true).is_empty());
+    assert_eq!(feed(&mut engine, touch(100, 200, 80))[0], tool(Key::BTN_TOOL_RUBBER, 1));
+
+    // Without a key, a quick three-finger tap switches the tool.
+    let fingers = |ms, v| at(ms, vec![
+        InputEvent::new(EventType::KEY, Key::BTN_TOOL_TRIPLETAP.code(), v),
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), v),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+    ]);
+    let conf = config::Config {
+        eraser: Some(config::Eraser::default()),
+        tablet: config::Tablet { passthrough_gestures: true, ..Default::default() },
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    feed(&mut engine, fingers(1000, 1));
+    feed(&mut engine, fingers(1100, 0));
+    assert_eq!(feed(&mut engine, at(1500, touch(100, 200, 80)))[0], tool(Key::BTN_TOOL_RUBBER, 1));
+    feed(&mut engine, at(1600, lift()));
+    // A slow one doesn't.
+    feed(&mut engine, fingers(2000, 1));
+    feed(&mut engine, fingers(3000, 0));
+    assert_eq!(feed(&mut engine, at(3500, touch(100, 200, 80)))[0], tool(Key::BTN_TOOL_RUBBER, 1));
+    feed(&mut engine, at(3600, lift()));
+    feed(&mut engine, fingers(4000, 1));
+    feed(&mut engine, fingers(4100, 0));
+    assert_eq!(feed(&mut engine, at(4500, touch(100, 200, 80)))[0], tool(Key::BTN_TOOL_PEN, 1));
+
+    let mut unknown = conf.clone();
+    unknown.eraser = Some(config::Eraser { key: Some(String::from("KEY_NOPE")), device: None });
+    assert_eq!(unknown.validate(), vec![String::from("Unknown eraser key: KEY_NOPE")]);
+}
+
+/// Tests pressing the buttons of the virtual pad with keys and taps,
+/// and that the pad has the buttons it's configured with.
+#[test]
+fn engine_pad() {
+    assert_eq!(device::pad_buttons(4), vec![Key::BTN_0, Key::BTN_1, Key::BTN_2, Key::BTN_3]);
+    assert_eq!(device::pad_buttons(20).len(), device::PAD_BUTTONS_MAX as usize);
+    assert_eq!(device::pad_name(device::VIRTUAL_NAME), "egawari virtual tablet pad");
+    assert_eq!(device::pad_name("x".repeat(80).as_str()).len(), device::VIRTUAL_NAME_MAX);
+    // Creating the pad needs /dev/uinput, which the tests may not have.
+    if let Ok(mut pad) = device::create_pad("egawari test", 4) {
+        let path = pad.enumerate_dev_nodes_blocking().unwrap().next().unwrap().unwrap();
+        let keys = evdev::Device::open(path).unwrap().supported_keys().unwrap().iter().collect::<Vec<Key>>();
+        assert_eq!(keys, device::pad_buttons(4));
+    }
+
+    let button = |key: Key, v| (EventType::KEY, key.code(), v);
+    let syn = (EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+    let pad = |engine: &mut engine::Engine| engine.take_pad().into_iter()
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect::<Vec<(EventType, u16, i32)>>();
+    let conf = config::Config {
+        pad: Some(config::Pad {
+            buttons: 2,
+            device: None,
+            bindings: vec![
+                config::PadBinding { button: 0, key: Some(String::from("BTN_RIGHT")), gesture: None },
+                config::PadBinding { button: 1, key: None, gesture: Some(config::PadGesture::FourFingerTap) }
+            ]
+        }),
+        ..Default::default()
+    };
+    assert!(conf.validate().is_empty());
+
+    // The keys of the touchpad press the buttons while they're held, repeats aside.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    let key = |v| vec![InputEvent::new(EventType::KEY, Key::BTN_RIGHT.code(), v)];
+    assert!(feed(&mut engine, key(1)).is_empty());
+    feed(&mut engine, key(2));
+    assert_eq!(pad(&mut engine), vec![button(Key::BTN_0, 1)]);
+    feed(&mut engine, key(0));
+    assert_eq!(pad(&mut engine), vec![button(Key::BTN_0, 0)]);
+
+    // A quick four-finger tap presses and releases its button, a three-finger one has none.
+    let fingers = |ms, tool: Key, v| at(ms, vec![
+        InputEvent::new(EventType::KEY, tool.code(), v),
+        InputEvent::new(EventType::KEY, Key::BTN_TOUCH.code(), v),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+    ]);
+    feed(&mut engine, fingers(1000, Key::BTN_TOOL_QUADTAP, 1));
+    feed(&mut engine, fingers(1100, Key::BTN_TOOL_QUADTAP, 0));
+    assert_eq!(pad(&mut engine), vec![button(Key::BTN_1, 1), syn, button(Key::BTN_1, 0)]);
+    feed(&mut engine, fingers(2000, Key::BTN_TOOL_TRIPLETAP, 1));
+    feed(&mut engine, fingers(2100, Key::BTN_TOOL_TRIPLETAP, 0));
+    assert!(pad(&mut engine).is_empty());
+    // A slow one doesn't press it.
+    feed(&mut engine, fingers(3000, Key::BTN_TOOL_QUADTAP, 1));
+    feed(&mut engine, fingers(4000, Key::BTN_TOOL_QUADTAP, 0));
+    assert!(pad(&mut engine).is_empty());
+
+    // A held button is released when its device goes away.
+    engine.set_pad_button(0, true);
+    engine.release_pad();
+    assert_eq!(pad(&mut engine), vec![button(Key::BTN_0, 1), syn, button(Key::BTN_0, 0)]);
+
+    let mut invalid = conf.clone();
+    invalid.pad = Some(config::Pad {
+        buttons: 1,
+        device: Some(String::from("Keyboard")),
+        bindings: vec![
+            config::PadBinding { button: 1, key: None, gesture: Some(config::PadGesture::ThreeFingerTap) },
+            config::PadBinding { button: 0, key: Some(String::from("KEY_NOPE")), gesture: None }
+        ]
+    });
+    invalid.eraser = Some(config::Eraser::default());
+    assert_eq!(invalid.validate(), vec![
+        String::from("The pad has no button 1, they're numbered from 0."),
+        String::from("Unknown pad key: KEY_NOPE"),
+        String::from("The three-finger tap can't both switch to the eraser and press a pad button.")
+    ]);
+}
+
+/// Tests holding the stylus buttons while their keys are, from another device and from the touchpad.
+#[test]
+fn engine_stylus_buttons() {
+    let button = |key: Key, v| (EventType::KEY, key.code(), v);
+    let syn = (EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0);
+    let events = |out: Vec<InputEvent>| out.into_iter().map(|e| (e.event_type(), e.code(), e.value())).collect::<Vec<(EventType, u16, i32)>>();
+    let binding = |key: &str, button| config::StylusBinding { key: String::from(key), button };
+    let stylus = |device: Option<&str>| config::Config {
+        stylus_buttons: Some(config::StylusButtons {
+            device: device.map(String::from),
+            bindings: vec![
+                binding("KEY_LEFTSHIFT", config::StylusButton::Lower),
+                binding("KEY_RIGHTSHIFT", config::StylusButton::Lower),
+                binding("BTN_LEFT", config::StylusButton::Upper)
+            ]
+        }),
+        ..Default::default()
+    };
+
+    let held = stylus(None).stylus_buttons.unwrap();
+    assert_eq!(held.held(&[]), [false, false]);
+    assert_eq!(held.held(&[Key::KEY_RIGHTSHIFT]), [true, false]);
+    assert_eq!(held.held(&[Key::BTN_LEFT, Key::KEY_A]), [false, true]);
+
+    let mut engine = engine::Engine::new(test_bounds(), &stylus(Some("Keyboard")));
+    let shift = InputEvent::new(EventType::KEY, Key::KEY_LEFTSHIFT.code(), 1);
+    assert_eq!(engine.stylus_key_held(&shift), Some((Key::KEY_LEFTSHIFT, true)));
+    assert_eq!(engine.stylus_key_held(&InputEvent::new(EventType::KEY, Key::KEY_LEFTSHIFT.code(), 2)), Some((Key::KEY_LEFTSHIFT, true)));
+    assert_eq!(engine.stylus_key_held(&InputEvent::new(EventType::KEY, Key::KEY_A.code(), 1)), None);
+
+    // Either key holds the lower button, which is released with the last of them.
+    assert_eq!(events(engine.set_stylus_key(Key::KEY_LEFTSHIFT, true)), vec![button(Key::BTN_STYLUS, 1), syn]);
+    assert_eq!(events(engine.set_stylus_key(Key::KEY_LEFTSHIFT, true)), vec![]);
+    assert_eq!(events(engine.set_stylus_key(Key::KEY_RIGHTSHIFT, true)), vec![]);
+    assert_eq!(events(engine.set_stylus_key(Key::KEY_LEFTSHIFT, false)), vec![]);
+    assert_eq!(events(engine.set_stylus_key(Key::KEY_RIGHTSHIFT, false)), vec![button(Key::BTN_STYLUS, 0), syn]);
+    assert_eq!(events(engine.set_stylus_key(Key::BTN_LEFT, true)), vec![button(Key::BTN_STYLUS2, 1), syn]);
+    assert_eq!(events(engine.release_stylus()), vec![button(Key::BTN_STYLUS2, 0), syn]);
+    assert_eq!(events(engine.release_stylus()), vec![]);
+
+    // The keys of the touchpad itself are read along with its events.
+    let mut engine = engine::Engine::new(test_bounds(), &stylus(None));
+    let click = |v| vec![InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), v)];
+    assert_eq!(feed(&mut engine, click(1)), vec![button(Key::BTN_STYLUS2, 1), syn]);
+    assert_eq!(feed(&mut engine, click(0)), vec![button(Key::BTN_STYLUS2, 0), syn]);
+    feed(&mut engine, click(1));
+    assert_eq!(events(engine.disconnect())[..2], [button(Key::BTN_STYLUS2, 0), syn]);
+
+    let mut invalid = stylus(None);
+    invalid.stylus_buttons = Some(config::StylusButtons { device: None, bindings: vec![binding("KEY_NOPE", config::StylusButton::Lower)] });
+    assert_eq!(invalid.validate(), vec![String::from("Unknown stylus button key: KEY_NOPE")]);
+    invalid.stylus_buttons = Some(config::StylusButtons { device: None, bindings: vec![] });
+    assert_eq!(invalid.validate(), vec![String::from("The stylus buttons need a binding.")]);
+    let parsed: config::StylusButtons = toml::from_str("bindings = [{ key = \"KEY_LEFTSHIFT\", button = \"upper\" }]").unwrap();
+    assert_eq!(parsed.bindings, vec![binding("KEY_LEFTSHIFT", config::StylusButton::Upper)]);
+}
+
+/// Tests routing the touchpad axes to relative axes and keys.
+#[test]
+fn engine_routes() {
+    let route = |from, to: &str, scale, threshold| config::Route { from, to: String::from(to), scale, threshold };
+    let conf = config::Config {
+        routes: vec![
+            route(config::RouteSource::Pressure, "REL_WHEEL", 0.1, 50),
+            route(config::RouteSource::X, "KEY_ZOOMIN", 0.1, 50)
+        ],
+        ..Default::default()
+    };
+    let routed = |out: Vec<(EventType, u16, i32)>| out.into_iter()
+        .filter(|(kind, code, _)| (*kind, *code) == (EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0) || (*kind, *code) == (EventType::KEY, Key::KEY_ZOOMIN.code()))
+        .collect::<Vec<(EventType, u16, i32)>>();
+    let wheel = |v| (EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, v);
+    let zoom = |v| (EventType::KEY, Key::KEY_ZOOMIN.code(), v);
+
+    // The first value of a contact only anchors the wheel, and the steps it's short of carry over.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    assert_eq!(routed(feed(&mut engine, touch(200, 250, 51))), vec![]);
+    assert_eq!(routed(feed(&mut engine, touch(200, 250, 153))), vec![wheel(4)]);
+    assert_eq!(routed(feed(&mut engine, touch(200, 250, 165))), vec![]);
+    assert_eq!(routed(feed(&mut engine, touch(200, 250, 180))), vec![wheel(1)]);
+    assert_eq!(routed(feed(&mut engine, touch(200, 250, 100))), vec![wheel(-3)]);
+
+    // The key is held from the threshold on and released with the contact.
+    assert_eq!(routed(feed(&mut engine, touch(600, 250, 102))), vec![zoom(1)]);
+    assert_eq!(routed(feed(&mut engine, touch(700, 250, 102))), vec![]);
+    assert_eq!(routed(feed(&mut engine, lift())), vec![zoom(0)]);
+    assert_eq!(routed(feed(&mut engine, touch(700, 250, 153))), vec![zoom(1)]);
+
+    let targets = device::tablet_capabilities(&test_bounds(), false, true, &conf.route_targets());
+    assert_eq!(targets.relative, vec![RelativeAxisType::REL_X, RelativeAxisType::REL_Y, RelativeAxisType::REL_WHEEL]);
+    assert!(targets.keys.contains(&Key::KEY_ZOOMIN));
+
+    let mut invalid = conf.clone();
+    invalid.routes = vec![route(config::RouteSource::Y, "REL_NOPE", 0.1, 50)];
+    assert_eq!(invalid.validate(), vec![String::from("Unknown route target: REL_NOPE")]);
+    invalid.routes = vec![route(config::RouteSource::Y, "BTN_LEFT", 0.1, 101)];
+    assert_eq!(invalid.validate(), vec![String::from("The route threshold must be from 0 to 100.")]);
+    let parsed: config::Config = toml::from_str("[[routes]]\nfrom = \"y\"\nto = \"REL_HWHEEL\"").unwrap();
+    assert_eq!(parsed.routes, vec![route(config::RouteSource::Y, "REL_HWHEEL", 0.1, 50)]);
+}
+
+/// Tests reusing an existing output device only when it reports what the virtual tablet would.
+#[test]
+fn device_tablet_target() {
+    let bounds = device::Bounds { min_x: 0, max_x: 1919, min_y: 0, max_y: 1079, pressure: Some((0, 255)), orientation: None };
+    let required = device::tablet_capabilities(&bounds, false, false, &[]);
+    assert_eq!(device::tablet_target(None, &required), device::TabletTarget::Create);
+    assert_eq!(device::tablet_target(Some(&required), &required), device::TabletTarget::Reuse);
+
+    // Extra capabilities don't matter, the ones the events use do.
+    let tilted = device::tablet_capabilities(&bounds, true, true, &[]);
+    assert_eq!(device::tablet_target(Some(&tilted), &required), device::TabletTarget::Reuse);
+    assert_eq!(device::tablet_target(Some(&required), &tilted), device::TabletTarget::Incompatible(vec![
+        String::from("ABS_TILT_X"), String::from("ABS_TILT_Y"), String::from("REL_X"), String::from("REL_Y")
+    ]));
+
+    let mut other = device::tablet_capabilities(&device::Bounds { max_x: 2559, ..bounds }, false, false, &[]);
+    other.keys.retain(|key| *key != Key::BTN_STYLUS2);
+    assert_eq!(device::tablet_target(Some(&other), &required), device::TabletTarget::Incompatible(vec![
+        String::from("BTN_STYLUS2"), String::from("ABS_X from 0 to 1919")
+    ]));
+}
+
+/// Tests telling the problems of /dev/uinput apart by the error number.
+#[test]
+fn uinput_problems() {
+    use device::UinputProblem;
+    let error = |errno| anyhow::Error::new(std::io::Error::from_raw_os_error(errno)).context("Couldn't open /dev/uinput.");
+
+    assert_eq!(UinputProblem::from_error(&error(libc::EACCES)), Some(UinputProblem::PermissionDenied));
+    assert_eq!(UinputProblem::from_error(&error(libc::EPERM)), Some(UinputProblem::PermissionDenied));
+    assert_eq!(UinputProblem::from_error(&error(libc::ENOENT)), Some(UinputProblem::Missing));
+    assert_eq!(UinputProblem::from_error(&error(libc::EINVAL)), None);
+    assert_eq!(UinputProblem::from_error(&anyhow::anyhow!("Couldn't create the virtual tablet.")), None);
+
+    assert_eq!(UinputProblem::PermissionDenied.message(), "No permission to create a virtual tablet with /dev/uinput.");
+    let hints: Vec<String> = UinputProblem::PermissionDenied.hints().iter().map(|hint| stdout::strip_escapes(hint)).collect();
+    assert!(hints.iter().any(|hint| hint.contains("usermod -aG input")), "{:?}", hints);
+    assert!(hints.iter().any(|hint| hint.ends_with(device::UINPUT_RULE)), "{:?}", hints);
+    assert!(hints.iter().any(|hint| hint.contains("egawari udev-rule --install")), "{:?}", hints);
+}
+
+/// Tests that the installed udev rule is the one `doctor` asks for.
+#[test]
+fn udev_rule_install() {
+    let path = env::temp_dir().join("egawari-test-udev.rules");
+    device::install_udev_rule(&path).unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let rules: Vec<&str> = contents.lines().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(rules, vec![device::UINPUT_RULE]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(device::install_udev_rule(&env::temp_dir().join("egawari-test-missing").join("99-egawari.rules")).is_err());
+}
+
+/// Tests that replaying a recording emits what the engine did while recording.
+#[test]
+fn record_replay() {
+    let path = env::temp_dir().join("egawari-test-record.jsonl");
+    let header = record::Header { device: String::from("Elan Touchpad"), bounds: test_bounds() };
+    let conf = config::Config {
+        tablet: config::Tablet { report_rate: 100, lift_timeout_ms: 50, ..Default::default() },
+        stylus_buttons: Some(config::StylusButtons {
+            device: Some(String::from("Keyboard")),
+            bindings: vec![config::StylusBinding { key: String::from("KEY_LEFTSHIFT"), button: config::StylusButton::Lower }]
+        }),
+        ..Default::default()
+    };
+
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    engine.record(record::Recorder::create(&path, &header).unwrap());
+    let mut live = vec![];
+    let interleaved = [at(1000, touch(100, 200, 40)), at(1004, touch(110, 200, 50)), at(1008, touch(120, 210, 60)), at(1030, lift())];
+    for events in interleaved.iter() {
+        live.extend(events.iter().map(|event| engine.process(*event)));
+        if let Some(deadline) = engine.deadline() {
+            live.push(engine.tick(deadline));
+        }
+    }
+    live.push(engine.set_paused(true));
+    live.push(engine.set_paused(false));
+    live.push(engine.set_stylus_key(Key::KEY_LEFTSHIFT, true));
+    live.push(engine.release_stylus());
+
+    // A reload mid-run is replayed with the reloaded config, not the one replaying.
+    let mut reloaded = conf.clone();
+    reloaded.mapping.flip_x = true;
+    engine.set_config(&reloaded);
+    for events in [at(2000, touch(100, 200, 40)), at(2030, lift())] {
+        live.extend(events.iter().map(|event| engine.process(*event)));
+    }
+    live.push(engine.disconnect());
+    drop(engine);
+
+    let (read_header, records) = record::read_recording(&path).unwrap();
+    assert_eq!(read_header, header);
+    assert!(records.contains(&record::Record::Paused { paused: true }));
+    assert!(records.contains(&record::Record::StylusKey { code: Key::KEY_LEFTSHIFT.code(), held: true }));
+    assert!(records.contains(&record::Record::StylusReleased));
+    assert_eq!(records.iter().filter(|record| matches!(record, record::Record::Config { .. })).count(), 2);
+    let replayed = record::replay(&read_header, &records, &conf);
+    let describe = |reports: &[Vec<InputEvent>]| reports.iter().map(|events| run::describe(events)).collect::<Vec<String>>();
+    assert_eq!(describe(&replayed), describe(&live));
+    assert!(live.iter().any(|events| !events.is_empty()));
+
+    std::fs::write(&path, "").unwrap();
+    assert!(record::read_recording(&path).is_err());
+    std::fs::remove_file(&path).ok();
+}
+
+/// Tests reading the pressure from each source, turned around or not.
+#[test]
+fn engine_pressure_source() {
+    use config::PressureSource;
+
+    let tablet = |pressure_source, invert_pressure, touch_threshold| config::Config {
+        tablet: config::Tablet {
+            pressure_source,
+            invert_pressure,
+            touch_threshold,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // Reports a force of 50 and a contact size of 100.
+    let report = |x| {
+        let mut events = touch(x, 200, 50);
+        events.insert(4, InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_TOUCH_MAJOR.0, 100));
+        events
+    };
+
+    let cases = [
+        (PressureSource::Force, false, 50),
+        (PressureSource::Force, true, 205),
+        (PressureSource::TouchMajor, false, 100),
+        (PressureSource::TouchMajor, true, 155)
+    ];
+    for (source, invert, expected) in cases.iter() {
+        let mut engine = engine::Engine::new(test_bounds(), &tablet(*source, *invert, 0));
+        assert!(feed(&mut engine, report(100)).contains(&pressure(*expected)), "{:?} {}", source, invert);
+        assert!(feed(&mut engine, report(110)).contains(&pressure(*expected)), "{:?} {}", source, invert);
+    }
+
+    // The threshold applies to the pressure as it's reported.
+    let mut engine = engine::Engine::new(test_bounds(), &tablet(PressureSource::Force, true, 210));
+    assert!(!feed(&mut engine, report(100)).contains(&pressure(205)));
+    let mut engine = engine::Engine::new(test_bounds(), &tablet(PressureSource::Force, true, 200));
+    assert!(feed(&mut engine, report(100)).contains(&pressure(205)));
+
+    let problems = run::reload_problems(&tablet(PressureSource::Force, false, 0), &tablet(PressureSource::TouchMajor, false, 0), test_bounds());
+    assert_eq!(problems, vec![String::from("The pressure source can't change while egawari is running.")]);
+}
+
+/// Tests merging the samples of a separate pressure device into the touchpad reports.
+#[test]
+fn engine_pressure_device() {
+    let sample = |v| vec![
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_PRESSURE.0, v),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+    ];
+    let feed_pressure = |engine: &mut engine::Engine, events: Vec<InputEvent>| events.into_iter()
+        .flat_map(|e| engine.process_pressure(e))
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect::<Vec<_>>();
+    let conf = config::Config {
+        tablet: config::Tablet {
+            pressure_device: Some(String::from("Pressure Pad")),
+            touch_threshold: 30,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+
+    // A sample without a contact is only kept for the next touchpad report.
+    assert_eq!(feed_pressure(&mut engine, sample(120)), vec![]);
+    // The pressure of the touchpad itself is left out.
+    let out = feed(&mut engine, touch(100, 200, 10));
+    assert!(out.contains(&pressure(120)) && out.contains(&touch_key(1)), "{:?}", out);
+
+    // Samples between the touchpad reports are reported on their own.
+    assert_eq!(feed_pressure(&mut engine, sample(150)), vec![pressure(150)]);
+    let out = feed(&mut engine, touch(110, 200, 10));
+    assert!(out.contains(&x(110)) && out.contains(&pressure(150)), "{:?}", out);
+    // A light sample lifts the pen while the finger rests.
+    assert_eq!(feed_pressure(&mut engine, sample(20)), vec![pressure(0), touch_key(0)]);
+    assert_eq!(feed_pressure(&mut engine, sample(90)), vec![pressure(90), touch_key(1)]);
+
+    // Without the pressure device the pen draws with the full pressure, right away while it's down.
+    let lost: Vec<_> = engine.lose_pressure().iter().map(|e| (e.event_type(), e.code(), e.value())).collect();
+    assert_eq!(lost, vec![pressure(255)]);
+    let out = feed(&mut engine, touch(120, 200, 10));
+    assert!(out.contains(&pressure(255)), "{:?}", out);
+    // A later sample is used again.
+    assert_eq!(feed_pressure(&mut engine, sample(60)), vec![pressure(60)]);
+}
+
+/// Tests the values the watch command shows for the touchpad events.
+#[test]
+fn watch_readout() {
+    let slot = |slot, id| vec![
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_SLOT.0, slot),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_TRACKING_ID.0, id)
+    ];
+    let mut readout = watch::Readout::new(AbsoluteAxisType::ABS_PRESSURE);
+    assert_eq!(readout.fingers(), 0);
+
+    for event in touch(250, 100, 40) {
+        readout.process(&event);
+    }
+    assert_eq!((readout.x, readout.y, readout.pressure, readout.touching), (250, 100, Some(40), true));
+    // Without slots, touching counts as a finger.
+    assert_eq!(readout.fingers(), 1);
+    for event in slot(0, 7).into_iter().chain(slot(1, 8)) {
+        readout.process(&event);
+    }
+    assert_eq!(readout.fingers(), 2);
+    for event in slot(0, -1) {
+        readout.process(&event);
+    }
+    assert_eq!(readout.fingers(), 1);
+
+    // The mapped position follows the config.
+    let conf = mapping_config(config::Mapping::default());
+    let lines: Vec<String> = readout.lines("Elan Touchpad", Path::new("/dev/input/event5"), &conf, test_bounds()).iter()
+        .map(|line| stdout::strip_escapes(line.as_str()))
+        .collect();
+    assert!(lines.contains(&String::from(" => X = 250 (0 to 1000)")), "{:?}", lines);
+    assert!(lines.contains(&String::from(" => Pressure = 40 (0 to 255)")), "{:?}", lines);
+    assert!(lines.contains(&String::from(" => Fingers = 1")), "{:?}", lines);
+    assert!(lines.contains(&String::from(" => X = 480 (0 to 1919)")), "{:?}", lines);
+    assert!(lines.contains(&String::from(" => Y = 216 (0 to 1079)")), "{:?}", lines);
+}
+
+/// Tests that the touchpad is only mapped while the activation key is held.
+#[test]
+fn engine_activation() {
+    let button = |v| vec![
+        InputEvent::new(EventType::KEY, Key::BTN_LEFT.code(), v),
+        InputEvent::new(EventType::SYNCHRONIZATION, Synchronization::SYN_REPORT.0, 0)
+    ];
+    let activation = |key: &str, device: Option<&str>| config::Config {
+        activation: Some(config::Activation {
+            key: key.to_string(),
+            device: device.map(String::from)
+        }),
+        ..Default::default()
+    };
+
+    let mut engine = engine::Engine::new(test_bounds(), &activation("BTN_LEFT", None));
+    assert!(feed(&mut engine, touch(100, 200, 50)).is_empty());
+    // A finger already on the touchpad draws from where it is.
+    assert_eq!(feed(&mut engine, button(1)), vec![pen(1), x(100), y(200), pressure(50), touch_key(1)]);
+    assert_eq!(feed(&mut engine, touch(110, 210, 50)), vec![x(110), y(210), pressure(50)]);
+    assert_eq!(feed(&mut engine, button(0)), vec![pressure(0), touch_key(0), pen(0)]);
+    assert!(feed(&mut engine, touch(120, 220, 50)).is_empty());
+    // The other keys of the touchpad still count the fingers.
+    let mut tripletap = vec![InputEvent::new(EventType::KEY, Key::BTN_TOOL_TRIPLETAP.code(), 1)];
+    tripletap.extend(touch(120, 220, 50));
+    feed(&mut engine, tripletap);
+    assert_eq!(engine.fingers(), 3);
+
+    // Keys of another device are passed in by the caller instead.
+    let mut engine = engine::Engine::new(test_bounds(), &activation("KEY_LEFTALT", Some("AT Translated Set 2 keyboard")));
+    let alt = InputEvent::new(EventType::KEY, Key::KEY_LEFTALT.code(), 2);
+    assert_eq!(engine.activation_held(&alt), Some(true));
+    assert_eq!(engine.activation_held(&InputEvent::new(EventType::KEY, Key::KEY_A.code(), 1)), None);
+    assert!(feed(&mut engine, button(1)).is_empty());
+    assert!(feed(&mut engine, touch(100, 200, 50)).is_empty());
+    assert!(engine.set_activated(true).is_empty());
+    assert_eq!(feed(&mut engine, touch(110, 210, 50)), vec![pen(1), x(110), y(210), pressure(50), touch_key(1)]);
+
+    assert_eq!(activation("KEY_NOPE", None).validate(), vec![String::from("Unknown activation key: KEY_NOPE")]);
+}
+
+/// Tests that multi-finger sequences are left to the system as gestures.
+#[test]
+fn engine_gestures() {
+    let slot = |slot, id| vec![
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_SLOT.0, slot),
+        InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_MT_TRACKING_ID.0, id)
+    ];
+    let conf = config::Config {
+        tablet: config::Tablet {
+            passthrough_gestures: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    let mut first = slot(0, 1);
+    first.extend(touch(100, 200, 50));
+    assert_eq!(feed(&mut engine, first), vec![pen(1), x(100), y(200), pressure(50), touch_key(1)]);
+
+    // A second finger lifts the pen until every finger is lifted.
+    let mut second = slot(1, 2);
+    second.extend(touch(110, 210, 50));
+    assert_eq!(feed(&mut engine, second), vec![pressure(0), touch_key(0), pen(0)]);
+    assert_eq!(engine.fingers(), 2);
+    let mut one_left = slot(1, -1);
+    one_left.extend(touch(120, 220, 50));
+    assert!(feed(&mut engine, one_left).is_empty());
+    let mut none_left = slot(0, -1);
+    none_left.extend(lift());
+    assert!(feed(&mut engine, none_left).is_empty());
+    assert_eq!(engine.fingers(), 0);
+
+    // The next single finger draws again.
+    let mut again = slot(0, 3);
+    again.extend(touch(130, 230, 50));
+    assert_eq!(feed(&mut engine, again), vec![pen(1), x(130), y(230), pressure(50), touch_key(1)]);
+
+    // A slot past the most kept, as in a crafted recording, is ignored.
+    let mut far = slot(i32::MAX, 4);
+    far.extend(touch(140, 240, 50));
+    feed(&mut engine, far);
+    assert_eq!(engine.fingers(), 1);
+
+    // The finger count tools work without slots.
+    let mut engine = engine::Engine::new(test_bounds(), &conf);
+    let mut tripletap = vec![InputEvent::new(EventType::KEY, Key::BTN_TOOL_TRIPLETAP.code(), 1)];
+    tripletap.extend(touch(100, 200, 50));
+    assert!(feed(&mut engine, tripletap).is_empty());
+    assert_eq!(engine.fingers(), 3);
+}
+
+/// Tests that a disconnection lifts the pen and forgets the fingers.
+#[test]
+fn engine_disconnect() {
+    let summary = |events: Vec<InputEvent>| events.iter()
+        .map(|e| (e.event_type(), e.code(), e.value()))
+        .collect::<Vec<(EventType, u16, i32)>>();
+
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    feed(&mut engine, touch(100, 200, 50));
+    assert_eq!(summary(engine.disconnect()), vec![pressure(0), touch_key(0), pen(0)]);
+    assert_eq!(engine.fingers(), 0);
+    assert!(engine.disconnect().is_empty());
+
+    // The next stroke after reconnecting starts from scratch.
+    assert_eq!(feed(&mut engine, touch(300, 400, 50))[0], pen(1));
+}
+
+/// Tests that reconnecting retries with a growing delay until the device is back.
+#[test]
+fn run_reconnect() {
+    let mut attempts = 0;
+    let mut sleeps = vec![];
+    let device = run::reconnect(|| {
+        attempts += 1;
+        if attempts > 8 { Some("touchpad") } else { None }
+    }, |delay| sleeps.push(delay.as_millis()));
+
+    assert_eq!(device, "touchpad");
+    assert_eq!(attempts, 9);
+    assert_eq!(sleeps, vec![100, 200, 400, 800, 1600, 3200, 5000, 5000]);
+
+    // A device that is already back isn't waited for.
+    let mut sleeps = vec![];
+    assert_eq!(run::reconnect(|| Some(1), |delay| sleeps.push(delay)), 1);
+    assert!(sleeps.is_empty());
+}
+
+/// Tests that tearing the session down lifts the pen once and does nothing the second time.
+#[test]
+fn run_teardown() {
+    let _lock = LOG_LOCK.lock().unwrap();
+
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    feed(&mut engine, touch(100, 200, 50));
+    let mut session = run::Session { output: Some(run::Output::Print), engine, cursor: None, runtime_files: false };
+
+    assert!(session.teardown());
+    assert!(session.output.is_none());
+    assert_eq!(session.engine.fingers(), 0);
+    assert!(!session.teardown());
+    drop(session);
+}
+
+/// Tests that a run with a short timeout waiting for its touchpad
+/// stops cleanly once the timeout is up, within a bounded time.
+#[test]
+fn run_stop_after() {
+    let _lock = STOP_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _log_lock = LOG_LOCK.lock().unwrap();
+    daemon::clear_stop();
+
+    let mut engine = engine::Engine::new(test_bounds(), &config::Config::default());
+    feed(&mut engine, touch(100, 200, 50));
+    let mut session = run::Session { output: Some(run::Output::Print), engine, cursor: None, runtime_files: false };
+    let missing = device::InputMatch::Path(env::temp_dir().join("egawari-test-missing-event"));
+
+    let start = Instant::now();
+    daemon::stop_after(1).unwrap();
+    let run::Session { output, engine, .. } = &mut session;
+    let reopened = run::reopen_input(&missing, output.as_mut().unwrap(), engine);
+    let elapsed = start.elapsed();
+    let stopped = daemon::stop_requested();
+    daemon::clear_stop();
+
+    assert!(matches!(reopened, Ok(None)));
+    assert!(stopped);
+    // The retries are 800ms apart by the time the timeout is up, a busy machine adds some more.
+    assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    assert!(session.teardown());
+    assert!(session.output.is_none());
+    assert_eq!(session.engine.fingers(), 0);
+}
+
+/// Tests reading the focused window from the `xprop` output.
+#[test]
+fn focus_parsing() {
+    assert_eq!(focus::parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007\n"), Some(String::from("0x3a00007")));
+    assert_eq!(focus::parse_active_window("_NET_ACTIVE_WINDOW(WINDOW): window id # 0x0\n"), None);
+    assert_eq!(focus::parse_active_window("_NET_ACTIVE_WINDOW:  not found.\n"), None);
+
+    let window = focus::parse_window("WM_CLASS(STRING) = \"krita\", \"Krita\"\n_NET_WM_NAME(UTF8_STRING) = \"\\\"sketch\\\" - Krita\"\n");
+    assert_eq!(window, focus::Window {
+        instance: String::from("krita"),
+        class: String::from("Krita"),
+        title: String::from("\"sketch\" - Krita")
+    });
+    assert_eq!(focus::parse_window("WM_CLASS:  not found.\n"), focus::Window::default());
+}
+
+/// Tests that the first matching rule picks the profile,
+/// and that the watcher only reports the changes.
+#[test]
+fn focus_rules() {
+    let rule = |class: Option<&str>, title: Option<&str>, profile: &str| config::FocusRule {
+        class: class.map(String::from),
+        title: title.map(String::from),
+        profile: String::from(profile)
+    };
+    let window = |class: &str, title: &str| focus::Window {
+        instance: class.to_lowercase(),
+        class: class.to_string(),
+        title: title.to_string()
+    };
+    let rules = vec![
+        rule(Some("^krita$"), Some("^Preferences"), "menus"),
+        rule(Some("^krita$"), None, "drawing"),
+        rule(None, Some("Inkscape$"), "vector")
+    ];
+
+    assert_eq!(focus::matching_profile(&rules, &window("Krita", "sketch.kra")), Some("drawing"));
+    assert_eq!(focus::matching_profile(&rules, &window("Krita", "Preferences")), Some("menus"));
+    assert_eq!(focus::matching_profile(&rules, &window("Org.inkscape.Inkscape", "logo.svg - Inkscape")), Some("vector"));
+    assert_eq!(focus::matching_profile(&rules, &window("Firefox", "krita.org")), None);
+
+    let mut watcher = focus::FocusWatcher::new(rules.clone(), Duration::ZERO);
+    assert_eq!(watcher.switch(Some(&window("Firefox", "Mozilla Firefox"))), None);
+    assert_eq!(watcher.switch(Some(&window("Krita", "sketch.kra"))), Some(Some(String::from("drawing"))));
+    assert_eq!(watcher.switch(Some(&window("Krita", "other.kra"))), None);
+    // Without any focused window, the config file is used again.
+    assert_eq!(watcher.switch(None), Some(None));
+
+    let conf = config::Config {
+        focus_rules: vec![rule(Some("(krita"), None, "drawing"), rule(None, None, "../drawing")],
+        ..Default::default()
+    };
+    assert_eq!(conf.validate(), vec![
+        String::from("Invalid focus rule pattern: (krita"),
+        String::from("Invalid focus rule profile name: ../drawing")
+    ]);
+
+    // The rules are written as an array of tables, between the other sections.
+    let conf = config::Config { focus_rules: rules, theme: Some(config::Theme::default()), ..Default::default() };
+    let raw = toml::to_string(&conf).unwrap();
+    assert!(raw.contains("[[focus_rules]]"));
+    assert_eq!(toml::from_str::<config::Config>(raw.as_str()).unwrap().focus_rules, conf.focus_rules);
+}
+
+/// Tests the profiles the profile switch keys go through for a sequence of presses.
+#[test]
+fn profile_switch_keys() {
+    let names = vec![String::from("drawing"), String::from("painting"), String::from("sketch")];
+    assert_eq!(profile::next_profile(&names, None), Some(String::from("drawing")));
+    assert_eq!(profile::next_profile(&names, Some("sketch")), Some(String::from("drawing")));
+    assert_eq!(profile::next_profile(&names, Some("deleted")), Some(String::from("drawing")));
+    assert_eq!(profile::next_profile(&[], Some("sketch")), None);
+
+    let switch = config::ProfileSwitch {
+        device: None,
+        notify: false,
+        bindings: vec![
+            config::ProfileBinding { key: String::from("KEY_F9"), profile: None },
+            config::ProfileBinding { key: String::from("BTN_MIDDLE"), profile: Some(String::from("sketch")) }
+        ]
+    };
+    let mut keys = profile::ProfileKeys::new(switch.clone(), Some(String::from("painting")));
+    assert!(keys.on_touchpad());
+
+    let key = |key: Key, value| InputEvent::new(EventType::KEY, key.code(), value);
+    let presses = [
+        key(Key::KEY_F9, 1),
+        key(Key::KEY_F9, 2),
+        key(Key::KEY_F9, 0),
+        key(Key::KEY_F10, 1),
+        key(Key::KEY_F9, 1),
+        key(Key::BTN_MIDDLE, 1),
+        key(Key::BTN_MIDDLE, 0),
+        key(Key::KEY_F9, 1)
+    ];
+    let switched: Vec<Option<String>> = presses.iter()
+        .map(|event| keys.press(event, || names.clone()))
+        .collect();
+    let some = |name: &str| Some(String::from(name));
+    assert_eq!(switched, vec![some("sketch"), None, None, None, some("drawing"), some("sketch"), None, some("drawing")]);
+
+    // The profiles are only listed for the keys switching to the next one.
+    let mut keys = profile::ProfileKeys::new(switch.clone(), None);
+    assert_eq!(keys.press(&key(Key::BTN_MIDDLE, 1), || panic!("Listed the profiles.")), some("sketch"));
+    assert_eq!(keys.press(&key(Key::KEY_F9, 1), Vec::new), None);
+
+    let conf = config::Config {
+        profile_switch: Some(config::ProfileSwitch {
+            bindings: vec![
+                config::ProfileBinding { key: String::from("KEY_NOPE"), profile: None },
+                config::ProfileBinding { key: String::from("KEY_F9"), profile: Some(String::from("../sketch")) }
+            ],
+            ..switch
+        }),
+        ..Default::default()
+    };
+    assert_eq!(conf.validate(), vec![
+        String::from("Unknown profile switch key: KEY_NOPE"),
+        String::from("Invalid profile switch profile name: ../sketch")
+    ]);
+}
+
+/// Tests that the config file is noticed when it changes,
+/// but not checked more than once per second.
+#[test]
+fn run_watcher() {
+    let path = env::temp_dir().join("egawari-test-watcher.toml");
+    std::fs::write(&path, "").unwrap();
+    let touch = |secs| std::fs::File::options().write(true).open(&path).unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    touch(1000);
+
+    let mut watcher = run::Watcher::new(path.clone());
+    let start = watcher.deadline();
+    assert!(!watcher.changed(start));
+
+    touch(2000);
+    assert!(!watcher.changed(start + Duration::from_millis(500)));
+    assert!(watcher.changed(start + Duration::from_secs(1)));
+    assert!(!watcher.changed(start + Duration::from_secs(2)));
+
+    // A removed file isn't a change to reload.
+    std::fs::remove_file(&path).unwrap();
+    assert!(!watcher.changed(start + Duration::from_secs(3)));
+}
+
+/// Tests that only the usable configs with the same output size are reloaded.
+#[test]
+fn run_reload_problems() {
+    let current = mapping_config(config::Mapping::default());
+    let bounds = test_bounds();
+    assert!(run::reload_problems(&current, &mapping_config(config::Mapping {
+        rotation: 90,
+        flip_x: true,
+        ..Default::default()
+    }), bounds).is_empty());
+
+    let resized = config::Config {
+        mapping: config::Mapping {
+            rotation: 45,
+            width: Some(2560),
+            ..current.mapping.clone()
+        },
+        ..current.clone()
+    };
+    assert_eq!(run::reload_problems(&current, &resized, bounds), vec![
+        String::from("The rotation must be 0, 90, 180 or 270 degrees, not 45."),
+        String::from("The output size can't change while egawari is running.")
+    ]);
+}
+
+/// Tests the toggle hiding the cursor and where it can't be used.
+#[test]
+fn cursor_toggle() {
+    let mut conf = mapping_config(config::Mapping::default());
+    assert_eq!(config::ConfigField::TabletHideCursor.get(&conf), Some(config::ConfigValue::Bool(false)));
+    config::ConfigField::TabletHideCursor.set(&mut conf, config::ConfigValue::Bool(true));
+    assert!(conf.tablet.hide_cursor);
+
+    // The cursor stays as it is until egawari runs again.
+    let current = mapping_config(config::Mapping::default());
+    assert_eq!(run::reload_problems(&current, &conf, test_bounds()), vec![
+        String::from("Hiding the cursor can't be turned on or off while egawari is running.")
+    ]);
+
+    if cursor::wayland() {
+        assert!(cursor::problem().is_some_and(|problem| problem.contains("Wayland")));
+        assert!(cursor::HiddenCursor::hide(None).is_err());
+    }
+}
+
+/// Returns a config mapping onto a 1920x1080 output.
+fn mapping_config(mapping: config::Mapping) -> config::Config {
+    config::Config {
+        mapping: config::Mapping {
+            width: Some(1920),
+            height: Some(1080),
+            ..mapping
+        },
+        ..Default::default()
+    }
+}
+
+/// Maps the raw position with the test bounds.
+fn map(x: i32, y: i32, conf: &config::Config) -> (i32, i32) {
+    let point = mapping::map_point(Point { x, y }, conf, test_bounds());
+    (point.x, point.y)
+}
+
+/// Tests that the default mapping keeps the touchpad positions.
+#[test]
+fn mapping_identity() {
+    let conf = config::Config::default();
+    assert_eq!(map(0, 0, &conf), (0, 0));
+    assert_eq!(map(250, 100, &conf), (250, 100));
+    assert_eq!(map(1000, 500, &conf), (1000, 500));
+    assert_eq!(mapping::output_bounds(&conf, test_bounds()), test_bounds());
+
+    // Positions outside the bounds are clamped.
+    assert_eq!(map(-10, 600, &conf), (0, 500));
+}
+
+/// Tests mapping onto the top left quarter of the output.
+#[test]
+fn mapping_half_area() {
+    let conf = mapping_config(config::Mapping {
+        area: Some(config::Area {
+            x: 0,
+            y: 0,
+            width: 960,
+            height: 540,
+            width_mm: None,
+            height_mm: None
+        }),
+        ..Default::default()
+    });
+    assert_eq!(map(0, 0, &conf), (0, 0));
+    assert_eq!(map(500, 250, &conf), (480, 270));
+    assert_eq!(map(1000, 500, &conf), (959, 539));
+}
+
+/// Tests placing a quarter of the output by each anchor, with and without margins.
+#[test]
+fn mapping_anchor() {
+    use config::Anchor;
+    let placed = |anchor: Anchor, x, y| {
+        let conf = mapping_config(config::Mapping {
+            area: Some(config::Area { x, y, width: 960, height: 540, width_mm: None, height_mm: None }),
+            anchor,
+            ..Default::default()
+        });
+        let area = mapping::target_area(&conf, test_bounds());
+        (area.x, area.y)
+    };
+
+    let anchors = [
+        (Anchor::TopLeft, (0, 0), (10, 20)),
+        (Anchor::Top, (480, 0), (490, 20)),
+        (Anchor::TopRight, (960, 0), (950, 20)),
+        (Anchor::Left, (0, 270), (10, 290)),
+        (Anchor::Center, (480, 270), (490, 290)),
+        (Anchor::Right, (960, 270), (950, 290)),
+        (Anchor::BottomLeft, (0, 540), (10, 520)),
+        (Anchor::Bottom, (480, 540), (490, 520)),
+        (Anchor::BottomRight, (960, 540), (950, 520))
+    ];
+    for (anchor, flush, margin) in anchors {
+        assert_eq!(placed(anchor, 0, 0), flush, "{:?}", anchor);
+        assert_eq!(placed(anchor, 10, 20), margin, "{:?}", anchor);
+    }
+
+    // The positions are mapped within the placed area.
+    let conf = mapping_config(config::Mapping {
+        area: Some(config::Area { x: 0, y: 0, width: 960, height: 540, width_mm: None, height_mm: None }),
+        anchor: Anchor::BottomRight,
+        ..Default::default()
+    });
+    assert_eq!(map(0, 0, &conf), (960, 540));
+    assert_eq!(map(1000, 500, &conf), (1919, 1079));
+    assert!(conf.validate().is_empty());
+
+    // Margins pushing the area off the output are refused, and the position can be left out.
+    let margin = |x| mapping_config(config::Mapping {
+        area: Some(config::Area { x, y: 0, width: 960, height: 540, width_mm: None, height_mm: None }),
+        anchor: Anchor::Right,
+        ..Default::default()
+    }).validate();
+    assert!(margin(960).is_empty());
+    assert_eq!(margin(961), vec![String::from("The area doesn't fit in the output.")]);
+    let area: config::Area = toml::from_str("width = 960\nheight = 540\n").unwrap();
+    assert_eq!((area.x, area.y), (0, 0));
+    let mapping: config::Mapping = toml::from_str("anchor = \"bottom_right\"\n").unwrap();
+    assert_eq!(mapping.anchor, Anchor::BottomRight);
+}
+
+/// Tests that the corners follow a 90 degree clockwise rotation.
+#[test]
+fn mapping_rotation() {
+    let conf = mapping_config(config::Mapping {
+        rotation: 90,
+        ..Default::default()
+    });
+    assert_eq!(map(0, 0, &conf), (1919, 0));
+    assert_eq!(map(1000, 0, &conf), (1919, 1079));
+    assert_eq!(map(1000, 500, &conf), (0, 1079));
+    assert_eq!(map(0, 500, &conf), (0, 0));
+}
+
+/// Tests that keeping the aspect ratio letterboxes the area.
+#[test]
+fn mapping_keep_aspect() {
+    let conf = mapping_config(config::Mapping {
+        keep_aspect: true,
+        ..Default::default()
+    });
+    assert_eq!(mapping::target_area(&conf, test_bounds()), config::Area { x: 0, y: 60, width: 1920, height: 960, width_mm: None, height_mm: None });
+    assert_eq!(map(0, 0, &conf), (0, 60));
+    assert_eq!(map(1000, 500, &conf), (1919, 1019));
+
+    // The rotated touchpad is taller than the output.
+    let conf = mapping_config(config::Mapping {
+        keep_aspect: true,
+        rotation: 90,
+        ..Default::default()
+    });
+    assert_eq!(mapping::target_area(&conf, test_bounds()), config::Area { x: 690, y: 0, width: 540, height: 1080, width_mm: None, height_mm: None });
+    assert_eq!(map(0, 0, &conf), (1229, 0));
+}
+
+/// Tests every flip combination on the corners, also combined with a rotation.
+#[test]
+fn mapping_flip() {
+    let corners = [(0, 0), (1000, 0), (1000, 500), (0, 500)];
+    let flip = |flip_x, flip_y, rotation| mapping_config(config::Mapping {
+        flip_x,
+        flip_y,
+        rotation,
+        ..Default::default()
+    });
+    let mapped = |conf: &config::Config| corners.iter().map(|&(x, y)| map(x, y, conf)).collect::<Vec<(i32, i32)>>();
+
+    let (tl, tr, br, bl) = ((0, 0), (1919, 0), (1919, 1079), (0, 1079));
+    assert_eq!(mapped(&flip(false, false, 0)), vec![tl, tr, br, bl]);
+    assert_eq!(mapped(&flip(true, false, 0)), vec![tr, tl, bl, br]);
+    assert_eq!(mapped(&flip(false, true, 0)), vec![bl, br, tr, tl]);
+    assert_eq!(mapped(&flip(true, true, 0)), vec![br, bl, tl, tr]);
+
+    // The flip is applied before the rotation.
+    assert_eq!(mapped(&flip(true, false, 90)), vec![br, tr, tl, bl]);
+}
+
+/// Tests that the scales stretch the motion around the center
+/// and compose with the area and the rotation.
+#[test]
+fn mapping_scale() {
+    let conf = mapping_config(config::Mapping {
+        scale_x: 2.0,
+        area: Some(config::Area {
+            x: 0,
+            y: 0,
+            width: 960,
+            height: 540,
+            width_mm: None,
+            height_mm: None
+        }),
+        ..Default::default()
+    });
+    assert_eq!(map(500, 250, &conf), (480, 270));
+    assert_eq!(map(625, 250, &conf), (719, 270));
+
+    // Positions beyond the edges are clamped to the area.
+    assert_eq!(map(750, 500, &conf), (959, 539));
+    assert_eq!(map(0, 0, &conf), (0, 0));
+
+    // The scales are along the axes of the touchpad.
+    let conf = mapping_config(config::Mapping {
+        scale_x: 2.0,
+        rotation: 90,
+        ..Default::default()
+    });
+    assert_eq!(map(750, 250, &conf), (960, 1079));
+    assert_eq!(map(500, 0, &conf), (1919, 540));
+}
+
+/// Tests that the corners of the touchpad land exactly on the corners of the quad.
+#[test]
+fn mapping_quad() {
+    let quad = |top_left, top_right, bottom_right, bottom_left| mapping_config(config::Mapping {
+        quad: Some(config::Quad { top_left, top_right, bottom_right, bottom_left }),
+        ..Default::default()
+    });
+    let corners = |conf: &config::Config| [map(0, 0, conf), map(1000, 0, conf), map(1000, 500, conf), map(0, 500, conf)];
+
+    let keystone = quad([100, 0], [1820, 0], [1919, 1079], [0, 1079]);
+    assert_eq!(corners(&keystone), [(100, 0), (1820, 0), (1919, 1079), (0, 1079)]);
+    // The middle of a symmetric trapezoid stays centered.
+    assert_eq!(map(500, 250, &keystone).0, 960);
+    assert!(keystone.validate().is_empty());
+
+    let skewed = quad([200, 50], [1700, 150], [1800, 1000], [50, 900]);
+    assert_eq!(corners(&skewed), [(200, 50), (1700, 150), (1800, 1000), (50, 900)]);
+    let parallelogram = quad([100, 0], [1100, 0], [1000, 500], [0, 500]);
+    assert_eq!(corners(&parallelogram), [(100, 0), (1100, 0), (1000, 500), (0, 500)]);
+    assert_eq!(map(500, 250, &parallelogram), (550, 250));
+
+    // The corners follow the rotation of the touchpad.
+    let mut rotated = skewed.clone();
+    rotated.mapping.rotation = 90;
+    assert_eq!(map(0, 500, &rotated), (200, 50));
+
+    let crossed = quad([0, 0], [1919, 1079], [1919, 0], [0, 1079]);
+    assert_eq!(crossed.validate(), vec![String::from("The corners of the quad must make a convex quadrilateral.")]);
+    let outside = quad([0, 0], [1920, 0], [1919, 1079], [0, 1079]);
+    assert_eq!(outside.validate(), vec![String::from("The quad doesn't fit in the output.")]);
+}
+
+/// Tests that positions near the edges snap onto them and the others don't.
+#[test]
+fn mapping_edge_snap() {
+    let snapping = |pixels, percent, area| mapping_config(config::Mapping {
+        edge_snap: Some(config::EdgeSnap { pixels, percent }),
+        area,
+        ..Default::default()
+    });
+
+    // 2 touchpad units are about 4 pixels on the 1920x1080 output.
+    let conf = snapping(8, 0.0, None);
+    assert_eq!(map(2, 2, &conf), (0, 0));
+    assert_eq!(map(998, 497, &conf), (1919, 1079));
+    assert_eq!(map(500, 250, &conf), (960, 540));
+    assert_eq!(map(10, 490, &conf), (19, 1057));
+    assert_eq!(map(2, 2, &mapping_config(config::Mapping::default())), (4, 4));
+
+    // The percent is of the area size, and the edges are the ones of the area.
+    let area = config::Area { x: 960, y: 540, width: 960, height: 540, width_mm: None, height_mm: None };
+    let conf = snapping(0, 2.0, Some(area));
+    assert_eq!(map(20, 1, &conf), (960, 540));
+    assert_eq!(map(985, 495, &conf), (1919, 1079));
+    assert_eq!(map(50, 30, &conf), (1008, 572));
+
+    // With a quad, the positions snap onto the edges of the output.
+    let quad = mapping_config(config::Mapping {
+        quad: Some(config::Quad { top_left: [2, 2], top_right: [1917, 2], bottom_right: [1917, 1077], bottom_left: [2, 1077] }),
+        edge_snap: Some(config::EdgeSnap { pixels: 4, percent: 0.0 }),
+        ..Default::default()
+    });
+    assert_eq!(map(0, 0, &quad), (0, 0));
+    assert_eq!(map(1000, 500, &quad), (1919, 1079));
+
+    assert_eq!(snapping(0, 60.0, None).validate(), vec![String::from("The edge snap percent must be from 0 to 50.")]);
+    assert!(snapping(0, 50.0, None).validate().is_empty());
+}
+
+/// Tests that the trace of a mapped position goes through every stage
+/// and ends where `map_point` does.
+#[test]
+fn mapping_trace() {
+    let conf = mapping_config(config::Mapping {
+        scale_x: 0.5,
+        flip_y: true,
+        rotation: 90,
+        edge_snap: Some(config::EdgeSnap { pixels: 8, percent: 0.0 }),
+        ..Default::default()
+    });
+
+    let trace = mapping::trace_point(Point { x: 250, y: 100 }, &conf, test_bounds());
+    assert_eq!(trace.normalized, (0.25, 0.2));
+    assert_eq!(trace.scaled, (0.375, 0.2));
+    assert_eq!(trace.flipped, (0.375, 0.8));
+    assert_eq!(trace.rotated, (0.19999999999999996, 0.375));
+    assert_eq!((trace.area.width, trace.area.height), (1920, 1080));
+    assert_eq!(trace.unsnapped, Point { x: 384, y: 405 });
+    assert_eq!(trace.point, mapping::map_point(Point { x: 250, y: 100 }, &conf, test_bounds()));
+
+    // Only the last stage snaps onto the edges.
+    let trace = mapping::trace_point(Point { x: 500, y: 250 }, &conf, test_bounds());
+    assert_eq!(trace.unsnapped, Point { x: 960, y: 540 });
+    assert_eq!(trace.point, trace.unsnapped);
+    let trace = mapping::trace_point(Point { x: 500, y: 2 }, &conf, test_bounds());
+    assert_eq!((trace.unsnapped.x, trace.point.x), (8, 0));
+}
+
+/// Tests that degenerate touchpad ranges map to the middle instead of failing,
+/// and are replaced by the fallback bounds when the config has them.
+#[test]
+fn mapping_degenerate_bounds() {
+    let conf = mapping_config(config::Mapping::default());
+    let empty = device::Bounds { max_x: 0, ..test_bounds() };
+    let inverted = device::Bounds { min_y: 500, max_y: 0, ..test_bounds() };
+    for (bounds, expected) in [(empty, Point { x: 960, y: 0 }), (inverted, Point { x: 0, y: 540 })] {
+        assert!(mapping::degenerate(bounds));
+        assert_eq!(mapping::map_point(Point { x: 0, y: 0 }, &conf, bounds), expected);
+        assert_eq!(mapping::usable_bounds(&conf, bounds), None);
+    }
+
+    // The widest range doesn't overflow.
+    let widest = device::Bounds { min_x: i32::MIN, max_x: i32::MAX, ..test_bounds() };
+    assert_eq!(mapping::map_point(Point { x: i32::MAX, y: 0 }, &conf, widest), Point { x: 1919, y: 0 });
+    assert_eq!(mapping::output_size(&config::Config::default(), widest).0, u32::MAX);
+
+    let mut fallback = conf.clone();
+    fallback.input.fallback_bounds = Some(config::InputBounds { min_x: 0, max_x: 100, min_y: 0, max_y: 50 });
+    assert_eq!(mapping::usable_bounds(&fallback, test_bounds()), Some(test_bounds()));
+    assert_eq!(mapping::usable_bounds(&fallback, empty), Some(device::Bounds { max_x: 100, max_y: 50, ..test_bounds() }));
+    assert_eq!(mapping::map_point(Point { x: 50, y: 25 }, &fallback, mapping::usable_bounds(&fallback, empty).unwrap()), Point { x: 960, y: 540 });
+
+    fallback.input.fallback_bounds = Some(config::InputBounds { min_x: 0, max_x: 0, min_y: 0, max_y: 50 });
+    assert_eq!(mapping::usable_bounds(&fallback, empty), None);
+    assert_eq!(fallback.validate(), vec![String::from("The fallback bounds must have each maximum above its minimum.")]);
+}
+
+/// Tests the resolution of the virtual tablet from the physical sizes.
+#[test]
+fn mapping_resolution() {
+    // Without a physical size, the screen is assumed to be 96 DPI.
+    let conf = mapping_config(config::Mapping::default());
+    assert_eq!(mapping::tablet_resolution(&conf, test_bounds(), None), (4, 4));
+
+    // The touchpad is 100x50mm, mapped onto 1920x1080 pixels.
+    assert_eq!(mapping::tablet_resolution(&conf, test_bounds(), Some((10, 10))), (19, 22));
+    let rotated = mapping_config(config::Mapping {
+        rotation: 90,
+        ..Default::default()
+    });
+    assert_eq!(mapping::tablet_resolution(&rotated, test_bounds(), Some((10, 10))), (38, 11));
+
+    // The physical size of the area overrides the size of the touchpad.
+    let conf = mapping_config(config::Mapping {
+        area: Some(config::Area {
+            x: 0,
+            y: 0,
+            width: 960,
+            height: 540,
+            width_mm: Some(96.0),
+            height_mm: None
+        }),
+        ..Default::default()
+    });
+    assert_eq!(mapping::tablet_resolution(&conf, test_bounds(), Some((10, 10))), (10, 11));
+    assert!(conf.validate().is_empty());
+
+    let mut invalid = conf.clone();
+    invalid.mapping.area.as_mut().unwrap().height_mm = Some(0.0);
+    assert_eq!(invalid.validate(), vec![String::from("The physical width and height of the area must be positive.")]);
+}
+
+/// Tests that the completion scripts cover every command and option.
+#[test]
+fn completion_scripts() {
+    for shell in completions::SHELLS {
+        let script = completions::script(shell).unwrap();
+        for (command, _) in completions::COMMANDS {
+            assert!(script.contains(command), "{} is missing from the {} script", command, shell);
+        }
+        for (long, _, _) in args::OPTIONS {
+            assert!(script.contains(long.trim_end_matches('=')), "{} is missing from the {} script", long, shell);
+        }
+        for command in completions::PROFILE_COMMANDS {
+            assert!(script.contains(command));
+        }
+        for (key, _, _) in config::KEYS {
+            assert!(script.contains(key), "{} is missing from the {} script", key, shell);
+        }
+    }
+
+    // Only the values of the file options stay open, and only those complete files.
+    let bash = completions::script("bash").unwrap();
+    assert!(bash.contains("complete -F _egawari egawari"));
+    assert!(bash.contains("--config|--dump-events) compopt -o nospace;"));
+    assert!(bash.contains("--color) COMPREPLY=($(compgen -W \"auto always never\""));
+    assert!(bash.contains("--autosave|--timeout|--bounds) return ;;"));
+    let fish = completions::script("fish").unwrap();
+    assert!(fish.contains("complete -c egawari -l color -r -f -a 'auto always never'"));
+    assert!(fish.contains("complete -c egawari -l timeout -r -f -d"));
+    assert!(completions::script("zsh").unwrap().contains("'--bounds=[Maps map-point and active-area with the given min_x,max_x,min_y,max_y]:value: '"));
+    assert!(completions::script("zsh").unwrap().starts_with("#compdef egawari"));
+    assert_eq!(completions::script("powershell"), None);
+}
+
+/// Tests scaling the area of the output onto the screen and the edges of its outline.
+#[test]
+fn overlay_geometry() {
+    let area = config::Area { x: 960, y: 540, width: 960, height: 540, width_mm: None, height_mm: None };
+    assert_eq!(overlay::screen_rect(area, (1920, 1080), (3840, 2160)), Rect { x: 1920, y: 1080, width: 1920, height: 1080 });
+    assert_eq!(overlay::screen_rect(area, (1920, 1080), (1920, 1080)), Rect { x: 960, y: 540, width: 960, height: 540 });
+    let empty = config::Area { width: 0, height: 0, ..area };
+    assert_eq!(overlay::screen_rect(empty, (1920, 1080), (1920, 1080)), Rect { x: 960, y: 540, width: 1, height: 1 });
+
+    assert_eq!(overlay::frame(100, 50, 4), vec![
+        Rect { x: 0, y: 0, width: 100, height: 4 },
+        Rect { x: 0, y: 46, width: 100, height: 4 },
+        Rect { x: 0, y: 0, width: 4, height: 50 },
+        Rect { x: 96, y: 0, width: 4, height: 50 }
+    ]);
+    assert!(overlay::frame(3, 2, 4).iter().all(|edge| edge.x == 0 && edge.y == 0));
+}
+
+/// Tests that the timeout of run requests the stop like Ctrl-C does once it's up, and not before.
+#[test]
+fn daemon_stop_after() {
+    let _lock = STOP_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    daemon::clear_stop();
+    let start = Instant::now();
+    daemon::stop_after(1).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(!daemon::stop_requested());
 
-    let input_name = &conf.input.name;
-    assert_eq!(config::get_config().unwrap().input.name, *input_name);
+    // The bound is loose, a busy machine may deliver the alarm late.
+    while !daemon::stop_requested() && start.elapsed() < Duration::from_secs(10) {
+        thread::sleep(Duration::from_millis(20));
+    }
+    let stopped = daemon::stop_requested();
+    daemon::clear_stop();
+    assert!(stopped);
+    assert!(start.elapsed() >= Duration::from_secs(1));
 }
@@ -0,0 +1,200 @@
+/****************************************************************************
+** egawari - Makes your touchpad work like a graphics tablet.
+** Copyright (C) 2021  acedron <acedrons@yahoo.co.jp>
+**
+** This program is free software: you can redistribute it and/or modify
+** it under the terms of the GNU General Public License as published by
+** the Free Software Foundation, either version 3 of the License, or
+** (at your option) any later version.
+**
+** This program is distributed in the hope that it will be useful,
+** but WITHOUT ANY WARRANTY; without even the implied warranty of
+** MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+** GNU General Public License for more details.
+**
+** You should have received a copy of the GNU General Public License
+** along with this program.  If not, see <https://www.gnu.org/licenses/>.
+****************************************************************************/
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process;
+use anyhow::{Context, Result};
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, Key};
+
+use crate::area;
+use crate::config::{self, Config};
+use crate::daemon;
+use crate::device::Bounds;
+use crate::mapping::{self, Point};
+use crate::run;
+use crate::stdout;
+
+/// The latest values the touchpad reported, as shown by `watch`.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// let mut readout = watch::Readout::new(AbsoluteAxisType::ABS_PRESSURE);
+/// for event in input.fetch_events()? {
+///     readout.process(&event);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Readout {
+    pressure_axis: AbsoluteAxisType,
+    pub x: i32,
+    pub y: i32,
+    pub pressure: Option<i32>,
+    pub touching: bool,
+    slot: usize,
+    slots: Vec<bool>
+}
+
+impl Readout {
+    /// Creates the readout reading the pressure from the given axis.
+    pub fn new(pressure_axis: AbsoluteAxisType) -> Readout {
+        Readout {
+            pressure_axis,
+            x: 0,
+            y: 0,
+            pressure: None,
+            touching: false,
+            slot: 0,
+            slots: vec![]
+        }
+    }
+
+    /// Records the value of the touchpad event.
+    pub fn process(&mut self, event: &InputEvent) {
+        match event.kind() {
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => self.x = event.value(),
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => self.y = event.value(),
+            InputEventKind::AbsAxis(axis) if axis == self.pressure_axis => self.pressure = Some(event.value()),
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_SLOT) => self.slot = event.value().max(0) as usize,
+            InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+                if self.slots.len() <= self.slot {
+                    self.slots.resize(self.slot + 1, false);
+                }
+                self.slots[self.slot] = event.value() != -1;
+            },
+            InputEventKind::Key(Key::BTN_TOUCH) => self.touching = event.value() != 0,
+            _ => ()
+        }
+    }
+
+    /// Returns the number of fingers on the touchpad.
+    /// Touchpads without slots count a single finger while touching.
+    pub fn fingers(&self) -> usize {
+        match self.slots.is_empty() {
+            true => self.touching as usize,
+            false => self.slots.iter().filter(|active| **active).count()
+        }
+    }
+
+    /// Lays out the lines showing the raw values within the bounds
+    /// and the position they're mapped to with the config.
+    pub fn lines(&self, name: &str, path: &Path, conf: &Config, bounds: Bounds) -> Vec<String> {
+        let mapped = mapping::map_point(Point { x: self.x, y: self.y }, conf, bounds);
+        let output = mapping::output_bounds(conf, bounds);
+        let pressure = match (self.pressure, bounds.pressure) {
+            (Some(pressure), Some((min, max))) => format!("{} \x1b[1;39m({} to {})", pressure, min, max),
+            (None, Some((min, max))) => format!("0 \x1b[1;39m({} to {})", min, max),
+            (_, None) => String::from("none")
+        };
+
+        vec![
+            String::from("---===egawari=Watch===---"),
+            String::new(),
+            String::from(r"=\[Touchpad\]="),
+            format!(" => Device = \x1b[0;39m{:?} \x1b[1;39m({})", name, path.display()),
+            format!(" => X = \x1b[0;39m{} \x1b[1;39m({} to {})", self.x, bounds.min_x, bounds.max_x),
+            format!(" => Y = \x1b[0;39m{} \x1b[1;39m({} to {})", self.y, bounds.min_y, bounds.max_y),
+            format!(" => Pressure = \x1b[0;39m{}", pressure),
+            format!(" => Fingers = \x1b[0;39m{}", self.fingers()),
+            format!(" => Touching = \x1b[0;39m{}", self.touching),
+            String::new(),
+            String::from(r"=\[Mapped\]="),
+            format!(" => X = \x1b[0;39m{} \x1b[1;39m({} to {})", mapped.x, output.min_x, output.max_x),
+            format!(" => Y = \x1b[0;39m{} \x1b[1;39m({} to {})", mapped.y, output.min_y, output.max_y),
+            String::new(),
+            String::from("---======================---"),
+            String::new(),
+            String::from(r#" => Press "Esc" or "q" to exit."#)
+        ]
+    }
+}
+
+/// Draws the lines from the top of the window.
+fn render(window: &pancurses::Window, lines: &[String]) {
+    window.erase();
+    for (y, line) in lines.iter().enumerate().take(window.get_max_y().max(0) as usize) {
+        colwmvaddstr!(window, y as i32, 0, line.as_str());
+    }
+    window.refresh();
+}
+
+/// Runs the `watch` command, showing the values the configured touchpad reports
+/// live, with the position the current config maps them to, until "Esc" is pressed.
+/// The touchpad isn't grabbed, so it keeps working meanwhile.
+/// 
+/// ## Example
+/// 
+/// ```rust
+/// watch::watch().unwrap();
+/// ```
+pub fn watch() -> Result<()> {
+    let conf = config::get_config()?;
+    if conf.input.names.iter().all(|name| name.is_empty()) && conf.input.id.is_none() && conf.input.path.is_none() {
+        errln!("No input device is configured.");
+        logln!("See: \x1b[0;39megawari config");
+        process::exit(1);
+    }
+    let conf = area::resolve_target(conf)?;
+
+    let (_, path, mut input, bounds) = run::open_touchpad(&conf)?;
+    let name = input.name().unwrap_or_default().to_string();
+    daemon::install_stop_handler()?;
+
+    let window = stdout::init_curses_wcolors();
+    window.keypad(true);
+    window.nodelay(true);
+    pancurses::noecho();
+    pancurses::curs_set(0);
+
+    let mut readout = Readout::new(conf.tablet.pressure_source.axis());
+    let result = (|| -> Result<()> {
+        loop {
+            render(&window, &readout.lines(name.as_str(), path.as_path(), &conf, bounds));
+
+            let readable = match run::wait_readable(&[input.as_raw_fd(), io::stdin().as_raw_fd()], None) {
+                Ok(readable) => readable,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => vec![false, false],
+                Err(e) => return Err(e).context("Couldn't wait for the input device.")
+            };
+            if daemon::stop_requested() {
+                return Ok(());
+            }
+
+            if readable[0] {
+                let events = input.fetch_events().context("Couldn't read from the input device.")?;
+                for event in events {
+                    readout.process(&event);
+                }
+            }
+            while let Some(key) = window.getch() {
+                match key {
+                    pancurses::Input::Character('\u{1b}') | pancurses::Input::Character('q') => return Ok(()),
+                    pancurses::Input::KeyResize => {
+                        pancurses::resize_term(0, 0);
+                    },
+                    _ => ()
+                }
+            }
+        }
+    })();
+
+    pancurses::curs_set(1);
+    pancurses::endwin();
+    result
+}